@@ -3,12 +3,17 @@
 use anyhow::{anyhow, Result};
 use clap::{ArgAction, Args, Parser, Subcommand};
 use std::path::PathBuf;
+use unplug::common::Compression;
 
 /// The minimum accepted volume level for playback.
 const MIN_VOLUME: i32 = 0;
 /// The maximum accepted volume level for playback.
 const MAX_VOLUME: i32 = 100;
 
+fn parse_compression(s: &str) -> Result<Compression> {
+    Compression::from_name(s).ok_or_else(|| anyhow!("format must be one of: yaz0, yay0"))
+}
+
 #[derive(Parser)]
 #[clap(name = "Unplug", version)]
 #[clap(about = "Chibi-Robo! Plug Into Adventure! Modding Toolkit")]
@@ -94,6 +99,14 @@ pub enum Command {
     #[clap(subcommand)]
     Messages(messages::Subcommand),
 
+    /// Create or apply distributable mod patches
+    #[clap(subcommand)]
+    Patch(patch::Subcommand),
+
+    /// Discard or bake changes in an overlay project
+    #[clap(subcommand)]
+    Overlay(overlay::Subcommand),
+
     /// Manage Unplug projects
     #[clap(subcommand)]
     Project(project::Subcommand),
@@ -165,6 +178,38 @@ pub mod config {
     }
 }
 
+pub mod overlay {
+    use super::*;
+
+    #[derive(Subcommand)]
+    pub enum Subcommand {
+        /// List the changes stored in the overlay
+        Status,
+        /// Delete all changes stored in the overlay
+        Discard(DiscardArgs),
+        /// Write a new ISO which combines the base ISO with the overlay's changes
+        Bake(BakeArgs),
+    }
+
+    #[derive(Args)]
+    pub struct DiscardArgs {
+        /// Do not prompt for confirmation
+        #[clap(short, long)]
+        pub force: bool,
+    }
+
+    #[derive(Args)]
+    pub struct BakeArgs {
+        /// Allow overwriting an existing ISO
+        #[clap(short, long)]
+        pub force: bool,
+
+        /// Path of the ISO to create
+        #[clap(short, value_name("PATH"))]
+        pub output: PathBuf,
+    }
+}
+
 pub mod project {
     use super::*;
 
@@ -237,6 +282,10 @@ pub mod project {
         /// Do not open the new project
         #[clap(long)]
         pub no_open: bool,
+
+        /// Store changes in an overlay directory instead of copying the ISO
+        #[clap(long)]
+        pub overlay: bool,
     }
 
     #[derive(Args)]
@@ -395,6 +444,48 @@ pub mod messages {
     }
 }
 
+pub mod patch {
+    use super::*;
+
+    #[derive(Subcommand)]
+    pub enum Subcommand {
+        /// Create a patch containing the files changed by the current project
+        Create(CreateArgs),
+        /// Apply one or more patches to the current project
+        Apply(ApplyArgs),
+    }
+
+    #[derive(Args)]
+    pub struct CreateArgs {
+        /// Path to the unmodified ISO (defaults to the default ISO)
+        #[clap(long, value_name("PATH"))]
+        pub base: Option<PathBuf>,
+
+        /// Name of the patch (defaults to the output filename)
+        #[clap(long)]
+        pub name: Option<String>,
+
+        /// Create the patch even if it cannot include every change
+        #[clap(short, long)]
+        pub force: bool,
+
+        /// Path of the patch file to create
+        #[clap(short, value_name("PATH"))]
+        pub output: PathBuf,
+    }
+
+    #[derive(Args)]
+    pub struct ApplyArgs {
+        /// Apply patches even if they conflict with the current files
+        #[clap(short, long)]
+        pub force: bool,
+
+        /// Paths to the patch files, in the order to apply them
+        #[clap(value_name("PATCH"), required = true)]
+        pub patches: Vec<PathBuf>,
+    }
+}
+
 pub mod globals {
     use super::*;
 
@@ -468,7 +559,7 @@ pub mod audio {
     pub enum Subcommand {
         /// Show information about an audio resource
         Info(InfoArgs),
-        /// Export one or more audio resources to wav files
+        /// Export one or more audio resources to WAV or FLAC files
         Export(ExportArgs),
         /// Export an entire sample bank to a directory
         ExportBank(ExportBankArgs),
@@ -478,6 +569,9 @@ pub mod audio {
         Import(ImportArgs),
         /// Play an audio resource
         Play(PlayArgs),
+        /// Export or import the sound effect playlist
+        #[clap(subcommand)]
+        Playlist(PlaylistSubcommand),
     }
 
     #[derive(Args)]
@@ -486,6 +580,10 @@ pub mod audio {
         /// Audacity's label track format
         #[clap(long)]
         pub labels: bool,
+
+        /// Export FLAC files instead of WAV files. Cue points are stored in the FLAC metadata.
+        #[clap(long)]
+        pub flac: bool,
     }
 
     #[derive(Args)]
@@ -497,28 +595,37 @@ pub mod audio {
 
     #[derive(Args)]
     pub struct InfoArgs {
-        /// The name or path of the audio resource
+        /// Read the sound from a Wii BRSAR file (e.g. cb_robo.brsar) instead of the game
+        #[clap(long, value_name("PATH"))]
+        pub brsar: Option<PathBuf>,
+
+        /// The name or path of the audio resource, or a sound name or index if --brsar is used
         pub name: String,
     }
 
     #[derive(Args)]
     pub struct ExportArgs {
-        /// If extracting one audio resource, the path of the .wav file to write, otherwise the
-        /// directory to write the audio files to
+        /// If extracting one audio resource, the path of the .wav or .flac file to write, otherwise
+        /// the directory to write the audio files to
         #[clap(short, value_name("PATH"))]
         pub output: Option<PathBuf>,
 
         #[clap(flatten)]
         pub settings: ExportSettings,
 
-        /// Names or paths of the audio resources to export
+        /// Read sounds from a Wii BRSAR file (e.g. cb_robo.brsar) instead of the game
+        #[clap(long, value_name("PATH"))]
+        pub brsar: Option<PathBuf>,
+
+        /// Names or paths of the audio resources to export, or sound names or indexes if --brsar is
+        /// used
         #[clap(required = true)]
         pub names: Vec<String>,
     }
 
     #[derive(Args)]
     pub struct ExportBankArgs {
-        /// The directory to write the bank's .wav files to (defaults to the bank name)
+        /// The directory to write the bank's audio files to (defaults to the bank name)
         #[clap(short, value_name("PATH"))]
         pub output: Option<PathBuf>,
 
@@ -541,7 +648,11 @@ pub mod audio {
 
     #[derive(Args)]
     pub struct ImportArgs {
-        /// Name or path of the sound resource to import
+        /// Write the sound into a Wii BRSAR file (e.g. cb_robo.brsar) instead of the game
+        #[clap(long, value_name("PATH"))]
+        pub brsar: Option<PathBuf>,
+
+        /// Name or path of the sound resource to import, or a sound name or index if --brsar is used
         pub name: String,
 
         #[clap(flatten)]
@@ -563,13 +674,42 @@ pub mod audio {
 
     #[derive(Args)]
     pub struct PlayArgs {
-        /// Name or path of the audio resource to play
+        /// Read the sound from a Wii BRSAR file (e.g. cb_robo.brsar) instead of the game
+        #[clap(long, value_name("PATH"))]
+        pub brsar: Option<PathBuf>,
+
+        /// Name or path of the audio resource to play, or a sound name or index if --brsar is used
         pub name: String,
 
         /// Volume level as a percentage (0-100, default 80)
         #[clap(long, default_value = "80", allow_hyphen_values = true, value_parser = parse_volume)]
         pub volume: f64,
     }
+
+    #[derive(Subcommand)]
+    pub enum PlaylistSubcommand {
+        /// Export sound effect materials to a JSON file
+        Export(PlaylistExportArgs),
+        /// Import sound effect materials from a JSON file
+        Import(PlaylistImportArgs),
+    }
+
+    #[derive(Args)]
+    pub struct PlaylistExportArgs {
+        /// Don't output unnecessary whitespace
+        #[clap(short, long)]
+        pub compact: bool,
+
+        /// Redirect output to a file instead of stdout
+        #[clap(short, value_name("PATH"))]
+        pub output: Option<PathBuf>,
+    }
+
+    #[derive(Args)]
+    pub struct PlaylistImportArgs {
+        /// Path to the input JSON file
+        pub input: PathBuf,
+    }
 }
 
 pub mod dolphin {
@@ -589,6 +729,7 @@ pub mod dolphin {
 
 pub mod iso {
     use super::*;
+    use unplug::dvd::ImageFormat;
 
     #[derive(Subcommand)]
     pub enum Subcommand {
@@ -600,11 +741,91 @@ pub mod iso {
         Extract(ExtractArgs),
         /// Extract all files from the ISO
         ExtractAll(ExtractAllArgs),
+        /// Build a new ISO from an extracted directory
+        Build(BuildArgs),
+        /// Defragment the ISO so that all free space is at the end
+        Compact(CompactArgs),
+        /// Write a copy of the ISO with all unused space zeroed out
+        Scrub(ScrubArgs),
+        /// Convert the ISO to a different image format
+        Convert(ConvertArgs),
         /// Replace a file in the ISO
         Replace(ReplaceArgs),
+        /// Add a new file or directory to the ISO
+        Add(AddArgs),
+        /// Remove files or directories from the ISO
+        #[clap(alias = "rm")]
+        Remove(RemoveArgs),
+        /// Move or rename a file or directory in the ISO
+        #[clap(alias = "mv")]
+        Move(MoveArgs),
         /// Change properties of the ISO
         #[clap(subcommand)]
         Set(SetCommand),
+        /// Export or import the banner image and text
+        #[clap(subcommand)]
+        Banner(BannerCommand),
+        /// Export or import the disc header (game ID, region, etc.)
+        #[clap(subcommand)]
+        Header(HeaderCommand),
+        /// Permanently apply Gecko codes to main.dol
+        PatchCodes(PatchCodesArgs),
+        /// Disassemble code in main.dol
+        #[clap(alias = "disasm")]
+        Disassemble(DisassembleArgs),
+        /// Compare the files in two ISOs
+        Diff(DiffArgs),
+        /// Check the ISO's files against a clean copy of the game
+        Verify(VerifyArgs),
+    }
+
+    #[derive(Subcommand)]
+    pub enum BannerCommand {
+        /// Export the banner image to a PNG file
+        Export {
+            /// Path of the PNG file to write
+            #[clap(short, value_name("PATH"))]
+            output: PathBuf,
+        },
+        /// Import the banner image from a 96x32 PNG file
+        Import {
+            /// Path of the PNG file to read
+            path: PathBuf,
+        },
+        /// Export the banner text for every language to JSON
+        ExportInfo {
+            /// Don't output unnecessary whitespace
+            #[clap(short, long)]
+            compact: bool,
+
+            /// Redirect output to a file instead of stdout
+            #[clap(short, value_name("PATH"))]
+            output: Option<PathBuf>,
+        },
+        /// Import the banner text for every language from JSON
+        ImportInfo {
+            /// Path to the input JSON file
+            path: PathBuf,
+        },
+    }
+
+    #[derive(Subcommand)]
+    pub enum HeaderCommand {
+        /// Export the disc header fields to JSON
+        Export {
+            /// Don't output unnecessary whitespace
+            #[clap(short, long)]
+            compact: bool,
+
+            /// Redirect output to a file instead of stdout
+            #[clap(short, value_name("PATH"))]
+            output: Option<PathBuf>,
+        },
+        /// Import the disc header fields from JSON
+        Import {
+            /// Path to the input JSON file
+            path: PathBuf,
+        },
     }
 
     #[derive(Subcommand)]
@@ -637,6 +858,10 @@ pub mod iso {
         #[clap(short, value_name("PATH"))]
         pub output: Option<PathBuf>,
 
+        /// Decompress Yaz0 and Yay0 files while extracting them
+        #[clap(long)]
+        pub decompress: bool,
+
         /// Paths of files to extract
         pub paths: Vec<String>,
     }
@@ -646,6 +871,73 @@ pub mod iso {
         /// The directory to extract files to
         #[clap(short, value_name("PATH"))]
         pub output: Option<PathBuf>,
+
+        /// Also extract system data, putting it in sys/ and game files in files/
+        #[clap(long)]
+        pub sys: bool,
+
+        /// Decompress Yaz0 and Yay0 files while extracting them
+        #[clap(long)]
+        pub decompress: bool,
+    }
+
+    #[derive(Args)]
+    pub struct BuildArgs {
+        /// Allow overwriting an existing ISO
+        #[clap(short, long)]
+        pub force: bool,
+
+        /// Path of the ISO to create
+        #[clap(short, value_name("PATH"))]
+        pub output: PathBuf,
+
+        /// Directory containing sys/ and files/ (see `iso extract-all --sys`)
+        #[clap(value_name("DIR"))]
+        pub input: PathBuf,
+    }
+
+    #[derive(Args)]
+    pub struct CompactArgs {
+        /// Truncate the ISO after the last file
+        #[clap(long)]
+        pub shrink: bool,
+    }
+
+    #[derive(Args)]
+    pub struct ScrubArgs {
+        /// Allow overwriting an existing ISO
+        #[clap(short, long)]
+        pub force: bool,
+
+        /// Truncate the copy after the last file
+        #[clap(long)]
+        pub trim: bool,
+
+        /// Path of the ISO to create
+        #[clap(short, value_name("PATH"))]
+        pub output: PathBuf,
+    }
+
+    fn parse_image_format(s: &str) -> Result<ImageFormat> {
+        match ImageFormat::from_extension(s) {
+            Some(format) if format.is_writable() => Ok(format),
+            _ => Err(anyhow!("format must be one of: iso, ciso, gcz")),
+        }
+    }
+
+    #[derive(Args)]
+    pub struct ConvertArgs {
+        /// Allow overwriting an existing image
+        #[clap(short, long)]
+        pub force: bool,
+
+        /// The image format to write (iso, ciso, or gcz). Defaults to the output's extension.
+        #[clap(long, value_parser = parse_image_format)]
+        pub format: Option<ImageFormat>,
+
+        /// Path of the image to create
+        #[clap(short, value_name("PATH"))]
+        pub output: PathBuf,
     }
 
     #[derive(Args)]
@@ -657,6 +949,119 @@ pub mod iso {
         /// Path to the file to replace it with
         #[clap(value_name("src"))]
         pub src_path: PathBuf,
+
+        /// Compress the file before writing it (yaz0 or yay0, defaults to yaz0)
+        #[clap(
+            long,
+            value_name("FORMAT"),
+            num_args(0..=1),
+            require_equals(true),
+            default_missing_value("yaz0"),
+            value_parser = parse_compression
+        )]
+        pub compress: Option<Compression>,
+    }
+
+    #[derive(Args)]
+    pub struct AddArgs {
+        /// Path of the new file or directory in the ISO
+        #[clap(value_name("dest"))]
+        pub dest_path: String,
+
+        /// Path to the local file or directory to add
+        #[clap(value_name("src"))]
+        pub src_path: PathBuf,
+    }
+
+    #[derive(Args)]
+    pub struct RemoveArgs {
+        /// Remove directories and everything inside them
+        #[clap(short, long)]
+        pub recursive: bool,
+
+        /// Paths of files or directories to remove
+        #[clap(required = true)]
+        pub paths: Vec<String>,
+    }
+
+    #[derive(Args)]
+    pub struct MoveArgs {
+        /// Path of the file or directory in the ISO to move
+        #[clap(value_name("src"))]
+        pub src_path: String,
+
+        /// The new path, or an existing directory to move the entry into
+        #[clap(value_name("dest"))]
+        pub dest_path: String,
+    }
+
+    #[derive(Args)]
+    pub struct PatchCodesArgs {
+        /// Only apply the code with this name (can be used more than once). Otherwise, if the
+        /// codes come from a Dolphin game INI, only the ones it has enabled are applied.
+        #[clap(long = "code", value_name("NAME"))]
+        pub codes: Vec<String>,
+
+        /// Path to a text file or Dolphin game INI containing Gecko codes
+        pub path: PathBuf,
+    }
+
+    fn parse_address(s: &str) -> Result<u32> {
+        let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+        u32::from_str_radix(hex, 16).map_err(|_| anyhow!("invalid hex address: {}", s))
+    }
+
+    #[derive(Args)]
+    pub struct DisassembleArgs {
+        /// Redirect output to a file instead of stdout
+        #[clap(short, value_name("PATH"))]
+        pub output: Option<PathBuf>,
+
+        /// Number of instructions to disassemble if no end address is given
+        #[clap(short = 'n', long, default_value_t = 32, conflicts_with("end"))]
+        pub count: u32,
+
+        /// Path to a symbol map (.map) to label addresses with
+        #[clap(long, value_name("PATH"))]
+        pub symbols: Option<PathBuf>,
+
+        /// Hex address to start disassembling at
+        #[clap(value_parser = parse_address)]
+        pub start: u32,
+
+        /// Hex address to stop disassembling at (exclusive)
+        #[clap(value_parser = parse_address)]
+        pub end: Option<u32>,
+    }
+
+    #[derive(Args)]
+    pub struct DiffArgs {
+        /// Redirect output to a file instead of stdout
+        #[clap(short, value_name("PATH"))]
+        pub output: Option<PathBuf>,
+
+        /// Write the differences as JSON
+        #[clap(long)]
+        pub json: bool,
+
+        /// Path to the original ISO
+        #[clap(value_name("OLD"))]
+        pub old: PathBuf,
+
+        /// Path to the modified ISO
+        #[clap(value_name("NEW"))]
+        pub new: PathBuf,
+    }
+
+    #[derive(Args)]
+    pub struct VerifyArgs {
+        /// Redirect output to a file instead of stdout
+        #[clap(short, value_name("PATH"))]
+        pub output: Option<PathBuf>,
+
+        /// Write the results as JSON
+        #[clap(long)]
+        pub json: bool,
     }
 }
 
@@ -698,6 +1103,23 @@ pub mod archive {
             #[clap(flatten)]
             args: ReplaceArgs,
         },
+        /// Add a new file or directory to the archive
+        Add {
+            /// Path to the U8 archive
+            path: String,
+            #[clap(flatten)]
+            args: AddArgs,
+        },
+        /// Remove files or directories from the archive
+        #[clap(alias = "rm")]
+        Remove {
+            /// Path to the U8 archive
+            path: String,
+            #[clap(flatten)]
+            args: RemoveArgs,
+        },
+        /// Build a new archive from a directory
+        Pack(PackArgs),
     }
 
     #[derive(Args)]
@@ -716,6 +1138,10 @@ pub mod archive {
         #[clap(short, value_name("PATH"))]
         pub output: Option<PathBuf>,
 
+        /// Decompress Yaz0 and Yay0 files while extracting them
+        #[clap(long)]
+        pub decompress: bool,
+
         /// Paths of files to extract
         pub paths: Vec<String>,
     }
@@ -725,6 +1151,10 @@ pub mod archive {
         /// The directory to extract files to
         #[clap(short, value_name("PATH"))]
         pub output: Option<PathBuf>,
+
+        /// Decompress Yaz0 and Yay0 files while extracting them
+        #[clap(long)]
+        pub decompress: bool,
     }
 
     #[derive(Args)]
@@ -736,6 +1166,54 @@ pub mod archive {
         /// Path to the file to replace it with
         #[clap(value_name("src"))]
         pub src_path: PathBuf,
+
+        /// Compress the file before writing it (yaz0 or yay0, defaults to yaz0)
+        #[clap(
+            long,
+            value_name("FORMAT"),
+            num_args(0..=1),
+            require_equals(true),
+            default_missing_value("yaz0"),
+            value_parser = parse_compression
+        )]
+        pub compress: Option<Compression>,
+    }
+
+    #[derive(Args)]
+    pub struct AddArgs {
+        /// Path of the new file or directory in the archive
+        #[clap(value_name("dest"))]
+        pub dest_path: String,
+
+        /// Path to the local file or directory to add
+        #[clap(value_name("src"))]
+        pub src_path: PathBuf,
+    }
+
+    #[derive(Args)]
+    pub struct RemoveArgs {
+        /// Remove directories and everything inside them
+        #[clap(short, long)]
+        pub recursive: bool,
+
+        /// Paths of files or directories to remove
+        #[clap(required = true)]
+        pub paths: Vec<String>,
+    }
+
+    #[derive(Args)]
+    pub struct PackArgs {
+        /// Allow overwriting an existing archive
+        #[clap(short, long)]
+        pub force: bool,
+
+        /// Path of the archive to create
+        #[clap(short, value_name("PATH"))]
+        pub output: PathBuf,
+
+        /// Directory containing the files to put in the archive
+        #[clap(value_name("DIR"))]
+        pub input: PathBuf,
     }
 
     #[derive(Subcommand)]
@@ -750,6 +1228,11 @@ pub mod archive {
         ExtractAll(ExtractAllArgs),
         /// Replace a file in qp.bin
         Replace(ReplaceArgs),
+        /// Add a new file or directory to qp.bin
+        Add(AddArgs),
+        /// Remove files or directories from qp.bin
+        #[clap(alias = "rm")]
+        Remove(RemoveArgs),
     }
 }
 
@@ -1058,6 +1541,10 @@ mod tests {
             assert_eq!(path, "qp.bin");
             assert_eq!(args.output, Some("out".into()));
             assert_eq!(args.paths, ["foo", "bar"]);
+            assert!(!args.decompress);
+        });
+        parse(["archive", "extract", "qp.bin", "--decompress"], map, |(_, args)| {
+            assert!(args.decompress);
         });
         assert_eq!(error(["archive", "extract"]), ErrorKind::MissingRequiredArgument);
     }
@@ -1073,6 +1560,10 @@ mod tests {
         parse(["archive", "extract-all", "qp.bin", "-o", "out"], map, |(path, args)| {
             assert_eq!(path, "qp.bin");
             assert_eq!(args.output, Some("out".into()));
+            assert!(!args.decompress);
+        });
+        parse(["archive", "extract-all", "qp.bin", "--decompress"], map, |(_, args)| {
+            assert!(args.decompress);
         });
         assert_eq!(error(["archive", "extract-all"]), ErrorKind::MissingRequiredArgument);
     }
@@ -1085,6 +1576,10 @@ mod tests {
             assert_eq!(path, "qp.bin");
             assert_eq!(args.dest_path, "foo");
             assert_eq!(args.src_path, Path::new("bar"));
+            assert_eq!(args.compress, None);
+        });
+        parse(["archive", "replace", "qp.bin", "foo", "bar", "--compress"], map, |(_, args)| {
+            assert_eq!(args.compress, Some(Compression::Yaz0));
         });
         assert_eq!(error(["archive", "replace"]), ErrorKind::MissingRequiredArgument);
         assert_eq!(error(["archive", "replace", "qp.bin"]), ErrorKind::MissingRequiredArgument);
@@ -1094,13 +1589,83 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cli_archive_add() {
+        use archive::*;
+        let map = mapper!(Command::Archive(Subcommand::Add { path, args }) => (path, args));
+        parse(["archive", "add", "qp.bin", "foo", "bar"], map, |(path, args)| {
+            assert_eq!(path, "qp.bin");
+            assert_eq!(args.dest_path, "foo");
+            assert_eq!(args.src_path, Path::new("bar"));
+        });
+        assert_eq!(error(["archive", "add", "qp.bin"]), ErrorKind::MissingRequiredArgument);
+        assert_eq!(error(["archive", "add", "qp.bin", "foo"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_archive_remove() {
+        use archive::*;
+        let map = mapper!(Command::Archive(Subcommand::Remove { path, args }) => (path, args));
+        multiparse(
+            [["archive", "remove", "qp.bin", "foo"], ["archive", "rm", "qp.bin", "foo"]],
+            map,
+            |(path, args)| {
+                assert_eq!(path, "qp.bin");
+                assert_eq!(args.paths, ["foo"]);
+                assert!(!args.recursive);
+            },
+        );
+        parse(["archive", "rm", "-r", "qp.bin", "foo", "bar"], map, |(path, args)| {
+            assert_eq!(path, "qp.bin");
+            assert_eq!(args.paths, ["foo", "bar"]);
+            assert!(args.recursive);
+        });
+        assert_eq!(error(["archive", "rm", "qp.bin"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_archive_pack() {
+        use archive::*;
+        let map = mapper!(Command::Archive(Subcommand::Pack(args)) => args);
+        parse(["archive", "pack", "-o", "foo.bin", "foo"], map, |args| {
+            assert!(!args.force);
+            assert_eq!(args.output, Path::new("foo.bin"));
+            assert_eq!(args.input, Path::new("foo"));
+        });
+        parse(["archive", "pack", "-f", "-o", "foo.bin", "foo"], map, |args| {
+            assert!(args.force);
+        });
+        assert_eq!(error(["archive", "pack", "foo"]), ErrorKind::MissingRequiredArgument);
+        assert_eq!(error(["archive", "pack", "-o", "foo.bin"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_qp_add_remove() {
+        use archive::*;
+        let map = mapper!(Command::Qp(QpSubcommand::Add(args)) => args);
+        parse(["qp", "add", "foo", "bar"], map, |args| {
+            assert_eq!(args.dest_path, "foo");
+            assert_eq!(args.src_path, Path::new("bar"));
+        });
+        let map = mapper!(Command::Qp(QpSubcommand::Remove(args)) => args);
+        multiparse([["qp", "remove", "-r", "foo"], ["qp", "rm", "-r", "foo"]], map, |args| {
+            assert_eq!(args.paths, ["foo"]);
+            assert!(args.recursive);
+        });
+    }
+
     #[test]
     fn test_cli_audio_info() {
         use audio::*;
         let map = mapper!(Command::Audio(Subcommand::Info(args)) => args);
         parse(["audio", "info", "foo"], map, |args| {
+            assert_eq!(args.brsar, None);
             assert_eq!(args.name, "foo");
         });
+        parse(["audio", "info", "--brsar", "cb_robo.brsar", "ufo_kime"], map, |args| {
+            assert_eq!(args.brsar.as_deref(), Some(Path::new("cb_robo.brsar")));
+            assert_eq!(args.name, "ufo_kime");
+        });
         assert_eq!(error(["audio", "info"]), ErrorKind::MissingRequiredArgument);
     }
 
@@ -1119,8 +1684,18 @@ mod tests {
         parse(["audio", "export", "-o", "out", "--labels", "foo"], map, |args| {
             assert_eq!(args.output.as_deref(), Some(Path::new("out")));
             assert!(args.settings.labels);
+            assert!(!args.settings.flac);
+            assert_eq!(args.names, ["foo"]);
+        });
+        parse(["audio", "export", "--flac", "foo"], map, |args| {
+            assert!(args.settings.flac);
+            assert_eq!(args.brsar, None);
             assert_eq!(args.names, ["foo"]);
         });
+        parse(["audio", "export", "--brsar", "cb_robo.brsar", "ufo_kime", "12"], map, |args| {
+            assert_eq!(args.brsar.as_deref(), Some(Path::new("cb_robo.brsar")));
+            assert_eq!(args.names, ["ufo_kime", "12"]);
+        });
         assert_eq!(error(["audio", "export"]), ErrorKind::MissingRequiredArgument);
     }
 
@@ -1153,6 +1728,9 @@ mod tests {
             assert_eq!(args.output, Path::new("out"));
             assert!(args.settings.labels);
         });
+        parse(["audio", "export-all", "-o", "out", "--flac"], map, |args| {
+            assert!(args.settings.flac);
+        });
         assert_eq!(error(["audio", "export-all"]), ErrorKind::MissingRequiredArgument);
     }
 
@@ -1170,6 +1748,11 @@ mod tests {
             assert_eq!(args.path, Path::new("bar"));
             assert!(args.settings.labels);
         });
+        parse(["audio", "import", "--brsar", "cb_robo.brsar", "foo", "bar"], map, |args| {
+            assert_eq!(args.brsar.as_deref(), Some(Path::new("cb_robo.brsar")));
+            assert_eq!(args.name, "foo");
+            assert_eq!(args.path, Path::new("bar"));
+        });
         assert_eq!(error(["audio", "import"]), ErrorKind::MissingRequiredArgument);
         assert_eq!(error(["audio", "import", "foo"]), ErrorKind::MissingRequiredArgument);
         assert_eq!(error(["audio", "import", "foo", "bar", "baz"]), ErrorKind::UnknownArgument);
@@ -1183,6 +1766,10 @@ mod tests {
             assert_eq!(args.name, "foo");
             assert!(approx_eq!(f64, args.volume, 0.8));
         });
+        parse(["audio", "play", "--brsar", "cb_robo.brsar", "foo"], map, |args| {
+            assert_eq!(args.brsar.as_deref(), Some(Path::new("cb_robo.brsar")));
+            assert_eq!(args.name, "foo");
+        });
         parse(["audio", "play", "foo", "--volume", "0"], map, |args| {
             assert!(approx_eq!(f64, args.volume, 0.0));
         });
@@ -1198,6 +1785,32 @@ mod tests {
         assert_eq!(error(["audio", "play", "foo", "--volume", "101"]), ErrorKind::ValueValidation);
     }
 
+    #[test]
+    fn test_cli_audio_playlist() {
+        use audio::*;
+        let map =
+            mapper!(Command::Audio(Subcommand::Playlist(PlaylistSubcommand::Export(args))) => args);
+        parse(["audio", "playlist", "export"], map, |args| {
+            assert!(!args.compact);
+            assert_eq!(args.output, None);
+        });
+        parse(["audio", "playlist", "export", "--compact", "-o", "out"], map, |args| {
+            assert!(args.compact);
+            assert_eq!(args.output.as_deref(), Some(Path::new("out")));
+        });
+
+        let map =
+            mapper!(Command::Audio(Subcommand::Playlist(PlaylistSubcommand::Import(args))) => args);
+        parse(["audio", "playlist", "import", "foo"], map, |args| {
+            assert_eq!(args.input, Path::new("foo"));
+        });
+        assert_eq!(error(["audio", "playlist", "import"]), ErrorKind::MissingRequiredArgument);
+        assert_eq!(
+            error(["audio", "playlist"]),
+            ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+        );
+    }
+
     #[test]
     fn test_cli_config() {
         use config::*;
@@ -1338,6 +1951,10 @@ mod tests {
         parse(["iso", "extract", "foo", "bar", "-o", "out"], map, |args| {
             assert_eq!(args.output.as_deref(), Some(Path::new("out")));
             assert_eq!(args.paths, ["foo", "bar"]);
+            assert!(!args.decompress);
+        });
+        parse(["iso", "extract", "--decompress", "foo"], map, |args| {
+            assert!(args.decompress);
         });
     }
 
@@ -1347,12 +1964,85 @@ mod tests {
         let map = mapper!(Command::Iso(Subcommand::ExtractAll(args)) => args);
         parse(["iso", "extract-all"], map, |args| {
             assert_eq!(args.output, None);
+            assert!(!args.sys);
+        });
+        parse(["iso", "extract-all", "--sys"], map, |args| {
+            assert!(args.sys);
+            assert!(!args.decompress);
+        });
+        parse(["iso", "extract-all", "--decompress"], map, |args| {
+            assert!(args.decompress);
         });
         parse(["iso", "extract-all", "-o", "out"], map, |args| {
             assert_eq!(args.output.as_deref(), Some(Path::new("out")));
         });
     }
 
+    #[test]
+    fn test_cli_iso_build() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::Build(args)) => args);
+        parse(["iso", "build", "dir", "-o", "out.iso"], map, |args| {
+            assert_eq!(args.input, Path::new("dir"));
+            assert_eq!(args.output, Path::new("out.iso"));
+            assert!(!args.force);
+        });
+        parse(["iso", "build", "-f", "dir", "-o", "out.iso"], map, |args| {
+            assert!(args.force);
+        });
+        assert_eq!(error(["iso", "build", "dir"]), ErrorKind::MissingRequiredArgument);
+        assert_eq!(error(["iso", "build", "-o", "out.iso"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_iso_compact() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::Compact(args)) => args);
+        parse(["iso", "compact"], map, |args| {
+            assert!(!args.shrink);
+        });
+        parse(["iso", "compact", "--shrink"], map, |args| {
+            assert!(args.shrink);
+        });
+    }
+
+    #[test]
+    fn test_cli_iso_scrub() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::Scrub(args)) => args);
+        parse(["iso", "scrub", "-o", "out.iso"], map, |args| {
+            assert_eq!(args.output, Path::new("out.iso"));
+            assert!(!args.trim);
+            assert!(!args.force);
+        });
+        parse(["iso", "scrub", "-o", "out.iso", "--trim", "-f"], map, |args| {
+            assert!(args.trim);
+            assert!(args.force);
+        });
+        assert_eq!(error(["iso", "scrub"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_iso_convert() {
+        use iso::*;
+        use unplug::dvd::ImageFormat;
+        let map = mapper!(Command::Iso(Subcommand::Convert(args)) => args);
+        parse(["iso", "convert", "-o", "out.gcz"], map, |args| {
+            assert_eq!(args.output, Path::new("out.gcz"));
+            assert_eq!(args.format, None);
+            assert!(!args.force);
+        });
+        parse(["iso", "convert", "-f", "-o", "out", "--format", "ciso"], map, |args| {
+            assert_eq!(args.format, Some(ImageFormat::Ciso));
+            assert!(args.force);
+        });
+        assert_eq!(
+            error(["iso", "convert", "-o", "out", "--format", "rvz"]),
+            ErrorKind::ValueValidation
+        );
+        assert_eq!(error(["iso", "convert"]), ErrorKind::MissingRequiredArgument);
+    }
+
     #[test]
     fn test_cli_iso_replace() {
         use iso::*;
@@ -1360,12 +2050,70 @@ mod tests {
         parse(["iso", "replace", "foo", "bar"], map, |args| {
             assert_eq!(args.dest_path, "foo");
             assert_eq!(args.src_path, Path::new("bar"));
+            assert_eq!(args.compress, None);
+        });
+        parse(["iso", "replace", "--compress", "foo", "bar"], map, |args| {
+            assert_eq!(args.dest_path, "foo");
+            assert_eq!(args.compress, Some(Compression::Yaz0));
+        });
+        parse(["iso", "replace", "foo", "bar", "--compress=yay0"], map, |args| {
+            assert_eq!(args.src_path, Path::new("bar"));
+            assert_eq!(args.compress, Some(Compression::Yay0));
         });
+        assert_eq!(
+            error(["iso", "replace", "foo", "bar", "--compress=zlib"]),
+            ErrorKind::ValueValidation
+        );
         assert_eq!(error(["iso", "replace"]), ErrorKind::MissingRequiredArgument);
         assert_eq!(error(["iso", "replace", "foo"]), ErrorKind::MissingRequiredArgument);
         assert_eq!(error(["iso", "replace", "foo", "bar", "baz"]), ErrorKind::UnknownArgument);
     }
 
+    #[test]
+    fn test_cli_iso_add() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::Add(args)) => args);
+        parse(["iso", "add", "foo", "bar"], map, |args| {
+            assert_eq!(args.dest_path, "foo");
+            assert_eq!(args.src_path, Path::new("bar"));
+        });
+        assert_eq!(error(["iso", "add"]), ErrorKind::MissingRequiredArgument);
+        assert_eq!(error(["iso", "add", "foo"]), ErrorKind::MissingRequiredArgument);
+        assert_eq!(error(["iso", "add", "foo", "bar", "baz"]), ErrorKind::UnknownArgument);
+    }
+
+    #[test]
+    fn test_cli_iso_remove() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::Remove(args)) => args);
+        multiparse([["iso", "remove", "foo"], ["iso", "rm", "foo"]], map, |args| {
+            assert_eq!(args.paths, ["foo"]);
+            assert!(!args.recursive);
+        });
+        multiparse(
+            [["iso", "rm", "-r", "foo", "bar"], ["iso", "rm", "foo", "bar", "--recursive"]],
+            map,
+            |args| {
+                assert_eq!(args.paths, ["foo", "bar"]);
+                assert!(args.recursive);
+            },
+        );
+        assert_eq!(error(["iso", "rm"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_iso_move() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::Move(args)) => args);
+        multiparse([["iso", "move", "foo", "bar"], ["iso", "mv", "foo", "bar"]], map, |args| {
+            assert_eq!(args.src_path, "foo");
+            assert_eq!(args.dest_path, "bar");
+        });
+        assert_eq!(error(["iso", "mv"]), ErrorKind::MissingRequiredArgument);
+        assert_eq!(error(["iso", "mv", "foo"]), ErrorKind::MissingRequiredArgument);
+        assert_eq!(error(["iso", "mv", "foo", "bar", "baz"]), ErrorKind::UnknownArgument);
+    }
+
     #[test]
     fn test_cli_iso_set() {
         use iso::*;
@@ -1380,6 +2128,132 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_cli_iso_banner() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::Banner(command)) => command);
+        parse(["iso", "banner", "export", "-o", "banner.png"], map, |command| {
+            let BannerCommand::Export { output } = command else { panic!() };
+            assert_eq!(output, Path::new("banner.png"));
+        });
+        parse(["iso", "banner", "import", "banner.png"], map, |command| {
+            let BannerCommand::Import { path } = command else { panic!() };
+            assert_eq!(path, Path::new("banner.png"));
+        });
+        assert_eq!(error(["iso", "banner", "export"]), ErrorKind::MissingRequiredArgument);
+        assert_eq!(error(["iso", "banner", "import"]), ErrorKind::MissingRequiredArgument);
+
+        parse(["iso", "banner", "export-info"], map, |command| {
+            let BannerCommand::ExportInfo { compact, output } = command else { panic!() };
+            assert!(!compact);
+            assert_eq!(output, None);
+        });
+        parse(["iso", "banner", "export-info", "-c", "-o", "banner.json"], map, |command| {
+            let BannerCommand::ExportInfo { compact, output } = command else { panic!() };
+            assert!(compact);
+            assert_eq!(output.as_deref(), Some(Path::new("banner.json")));
+        });
+        parse(["iso", "banner", "import-info", "banner.json"], map, |command| {
+            let BannerCommand::ImportInfo { path } = command else { panic!() };
+            assert_eq!(path, Path::new("banner.json"));
+        });
+        assert_eq!(error(["iso", "banner", "import-info"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_iso_header() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::Header(command)) => command);
+        parse(["iso", "header", "export"], map, |command| {
+            let HeaderCommand::Export { compact, output } = command else { panic!() };
+            assert!(!compact);
+            assert_eq!(output, None);
+        });
+        parse(["iso", "header", "export", "-c", "-o", "header.json"], map, |command| {
+            let HeaderCommand::Export { compact, output } = command else { panic!() };
+            assert!(compact);
+            assert_eq!(output.as_deref(), Some(Path::new("header.json")));
+        });
+        parse(["iso", "header", "import", "header.json"], map, |command| {
+            let HeaderCommand::Import { path } = command else { panic!() };
+            assert_eq!(path, Path::new("header.json"));
+        });
+        assert_eq!(error(["iso", "header", "import"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_iso_patch_codes() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::PatchCodes(args)) => args);
+        parse(["iso", "patch-codes", "codes.txt"], map, |args| {
+            assert_eq!(args.path, Path::new("codes.txt"));
+            assert!(args.codes.is_empty());
+        });
+        parse(["iso", "patch-codes", "--code", "A", "codes.ini", "--code", "B C"], map, |args| {
+            assert_eq!(args.path, Path::new("codes.ini"));
+            assert_eq!(args.codes, ["A", "B C"]);
+        });
+        assert_eq!(error(["iso", "patch-codes"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_iso_disassemble() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::Disassemble(args)) => args);
+        parse(["iso", "disassemble", "80003100"], map, |args| {
+            assert_eq!(args.start, 0x80003100);
+            assert_eq!(args.end, None);
+            assert_eq!(args.count, 32);
+            assert_eq!(args.output, None);
+            assert_eq!(args.symbols, None);
+        });
+        parse(["iso", "disasm", "--symbols", "main.map", "80003100"], map, |args| {
+            assert_eq!(args.symbols.as_deref(), Some(Path::new("main.map")));
+        });
+        parse(["iso", "disasm", "0x80003100", "0x80003200", "-o", "out.s"], map, |args| {
+            assert_eq!(args.start, 0x80003100);
+            assert_eq!(args.end, Some(0x80003200));
+            assert_eq!(args.output.as_deref(), Some(Path::new("out.s")));
+        });
+        parse(["iso", "disasm", "-n", "4", "80003100"], map, |args| {
+            assert_eq!(args.count, 4);
+        });
+        assert_eq!(error(["iso", "disasm"]), ErrorKind::MissingRequiredArgument);
+        assert_eq!(error(["iso", "disasm", "xyz"]), ErrorKind::ValueValidation);
+        assert_eq!(error(["iso", "disasm", "-n", "4", "0", "4"]), ErrorKind::ArgumentConflict);
+    }
+
+    #[test]
+    fn test_cli_iso_diff() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::Diff(args)) => args);
+        parse(["iso", "diff", "a.iso", "b.iso"], map, |args| {
+            assert_eq!(args.old, Path::new("a.iso"));
+            assert_eq!(args.new, Path::new("b.iso"));
+            assert_eq!(args.output, None);
+            assert!(!args.json);
+        });
+        parse(["iso", "diff", "--json", "-o", "out.json", "a.iso", "b.iso"], map, |args| {
+            assert!(args.json);
+            assert_eq!(args.output.as_deref(), Some(Path::new("out.json")));
+        });
+        assert_eq!(error(["iso", "diff", "a.iso"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_iso_verify() {
+        use iso::*;
+        let map = mapper!(Command::Iso(Subcommand::Verify(args)) => args);
+        parse(["iso", "verify"], map, |args| {
+            assert_eq!(args.output, None);
+            assert!(!args.json);
+        });
+        parse(["iso", "verify", "--json", "-o", "out.json"], map, |args| {
+            assert!(args.json);
+            assert_eq!(args.output.as_deref(), Some(Path::new("out.json")));
+        });
+    }
+
     #[test]
     fn test_cli_list() {
         use list::*;
@@ -1428,6 +2302,60 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_cli_overlay() {
+        use overlay::*;
+        let map = mapper!(Command::Overlay(c) => c);
+        parse(["overlay", "status"], map, |c| {
+            assert!(matches!(c, Subcommand::Status));
+        });
+
+        let map = mapper!(Command::Overlay(Subcommand::Discard(args)) => args);
+        parse(["overlay", "discard"], map, |args| assert!(!args.force));
+        parse(["overlay", "discard", "-f"], map, |args| assert!(args.force));
+
+        let map = mapper!(Command::Overlay(Subcommand::Bake(args)) => args);
+        parse(["overlay", "bake", "-o", "out.iso"], map, |args| {
+            assert!(!args.force);
+            assert_eq!(args.output, Path::new("out.iso"));
+        });
+        parse(["overlay", "bake", "-f", "-o", "out.iso"], map, |args| assert!(args.force));
+        assert_eq!(error(["overlay", "bake"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_patch_create() {
+        use patch::*;
+        let map = mapper!(Command::Patch(Subcommand::Create(args)) => args);
+        parse(["patch", "create", "-o", "mod.patch"], map, |args| {
+            assert_eq!(args.output, Path::new("mod.patch"));
+            assert_eq!(args.base, None);
+            assert_eq!(args.name, None);
+            assert!(!args.force);
+        });
+        parse(["patch", "create", "--base", "a.iso", "--name", "foo", "-o", "x"], map, |args| {
+            assert_eq!(args.base.as_deref(), Some(Path::new("a.iso")));
+            assert_eq!(args.name.as_deref(), Some("foo"));
+        });
+        parse(["patch", "create", "-f", "-o", "x"], map, |args| assert!(args.force));
+        assert_eq!(error(["patch", "create"]), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn test_cli_patch_apply() {
+        use patch::*;
+        let map = mapper!(Command::Patch(Subcommand::Apply(args)) => args);
+        parse(["patch", "apply", "a.patch"], map, |args| {
+            assert_eq!(args.patches, [Path::new("a.patch")]);
+            assert!(!args.force);
+        });
+        parse(["patch", "apply", "-f", "a.patch", "b.patch"], map, |args| {
+            assert_eq!(args.patches, [Path::new("a.patch"), Path::new("b.patch")]);
+            assert!(args.force);
+        });
+        assert_eq!(error(["patch", "apply"]), ErrorKind::MissingRequiredArgument);
+    }
+
     #[test]
     fn test_cli_project_info() {
         use project::*;
@@ -1473,6 +2401,10 @@ mod tests {
             assert_eq!(args.name, "foo");
             assert!(args.no_open);
         });
+        parse(["project", "new", "foo", "--overlay"], map, |args| {
+            assert_eq!(args.name, "foo");
+            assert!(args.overlay);
+        });
         multiparse(
             [["project", "new", "foo", "-f"], ["project", "new", "foo", "--force"]],
             map,
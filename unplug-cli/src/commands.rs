@@ -8,6 +8,8 @@ pub mod globals;
 pub mod iso;
 pub mod list;
 pub mod messages;
+pub mod overlay;
+pub mod patch;
 pub mod project;
 pub mod script;
 pub mod shop;
@@ -31,6 +33,8 @@ pub fn execute(ctx: Context, command: Command) -> Result<()> {
         Command::Iso(command) => iso::command(ctx, command),
         Command::List(command) => list::command(ctx, command),
         Command::Messages(command) => messages::command(ctx, command),
+        Command::Overlay(command) => overlay::command(ctx, command),
+        Command::Patch(command) => patch::command(ctx, command),
         Command::Project(command) => project::command(ctx, command),
         Command::Qp(command) => archive::command_qp(ctx, command),
         Command::Script(command) => script::command(ctx, command),
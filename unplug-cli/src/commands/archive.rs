@@ -1,16 +1,20 @@
 use crate::args::archive::*;
 
 use crate::common::output_dir_and_name;
-use crate::context::Context;
+use crate::context::{Context, OpenContext};
 use crate::fst::{extract_file, list_files};
+use crate::io::open_compressed;
 use anyhow::{bail, Result};
 use humansize::{FormatSize, BINARY};
 use log::{debug, info};
 use std::fs::{self, File};
-use std::io::Seek;
+use std::io::{BufWriter, Seek};
+use std::path::Path;
 use tempfile::NamedTempFile;
+use unplug::common::compression::open_decompressed;
 use unplug::common::io::BUFFER_SIZE;
-use unplug::dvd::{ArchiveBuilder, ArchiveReader, Glob, GlobMode};
+use unplug::common::ReadWriteSeek;
+use unplug::dvd::{ArchiveBuilder, ArchiveReader, EntryId, Glob, GlobMode};
 
 /// The path that `qp` passes to the `archive` commands.
 const QP_ALIAS_PATH: &str = "dvd:qp.bin";
@@ -20,7 +24,7 @@ fn command_info(ctx: Context, path: &str) -> Result<()> {
     let mut ctx = ctx.open_read()?;
     let file = ctx.file_at(path)?;
     let info = ctx.query_file(&file)?;
-    let archive = ArchiveReader::open(ctx.open_file(&file)?)?;
+    let archive = ArchiveReader::open(open_decompressed(ctx.open_file(&file)?)?)?;
     println!("{}: U8 archive", &info.name);
     println!("Size: {}", info.size.format_size(BINARY));
     println!("File Entries: {}", archive.files.len());
@@ -30,7 +34,7 @@ fn command_info(ctx: Context, path: &str) -> Result<()> {
 /// The `archive list` CLI command.
 fn command_list(ctx: Context, path: &str, args: ListArgs) -> Result<()> {
     let mut ctx = ctx.open_read()?;
-    let archive = ArchiveReader::open(ctx.open_file_at(path)?)?;
+    let archive = ArchiveReader::open(open_decompressed(ctx.open_file_at(path)?)?)?;
     list_files(&archive.files, &args.settings, &Glob::new(GlobMode::Prefix, args.paths))?;
     Ok(())
 }
@@ -38,7 +42,7 @@ fn command_list(ctx: Context, path: &str, args: ListArgs) -> Result<()> {
 /// The `archive extract` CLI command.
 fn command_extract(ctx: Context, path: &str, args: ExtractArgs) -> Result<()> {
     let mut ctx = ctx.open_read()?;
-    let mut archive = ArchiveReader::open(ctx.open_file_at(path)?)?;
+    let mut archive = ArchiveReader::open(open_decompressed(ctx.open_file_at(path)?)?)?;
     let files = Glob::new(GlobMode::Exact, args.paths).find(&archive.files).collect::<Vec<_>>();
     if files.is_empty() {
         bail!("Nothing to extract");
@@ -47,7 +51,8 @@ fn command_extract(ctx: Context, path: &str, args: ExtractArgs) -> Result<()> {
     fs::create_dir_all(out_dir)?;
     let mut io_buf = vec![0u8; BUFFER_SIZE].into_boxed_slice();
     for (path, entry) in files {
-        extract_file(&mut archive, entry, &path, out_dir, out_name.as_deref(), &mut io_buf)?;
+        let name = out_name.as_deref();
+        extract_file(&mut archive, entry, &path, out_dir, name, args.decompress, &mut io_buf)?;
     }
     Ok(())
 }
@@ -55,12 +60,13 @@ fn command_extract(ctx: Context, path: &str, args: ExtractArgs) -> Result<()> {
 /// The `archive extract-all` CLI command.
 fn command_extract_all(ctx: Context, path: &str, args: ExtractAllArgs) -> Result<()> {
     let mut ctx = ctx.open_read()?;
-    let mut archive = ArchiveReader::open(ctx.open_file_at(path)?)?;
+    let mut archive = ArchiveReader::open(open_decompressed(ctx.open_file_at(path)?)?)?;
     let (out_dir, out_name) = output_dir_and_name(args.output.as_deref(), false);
     fs::create_dir_all(out_dir)?;
     let mut io_buf = vec![0u8; BUFFER_SIZE].into_boxed_slice();
     let root = archive.files.root();
-    extract_file(&mut archive, root, "/", out_dir, out_name.as_deref(), &mut io_buf)?;
+    let name = out_name.as_deref();
+    extract_file(&mut archive, root, "/", out_dir, name, args.decompress, &mut io_buf)?;
     Ok(())
 }
 
@@ -75,12 +81,12 @@ fn command_replace(ctx: Context, path: &str, args: ReplaceArgs) -> Result<()> {
         bail!("{} is a directory", archive.files[entry].name());
     }
 
-    let reader = File::open(&args.src_path)?;
+    let reader = open_compressed(&args.src_path, args.compress)?;
     info!("Rebuilding archive data");
     let mut temp = NamedTempFile::new()?;
     debug!("Writing new archive to {}", temp.path().to_string_lossy());
     let mut builder = ArchiveBuilder::with_archive(&mut archive);
-    builder.replace(entry, || reader).write_to(&mut temp)?;
+    builder.replace(entry, || Ok(reader)).write_to(&mut temp)?;
     temp.rewind()?;
     drop(builder);
     drop(archive);
@@ -90,6 +96,108 @@ fn command_replace(ctx: Context, path: &str, args: ReplaceArgs) -> Result<()> {
     Ok(())
 }
 
+/// Adds the local file or directory at `src_path` to `builder` as `name` inside `parent`.
+fn add_entry(
+    builder: &mut ArchiveBuilder<'_>,
+    parent: EntryId,
+    name: &str,
+    src_path: &Path,
+) -> Result<()> {
+    if src_path.is_dir() {
+        let id = builder.add_dir(parent, name)?;
+        let mut children = fs::read_dir(src_path)?.collect::<Result<Vec<_>, _>>()?;
+        children.sort_unstable_by_key(|c| c.file_name());
+        for child in children {
+            let child_name = child.file_name();
+            let Some(child_name) = child_name.to_str() else {
+                bail!("Invalid filename: {}", child.path().display());
+            };
+            add_entry(builder, id, child_name, &child.path())?;
+        }
+    } else {
+        let path = src_path.to_owned();
+        builder.add_file(parent, name, move || {
+            info!("Adding {}", path.display());
+            File::open(path)
+        })?;
+    }
+    Ok(())
+}
+
+/// Rebuilds the archive at `path` after letting `edit` modify it.
+fn edit_archive<T: ReadWriteSeek>(
+    ctx: &mut OpenContext<T>,
+    path: &str,
+    edit: impl FnOnce(&mut ArchiveBuilder<'_>) -> Result<()>,
+) -> Result<()> {
+    let file = ctx.file_at(path)?;
+    let info = ctx.query_file(&file)?;
+    let mut archive = ArchiveReader::open(ctx.open_file(&file)?)?;
+    let mut builder = ArchiveBuilder::with_archive(&mut archive);
+    edit(&mut builder)?;
+
+    info!("Rebuilding archive data");
+    let mut temp = NamedTempFile::new()?;
+    debug!("Writing new archive to {}", temp.path().to_string_lossy());
+    builder.write_to(&mut temp)?;
+    temp.rewind()?;
+    drop(builder);
+    drop(archive);
+
+    info!("Writing new {}", info.name);
+    ctx.begin_update().write_file(&file, temp).commit()?;
+    Ok(())
+}
+
+/// The `archive add` CLI command.
+fn command_add(ctx: Context, path: &str, args: AddArgs) -> Result<()> {
+    let mut ctx = ctx.open_read_write()?;
+    edit_archive(&mut ctx, path, |builder| {
+        let (parent, name) = builder.files().parent_and_name(&args.dest_path)?;
+        add_entry(builder, parent, name, &args.src_path)
+    })
+}
+
+/// The `archive remove` CLI command.
+fn command_remove(ctx: Context, path: &str, args: RemoveArgs) -> Result<()> {
+    let mut ctx = ctx.open_read_write()?;
+    edit_archive(&mut ctx, path, |builder| {
+        for path in &args.paths {
+            let entry = builder.files().at(path)?;
+            if builder.files()[entry].is_dir() && !args.recursive {
+                bail!("{} is a directory (use --recursive to remove it)", path);
+            }
+            info!("Removing {}", path);
+            builder.remove(entry)?;
+        }
+        Ok(())
+    })
+}
+
+/// The `archive pack` CLI command.
+fn command_pack(args: PackArgs) -> Result<()> {
+    if !args.force && args.output.exists() {
+        bail!("The output file already exists (use --force to overwrite)");
+    }
+    if !args.input.is_dir() {
+        bail!("{} is not a directory", args.input.display());
+    }
+    let mut builder = ArchiveBuilder::new();
+    let root = builder.files().root();
+    let mut children = fs::read_dir(&args.input)?.collect::<Result<Vec<_>, _>>()?;
+    children.sort_unstable_by_key(|c| c.file_name());
+    for child in children {
+        let name = child.file_name();
+        let Some(name) = name.to_str() else {
+            bail!("Invalid filename: {}", child.path().display());
+        };
+        add_entry(&mut builder, root, name, &child.path())?;
+    }
+    info!("Writing {}", args.output.display());
+    builder.write_to(BufWriter::new(File::create(&args.output)?))?;
+    Ok(())
+}
+
 /// The `archive` CLI command.
 pub fn command(ctx: Context, command: Subcommand) -> Result<()> {
     match command {
@@ -98,6 +206,9 @@ pub fn command(ctx: Context, command: Subcommand) -> Result<()> {
         Subcommand::Extract { path, args } => command_extract(ctx, &path, args),
         Subcommand::ExtractAll { path, args } => command_extract_all(ctx, &path, args),
         Subcommand::Replace { path, args } => command_replace(ctx, &path, args),
+        Subcommand::Add { path, args } => command_add(ctx, &path, args),
+        Subcommand::Remove { path, args } => command_remove(ctx, &path, args),
+        Subcommand::Pack(args) => command_pack(args),
     }
 }
 
@@ -109,5 +220,7 @@ pub fn command_qp(ctx: Context, command: QpSubcommand) -> Result<()> {
         QpSubcommand::Extract(args) => command_extract(ctx, QP_ALIAS_PATH, args),
         QpSubcommand::ExtractAll(args) => command_extract_all(ctx, QP_ALIAS_PATH, args),
         QpSubcommand::Replace(args) => command_replace(ctx, QP_ALIAS_PATH, args),
+        QpSubcommand::Add(args) => command_add(ctx, QP_ALIAS_PATH, args),
+        QpSubcommand::Remove(args) => command_remove(ctx, QP_ALIAS_PATH, args),
     }
 }
@@ -2,28 +2,36 @@ use crate::args::audio::*;
 
 use crate::common::{format_duration, output_dir_and_name};
 use crate::context::{Context, FileId, OpenContext};
+use crate::io::OutputRedirect;
+use crate::json::MaxIndentJsonFormatter;
 use crate::playback::{self, PlaybackDevice, PlaybackSource};
 use crate::terminal::{progress_bar, progress_spinner, update_audio_progress};
 use anyhow::{anyhow, bail, Result};
 use log::{debug, info, log_enabled, warn, Level};
+use serde::{Deserialize, Serialize};
+use serde_json::ser::Serializer;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter, Cursor, Seek};
+use std::io::{self, BufReader, BufWriter, Cursor, Seek, Write};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::time::Duration;
+use tempfile::NamedTempFile;
 use unplug::audio::format::PcmS16Le;
 use unplug::audio::metadata::audacity;
+use unplug::audio::metadata::sem::{Action, Command, SoundMaterial};
 use unplug::audio::metadata::SfxPlaylist;
 use unplug::audio::transport::hps::{Looping, PcmHpsWriter};
+use unplug::audio::transport::rwav::Codec;
 use unplug::audio::transport::ssm::BankSample;
 use unplug::audio::transport::{
-    FlacReader, HpsReader, Mp3Reader, OggReader, SfxBank, WavReader, WavWriter,
+    Brsar, FlacReader, FlacWriter, HpsReader, Mp3Reader, OggReader, Rwav, SfxBank, WavReader,
+    WavWriter,
 };
-use unplug::audio::{Cue, ReadSamples};
-use unplug::common::{ReadSeek, ReadWriteSeek, WriteTo};
+use unplug::audio::{Cue, ProgressHint, ReadSamples, Samples, SourceTag};
+use unplug::common::{ReadFrom, ReadSeek, ReadWriteSeek, WriteTo};
 use unplug::data::{Music, Resource, Sfx, SfxGroup, SfxSample, Sound};
 
 /// The highest sample rate that imported music can have. Music sampled higher than this will be
@@ -40,12 +48,17 @@ const MUSIC_DIR: &str = "streaming";
 /// Extension to use for Audacity label output
 const LABELS_EXT: &str = "labels.txt";
 
+/// Maximum JSON indentation for playlist exports (each action is kept on one line)
+const PLAYLIST_MAX_INDENT: usize = 5;
+
 /// Opens the sound file at `path`, optionally reads Audacity labels from `labels`, and enqueues it
-/// for resampling if the sample rate is higher than `max_sample_rate`.
+/// for resampling if the sample rate is higher than `max_sample_rate`. If `trim_to_loop` is true,
+/// audio after the end of a WAV file's loop is discarded.
 fn open_sound_file(
     path: &Path,
     settings: &ImportSettings,
     max_sample_rate: u32,
+    trim_to_loop: bool,
 ) -> Result<Box<dyn ReadSamples<'static, Format = PcmS16Le>>> {
     let name = path.file_name().map(|p| p.to_str().unwrap()).unwrap_or_default().to_owned();
     let ext = path.extension().map(|p| p.to_str().unwrap().to_lowercase()).unwrap_or_default();
@@ -58,7 +71,13 @@ fn open_sound_file(
         "flac" => FlacReader::new(file, tag)?.convert(),
         "mp3" => Box::from(Mp3Reader::new(file, tag)?),
         "ogg" => Box::from(OggReader::new(file, tag)?),
-        "wav" => Box::from(WavReader::new(file, tag)?),
+        "wav" => {
+            let mut wav = WavReader::new(file, tag)?;
+            if trim_to_loop {
+                wav.trim_to_loop();
+            }
+            Box::from(wav)
+        }
         other => bail!("unsupported file extension: \"{}\"", other),
     };
 
@@ -208,6 +227,68 @@ impl AudioResource {
     }
 }
 
+/// A Wii BRSAR file which wave sounds can be read from.
+struct BrsarFile {
+    name: String,
+    reader: BufReader<File>,
+    brsar: Brsar,
+}
+
+impl BrsarFile {
+    /// Opens the BRSAR file at `path` and reads its sound information.
+    fn open(path: &Path) -> Result<Self> {
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let spinner = progress_spinner(format!("Reading {}", name));
+        let mut reader = BufReader::new(File::open(path)?);
+        let brsar = Brsar::read_from(&mut reader)?;
+        spinner.finish_and_clear();
+        Ok(Self { name, reader, brsar })
+    }
+
+    /// Finds a sound by name or index and returns its index.
+    fn find_sound(&self, name: &str) -> Result<usize> {
+        match name.parse::<usize>() {
+            Ok(i) if i < self.brsar.sounds.len() => Ok(i),
+            Ok(_) => bail!("Sound index is out of range: {}", name),
+            Err(_) => {
+                self.brsar.find_sound(name).ok_or_else(|| anyhow!("Unknown BRSAR sound: {}", name))
+            }
+        }
+    }
+
+    /// Finds a sound by name or index and decodes it. Returns the sound's name and a reader for
+    /// its audio data.
+    fn open_sound(&mut self, name: &str) -> Result<(String, AudioReader<'static>)> {
+        let index = self.find_sound(name)?;
+        let sound = &self.brsar.sounds[index];
+        debug!("Resolved BRSAR sound \"{}\": index={}, kind={:?}", name, index, sound.sound_kind());
+        // Sound names are uppercase in the BRSAR, but the GameCube version uses lowercase names
+        let sound_name = match self.brsar.symbol(sound.name_index) {
+            "" => format!("{:>04}", index),
+            symbol => symbol.to_lowercase(),
+        };
+
+        let rwav = self.brsar.read_wave(&mut self.reader, index)?;
+        let mut decoder = rwav.decoder();
+        let samples = decoder.read_all_samples()?.into_owned();
+        let cues = decoder.cues().collect();
+        drop(decoder);
+        let wave = WaveSound { samples, cues, tag: rwav.tag, index, archive: self.name.clone() };
+        Ok((sound_name, AudioReader::Wave(wave)))
+    }
+}
+
+/// A decoded wave sound from a BRSAR file.
+struct WaveSound {
+    samples: Samples<'static, PcmS16Le>,
+    cues: Vec<Cue>,
+    tag: SourceTag,
+    /// The index of the sound in the BRSAR.
+    index: usize,
+    /// The name of the BRSAR file.
+    archive: String,
+}
+
 /// Wraps a file ID for an audio resource.
 enum AudioFileId {
     Music(FileId),
@@ -250,6 +331,7 @@ impl AudioFileId {
 enum AudioReader<'r> {
     Music(HpsReader<'r>),
     Sfx { bank: Rc<SfxBank>, index: usize },
+    Wave(WaveSound),
 }
 
 impl<'r> AudioReader<'r> {
@@ -280,6 +362,7 @@ impl<'r> AudioReader<'r> {
         match self {
             Self::Music(hps) => hps.channels(),
             Self::Sfx { bank, index } => bank.sample(*index).channels.len(),
+            Self::Wave(wave) => wave.samples.channels,
         }
     }
 
@@ -288,6 +371,7 @@ impl<'r> AudioReader<'r> {
         match self {
             Self::Music(hps) => hps.sample_rate(),
             Self::Sfx { bank, index } => bank.sample(*index).rate,
+            Self::Wave(wave) => wave.samples.rate,
         }
     }
 
@@ -303,6 +387,9 @@ impl<'r> AudioReader<'r> {
         match self {
             Self::Music(hps) => hps.decoder(),
             Self::Sfx { bank, index } => bank.decoder(*index),
+            Self::Wave(wave) => Box::new(
+                wave.samples.clone().into_reader(wave.tag.clone()).with_cues(wave.cues.clone()),
+            ),
         }
     }
 }
@@ -318,25 +405,63 @@ fn export_labels(cues: Vec<Cue>, sample_rate: u32, sound_path: &Path) -> Result<
     Ok(())
 }
 
+/// Returns the file extension to use for exported audio files.
+fn export_ext(settings: &ExportSettings) -> &'static str {
+    if settings.flac {
+        "flac"
+    } else {
+        "wav"
+    }
+}
+
+/// Writes `audio` to `out` using the file format selected by `settings`.
+fn write_audio<'r>(
+    audio: impl ReadSamples<'static, Format = PcmS16Le> + 'r,
+    settings: &ExportSettings,
+    out: impl Write + Seek,
+    on_progress: impl FnMut(Option<ProgressHint>) + 'r,
+) -> Result<()> {
+    if settings.flac {
+        FlacWriter::new(audio).on_progress(on_progress).write_to(out)?;
+    } else {
+        WavWriter::new(audio).on_progress(on_progress).write_to(out)?;
+    }
+    Ok(())
+}
+
 /// The `audio info` CLI command.
 fn command_info(ctx: Context, args: InfoArgs) -> Result<()> {
+    if let Some(path) = &args.brsar {
+        let mut brsar = BrsarFile::open(path)?;
+        let (name, audio) = brsar.open_sound(&args.name)?;
+        print_info(&name, None, &audio);
+        return Ok(());
+    }
     let mut ctx = ctx.open_read()?;
     let mut cache = AudioCache::new();
     let resource = AudioResource::find(&mut ctx, &args.name)?;
-    let name = resource.name();
     let file = AudioFileId::get(&mut ctx, &mut cache, &resource)?;
     let audio = AudioReader::open(&mut ctx, &mut cache, &file)?;
+    print_info(resource.name(), resource.id(), &audio);
+    Ok(())
+}
+
+/// Prints information about an audio resource.
+fn print_info(name: &str, id: Option<Sound>, audio: &AudioReader<'_>) {
     let duration = audio.duration();
     let cues = audio.decoder().cues().collect::<Vec<_>>();
     let looping = cues.iter().any(|c| c.is_loop());
     let num_cues = cues.iter().filter(|c| !c.is_loop()).count();
-    match &audio {
+    match audio {
         AudioReader::Music(_) => print!("{}: Program stream", name),
         AudioReader::Sfx { bank, index } => {
             print!("{}: Sound sample {} in {}", name, index, bank.tag().name);
         }
+        AudioReader::Wave(wave) => {
+            print!("{}: Wave sound {} in {}", name, wave.index, wave.archive);
+        }
     }
-    match resource.id() {
+    match id {
         Some(id) => println!(" (ID 0x{:08x})", id.value()),
         None => println!(),
     }
@@ -345,27 +470,42 @@ fn command_info(ctx: Context, args: InfoArgs) -> Result<()> {
     println!("Sample Rate: {} Hz", audio.sample_rate());
     println!("Looping: {}", if looping { "Yes" } else { "No" });
     println!("Cues: {}", num_cues);
-    Ok(())
 }
 
 /// The `audio export` CLI command.
-fn command_export(ctx: Context, args: ExportArgs) -> Result<()> {
-    let mut ctx = ctx.open_read()?;
-    let mut cache = AudioCache::new();
+fn command_export(ctx: Context, mut args: ExportArgs) -> Result<()> {
     if args.names.is_empty() {
         bail!("Nothing to export");
     }
     let (out_dir, out_name) = output_dir_and_name(args.output.as_deref(), args.names.len() > 1);
+    if out_name.as_ref().is_some_and(|n| Path::new(n).extension() == Some("flac".as_ref())) {
+        args.settings.flac = true;
+    }
     fs::create_dir_all(out_dir)?;
-    for name in &args.names {
-        let resource = AudioResource::find(&mut ctx, name)?;
-        let default_name = format!("{}.wav", resource.name());
+    let settings = &args.settings;
+    let export_as = |audio: &AudioReader<'_>, name: &str| -> Result<()> {
+        let default_name = format!("{}.{}", name, export_ext(settings));
         let filename = out_name.as_ref().unwrap_or(&default_name);
         info!("Exporting {}", filename);
+        export(audio, settings, &out_dir.join(filename))
+    };
+
+    if let Some(path) = &args.brsar {
+        let mut brsar = BrsarFile::open(path)?;
+        for name in &args.names {
+            let (sound_name, audio) = brsar.open_sound(name)?;
+            export_as(&audio, &sound_name)?;
+        }
+        return Ok(());
+    }
+
+    let mut ctx = ctx.open_read()?;
+    let mut cache = AudioCache::new();
+    for name in &args.names {
+        let resource = AudioResource::find(&mut ctx, name)?;
         let file = AudioFileId::get(&mut ctx, &mut cache, &resource)?;
         let audio = AudioReader::open(&mut ctx, &mut cache, &file)?;
-        let output = out_dir.join(filename);
-        export(&audio, &args.settings, &output)?;
+        export_as(&audio, resource.name())?;
     }
     Ok(())
 }
@@ -401,11 +541,12 @@ fn command_export_all(ctx: Context, args: ExportAllArgs) -> Result<()> {
     fs::create_dir_all(&music_dir)?;
     let mut cache = AudioCache::new();
     for music in Music::iter().filter(|m| m.is_some()) {
-        info!("Exporting {}/{}.wav", MUSIC_DIR, music.name());
+        let filename = format!("{}.{}", music.name(), export_ext(&args.settings));
+        info!("Exporting {}/{}", MUSIC_DIR, filename);
         let resource = AudioResource::Music(music);
         let file = AudioFileId::get(&mut ctx, &mut cache, &resource)?;
         let audio = AudioReader::open(&mut ctx, &mut cache, &file)?;
-        let output = music_dir.join(format!("{}.wav", music.name()));
+        let output = music_dir.join(filename);
         export(&audio, &args.settings, &output)?;
     }
     Ok(())
@@ -421,7 +562,7 @@ fn export(audio: &AudioReader<'_>, settings: &ExportSettings, path: &Path) -> Re
     let out = BufWriter::new(File::create(path)?);
     let decoder = audio.decoder();
     let cues = decoder.cues().collect::<Vec<_>>();
-    WavWriter::new(decoder).on_progress(|p| update_audio_progress(&progress, p)).write_to(out)?;
+    write_audio(decoder, settings, out, |p| update_audio_progress(&progress, p))?;
     progress.finish_using_style();
 
     if settings.labels {
@@ -461,7 +602,7 @@ fn export_bank_impl<T: ReadSeek>(
     let progress = progress_bar(bank.len() as u64);
     for (i, _) in bank.samples().enumerate() {
         let name = sfx_name(&bank, i, have_names);
-        let filename = format!("{}.wav", name);
+        let filename = format!("{}.{}", name, export_ext(settings));
         if progress.is_hidden() {
             info!("Writing {}{}", display_prefix, filename);
         } else {
@@ -471,7 +612,7 @@ fn export_bank_impl<T: ReadSeek>(
         let out = BufWriter::new(File::create(&out_path)?);
         let decoder = bank.decoder(i);
         let cues: Vec<_> = decoder.cues().collect();
-        WavWriter::new(decoder).write_to(out)?;
+        write_audio(decoder, settings, out, |_| ())?;
         if settings.labels {
             export_labels(cues, bank.sample(i).rate, &out_path)?;
         }
@@ -483,6 +624,9 @@ fn export_bank_impl<T: ReadSeek>(
 
 /// The `audio import` CLI command.
 fn command_import(ctx: Context, args: ImportArgs) -> Result<()> {
+    if let Some(path) = &args.brsar {
+        return import_brsar(path, &args);
+    }
     let mut ctx = ctx.open_read_write()?;
     let resource = AudioResource::find(&mut ctx, &args.name)?;
     info!("Opening {}", resource.name());
@@ -502,7 +646,9 @@ fn import_music<T: ReadWriteSeek>(
     let name = ctx.query_file(&file)?.name;
     let original_loop = ctx.open_music_file(&file)?.loop_start();
 
-    let audio = open_sound_file(&args.path, &args.settings, MAX_MUSIC_SAMPLE_RATE)?;
+    // HPS streams can only loop back from the end, so audio after the loop can't be played
+    let audio = open_sound_file(&args.path, &args.settings, MAX_MUSIC_SAMPLE_RATE, true)?;
+    let has_loop = audio.cues().any(|c| c.is_loop());
     info!("Analyzing audio waveform");
     let progress = progress_bar(1);
     progress.set_message(audio.tag().name.clone());
@@ -513,8 +659,15 @@ fn import_music<T: ReadWriteSeek>(
     info!("Encoding audio to GameCube format");
     let progress = progress_bar(1);
     progress.set_message(name);
-    // Copy the loop setting from the original HPS
-    let looping = if original_loop.is_some() { Looping::Enabled } else { Looping::Disabled };
+    // Use the loop point from the new audio if it has one, otherwise copy the loop setting from the
+    // original HPS
+    let looping = if has_loop {
+        Looping::Auto
+    } else if original_loop.is_some() {
+        Looping::Enabled
+    } else {
+        Looping::Disabled
+    };
     let mut writer = Cursor::new(vec![]);
     encoder
         .looping(looping)
@@ -537,7 +690,7 @@ fn import_sfx<T: ReadWriteSeek>(
     let name = ctx.query_file(&file)?.name;
     let mut bank = ctx.read_bank_file(&file)?;
 
-    let mut audio = open_sound_file(&args.path, &args.settings, MAX_SFX_SAMPLE_RATE)?;
+    let mut audio = open_sound_file(&args.path, &args.settings, MAX_SFX_SAMPLE_RATE, false)?;
     info!("Encoding audio to GameCube format");
     let mut new_sample = BankSample::from_pcm(&mut audio)?;
     let old_sample = bank.sample(index);
@@ -559,13 +712,49 @@ fn import_sfx<T: ReadWriteSeek>(
     Ok(())
 }
 
+fn import_brsar(path: &Path, args: &ImportArgs) -> Result<()> {
+    let mut brsar = BrsarFile::open(path)?;
+    let index = brsar.find_sound(&args.name)?;
+    let old_rwav = brsar.brsar.read_wave(&mut brsar.reader, index)?;
+
+    // Keep the original sound's codec so that we don't blow up the file size
+    let codec = Codec::try_from(old_rwav.format)?;
+    let mut audio = open_sound_file(&args.path, &args.settings, MAX_SFX_SAMPLE_RATE, false)?;
+    info!("Encoding audio to Wii format");
+    let mut rwav = Rwav::from_pcm(&mut audio, codec)?;
+    if old_rwav.looping && !rwav.looping {
+        warn!("Setting loop point at the start because none was defined");
+        rwav.looping = true;
+    }
+
+    info!("Rebuilding {}", brsar.name);
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    let mut temp = NamedTempFile::new_in(dir)?;
+    let mut writer = BufWriter::new(temp.as_file_mut());
+    brsar.brsar.replace_wave(&mut brsar.reader, &mut writer, index, &rwav)?;
+    writer.flush()?;
+    drop(writer);
+    // Make sure the original file is closed before replacing it
+    drop(brsar);
+    fs::set_permissions(temp.path(), fs::metadata(path)?.permissions())?;
+    temp.persist(path)?;
+    Ok(())
+}
+
 /// The `audio play` subcommand.
 fn command_play(ctx: Context, args: PlayArgs) -> Result<()> {
-    let ctx = Box::leak(Box::new(ctx.open_read()?));
-    let mut cache = AudioCache::new();
-    let resource = AudioResource::find(ctx, &args.name)?;
-    let file = AudioFileId::get(ctx, &mut cache, &resource)?;
-    let audio = Box::leak(Box::new(AudioReader::open(ctx, &mut cache, &file)?));
+    let (name, audio) = if let Some(path) = &args.brsar {
+        let mut brsar = BrsarFile::open(path)?;
+        let (name, audio) = brsar.open_sound(&args.name)?;
+        (name, Box::leak(Box::new(audio)))
+    } else {
+        let ctx = Box::leak(Box::new(ctx.open_read()?));
+        let mut cache = AudioCache::new();
+        let resource = AudioResource::find(ctx, &args.name)?;
+        let file = AudioFileId::get(ctx, &mut cache, &resource)?;
+        let audio = Box::leak(Box::new(AudioReader::open(ctx, &mut cache, &file)?));
+        (resource.name().to_owned(), audio)
+    };
     let decoder = audio.decoder();
     let source = PlaybackSource::new(decoder)?.with_volume(args.volume);
 
@@ -573,12 +762,178 @@ fn command_play(ctx: Context, args: PlayArgs) -> Result<()> {
     let mut device = PlaybackDevice::open_default(source.sample_rate())?;
 
     info!("Starting playback");
-    playback::play(&mut device, source, resource.name().to_owned());
+    playback::play(&mut device, source, name);
 
     info!("Playback finished");
     Ok(())
 }
 
+/// A sound effect group as represented in an exported playlist.
+#[derive(Serialize, Deserialize)]
+struct PlaylistGroupModel {
+    /// Group (sample bank) name
+    group: String,
+    /// Sound materials in the group
+    sounds: Vec<SoundMaterialModel>,
+}
+
+/// A sound material as represented in an exported playlist.
+#[derive(Serialize, Deserialize)]
+struct SoundMaterialModel {
+    /// Sound effect name
+    name: String,
+    /// Actions to perform when the sound effect plays
+    actions: Vec<ActionModel>,
+}
+
+/// A sound material action as represented in an exported playlist.
+#[derive(Serialize, Deserialize)]
+struct ActionModel {
+    /// Command name
+    command: String,
+    /// Delay after the action in 5ms units
+    #[serde(default)]
+    delay: u8,
+    /// Sample name for `sample` commands
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sample: Option<String>,
+    /// Command-specific data for other commands
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<u16>,
+}
+
+/// Returns the name of `command` as it appears in exported playlists.
+fn command_name(command: Command) -> String {
+    format!("{:?}", command).to_lowercase()
+}
+
+/// Looks up a playlist command by name.
+fn find_command(name: &str) -> Option<Command> {
+    (0..=u8::MAX)
+        .filter_map(|code| Command::try_from(code).ok())
+        .find(|&c| command_name(c).eq_ignore_ascii_case(name))
+}
+
+impl TryFrom<&Action> for ActionModel {
+    type Error = anyhow::Error;
+    fn try_from(action: &Action) -> Result<Self> {
+        let (sample, data) = if action.command == Command::Sample {
+            let sample = match SfxSample::try_from(u32::from(action.data)) {
+                Ok(sample) => sample,
+                Err(_) => bail!("Invalid sample ID: {}", action.data),
+            };
+            (Some(sample.name().to_owned()), None)
+        } else {
+            (None, Some(action.data).filter(|&d| d != 0))
+        };
+        Ok(Self { command: command_name(action.command), delay: action.delay, sample, data })
+    }
+}
+
+impl TryFrom<&ActionModel> for Action {
+    type Error = anyhow::Error;
+    fn try_from(model: &ActionModel) -> Result<Self> {
+        let command = match find_command(&model.command) {
+            Some(command) => command,
+            None => bail!("Invalid command name: \"{}\"", model.command),
+        };
+        let data = match (command, &model.sample) {
+            (Command::Sample, Some(name)) => match SfxSample::find(name) {
+                Some(sample) => u32::from(sample) as u16,
+                None => bail!("Invalid sample name: \"{}\"", name),
+            },
+            (Command::Sample, None) => bail!("Sample command does not have a sample name"),
+            (_, Some(_)) => bail!("Only sample commands can have a sample name"),
+            (_, None) => model.data.unwrap_or(0),
+        };
+        Ok(Self { command, delay: model.delay, data })
+    }
+}
+
+/// The `audio playlist export` CLI command.
+fn command_playlist_export(ctx: Context, args: PlaylistExportArgs) -> Result<()> {
+    let mut ctx = ctx.open_read()?;
+    let out = BufWriter::new(OutputRedirect::new(args.output)?);
+
+    info!("Reading sfx_sample.sem");
+    let playlist = ctx.read_playlist()?;
+    if playlist.group_indexes.len() != SfxGroup::COUNT || playlist.sounds.len() != Sfx::COUNT {
+        bail!("The SFX playlist does not match the expected layout");
+    }
+
+    info!("Writing to JSON");
+    let mut groups = Vec::with_capacity(playlist.group_indexes.len());
+    for (i, &start) in playlist.group_indexes.iter().enumerate() {
+        let end = playlist.group_indexes.get(i + 1).copied().unwrap_or(Sfx::COUNT as u32);
+        let mut sounds = vec![];
+        for index in start..end {
+            let actions = playlist.sounds[index as usize].actions.iter();
+            sounds.push(SoundMaterialModel {
+                name: Sfx::at(index).name().to_owned(),
+                actions: actions.map(ActionModel::try_from).collect::<Result<_>>()?,
+            });
+        }
+        let group = SfxGroup::at(i as u16);
+        groups.push(PlaylistGroupModel { group: group.name().to_owned(), sounds });
+    }
+    if args.compact {
+        serde_json::to_writer(out, &groups)?;
+    } else {
+        let formatter = MaxIndentJsonFormatter::new(PLAYLIST_MAX_INDENT);
+        let mut serializer = Serializer::with_formatter(out, formatter);
+        groups.serialize(&mut serializer)?;
+    }
+    Ok(())
+}
+
+/// The `audio playlist import` CLI command.
+fn command_playlist_import(ctx: Context, args: PlaylistImportArgs) -> Result<()> {
+    let mut ctx = ctx.open_read_write()?;
+    info!("Reading input JSON");
+    let json = BufReader::new(File::open(args.input)?);
+    let models: Vec<PlaylistGroupModel> = serde_json::from_reader(json)?;
+
+    info!("Reading sfx_sample.sem");
+    let mut playlist = ctx.read_playlist()?;
+
+    let mut imported = HashSet::new();
+    for group_model in &models {
+        let group = match SfxGroup::find(&group_model.group) {
+            Some(group) => group,
+            None => bail!("Invalid group name: \"{}\"", group_model.group),
+        };
+        for model in &group_model.sounds {
+            let sfx = match Sfx::find(&model.name) {
+                Some(sfx) => sfx,
+                None => bail!("Invalid sound effect name: \"{}\"", model.name),
+            };
+            if sfx.group() != group {
+                bail!("Sound effect \"{}\" is not in group \"{}\"", model.name, group.name());
+            }
+            if !imported.insert(sfx) {
+                bail!("Sound effect \"{}\" appears more than once", model.name);
+            }
+            let actions = model.actions.iter().map(Action::try_from).collect::<Result<_>>()?;
+            let material = SoundMaterial { actions };
+            // Catch invalid actions now so that we can report which sound they belong to
+            if let Err(e) = material.write_to(&mut io::sink()) {
+                bail!("Sound effect \"{}\" is invalid: {}", model.name, e);
+            }
+            playlist.sounds[sfx.material_index()] = material;
+        }
+    }
+
+    info!("Rebuilding sfx_sample.sem");
+    let mut writer = Cursor::new(vec![]);
+    playlist.write_to(&mut writer)?;
+
+    info!("Updating game files");
+    writer.rewind()?;
+    let file = ctx.disc_file_at(Sfx::DISC_PLAYLIST_PATH)?;
+    ctx.begin_update().write_file(&file, writer).commit()?;
+    Ok(())
+}
+
 /// The `audio` CLI command.
 pub fn command(ctx: Context, args: Subcommand) -> Result<()> {
     match args {
@@ -588,5 +943,11 @@ pub fn command(ctx: Context, args: Subcommand) -> Result<()> {
         Subcommand::ExportAll(args) => command_export_all(ctx, args),
         Subcommand::Import(args) => command_import(ctx, args),
         Subcommand::Play(args) => command_play(ctx, args),
+        Subcommand::Playlist(PlaylistSubcommand::Export(args)) => {
+            command_playlist_export(ctx, args)
+        }
+        Subcommand::Playlist(PlaylistSubcommand::Import(args)) => {
+            command_playlist_import(ctx, args)
+        }
     }
 }
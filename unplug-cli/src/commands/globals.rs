@@ -348,6 +348,11 @@ impl From<Metadata> for MetadataDef {
     }
 }
 
+/// Converts global metadata into the JSON structure used by `globals export`.
+pub fn metadata_json(metadata: Metadata) -> Result<serde_json::Value> {
+    Ok(serde_json::to_value(MetadataDef::from(metadata))?)
+}
+
 /// The `globals export` CLI command.
 pub fn command_export(ctx: Context, args: ExportArgs) -> Result<()> {
     let mut ctx = ctx.open_read()?;
@@ -1,22 +1,46 @@
 use crate::args::iso::*;
 
 use crate::common::output_dir_and_name;
-use crate::context::Context;
+use crate::context::{edit_disc, open_disc_read, Context, OpenContext};
+use crate::diff::{diff_discs, ChangeKind};
 use crate::fst::{extract_file, list_files};
+use crate::io::{open_compressed, OutputRedirect};
+use crate::verify::{verify_disc, FileStatus};
 use anyhow::{bail, Result};
 use humansize::{FormatSize, BINARY};
-use log::info;
-use std::fs::{self, File};
-use unplug::common::io::BUFFER_SIZE;
-use unplug::common::Text;
-use unplug::dvd::{Banner, DiscStream, Glob, GlobMode};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use unplug::common::io::{copy_buffered, BUFFER_SIZE};
+use unplug::common::text::FixedText;
+use unplug::common::{ReadFrom, ReadSeek, ReadWriteSeek, Text};
+use unplug::dvd::banner::{IMAGE_HEIGHT, IMAGE_WIDTH};
+use unplug::dvd::disasm::disassemble;
+use unplug::dvd::gecko::{GeckoCode, GeckoPatcher};
+use unplug::dvd::image::{open_image, write_image};
+use unplug::dvd::{
+    Banner, DiscBuilder, DiscStream, EntryId, GameInfo, Glob, GlobMode, ImageFormat, OpenFile,
+    RegionCode, SymbolMap,
+};
 
 const BANNER_PATH: &str = "opening.bnr";
 
+/// Name of the directory containing system data in an extracted disc.
+const SYS_DIR_NAME: &str = "sys";
+/// Name of the directory containing game files in an extracted disc.
+const FILES_DIR_NAME: &str = "files";
+
+const BOOT_NAME: &str = "boot.bin";
+const BI2_NAME: &str = "bi2.bin";
+const APPLOADER_NAME: &str = "apploader.img";
+const DOL_NAME: &str = "main.dol";
+
 /// The `iso info` CLI command.
 fn command_info(ctx: Context) -> Result<()> {
     let path = ctx.into_iso_path()?;
-    let mut disc = DiscStream::open(File::open(&path)?)?;
+    let mut disc = open_disc_read(&path)?;
     let banner = disc.read_banner()?;
     let name = path.file_name().unwrap().to_string_lossy();
     println!("{}: [{}] {}", name, disc.game_id(), disc.game_name());
@@ -49,14 +73,14 @@ fn command_info(ctx: Context) -> Result<()> {
 /// The `iso list` CLI command.
 fn command_list(ctx: Context, args: ListArgs) -> Result<()> {
     let path = ctx.into_iso_path()?;
-    let disc = DiscStream::open(File::open(path)?)?;
+    let disc = open_disc_read(&path)?;
     list_files(disc.files(), &args.settings, &Glob::new(GlobMode::Prefix, args.paths))
 }
 
 /// The `iso extract` CLI command.
 fn command_extract(ctx: Context, args: ExtractArgs) -> Result<()> {
     let path = ctx.into_iso_path()?;
-    let mut disc = DiscStream::open(File::open(path)?)?;
+    let mut disc = open_disc_read(&path)?;
     let files = Glob::new(GlobMode::Exact, args.paths).find(disc.files()).collect::<Vec<_>>();
     if files.is_empty() {
         bail!("Nothing to extract");
@@ -65,20 +89,224 @@ fn command_extract(ctx: Context, args: ExtractArgs) -> Result<()> {
     fs::create_dir_all(out_dir)?;
     let mut io_buf = vec![0u8; BUFFER_SIZE].into_boxed_slice();
     for (path, entry) in files {
-        extract_file(&mut disc, entry, &path, out_dir, out_name.as_deref(), &mut io_buf)?;
+        let name = out_name.as_deref();
+        extract_file(&mut disc, entry, &path, out_dir, name, args.decompress, &mut io_buf)?;
     }
     Ok(())
 }
 
+/// Writes a system file named `name` into `dir`.
+fn extract_sys_file(
+    dir: &Path,
+    name: &str,
+    reader: &mut dyn Read,
+    io_buf: &mut [u8],
+) -> Result<()> {
+    info!("Extracting {}", name);
+    let mut writer = File::create(dir.join(name))?;
+    copy_buffered(reader, &mut writer, io_buf)?;
+    Ok(())
+}
+
+/// Extracts a disc's system data into `dir`.
+fn extract_sys(disc: &mut DiscStream<impl ReadSeek>, dir: &Path, io_buf: &mut [u8]) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    extract_sys_file(dir, BOOT_NAME, &mut disc.open_boot()?, io_buf)?;
+    extract_sys_file(dir, BI2_NAME, &mut disc.open_bi2()?, io_buf)?;
+    extract_sys_file(dir, APPLOADER_NAME, &mut disc.open_apploader()?, io_buf)?;
+    extract_sys_file(dir, DOL_NAME, &mut disc.open_dol()?.1, io_buf)?;
+    Ok(())
+}
+
 /// The `iso extract-all` CLI command.
 fn command_extract_all(ctx: Context, args: ExtractAllArgs) -> Result<()> {
     let path = ctx.into_iso_path()?;
-    let mut disc = DiscStream::open(File::open(path)?)?;
+    let mut disc = open_disc_read(&path)?;
     let (out_dir, out_name) = output_dir_and_name(args.output.as_deref(), false);
     fs::create_dir_all(out_dir)?;
     let mut io_buf = vec![0u8; BUFFER_SIZE].into_boxed_slice();
     let root = disc.files().root();
-    extract_file(&mut disc, root, "/", out_dir, out_name.as_deref(), &mut io_buf)?;
+    if args.sys {
+        let root_dir = match out_name {
+            Some(name) => out_dir.join(name),
+            None => out_dir.to_owned(),
+        };
+        extract_sys(&mut disc, &root_dir.join(SYS_DIR_NAME), &mut io_buf)?;
+        let name = Some(FILES_DIR_NAME);
+        extract_file(&mut disc, root, "/", &root_dir, name, args.decompress, &mut io_buf)?;
+    } else {
+        let name = out_name.as_deref();
+        extract_file(&mut disc, root, "/", out_dir, name, args.decompress, &mut io_buf)?;
+    }
+    Ok(())
+}
+
+/// Recursively adds the contents of the local directory at `dir_path` to the disc being built.
+fn build_dir(builder: &mut DiscBuilder<'_>, parent: EntryId, dir_path: &Path) -> Result<()> {
+    let mut children = fs::read_dir(dir_path)?.collect::<Result<Vec<_>, _>>()?;
+    children.sort_unstable_by_key(|c| c.file_name());
+    for child in children {
+        let name = child.file_name();
+        let Some(name) = name.to_str() else {
+            bail!("Invalid filename: {}", child.path().display());
+        };
+        let path = child.path();
+        if child.file_type()?.is_dir() {
+            let id = builder.create_dir(parent, name)?;
+            build_dir(builder, id, &path)?;
+        } else {
+            builder.create_file(parent, name, move || {
+                info!("Adding {}", path.display());
+                File::open(path)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// The `iso build` CLI command.
+fn command_build(args: BuildArgs) -> Result<()> {
+    if !args.force && args.output.exists() {
+        bail!("The output file already exists (use --force to overwrite)");
+    }
+    let sys_dir = args.input.join(SYS_DIR_NAME);
+    let files_dir = args.input.join(FILES_DIR_NAME);
+    if !sys_dir.is_dir() || !files_dir.is_dir() {
+        bail!(
+            "{} must contain {}/ and {}/ directories (use `iso extract-all --sys`)",
+            args.input.display(),
+            SYS_DIR_NAME,
+            FILES_DIR_NAME
+        );
+    }
+
+    let open = |name: &str| -> Result<BufReader<File>> {
+        Ok(BufReader::new(File::open(sys_dir.join(name))?))
+    };
+    let mut builder = DiscBuilder::new(
+        &mut open(BOOT_NAME)?,
+        &mut open(BI2_NAME)?,
+        &mut open(APPLOADER_NAME)?,
+        &mut open(DOL_NAME)?,
+    )?;
+    let root = builder.files().root();
+    build_dir(&mut builder, root, &files_dir)?;
+
+    info!("Writing {}", args.output.display());
+    builder.write_to(File::create(&args.output)?)?;
+    Ok(())
+}
+
+/// Logs how much free space is on a disc and how fragmented it is.
+fn log_free_space(disc: &DiscStream<impl ReadSeek>) {
+    let free = disc.free_regions();
+    let size = free.iter().map(|r| r.size as u64).sum::<u64>();
+    info!("Free space: {} in {} region(s)", size.format_size(BINARY), free.len());
+}
+
+/// The `iso compact` CLI command.
+fn command_compact(ctx: Context, args: CompactArgs) -> Result<()> {
+    let path = ctx.into_editable_iso_path()?;
+    if args.shrink && ImageFormat::detect(&mut File::open(&path)?)? != ImageFormat::Iso {
+        bail!("Only raw ISOs can be shrunk");
+    }
+    let end = edit_disc(&path, |disc| {
+        log_free_space(disc);
+        info!("Compacting disc");
+        let end = disc.compact()?;
+        log_free_space(disc);
+        Ok(end)
+    })?;
+    if args.shrink {
+        info!("Shrinking ISO to {}", (end as u64).format_size(BINARY));
+        OpenOptions::new().write(true).open(&path)?.set_len(end.into())?;
+    }
+    Ok(())
+}
+
+/// Reads all of the data in `reader` into memory.
+fn read_all(mut reader: impl Read) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Checks that the system data and every file on `original` read identically from `copy`.
+fn verify_copy(
+    original: &mut DiscStream<impl ReadSeek>,
+    copy: &mut DiscStream<impl ReadSeek>,
+) -> Result<()> {
+    if read_all(original.open_boot()?)? != read_all(copy.open_boot()?)?
+        || read_all(original.open_bi2()?)? != read_all(copy.open_bi2()?)?
+        || read_all(original.open_apploader()?)? != read_all(copy.open_apploader()?)?
+        || read_all(original.open_dol()?.1)? != read_all(copy.open_dol()?.1)?
+    {
+        bail!("Verification failed: system data does not match");
+    }
+    let files = original.files().recurse().filter(|(_, id)| original.files()[*id].is_file());
+    for (path, id) in files.collect::<Vec<_>>() {
+        let copy_id = copy.files().at(&path)?;
+        if read_all(original.open_file(id)?)? != read_all(copy.open_file(copy_id)?)? {
+            bail!("Verification failed: {} does not match", path);
+        }
+    }
+    Ok(())
+}
+
+/// The `iso scrub` CLI command.
+fn command_scrub(ctx: Context, args: ScrubArgs) -> Result<()> {
+    let path = ctx.into_iso_path()?;
+    if args.output.exists() {
+        if !args.force {
+            bail!("The output file already exists (use --force to overwrite)");
+        }
+        if args.output.canonicalize()? == path.canonicalize()? {
+            bail!("The source and destination paths cannot be the same");
+        }
+    }
+    let mut disc = open_disc_read(&path)?;
+
+    info!("Writing {}", args.output.display());
+    let mut writer = BufWriter::new(File::create(&args.output)?);
+    let size = disc.write_scrubbed(&mut writer, args.trim)?;
+    writer.flush()?;
+    drop(writer);
+    info!("Wrote {}", size.format_size(BINARY));
+
+    info!("Verifying files");
+    let mut copy = open_disc_read(&args.output)?;
+    verify_copy(&mut disc, &mut copy)?;
+    info!("Verification succeeded");
+    Ok(())
+}
+
+/// The `iso convert` CLI command.
+fn command_convert(ctx: Context, args: ConvertArgs) -> Result<()> {
+    let path = ctx.into_iso_path()?;
+    let ext = args.output.extension().and_then(|e| e.to_str());
+    let Some(format) = args.format.or_else(|| ext.and_then(ImageFormat::from_extension)) else {
+        bail!("Could not determine the output format (use --format)");
+    };
+    if !format.is_writable() {
+        bail!("{} images cannot be written", format);
+    }
+    if args.output.exists() {
+        if !args.force {
+            bail!("The output file already exists (use --force to overwrite)");
+        }
+        if args.output.canonicalize()? == path.canonicalize()? {
+            bail!("The source and destination paths cannot be the same");
+        }
+    }
+
+    let (src_format, mut reader) = open_image(File::open(&path)?)?;
+    // Don't copy anything past the end of the disc (e.g. CISO padding)
+    let size = DiscStream::open(&mut reader)?.total_size() as u64;
+    let size = size.min(reader.seek(SeekFrom::End(0))?);
+    reader.rewind()?;
+    info!("Converting {} image to {}: {}", src_format, format, args.output.display());
+    let mut reader = BufReader::new(reader);
+    write_image(format, &mut reader, size, BufWriter::new(File::create(&args.output)?))?;
     Ok(())
 }
 
@@ -87,12 +315,73 @@ fn command_replace(ctx: Context, args: ReplaceArgs) -> Result<()> {
     let mut ctx = ctx.open_read_write()?;
     let file = ctx.disc_file_at(&args.dest_path)?;
     let info = ctx.query_file(&file)?;
-    let reader = File::open(&args.src_path)?;
+    let reader = open_compressed(&args.src_path, args.compress)?;
     info!("Writing {}", info.name);
     ctx.begin_update().write_file(&file, reader).commit()?;
     Ok(())
 }
 
+/// Recursively adds the local file or directory at `src_path` to the disc at `dest_path`.
+fn add_entry<T: ReadWriteSeek>(
+    ctx: &mut OpenContext<T>,
+    src_path: &Path,
+    dest_path: &str,
+) -> Result<()> {
+    if src_path.is_dir() {
+        info!("Creating {}", dest_path);
+        ctx.create_disc_dir_at(dest_path)?;
+        let mut children = fs::read_dir(src_path)?.collect::<Result<Vec<_>, _>>()?;
+        children.sort_unstable_by_key(|c| c.file_name());
+        for child in children {
+            let name = child.file_name();
+            let child_dest =
+                format!("{}/{}", dest_path.trim_end_matches('/'), name.to_string_lossy());
+            add_entry(ctx, &child.path(), &child_dest)?;
+        }
+    } else {
+        info!("Adding {}", dest_path);
+        ctx.create_disc_file_at(dest_path, File::open(src_path)?)?;
+    }
+    Ok(())
+}
+
+/// The `iso add` CLI command.
+fn command_add(ctx: Context, args: AddArgs) -> Result<()> {
+    let mut ctx = ctx.open_read_write()?;
+    add_entry(&mut ctx, &args.src_path, &args.dest_path)
+}
+
+/// The `iso remove` CLI command.
+fn command_remove(ctx: Context, args: RemoveArgs) -> Result<()> {
+    let mut ctx = ctx.open_read_write()?;
+    for path in &args.paths {
+        let files = ctx.disc_files()?;
+        let entry = files.at(path)?;
+        if files[entry].is_dir() && !args.recursive {
+            bail!("{} is a directory (use --recursive to remove it)", path);
+        }
+        info!("Removing {}", path);
+        ctx.remove_disc_entry_at(path)?;
+    }
+    Ok(())
+}
+
+/// The `iso move` CLI command.
+fn command_move(ctx: Context, args: MoveArgs) -> Result<()> {
+    let mut ctx = ctx.open_read_write()?;
+    let files = ctx.disc_files()?;
+    let src = files.at(&args.src_path)?;
+    // If the destination is an existing directory, move the entry inside it
+    let dest_path = match files.at(&args.dest_path) {
+        Ok(dest) if files[dest].is_dir() && dest != src => {
+            format!("{}/{}", args.dest_path.trim_end_matches('/'), files[src].name())
+        }
+        _ => args.dest_path,
+    };
+    info!("Moving {} to {}", args.src_path, dest_path);
+    ctx.rename_disc_entry_at(&args.src_path, &dest_path)
+}
+
 /// Read, edit, and save opening.bnr.
 fn edit_banner<F>(ctx: Context, f: F) -> Result<()>
 where
@@ -127,6 +416,401 @@ fn command_set_name(ctx: Context, name: String) -> Result<()> {
     })
 }
 
+/// The `iso banner export` CLI command.
+fn command_banner_export(ctx: Context, output: &Path) -> Result<()> {
+    let mut ctx = ctx.open_read()?;
+    let file = ctx.disc_file_at(BANNER_PATH)?;
+    let banner: Banner = ctx.deserialize_file(&file)?;
+    let rgba = banner.image_rgba()?;
+
+    info!("Writing {}", output.display());
+    let writer = BufWriter::new(File::create(output)?);
+    let mut encoder = png::Encoder::new(writer, IMAGE_WIDTH as u32, IMAGE_HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&rgba)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Reads a PNG file and returns its pixels as 8-bit RGBA.
+fn read_png_rgba(path: &Path) -> Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    buf.truncate(info.buffer_size());
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buf,
+        png::ColorType::Rgb => buf.chunks(3).flat_map(|p| [p[0], p[1], p[2], 0xff]).collect(),
+        png::ColorType::GrayscaleAlpha => {
+            buf.chunks(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect()
+        }
+        png::ColorType::Grayscale => buf.iter().flat_map(|&p| [p, p, p, 0xff]).collect(),
+        png::ColorType::Indexed => bail!("Unsupported PNG color type"),
+    };
+    Ok((info.width, info.height, rgba))
+}
+
+/// The `iso banner import` CLI command.
+fn command_banner_import(ctx: Context, path: &Path) -> Result<()> {
+    info!("Reading {}", path.display());
+    let (width, height, rgba) = read_png_rgba(path)?;
+    if (width as usize, height as usize) != (IMAGE_WIDTH, IMAGE_HEIGHT) {
+        bail!(
+            "The banner image must be {}x{} (got {}x{})",
+            IMAGE_WIDTH,
+            IMAGE_HEIGHT,
+            width,
+            height
+        );
+    }
+    edit_banner(ctx, |banner| Ok(banner.set_image_rgba(&rgba)?))
+}
+
+/// Banner text for one language as represented in the exported JSON.
+#[derive(Serialize, Deserialize)]
+struct GameInfoModel {
+    name_short: String,
+    maker_short: String,
+    name_long: String,
+    maker_long: String,
+    description: String,
+}
+
+impl GameInfoModel {
+    /// Creates a new `GameInfoModel` from `info`.
+    fn with_info(info: &GameInfo) -> Self {
+        Self {
+            name_short: info.name_short.decode_replacing().into_owned(),
+            maker_short: info.maker_short.decode_replacing().into_owned(),
+            name_long: info.name_long.decode_replacing().into_owned(),
+            maker_long: info.maker_long.decode_replacing().into_owned(),
+            description: info.description.decode_replacing().into_owned(),
+        }
+    }
+
+    /// Encodes the model's text into a `GameInfo`, failing if any string is too long.
+    fn to_info(&self, language: usize) -> Result<GameInfo> {
+        fn encode<const N: usize>(
+            text: &str,
+            language: usize,
+            field: &str,
+        ) -> Result<FixedText<N>> {
+            match FixedText::encode(text) {
+                Ok(text) => Ok(text),
+                Err(e) => bail!("Language {} {}: {}", language, field, e),
+            }
+        }
+        Ok(GameInfo {
+            name_short: encode(&self.name_short, language, "name_short")?,
+            maker_short: encode(&self.maker_short, language, "maker_short")?,
+            name_long: encode(&self.name_long, language, "name_long")?,
+            maker_long: encode(&self.maker_long, language, "maker_long")?,
+            description: encode(&self.description, language, "description")?,
+        })
+    }
+}
+
+/// Banner text as represented in the exported JSON.
+#[derive(Serialize, Deserialize)]
+struct BannerInfoModel {
+    /// One entry for NTSC banners, or six for PAL banners (English, German, French, Spanish,
+    /// Italian, Dutch)
+    languages: Vec<GameInfoModel>,
+}
+
+/// The `iso banner export-info` CLI command.
+fn command_banner_export_info(ctx: Context, compact: bool, output: Option<PathBuf>) -> Result<()> {
+    let mut ctx = ctx.open_read()?;
+    let out = BufWriter::new(OutputRedirect::new(output)?);
+    let file = ctx.disc_file_at(BANNER_PATH)?;
+    let banner: Banner = ctx.deserialize_file(&file)?;
+    let model = BannerInfoModel {
+        languages: banner.languages.iter().map(GameInfoModel::with_info).collect(),
+    };
+    if compact {
+        serde_json::to_writer(out, &model)?;
+    } else {
+        serde_json::to_writer_pretty(out, &model)?;
+    }
+    Ok(())
+}
+
+/// The `iso banner import-info` CLI command.
+fn command_banner_import_info(ctx: Context, path: &Path) -> Result<()> {
+    info!("Reading {}", path.display());
+    let json = BufReader::new(File::open(path)?);
+    let model: BannerInfoModel = serde_json::from_reader(json)?;
+    let languages = model
+        .languages
+        .iter()
+        .enumerate()
+        .map(|(i, info)| info.to_info(i))
+        .collect::<Result<Vec<_>>>()?;
+    edit_banner(ctx, |banner| {
+        if languages.len() != banner.languages.len() {
+            bail!(
+                "The banner has {} language(s), but the JSON has {}",
+                banner.languages.len(),
+                languages.len()
+            );
+        }
+        banner.languages = languages;
+        Ok(())
+    })
+}
+
+/// Region codes as represented in the exported JSON.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RegionModel {
+    Japan,
+    Usa,
+    Pal,
+    Korea,
+    #[serde(untagged)]
+    Unknown(u32),
+}
+
+impl From<RegionCode> for RegionModel {
+    fn from(region: RegionCode) -> Self {
+        match region {
+            RegionCode::Japan => Self::Japan,
+            RegionCode::Usa => Self::Usa,
+            RegionCode::Pal => Self::Pal,
+            RegionCode::Korea => Self::Korea,
+            RegionCode::Unknown(code) => Self::Unknown(code),
+        }
+    }
+}
+
+impl From<RegionModel> for RegionCode {
+    fn from(region: RegionModel) -> Self {
+        match region {
+            RegionModel::Japan => Self::Japan,
+            RegionModel::Usa => Self::Usa,
+            RegionModel::Pal => Self::Pal,
+            RegionModel::Korea => Self::Korea,
+            RegionModel::Unknown(code) => Self::from(code),
+        }
+    }
+}
+
+/// Apploader information as represented in the exported JSON.
+#[derive(Serialize)]
+struct ApploaderModel {
+    date: String,
+    entry_point: u32,
+    code_size: u32,
+    trailer_size: u32,
+}
+
+/// Disc header fields as represented in the exported JSON.
+#[derive(Serialize, Deserialize)]
+struct HeaderModel {
+    // boot.bin
+    game_id: String,
+    game_name: String,
+    disc_id: u8,
+    version: u8,
+    audio_streaming: u8,
+    stream_buffer_size: u8,
+
+    // bi2.bin
+    region: RegionModel,
+    debug_flag: u32,
+    debug_monitor_size: u32,
+    simulated_memory_size: u32,
+
+    /// The apploader cannot be edited, so this is ignored on import.
+    #[serde(default, skip_deserializing)]
+    apploader: Option<ApploaderModel>,
+}
+
+/// The `iso header export` CLI command.
+fn command_header_export(ctx: Context, compact: bool, output: Option<PathBuf>) -> Result<()> {
+    let path = ctx.into_iso_path()?;
+    let mut disc = open_disc_read(&path)?;
+    let header = disc.header().clone();
+    let info = disc.read_header_info()?;
+    let apploader = disc.read_apploader_header()?;
+    let model = HeaderModel {
+        game_id: header.game_id(),
+        game_name: header.game_name.decode_replacing().into_owned(),
+        disc_id: header.disc_id,
+        version: header.version,
+        audio_streaming: header.audio_streaming,
+        stream_buffer_size: header.stream_buffer_size,
+        region: info.region.into(),
+        debug_flag: info.debug_flag,
+        debug_monitor_size: info.debug_monitor_size,
+        simulated_memory_size: info.simulated_memory_size,
+        apploader: Some(ApploaderModel {
+            date: apploader.date.decode_replacing().into_owned(),
+            entry_point: apploader.entry_point,
+            code_size: apploader.code_size,
+            trailer_size: apploader.trailer_size,
+        }),
+    };
+    let out = BufWriter::new(OutputRedirect::new(output)?);
+    if compact {
+        serde_json::to_writer(out, &model)?;
+    } else {
+        serde_json::to_writer_pretty(out, &model)?;
+    }
+    Ok(())
+}
+
+/// The `iso header import` CLI command.
+fn command_header_import(ctx: Context, path: &Path) -> Result<()> {
+    info!("Reading {}", path.display());
+    let json = BufReader::new(File::open(path)?);
+    let model: HeaderModel = serde_json::from_reader(json)?;
+
+    let path = ctx.into_editable_iso_path()?;
+    edit_disc(&path, |disc| {
+        let mut header = disc.header().clone();
+        header.set_game_id(&model.game_id)?;
+        header.game_name = match FixedText::encode(&model.game_name) {
+            Ok(text) => text,
+            Err(e) => bail!("game_name: {}", e),
+        };
+        header.disc_id = model.disc_id;
+        header.version = model.version;
+        header.audio_streaming = model.audio_streaming;
+        header.stream_buffer_size = model.stream_buffer_size;
+
+        let mut info = disc.read_header_info()?;
+        info.region = model.region.into();
+        info.debug_flag = model.debug_flag;
+        info.debug_monitor_size = model.debug_monitor_size;
+        info.simulated_memory_size = model.simulated_memory_size;
+
+        info!("Writing {}", BOOT_NAME);
+        disc.write_header(&header)?;
+        info!("Writing {}", BI2_NAME);
+        disc.write_header_info(&info)?;
+        Ok(())
+    })
+}
+
+/// The `iso patch-codes` CLI command.
+fn command_patch_codes(ctx: Context, args: PatchCodesArgs) -> Result<()> {
+    let text = fs::read_to_string(&args.path)?;
+    // Codes which are named explicitly can be applied even if the INI has them disabled
+    let mut codes = if args.codes.is_empty() {
+        GeckoCode::parse_enabled(&text)?
+    } else {
+        GeckoCode::parse_all(&text)?
+    };
+    if !args.codes.is_empty() {
+        if let Some(missing) = args.codes.iter().find(|&n| !codes.iter().any(|c| c.name == *n)) {
+            bail!("Code not found: {}", missing);
+        }
+        codes.retain(|c| args.codes.contains(&c.name));
+    }
+    if codes.is_empty() {
+        bail!("No codes found in {}", args.path.display());
+    }
+
+    let path = ctx.into_editable_iso_path()?;
+    edit_disc(&path, |disc| {
+        let mut dol = disc.read_dol()?;
+        let mut patcher = GeckoPatcher::new(&mut dol)?;
+        for code in &codes {
+            info!("Applying {}", code.name);
+            patcher.apply(code)?;
+        }
+        patcher.finish()?;
+        info!("Writing {}", DOL_NAME);
+        disc.write_dol(&dol)?;
+        Ok(())
+    })
+}
+
+/// The `iso disassemble` CLI command.
+fn command_disassemble(ctx: Context, args: DisassembleArgs) -> Result<()> {
+    let len = match args.end {
+        Some(end) if end <= args.start => bail!("The end address must be after the start address"),
+        Some(end) => end - args.start,
+        None => args.count.saturating_mul(4),
+    };
+    let mut disc = open_disc_read(&ctx.into_iso_path()?)?;
+    let dol = disc.read_dol()?;
+    let symbols = match &args.symbols {
+        Some(path) => SymbolMap::read_from(&mut BufReader::new(File::open(path)?))?,
+        None => SymbolMap::new(),
+    };
+    let mut out = BufWriter::new(OutputRedirect::new(args.output)?);
+    for instruction in disassemble(&dol, args.start, len)? {
+        if let Some(symbol) = symbols.at(instruction.address) {
+            writeln!(out, "\n{:08x} <{}>:", symbol.address, symbol.name)?;
+        }
+        write!(out, "{:08x}  {:08x}  {}", instruction.address, instruction.code, instruction)?;
+        if let Some(label) = instruction.branch_target().and_then(|t| symbols.label(t)) {
+            write!(out, " <{}>", label)?;
+        }
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// The `iso diff` CLI command.
+fn command_diff(args: DiffArgs) -> Result<()> {
+    let mut old = Context::Iso(args.old).open_read()?;
+    let mut new = Context::Iso(args.new).open_read()?;
+    info!("Comparing files");
+    let diff = diff_discs(&mut old, &mut new)?;
+    let mut out = BufWriter::new(OutputRedirect::new(args.output)?);
+    if args.json {
+        serde_json::to_writer_pretty(&mut out, &diff)?;
+        writeln!(out)?;
+    } else {
+        diff.write_text(&mut out)?;
+    }
+    out.flush()?;
+    info!(
+        "{} changed, {} added, {} removed",
+        diff.count(ChangeKind::Changed),
+        diff.count(ChangeKind::Added),
+        diff.count(ChangeKind::Removed)
+    );
+    Ok(())
+}
+
+/// The `iso verify` CLI command.
+fn command_verify(ctx: Context, args: VerifyArgs) -> Result<()> {
+    let mut ctx = ctx.open_read()?;
+    info!("Verifying files");
+    let report = verify_disc(&mut ctx)?;
+    let mut out = BufWriter::new(OutputRedirect::new(args.output)?);
+    if args.json {
+        serde_json::to_writer_pretty(&mut out, &report)?;
+        writeln!(out)?;
+    } else {
+        for (path, status) in &report.files {
+            writeln!(out, "{:<8} {}", status, path)?;
+        }
+    }
+    out.flush()?;
+    if report.is_clean() {
+        info!("All {} files match a clean copy of the game", report.checked);
+    } else {
+        warn!(
+            "{} modified, {} missing, {} corrupt, {} added",
+            report.count(FileStatus::Modified),
+            report.count(FileStatus::Missing),
+            report.count(FileStatus::Corrupt),
+            report.count(FileStatus::Added)
+        );
+    }
+    Ok(())
+}
+
 /// The `iso` CLI command.
 pub fn command(ctx: Context, command: Subcommand) -> Result<()> {
     match command {
@@ -134,8 +818,79 @@ pub fn command(ctx: Context, command: Subcommand) -> Result<()> {
         Subcommand::List(args) => command_list(ctx, args),
         Subcommand::Extract(args) => command_extract(ctx, args),
         Subcommand::ExtractAll(args) => command_extract_all(ctx, args),
+        Subcommand::Build(args) => command_build(args),
+        Subcommand::Compact(args) => command_compact(ctx, args),
+        Subcommand::Scrub(args) => command_scrub(ctx, args),
+        Subcommand::Convert(args) => command_convert(ctx, args),
         Subcommand::Replace(args) => command_replace(ctx, args),
+        Subcommand::Add(args) => command_add(ctx, args),
+        Subcommand::Remove(args) => command_remove(ctx, args),
+        Subcommand::Move(args) => command_move(ctx, args),
         Subcommand::Set(SetCommand::Maker { name }) => command_set_maker(ctx, name),
         Subcommand::Set(SetCommand::Name { name }) => command_set_name(ctx, name),
+        Subcommand::Banner(BannerCommand::Export { output }) => command_banner_export(ctx, &output),
+        Subcommand::Banner(BannerCommand::Import { path }) => command_banner_import(ctx, &path),
+        Subcommand::Banner(BannerCommand::ExportInfo { compact, output }) => {
+            command_banner_export_info(ctx, compact, output)
+        }
+        Subcommand::Banner(BannerCommand::ImportInfo { path }) => {
+            command_banner_import_info(ctx, &path)
+        }
+        Subcommand::Header(HeaderCommand::Export { compact, output }) => {
+            command_header_export(ctx, compact, output)
+        }
+        Subcommand::Header(HeaderCommand::Import { path }) => command_header_import(ctx, &path),
+        Subcommand::PatchCodes(args) => command_patch_codes(ctx, args),
+        Subcommand::Disassemble(args) => command_disassemble(ctx, args),
+        Subcommand::Diff(args) => command_diff(args),
+        Subcommand::Verify(args) => command_verify(ctx, args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_model(len: usize) -> GameInfoModel {
+        GameInfoModel {
+            name_short: "a".repeat(len.min(0x1f)),
+            maker_short: "b".repeat(len.min(0x1f)),
+            name_long: "c".repeat(len.min(0x3f)),
+            maker_long: "d".repeat(len.min(0x3f)),
+            description: "e".repeat(len.min(0x7f)),
+        }
+    }
+
+    #[test]
+    fn test_game_info_model_to_info() -> Result<()> {
+        let info = make_model(usize::MAX).to_info(0)?;
+        assert_eq!(info.name_short.decode()?, "a".repeat(0x1f));
+        assert_eq!(info.maker_short.decode()?, "b".repeat(0x1f));
+        assert_eq!(info.name_long.decode()?, "c".repeat(0x3f));
+        assert_eq!(info.maker_long.decode()?, "d".repeat(0x3f));
+        assert_eq!(info.description.decode()?, "e".repeat(0x7f));
+
+        let info = make_model(0).to_info(0)?;
+        assert_eq!(info.name_short.decode()?, "");
+        assert_eq!(info.maker_short.decode()?, "");
+        assert_eq!(info.name_long.decode()?, "");
+        assert_eq!(info.maker_long.decode()?, "");
+        assert_eq!(info.description.decode()?, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_game_info_model_to_info_too_long() {
+        let error = |model: GameInfoModel| model.to_info(1).unwrap_err().to_string();
+        let model = GameInfoModel { name_short: "a".repeat(0x20), ..make_model(usize::MAX) };
+        assert!(error(model).starts_with("Language 1 name_short: "));
+        let model = GameInfoModel { maker_short: "b".repeat(0x20), ..make_model(usize::MAX) };
+        assert!(error(model).starts_with("Language 1 maker_short: "));
+        let model = GameInfoModel { name_long: "c".repeat(0x40), ..make_model(usize::MAX) };
+        assert!(error(model).starts_with("Language 1 name_long: "));
+        let model = GameInfoModel { maker_long: "d".repeat(0x40), ..make_model(usize::MAX) };
+        assert!(error(model).starts_with("Language 1 maker_long: "));
+        let model = GameInfoModel { description: "e".repeat(0x80), ..make_model(usize::MAX) };
+        assert!(error(model).starts_with("Language 1 description: "));
     }
 }
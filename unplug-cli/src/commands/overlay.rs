@@ -0,0 +1,70 @@
+use crate::args::overlay::*;
+
+use crate::context::Context;
+use crate::overlay::OverlayDisc;
+use crate::terminal::ask_yes_no;
+use anyhow::{bail, Result};
+use log::info;
+
+/// The `overlay status` CLI command.
+fn command_status(ctx: Context) -> Result<()> {
+    let (base, dir) = ctx.into_overlay_paths()?;
+    let overlay = OverlayDisc::open(&base, &dir)?;
+    let manifest = overlay.manifest();
+    if manifest.is_empty() {
+        info!("The overlay has no changes");
+        return Ok(());
+    }
+    for path in &manifest.removed {
+        println!("removed   {}", path);
+    }
+    for path in &manifest.dirs {
+        println!("added     {}/", path);
+    }
+    for path in manifest.files.keys() {
+        let status = if overlay.base().files().at(path).is_ok() { "modified" } else { "added" };
+        println!("{:<9} {}", status, path);
+    }
+    Ok(())
+}
+
+/// The `overlay discard` CLI command.
+fn command_discard(ctx: Context, args: DiscardArgs) -> Result<()> {
+    let (base, dir) = ctx.into_overlay_paths()?;
+    let mut overlay = OverlayDisc::open(&base, &dir)?;
+    if !overlay.is_modified() {
+        info!("The overlay has no changes");
+        return Ok(());
+    }
+    if !args.force {
+        print!("This will irreversibly delete all changes in the overlay! Continue (y/n)? ");
+        if !ask_yes_no() {
+            println!("Canceled.");
+            return Ok(());
+        }
+    }
+    overlay.discard()?;
+    info!("Discarded all changes");
+    Ok(())
+}
+
+/// The `overlay bake` CLI command.
+fn command_bake(ctx: Context, args: BakeArgs) -> Result<()> {
+    if !args.force && args.output.exists() {
+        bail!("The output file already exists (use --force to overwrite)");
+    }
+    let (base, dir) = ctx.into_overlay_paths()?;
+    let overlay = OverlayDisc::open(&base, &dir)?;
+    overlay.bake(&args.output)?;
+    info!("Wrote {}", args.output.display());
+    Ok(())
+}
+
+/// The `overlay` CLI command.
+pub fn command(ctx: Context, command: Subcommand) -> Result<()> {
+    match command {
+        Subcommand::Status => command_status(ctx),
+        Subcommand::Discard(args) => command_discard(ctx, args),
+        Subcommand::Bake(args) => command_bake(ctx, args),
+    }
+}
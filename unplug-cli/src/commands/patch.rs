@@ -0,0 +1,314 @@
+use crate::args::patch::*;
+
+use crate::common::FileHash;
+use crate::config::Config;
+use crate::context::{Context, OpenContext};
+use crate::fst::file_paths;
+use crate::io::copy_into_memory;
+use crate::verify::warn_if_unclean;
+use anyhow::{bail, Result};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor};
+use std::path::{Path, PathBuf};
+use unplug::common::ReadSeek;
+use unplug::dvd::{ArchiveBuilder, ArchiveReader, OpenFile};
+
+/// The version of the patch format which is written and accepted.
+const PATCH_VERSION: u32 = 1;
+/// Path to the manifest within a patch archive.
+const MANIFEST_PATH: &str = "patch.json";
+/// Path to the directory containing file data within a patch archive.
+const DATA_DIR: &str = "files";
+
+/// Path to qp.bin within the disc.
+const QP_PATH: &str = "qp.bin";
+/// Context path prefix for disc files.
+const DVD_PREFIX: &str = "dvd";
+/// Context path prefix for files in qp.bin.
+const QP_PREFIX: &str = "qp";
+
+/// A file which a patch writes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PatchFile {
+    /// The context path of the file, e.g. `dvd:foo.bin` or `qp:bin/e/ahk.bin`.
+    path: String,
+    /// The file's hash on the base disc, or `None` if the patch adds it.
+    base: Option<FileHash>,
+    /// The file's hash after the patch is applied.
+    hash: FileHash,
+}
+
+/// Describes the contents of a patch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    version: u32,
+    name: String,
+    /// The ID of the game the patch was created from.
+    game_id: String,
+    /// The files in the patch. The data for each file is stored in the archive under `DATA_DIR`
+    /// using its index in this list as the filename.
+    files: Vec<PatchFile>,
+}
+
+/// An opened patch file.
+struct Patch {
+    manifest: Manifest,
+    archive: ArchiveReader<File>,
+}
+
+impl Patch {
+    /// Opens the patch file at `path`.
+    fn open(path: &Path) -> Result<Self> {
+        let mut archive = ArchiveReader::open(File::open(path)?)?;
+        let reader = BufReader::new(archive.open_file_at(MANIFEST_PATH)?);
+        let manifest: Manifest = serde_json::from_reader(reader)?;
+        if manifest.version != PATCH_VERSION {
+            bail!("{} has an unsupported version ({})", path.display(), manifest.version);
+        }
+        Ok(Self { manifest, archive })
+    }
+}
+
+/// Returns the path to the data for the file at `index` in a patch.
+fn data_path(index: usize) -> String {
+    format!("{}/{}", DATA_DIR, index)
+}
+
+/// Hashes the file at the context path `path`, returning `None` if the file does not exist.
+fn hash_file<T: ReadSeek>(ctx: &mut OpenContext<T>, path: &str) -> Result<Option<FileHash>> {
+    let (prefix, inner) = path.split_once(':').unwrap_or_default();
+    let exists = match prefix {
+        DVD_PREFIX => ctx.disc_files()?.at(inner).is_ok(),
+        QP_PREFIX => ctx.qp_files()?.at(inner).is_ok(),
+        _ => bail!("Invalid patch file path: {}", path),
+    };
+    if !exists {
+        return Ok(None);
+    }
+    Ok(Some(FileHash::of(ctx.open_file_at(path)?)?))
+}
+
+/// Scans the files in `prefix` for ones which changed between `base` and `modded`. Changes which
+/// a patch cannot represent are logged and counted in `unsupported`.
+fn find_changes<T: ReadSeek>(
+    base: &mut OpenContext<T>,
+    modded: &mut OpenContext<T>,
+    prefix: &str,
+    files: &mut Vec<PatchFile>,
+    unsupported: &mut usize,
+) -> Result<()> {
+    let (base_paths, modded_paths) = match prefix {
+        QP_PREFIX => (file_paths(base.qp_files()?), file_paths(modded.qp_files()?)),
+        _ => (file_paths(base.disc_files()?), file_paths(modded.disc_files()?)),
+    };
+    for path in base_paths.difference(&modded_paths) {
+        error!("{}:{} was removed, but patches cannot remove files", prefix, path);
+        *unsupported += 1;
+    }
+    for path in &modded_paths {
+        let context_path = format!("{}:{}", prefix, path);
+        let hash = FileHash::of(modded.open_file_at(&context_path)?)?;
+        let base_hash = match base_paths.contains(path) {
+            true => Some(FileHash::of(base.open_file_at(&context_path)?)?),
+            false => None,
+        };
+        if base_hash == Some(hash) {
+            continue;
+        }
+        if prefix == DVD_PREFIX && path.eq_ignore_ascii_case(QP_PATH) && base_hash.is_some() {
+            // Patch individual files inside qp.bin so that patches which touch different files
+            // in it can be stacked
+            find_changes(base, modded, QP_PREFIX, files, unsupported)?;
+            continue;
+        }
+        if prefix == QP_PREFIX && base_hash.is_none() {
+            bail!("{} was added to qp.bin, but patches cannot add files to qp.bin", path);
+        }
+        info!("Found changes in {}", context_path);
+        files.push(PatchFile { path: context_path, base: base_hash, hash });
+    }
+    Ok(())
+}
+
+/// The `patch create` CLI command.
+fn command_create(ctx: Context, args: CreateArgs) -> Result<()> {
+    let base_path = match args.base {
+        Some(path) => path,
+        None => {
+            let config = Config::get();
+            if config.settings.default_iso.is_empty() {
+                bail!("No base ISO was given and no default ISO is configured");
+            }
+            PathBuf::from(&config.settings.default_iso)
+        }
+    };
+    let name = match args.name {
+        Some(name) => name,
+        None => args.output.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+    };
+
+    let mut base = Context::Iso(base_path).open_read()?;
+    warn_if_unclean(&mut base)?;
+    let mut modded = ctx.open_read()?;
+    let game_id = base.game_id()?;
+    if modded.game_id()? != game_id {
+        warn!("The game IDs do not match, so the patch may not work correctly");
+    }
+    let mut unsupported = 0;
+    if base.read_dol()? != modded.read_dol()? {
+        error!("main.dol was changed, but patches cannot include main.dol changes");
+        unsupported += 1;
+    }
+
+    info!("Comparing files");
+    let mut files = vec![];
+    find_changes(&mut base, &mut modded, DVD_PREFIX, &mut files, &mut unsupported)?;
+    if unsupported > 0 {
+        if !args.force {
+            bail!("The patch cannot include every change (use --force to create it anyway)");
+        }
+        warn!("Creating the patch without the changes it cannot include");
+    }
+    if files.is_empty() {
+        bail!("No files were changed");
+    }
+
+    info!("Writing {}", args.output.display());
+    let manifest = Manifest { version: PATCH_VERSION, name, game_id, files };
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    let mut builder = ArchiveBuilder::new();
+    builder.add_file_at(MANIFEST_PATH, move || Ok(Cursor::new(manifest_json)))?;
+    builder.add_dir_at(DATA_DIR)?;
+    for (i, file) in manifest.files.iter().enumerate() {
+        let data = copy_into_memory(modded.open_file_at(&file.path)?)?;
+        builder.add_file_at(&data_path(i), move || Ok(data))?;
+    }
+    builder.write_to(BufWriter::new(File::create(&args.output)?))?;
+    info!("Created patch with {} file(s)", manifest.files.len());
+    Ok(())
+}
+
+/// Checks that no two patches write different data to the same file.
+fn check_stacking(paths: &[PathBuf], patches: &[Patch]) -> Result<()> {
+    let mut writers: HashMap<&str, (usize, FileHash)> = HashMap::new();
+    let mut conflicts = 0;
+    for (i, patch) in patches.iter().enumerate() {
+        for file in &patch.manifest.files {
+            match writers.get(file.path.as_str()) {
+                Some(&(other, hash)) if hash != file.hash => {
+                    error!(
+                        "{} and {} both change {}",
+                        paths[other].display(),
+                        paths[i].display(),
+                        file.path
+                    );
+                    conflicts += 1;
+                }
+                Some(_) => (),
+                None => {
+                    writers.insert(&file.path, (i, file.hash));
+                }
+            }
+        }
+    }
+    if conflicts > 0 {
+        bail!("The patches conflict with each other and cannot be applied together");
+    }
+    Ok(())
+}
+
+/// The `patch apply` CLI command.
+fn command_apply(ctx: Context, args: ApplyArgs) -> Result<()> {
+    let mut patches = vec![];
+    for path in &args.patches {
+        info!("Reading {}", path.display());
+        patches.push(Patch::open(path)?);
+    }
+    check_stacking(&args.patches, &patches)?;
+
+    let mut ctx = ctx.open_read_write()?;
+    let game_id = ctx.game_id()?;
+
+    info!("Checking files");
+    let mut pending = vec![];
+    let mut seen = HashSet::new();
+    let mut conflicts = 0;
+    for (p, patch) in patches.iter().enumerate() {
+        let manifest = &patch.manifest;
+        if manifest.game_id != game_id {
+            error!("{} is for {}, but the ISO is {}", manifest.name, manifest.game_id, game_id);
+            conflicts += 1;
+        }
+        for (i, file) in manifest.files.iter().enumerate() {
+            if !seen.insert(file.path.as_str()) {
+                // Another patch already writes the same data
+                continue;
+            }
+            let current = hash_file(&mut ctx, &file.path)?;
+            if current == Some(file.hash) {
+                info!("{} is already up-to-date", file.path);
+            } else if current == file.base {
+                pending.push((p, i));
+            } else {
+                error!("{} does not match the version {} expects", file.path, manifest.name);
+                conflicts += 1;
+                pending.push((p, i));
+            }
+        }
+    }
+    if conflicts > 0 {
+        if !args.force {
+            bail!("The patches conflict with the current files (use --force to apply anyway)");
+        }
+        warn!("Applying patches despite conflicts");
+    }
+    if pending.is_empty() {
+        info!("All files are already up-to-date");
+        return Ok(());
+    }
+
+    // Adding files takes effect immediately, so everything is done in one transaction to avoid
+    // leaving the disc half-patched if something fails
+    ctx.transaction(|ctx| {
+        info!("Updating game files");
+        let mut updates = vec![];
+        for (p, i) in pending {
+            let patch = &mut patches[p];
+            let path = &patch.manifest.files[i].path;
+            let data = copy_into_memory(patch.archive.open_file_at(&data_path(i))?)?;
+            match path.strip_prefix("dvd:") {
+                Some(disc_path) if ctx.disc_files()?.at(disc_path).is_err() => {
+                    info!("Adding {}", path);
+                    for (index, _) in disc_path.match_indices('/') {
+                        let dir = &disc_path[..index];
+                        if ctx.disc_files()?.at(dir).is_err() {
+                            ctx.create_disc_dir_at(dir)?;
+                        }
+                    }
+                    ctx.create_disc_file_at(disc_path, data)?;
+                }
+                _ => {
+                    info!("Updating {}", path);
+                    updates.push((path.clone(), data));
+                }
+            }
+        }
+        let mut update = ctx.begin_update();
+        for (path, data) in updates {
+            update = update.write_file_at(&path, data)?;
+        }
+        update.commit()
+    })
+}
+
+/// The `patch` CLI command.
+pub fn command(ctx: Context, command: Subcommand) -> Result<()> {
+    match command {
+        Subcommand::Create(args) => command_create(ctx, args),
+        Subcommand::Apply(args) => command_apply(ctx, args),
+    }
+}
@@ -4,6 +4,7 @@ use crate::common::IString;
 use crate::config::{Config, Project, ProjectKind};
 use crate::context::Context;
 use crate::terminal::ask_yes_no;
+use crate::verify::warn_if_unclean;
 use anyhow::{anyhow, bail, Result};
 use log::info;
 use std::fs;
@@ -24,7 +25,12 @@ fn command_info(args: InfoArgs) -> Result<()> {
             }
         }
     };
-    println!("{} ({} at {})", name, project.kind, project.path);
+    match project.kind {
+        ProjectKind::Iso => println!("{} ({} at {})", name, project.kind, project.path),
+        ProjectKind::Overlay => {
+            println!("{} ({} at {} on {})", name, project.kind, project.path, project.base);
+        }
+    }
     Ok(())
 }
 
@@ -51,9 +57,10 @@ fn command_new(args: NewArgs) -> Result<()> {
         }
         None => bail!("No default ISO is configured. Use `config set default-iso <PATH>`."),
     };
+    let extension = if args.overlay { "overlay" } else { "iso" };
     let dest = match args.output {
         Some(o) => o.canonicalize()?,
-        None => source.with_file_name(format!("{}.iso", args.name)),
+        None => source.with_file_name(format!("{}.{}", args.name, extension)),
     };
 
     info!("Source path: {}", source.display());
@@ -61,12 +68,22 @@ fn command_new(args: NewArgs) -> Result<()> {
     if source == dest {
         bail!("The source and destination paths cannot be the same");
     }
+    warn_if_unclean(&mut Context::Iso(source.clone()).open_read()?)?;
     if !args.force && dest.exists() {
         bail!("The destination file already exists (use --force to overwrite)");
     }
-    fs::copy(&source, &dest)?;
-
-    let project = Project { kind: ProjectKind::Iso, path: dest.to_string_lossy().into_owned() };
+    let path = dest.to_string_lossy().into_owned();
+    let project = if args.overlay {
+        // The base ISO is left untouched and the overlay directory is created on the first write
+        if dest.exists() {
+            fs::remove_dir_all(&dest)?;
+        }
+        let base = source.to_string_lossy().into_owned();
+        Project { kind: ProjectKind::Overlay, path, base }
+    } else {
+        fs::copy(&source, &dest)?;
+        Project { kind: ProjectKind::Iso, path, ..Default::default() }
+    };
     config.projects.insert(project_key, project);
     let open = !args.no_open;
     if open {
@@ -93,8 +110,18 @@ fn command_wipe(args: WipeArgs) -> Result<()> {
         }
     }
 
-    info!("Deleting file: {}", project.path);
-    fs::remove_file(&project.path)?;
+    match project.kind {
+        ProjectKind::Iso => {
+            info!("Deleting file: {}", project.path);
+            fs::remove_file(&project.path)?;
+        }
+        ProjectKind::Overlay => {
+            info!("Deleting directory: {}", project.path);
+            if Path::new(&project.path).exists() {
+                fs::remove_dir_all(&project.path)?;
+            }
+        }
+    }
 
     let project_key = IString::new(name);
     config.projects.remove_entry(&project_key);
@@ -130,8 +157,11 @@ fn command_add(args: AddArgs) -> Result<()> {
     if config.projects.contains_key(&key) {
         bail!("Project \"{}\" is already defined", name);
     }
-    let project =
-        Project { kind: ProjectKind::Iso, path: args.path.to_string_lossy().into_owned() };
+    let project = Project {
+        kind: ProjectKind::Iso,
+        path: args.path.to_string_lossy().into_owned(),
+        ..Default::default()
+    };
     config.projects.insert(key, project);
     config.save()?;
     info!("Added project: {}", name);
@@ -235,6 +235,12 @@ fn write_stage(stage: Stage, out: impl Write) -> Result<()> {
     Ok(())
 }
 
+/// Converts a stage's object placements into the JSON structure used by `stage export`.
+pub fn stage_objects_json(stage: &Stage) -> Result<serde_json::Value> {
+    let root = StageDef { objects: ObjectPlacementWrapper::wrap(stage.objects.iter().cloned()) };
+    Ok(serde_json::to_value(root)?)
+}
+
 /// The `stage` CLI command.
 pub fn command(ctx: Context, command: Subcommand) -> Result<()> {
     match command {
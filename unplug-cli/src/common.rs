@@ -1,10 +1,14 @@
 use crate::context::{FileId, OpenContext};
 use anyhow::{bail, Result};
+use seahash::SeaHasher;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::{self, Display};
+use std::hash::Hasher;
+use std::io::{self, Read};
 use std::path::Path;
 use std::time::Duration;
 use unicase::UniCase;
+use unplug::common::io::BUFFER_SIZE;
 use unplug::common::ReadSeek;
 use unplug::data::{Resource, Stage};
 
@@ -89,6 +93,56 @@ pub fn output_dir_and_name(output: Option<&Path>, multiple_items: bool) -> (&Pat
     }
 }
 
+/// Serialize/Deserialize implementation for hashes which stores them as hex strings.
+mod hex_hash {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(hash: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:016x}", hash))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        u64::from_str_radix(&hex, 16)
+            .map_err(|_| D::Error::custom(format!("invalid hash: {}", hex)))
+    }
+}
+
+/// The size and hash of a file's contents.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileHash {
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The SeaHash of the file's contents.
+    #[serde(with = "hex_hash")]
+    pub hash: u64,
+}
+
+impl FileHash {
+    /// Reads all of the data from `reader` and hashes it.
+    pub fn of(mut reader: impl Read) -> io::Result<Self> {
+        let mut hasher = SeaHasher::new();
+        let mut buf = vec![0; BUFFER_SIZE];
+        let mut size = 0;
+        loop {
+            let len = reader.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            hasher.write(&buf[..len]);
+            size += len as u64;
+        }
+        Ok(Self { size, hash: hasher.finish() })
+    }
+}
+
+impl Display for FileHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.hash)
+    }
+}
+
 /// A case-insensitive string wrapper with support for serde.
 #[derive(Debug, Default, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct IString(UniCase<String>);
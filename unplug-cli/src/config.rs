@@ -138,12 +138,15 @@ pub enum ProjectKind {
     /// The project is a .iso file.
     #[default]
     Iso,
+    /// The project is an overlay directory on top of a read-only .iso file.
+    Overlay,
 }
 
 impl Display for ProjectKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             Self::Iso => f.write_str("ISO"),
+            Self::Overlay => f.write_str("overlay"),
         }
     }
 }
@@ -156,6 +159,9 @@ pub struct Project {
     pub kind: ProjectKind,
     /// The path to the project file(s).
     pub path: String,
+    /// For overlay projects, the path to the base ISO.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub base: String,
 }
 
 /// Attempts to load the `Context` for a project, returning `Ok(None)` if no project is open.
@@ -165,9 +171,16 @@ pub fn load_project(config: &Config, name: Option<&str>) -> Result<Option<Contex
         return Ok(None);
     }
     match config.find_project(project_name) {
-        Ok((name, project)) => Ok(Some(Context::ProjectIso {
-            name: name.to_owned(),
-            path: Path::new(&project.path).to_owned(),
+        Ok((name, project)) => Ok(Some(match project.kind {
+            ProjectKind::Iso => Context::ProjectIso {
+                name: name.to_owned(),
+                path: Path::new(&project.path).to_owned(),
+            },
+            ProjectKind::Overlay => Context::ProjectOverlay {
+                name: name.to_owned(),
+                base: Path::new(&project.base).to_owned(),
+                path: Path::new(&project.path).to_owned(),
+            },
         })),
         Err(e) if name.is_some() => Err(e),
         _ => {
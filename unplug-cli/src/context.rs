@@ -1,20 +1,27 @@
 use crate::io::{copy_into_memory, MemoryCursor};
-use anyhow::{anyhow, Error, Result};
+use crate::journal::{self, JournalHandle};
+use crate::overlay::OverlayDisc;
+use anyhow::{anyhow, bail, Error, Result};
 use lazy_static::lazy_static;
 use log::{debug, error, info, warn};
 use regex::Regex;
+use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::fs::{self, File, OpenOptions};
-use std::io::{BufReader, Cursor, Seek, Write};
+use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, MutexGuard};
 use tempfile::NamedTempFile;
 use unplug::audio::metadata::SfxPlaylist;
 use unplug::audio::transport::{HpsReader, SfxBank};
 use unplug::common::io::{copy_buffered, BUFFER_SIZE};
 use unplug::common::{ReadFrom, ReadSeek, ReadWriteSeek, WriteTo};
 use unplug::data::{Music, Sfx, SfxGroup, Stage as StageId};
-use unplug::dvd::{ArchiveBuilder, ArchiveReader, DiscStream, EntryId, FileTree, OpenFile};
+use unplug::dvd::image::{open_image, write_image};
+use unplug::dvd::{
+    ArchiveBuilder, ArchiveReader, DiscStream, Dol, EntryId, FileTree, ImageFormat, OpenFile,
+};
 use unplug::globals::{GlobalsBuilder, GlobalsReader, Libs};
 use unplug::stage::Stage;
 
@@ -41,6 +48,9 @@ fn dir_error(path: &str) -> Error {
 /// - `dvd:` - Relative to the disc
 /// - `file:` - Absolute or relative local file path
 /// - `qp:` - Relative to qp.bin
+///
+/// A path can pass through U8 archives by naming the archive as if it were a directory, e.g.
+/// `dvd:some.arc/inner/file.dat`. Archives can be nested inside each other to any depth.
 enum ContextPath<'a> {
     Dvd(&'a str),
     File(&'a str),
@@ -63,17 +73,259 @@ impl<'a> ContextPath<'a> {
     }
 }
 
+/// Opens the disc image at `path` for read access. Compressed images are decoded on the fly.
+pub fn open_disc_read(path: &Path) -> Result<DiscStream<Box<dyn ReadSeek>>> {
+    if journal::journal_path(path).exists() {
+        // The journal can only be recovered if no other process is still editing the image, which
+        // opening it for writing checks for
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        drop(JournalHandle::open(path, file)?);
+    }
+    let (format, stream) = open_image(File::open(path)?)?;
+    debug!("Image format: {}", format);
+    Ok(DiscStream::open(stream)?)
+}
+
+/// Splits `path` at the first component which names a file in `tree`. Returns the path of the file
+/// and the path remaining inside it, or `None` if `path` is an entry in the tree or does not pass
+/// through a file.
+fn split_archive_path<'p>(tree: &FileTree, path: &'p str) -> Option<(&'p str, &'p str)> {
+    if tree.at(path).is_ok() {
+        return None;
+    }
+    path.match_indices(['/', '\\'])
+        .map(|(i, _)| (&path[..i], &path[(i + 1)..]))
+        .find(|&(archive, _)| tree.at(archive).is_ok_and(|e| tree[e].is_file()))
+}
+
+/// A disc stream opened for read and write access.
+pub type WritableDisc = DiscStream<Box<dyn ReadWriteSeek>>;
+
+/// Opens the disc image at `path` for read and write access. Writes only become permanent when
+/// they are committed through the returned `EditHandle`.
+pub fn open_disc_read_write(path: &Path) -> Result<(WritableDisc, EditHandle)> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let format = ImageFormat::detect(&mut file)?;
+    debug!("Image format: {}", format);
+    let (stream, handle): (Box<dyn ReadWriteSeek>, _) = match format {
+        ImageFormat::Iso => {
+            let journal = JournalHandle::open(path, file)?;
+            (Box::from(journal.clone()), EditHandle::Journaled(journal))
+        }
+        _ if format.is_writable() => {
+            let image = DecodedImage::decode(path, file, format)?;
+            (Box::from(image.clone()), EditHandle::Decoded(image))
+        }
+        _ => bail!("{} images cannot be edited (use `iso convert` to convert it first)", format),
+    };
+    Ok((DiscStream::open(stream)?, handle))
+}
+
+/// Opens the disc image at `path` for read and write access and edits it with `f` in a single
+/// transaction. If `f` fails, nothing it wrote is kept.
+pub fn edit_disc<R>(path: &Path, f: impl FnOnce(&mut WritableDisc) -> Result<R>) -> Result<R> {
+    let (mut disc, handle) = open_disc_read_write(path)?;
+    handle.begin();
+    match f(&mut disc) {
+        Ok(result) => {
+            handle.commit()?;
+            Ok(result)
+        }
+        Err(e) => {
+            warn!("Rolling back changes to the disc");
+            handle.rollback()?;
+            Err(e)
+        }
+    }
+}
+
+/// A handle to a disc image which is open for editing. This controls when writes to the image
+/// become permanent. Clones of a handle refer to the same image.
+#[derive(Clone)]
+pub enum EditHandle {
+    /// A raw ISO which is written in place. Writes are journaled so that an interrupted
+    /// transaction can be rolled back.
+    Journaled(JournalHandle),
+    /// A compressed image which was decoded into a temporary file. Writes go to the temporary file
+    /// and the image is only rewritten when a transaction is committed.
+    Decoded(DecodedImage),
+}
+
+impl EditHandle {
+    /// Begins a transaction.
+    pub fn begin(&self) {
+        match self {
+            Self::Journaled(journal) => journal.begin(),
+            Self::Decoded(_) => (),
+        }
+    }
+
+    /// Ends the current transaction and makes everything written during it permanent.
+    pub fn commit(&self) -> Result<()> {
+        match self {
+            Self::Journaled(journal) => journal.commit(),
+            Self::Decoded(image) => image.encode(),
+        }
+    }
+
+    /// Ends the current transaction and throws away everything written during it.
+    pub fn rollback(&self) -> Result<()> {
+        match self {
+            Self::Journaled(journal) => journal.rollback(),
+            Self::Decoded(image) => image.revert(),
+        }
+    }
+}
+
 /// Opens the iso at `path` for read access.
 fn open_iso_read(path: &Path) -> Result<DiscSource<Box<dyn ReadSeek>>> {
-    let file: Box<dyn ReadSeek> = Box::from(File::open(path)?);
-    Ok(DiscSource::Iso(DiscStream::open(file)?.into()))
+    Ok(DiscSource::Iso(open_disc_read(path)?.into()))
+}
+
+/// A disc source opened for read and write access.
+type WritableSource = DiscSource<Box<dyn ReadWriteSeek>>;
+
+/// Opens the iso at `path` for read and write access, along with its edit handle.
+fn open_iso_read_write(path: &Path) -> Result<(WritableSource, Option<EditHandle>)> {
+    let (disc, handle) = open_disc_read_write(path)?;
+    Ok((DiscSource::Iso(disc.into()), Some(handle)))
+}
+
+/// Opens the overlay in `dir` on top of the iso at `base`.
+fn open_overlay<T: ReadSeek>(base: &Path, dir: &Path) -> Result<DiscSource<T>> {
+    Ok(DiscSource::Overlay(OverlayDisc::open(base, dir)?.into()))
+}
+
+fn overlay_edit_error() -> Error {
+    anyhow!("This command cannot edit overlay projects directly. Use `overlay bake` first.")
+}
+
+fn overlay_iso_error() -> Error {
+    anyhow!("This command needs an ISO and cannot read overlay projects. Use `overlay bake` first.")
+}
+
+/// A compressed disc image which has been decoded into a temporary file so that it can be edited.
+/// Changes are only written back to the original image by `encode()`, and the temporary file is
+/// deleted once every handle to it is dropped. Clones of a handle share the same file.
+#[derive(Clone)]
+pub struct DecodedImage {
+    inner: Arc<Mutex<DecodedFile>>,
+}
+
+struct DecodedFile {
+    /// The temporary file holding the decoded disc data.
+    file: NamedTempFile,
+    /// The path to the original image.
+    path: PathBuf,
+    /// The format of the original image.
+    format: ImageFormat,
+    /// True if the decoded data has been written to since it was last encoded.
+    dirty: bool,
+    /// True if the decoded data is incomplete and must not be used.
+    discarded: bool,
+}
+
+impl DecodedImage {
+    /// Decodes the `format` image in `image`, which was opened from `path`.
+    fn decode(path: &Path, image: File, format: ImageFormat) -> Result<Self> {
+        info!("Decoding {} image", format);
+        let mut file = NamedTempFile::new()?;
+        debug!("Decoding to {}", file.path().display());
+        decode_to(image, file.as_file_mut())?;
+        let inner =
+            DecodedFile { file, path: path.to_owned(), format, dirty: false, discarded: false };
+        Ok(Self { inner: Arc::new(Mutex::new(inner)) })
+    }
+
+    /// Encodes the decoded data back into the original image if it has been written to. A new
+    /// image is written next to the original one and then moved over it so that a failure cannot
+    /// corrupt it.
+    pub fn encode(&self) -> Result<()> {
+        let mut inner = self.lock();
+        if inner.discarded {
+            bail!("Changes to {} were discarded", inner.path.display());
+        }
+        if !inner.dirty {
+            return Ok(());
+        }
+        info!("Encoding {} image", inner.format);
+        let inner = &mut *inner;
+        let pos = inner.file.stream_position()?;
+        let size = inner.file.seek(SeekFrom::End(0))?;
+        inner.file.rewind()?;
+        let dir = inner
+            .path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let mut temp = NamedTempFile::new_in(dir)?;
+        let mut reader = BufReader::new(inner.file.as_file_mut());
+        write_image(inner.format, &mut reader, size, BufWriter::new(temp.as_file_mut()))?;
+        fs::set_permissions(temp.path(), fs::metadata(&inner.path)?.permissions())?;
+        temp.persist(&inner.path)?;
+        inner.file.seek(SeekFrom::Start(pos))?;
+        inner.dirty = false;
+        Ok(())
+    }
+
+    /// Throws away everything which was written to the decoded data since it was last encoded by
+    /// decoding the original image again. If this fails, the decoded data can't be used anymore.
+    pub fn revert(&self) -> Result<()> {
+        let mut inner = self.lock();
+        if !inner.dirty {
+            return Ok(());
+        }
+        warn!("Discarding changes to {}", inner.path.display());
+        inner.discarded = true;
+        let image = File::open(&inner.path)?;
+        let file = inner.file.as_file_mut();
+        file.set_len(0)?;
+        file.rewind()?;
+        decode_to(image, file)?;
+        inner.dirty = false;
+        inner.discarded = false;
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DecodedFile> {
+        self.inner.lock().unwrap()
+    }
+}
+
+/// Decodes the disc image in `image` into `file`.
+fn decode_to(image: File, file: &mut File) -> Result<()> {
+    let (_, mut stream) = open_image(image)?;
+    let mut buf = vec![0u8; BUFFER_SIZE].into_boxed_slice();
+    copy_buffered(&mut stream, file, &mut buf)?;
+    file.rewind()?;
+    Ok(())
+}
+
+impl Read for DecodedImage {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.lock().file.read(buf)
+    }
+}
+
+impl Write for DecodedImage {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inner = self.lock();
+        if inner.discarded {
+            return Err(io::Error::other("the disc image was discarded"));
+        }
+        inner.dirty = true;
+        inner.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().file.flush()
+    }
 }
 
-/// Opens the iso at `path` for read and write access.
-fn open_iso_read_write(path: &Path) -> Result<DiscSource<Box<dyn ReadWriteSeek>>> {
-    let file: Box<dyn ReadWriteSeek> =
-        Box::from(OpenOptions::new().read(true).write(true).open(path)?);
-    Ok(DiscSource::Iso(DiscStream::open(file)?.into()))
+impl Seek for DecodedImage {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.lock().file.seek(pos)
+    }
 }
 
 /// The context which a command is run in.
@@ -88,6 +340,9 @@ pub enum Context {
     DefaultIso(PathBuf),
     /// The command has access to files inside a project ISO.
     ProjectIso { name: String, path: PathBuf },
+    /// The command has access to files inside a project which stores changes in an overlay
+    /// directory at `path` on top of the read-only ISO at `base`.
+    ProjectOverlay { name: String, base: PathBuf, path: PathBuf },
 }
 
 impl Context {
@@ -113,28 +368,48 @@ impl Context {
                 info!("Opening ISO: {} ({})", name, path.display());
                 open_iso_read(&path)?
             }
+            Self::ProjectOverlay { name, base, path } => {
+                info!("Opening overlay: {} ({} on {})", name, path.display(), base.display());
+                open_overlay(&base, &path)?
+            }
         };
         Ok(OpenContext::new(disc))
     }
 
     /// Opens the context files for reading and writing.
     pub fn open_read_write(self) -> Result<OpenContext<Box<dyn ReadWriteSeek>>> {
-        let disc = match self {
-            Self::Local => DiscSource::None,
+        let (disc, handle) = match self {
+            Self::Local => (DiscSource::None, None),
             Self::Iso(path) => {
                 info!("Opening ISO: {}", path.display());
                 open_iso_read_write(&path)?
             }
             Self::DefaultIso(_) => {
                 warn!("Editing commands do not load the default ISO, as a precaution");
-                DiscSource::None
+                (DiscSource::None, None)
             }
             Self::ProjectIso { name, path } => {
                 info!("Opening ISO: {} ({})", name, path.display());
                 open_iso_read_write(&path)?
             }
+            Self::ProjectOverlay { name, base, path } => {
+                info!("Opening overlay: {} ({} on {})", name, path.display(), base.display());
+                (open_overlay(&base, &path)?, None)
+            }
         };
-        Ok(OpenContext::new(disc))
+        Ok(OpenContext { handle, ..OpenContext::new(disc) })
+    }
+
+    /// Requires the context to be an ISO which can be edited and returns its path.
+    pub fn into_editable_iso_path(self) -> Result<PathBuf> {
+        match self {
+            Self::DefaultIso(_) => {
+                warn!("Editing commands do not load the default ISO, as a precaution");
+                Err(no_disc_error())
+            }
+            Self::ProjectOverlay { .. } => Err(overlay_edit_error()),
+            _ => self.into_iso_path(),
+        }
     }
 
     /// Requires the context to be an ISO and returns its path.
@@ -153,6 +428,19 @@ impl Context {
                 info!("Using ISO: {} ({})", name, path.display());
                 Ok(path)
             }
+            Context::ProjectOverlay { .. } => Err(overlay_iso_error()),
+        }
+    }
+
+    /// Requires the context to be an overlay project and returns the paths to its base ISO and
+    /// overlay directory.
+    pub fn into_overlay_paths(self) -> Result<(PathBuf, PathBuf)> {
+        match self {
+            Context::ProjectOverlay { name, base, path } => {
+                info!("Using overlay: {} ({} on {})", name, path.display(), base.display());
+                Ok((base, path))
+            }
+            _ => Err(anyhow!("The current project is not an overlay project")),
         }
     }
 }
@@ -163,6 +451,8 @@ enum DiscSource<T: ReadSeek> {
     None,
     /// Disc data is stored in a .iso.
     Iso(Box<DiscStream<T>>),
+    /// Disc data is stored in an overlay on top of a read-only .iso.
+    Overlay(Box<OverlayDisc>),
 }
 
 impl<T: ReadSeek> DiscSource<T> {
@@ -171,6 +461,7 @@ impl<T: ReadSeek> DiscSource<T> {
         let files = match self {
             Self::None => return Err(no_disc_error()),
             Self::Iso(disc) => disc.files(),
+            Self::Overlay(disc) => disc.files(),
         };
         let entry = files.at(path)?;
         if files[entry].is_dir() {
@@ -185,6 +476,7 @@ impl<T: ReadSeek> DiscSource<T> {
             let info = match self {
                 Self::None => return Err(no_disc_error()),
                 Self::Iso(disc) => disc.files()[entry].file().unwrap(),
+                Self::Overlay(disc) => disc.files()[entry].file().unwrap(),
             };
             Ok(FileInfo { name: info.name.clone(), size: info.size.into() })
         } else {
@@ -198,6 +490,7 @@ impl<T: ReadSeek> DiscSource<T> {
             match self {
                 Self::None => Err(no_disc_error()),
                 Self::Iso(disc) => Ok(disc.open_file(entry)?),
+                Self::Overlay(disc) => disc.open_file(entry),
             }
         } else {
             panic!("cannot open a non-disc file");
@@ -212,20 +505,71 @@ impl<T: ReadWriteSeek> DiscSource<T> {
             match self {
                 Self::None => Err(no_disc_error()),
                 Self::Iso(disc) => Ok(disc.replace_file(entry, reader)?),
+                Self::Overlay(disc) => disc.replace_file(entry, reader),
             }
         } else {
             panic!("cannot write a non-disc file");
         }
     }
+
+    /// Creates a new disc file at `path` using data read from `reader`.
+    fn create_file_at(&mut self, path: &str, reader: impl ReadSeek) -> Result<EntryId> {
+        match self {
+            Self::None => Err(no_disc_error()),
+            Self::Iso(disc) => Ok(disc.create_file_at(path, reader)?),
+            Self::Overlay(disc) => disc.create_file_at(path, reader),
+        }
+    }
+
+    /// Creates a new empty disc directory at `path`.
+    fn create_dir_at(&mut self, path: &str) -> Result<()> {
+        match self {
+            Self::None => return Err(no_disc_error()),
+            Self::Iso(disc) => disc.create_dir_at(path)?,
+            Self::Overlay(disc) => disc.create_dir_at(path)?,
+        };
+        Ok(())
+    }
+
+    /// Removes the disc file or directory at `path`.
+    fn remove_at(&mut self, path: &str) -> Result<()> {
+        match self {
+            Self::None => Err(no_disc_error()),
+            Self::Iso(disc) => Ok(disc.remove_at(path)?),
+            Self::Overlay(disc) => disc.remove_at(path),
+        }
+    }
+
+    /// Moves the disc file or directory at `path` to `new_path`.
+    fn rename_at(&mut self, path: &str, new_path: &str) -> Result<()> {
+        match self {
+            Self::None => Err(no_disc_error()),
+            Self::Iso(disc) => Ok(disc.rename_at(path, new_path)?),
+            Self::Overlay(disc) => disc.rename_at(path, new_path),
+        }
+    }
 }
 
-/// An identifier for a file which resides in a .iso, qp.bin, or the local filesystem.
+/// An identifier for a file which resides in a .iso, qp.bin, the local filesystem, or an archive
+/// stored in any of those.
 #[non_exhaustive]
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub enum FileId {
     Iso(EntryId),
     Qp(EntryId),
     File(Arc<Path>),
+    /// A file inside the U8 archive identified by the first field.
+    Archive(Arc<FileId>, EntryId),
+}
+
+impl FileId {
+    /// Returns the number of archives this file is nested inside of, not counting qp.bin.
+    fn archive_depth(&self) -> usize {
+        match self {
+            Self::Archive(archive, _) => archive.archive_depth() + 1,
+            _ => 0,
+        }
+    }
 }
 
 /// File metadata.
@@ -250,11 +594,58 @@ struct QpCache {
 pub struct OpenContext<T: ReadSeek> {
     disc: DiscSource<T>,
     qp: Option<QpCache>,
+    /// Cached file trees of archives which have been opened.
+    archives: HashMap<FileId, FileTree>,
+    /// The edit handle for the disc, if it is open for writing.
+    handle: Option<EditHandle>,
+    /// The number of transactions which are currently running.
+    transaction_depth: usize,
 }
 
 impl<T: ReadSeek> OpenContext<T> {
     fn new(disc: DiscSource<T>) -> Self {
-        Self { disc, qp: None }
+        Self { disc, qp: None, archives: HashMap::new(), handle: None, transaction_depth: 0 }
+    }
+
+    /// Returns the disc's file tree.
+    pub fn disc_files(&self) -> Result<&FileTree> {
+        match &self.disc {
+            DiscSource::None => Err(no_disc_error()),
+            DiscSource::Iso(disc) => Ok(disc.files()),
+            DiscSource::Overlay(disc) => Ok(disc.files()),
+        }
+    }
+
+    /// Returns qp.bin's file tree.
+    pub fn qp_files(&mut self) -> Result<&FileTree> {
+        Ok(&self.load_qp()?.tree)
+    }
+
+    /// Returns the disc's game ID (e.g. `GGTE01`).
+    pub fn game_id(&self) -> Result<String> {
+        match &self.disc {
+            DiscSource::None => Err(no_disc_error()),
+            DiscSource::Iso(disc) => Ok(disc.game_id()),
+            DiscSource::Overlay(disc) => Ok(disc.game_id()),
+        }
+    }
+
+    /// Reads the disc's main.dol.
+    pub fn read_dol(&mut self) -> Result<Dol> {
+        match &mut self.disc {
+            DiscSource::None => Err(no_disc_error()),
+            DiscSource::Iso(disc) => Ok(disc.read_dol()?),
+            DiscSource::Overlay(disc) => disc.read_dol(),
+        }
+    }
+
+    /// Opens the disc's main.dol for reading.
+    pub fn open_dol(&mut self) -> Result<Box<dyn ReadSeek + '_>> {
+        match &mut self.disc {
+            DiscSource::None => Err(no_disc_error()),
+            DiscSource::Iso(disc) => Ok(disc.open_dol()?.1),
+            DiscSource::Overlay(disc) => disc.open_dol(),
+        }
     }
 
     /// Gets the ID of a file located using a context path.
@@ -283,9 +674,19 @@ impl<T: ReadSeek> OpenContext<T> {
 
     fn get_file_impl(&mut self, path: &ContextPath<'_>) -> Result<FileId> {
         match path {
-            ContextPath::Dvd(path) => self.disc.get(path),
+            ContextPath::Dvd(path) => {
+                if let Some((archive, inner)) = split_archive_path(self.disc_files()?, path) {
+                    let archive = self.disc.get(archive)?;
+                    return self.archive_file_at(archive, inner);
+                }
+                self.disc.get(path)
+            }
             ContextPath::Qp(path) => {
                 let qp = self.load_qp()?;
+                if let Some((archive, inner)) = split_archive_path(&qp.tree, path) {
+                    let archive = FileId::Qp(qp.tree.at(archive)?);
+                    return self.archive_file_at(archive, inner);
+                }
                 let entry = qp.tree.at(path)?;
                 if qp.tree[entry].is_dir() {
                     return Err(dir_error(path));
@@ -293,7 +694,19 @@ impl<T: ReadSeek> OpenContext<T> {
                 Ok(FileId::Qp(entry))
             }
             ContextPath::File(path) | ContextPath::Other(path) => {
-                let canonical = fs::canonicalize(path)?;
+                let canonical = match fs::canonicalize(path) {
+                    Ok(canonical) => canonical,
+                    Err(e) => {
+                        // The path might go through an archive on the local filesystem
+                        let path = Path::new(path);
+                        let Some(archive) = path.ancestors().skip(1).find(|p| p.is_file()) else {
+                            return Err(e.into());
+                        };
+                        let inner = path.strip_prefix(archive)?.to_string_lossy();
+                        let archive = FileId::File(fs::canonicalize(archive)?.into());
+                        return self.archive_file_at(archive, &inner);
+                    }
+                };
                 if canonical.is_dir() {
                     return Err(dir_error(path));
                 }
@@ -302,6 +715,26 @@ impl<T: ReadSeek> OpenContext<T> {
         }
     }
 
+    /// Gets the ID of the file at `path` inside `archive`, descending into nested archives.
+    fn archive_file_at(&mut self, mut archive: FileId, mut path: &str) -> Result<FileId> {
+        loop {
+            let tree = self.load_archive(&archive)?;
+            let nested = split_archive_path(tree, path);
+            let entry = tree.at(nested.map_or(path, |(name, _)| name))?;
+            if tree[entry].is_dir() {
+                return Err(dir_error(path));
+            }
+            let file = FileId::Archive(archive.into(), entry);
+            match nested {
+                Some((_, inner)) => {
+                    archive = file;
+                    path = inner;
+                }
+                None => return Ok(file),
+            }
+        }
+    }
+
     /// Queries information about a file without opening it.
     pub fn query_file(&mut self, file: &FileId) -> Result<FileInfo> {
         match file {
@@ -319,6 +752,10 @@ impl<T: ReadSeek> OpenContext<T> {
                 let info = fs::metadata(path)?;
                 Ok(FileInfo { name, size: info.len() })
             }
+            FileId::Archive(archive, entry) => {
+                let info = self.load_archive(archive)?[*entry].file().unwrap();
+                Ok(FileInfo { name: info.name.clone(), size: info.size.into() })
+            }
         }
     }
 
@@ -333,6 +770,11 @@ impl<T: ReadSeek> OpenContext<T> {
                 Ok(entry.open(reader)?)
             }
             FileId::File(path) => Ok(Box::from(File::open(path.as_ref())?)),
+            FileId::Archive(archive, entry) => {
+                let info = self.load_archive(archive)?[*entry].file().unwrap().clone();
+                let reader = self.open_file(archive)?;
+                Ok(info.open(reader)?)
+            }
         }
     }
 
@@ -440,6 +882,17 @@ impl<T: ReadSeek> OpenContext<T> {
         }
         Ok(self.qp.as_ref().unwrap())
     }
+
+    /// Loads and caches the file tree of `archive` if it is not available.
+    fn load_archive(&mut self, archive: &FileId) -> Result<&FileTree> {
+        if !self.archives.contains_key(archive) {
+            let name = self.query_file(archive)?.name;
+            debug!("Reading archive {}", name);
+            let files = ArchiveReader::open(self.open_file(archive)?)?.files;
+            self.archives.insert(archive.clone(), files);
+        }
+        Ok(&self.archives[archive])
+    }
 }
 
 impl<T: ReadWriteSeek> OpenContext<T> {
@@ -448,11 +901,128 @@ impl<T: ReadWriteSeek> OpenContext<T> {
     pub fn begin_update<'r>(&mut self) -> UpdateQueue<'_, 'r, T> {
         UpdateQueue::new(self)
     }
+
+    /// Runs `f` as a single transaction on the disc. If `f` fails, everything it wrote to the disc
+    /// is rolled back. Otherwise, its writes become permanent when the outermost transaction ends.
+    ///
+    /// Writes to raw ISOs are journaled so that the disc can't be left half-written if something
+    /// goes wrong. If the process is killed, the journal will be recovered the next time the disc
+    /// is opened. Compressed images are only re-encoded after a transaction succeeds, and overlay
+    /// manifests are only saved after a transaction succeeds.
+    pub fn transaction<R>(&mut self, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        if self.transaction_depth == 0 {
+            if let Some(handle) = &self.handle {
+                handle.begin();
+            }
+            if let DiscSource::Overlay(disc) = &mut self.disc {
+                disc.begin();
+            }
+        }
+        self.transaction_depth += 1;
+        let result = f(self);
+        self.transaction_depth -= 1;
+        if self.transaction_depth > 0 {
+            return result;
+        }
+        match result {
+            Ok(result) => {
+                if let Some(handle) = &self.handle {
+                    handle.commit()?;
+                }
+                if let DiscSource::Overlay(disc) = &mut self.disc {
+                    disc.commit()?;
+                }
+                Ok(result)
+            }
+            Err(e) => {
+                // The original error is more useful than anything that goes wrong here
+                if let Err(rollback_error) = self.rollback() {
+                    error!("Could not roll back changes: {:#}", rollback_error);
+                }
+                self.qp = None;
+                self.archives.clear();
+                Err(e)
+            }
+        }
+    }
+
+    /// Throws away everything written during the current transaction.
+    fn rollback(&mut self) -> Result<()> {
+        if let Some(handle) = &self.handle {
+            warn!("Rolling back changes to the disc");
+            // The in-memory FST no longer matches what's on the disc, so it has to be read again.
+            // If anything fails, the disc is left closed rather than out of sync.
+            let disc = mem::replace(&mut self.disc, DiscSource::None);
+            handle.rollback()?;
+            if let DiscSource::Iso(disc) = disc {
+                self.disc = DiscSource::Iso(DiscStream::open(disc.into_inner())?.into());
+            }
+        } else if let DiscSource::Overlay(disc) = &mut self.disc {
+            warn!("Rolling back changes to the overlay");
+            disc.rollback()?;
+        }
+        Ok(())
+    }
+
+    /// Creates a new file on the disc at `path` using data read from `reader`. Unlike writes made
+    /// through `begin_update()`, this takes effect immediately.
+    pub fn create_disc_file_at(&mut self, path: &str, reader: impl ReadSeek) -> Result<FileId> {
+        let entry = self.transaction(|ctx| ctx.disc.create_file_at(path, reader))?;
+        Ok(FileId::Iso(entry))
+    }
+
+    /// Creates a new empty directory on the disc at `path`. This takes effect immediately.
+    pub fn create_disc_dir_at(&mut self, path: &str) -> Result<()> {
+        self.transaction(|ctx| ctx.disc.create_dir_at(path))
+    }
+
+    /// Removes the file or directory on the disc at `path`. This takes effect immediately.
+    pub fn remove_disc_entry_at(&mut self, path: &str) -> Result<()> {
+        self.transaction(|ctx| ctx.disc.remove_at(path))?;
+        // qp.bin might not exist anymore
+        self.qp = None;
+        self.archives.clear();
+        Ok(())
+    }
+
+    /// Moves the file or directory on the disc at `path` to `new_path`. This takes effect
+    /// immediately.
+    pub fn rename_disc_entry_at(&mut self, path: &str, new_path: &str) -> Result<()> {
+        self.transaction(|ctx| ctx.disc.rename_at(path, new_path))?;
+        self.qp = None;
+        self.archives.clear();
+        Ok(())
+    }
+
+    /// Rebuilds qp.bin with the files in `files` replaced.
+    fn write_qp_files<'r>(&mut self, files: Vec<(EntryId, Box<dyn ReadSeek + 'r>)>) -> Result<()> {
+        self.load_qp()?;
+        debug!("Rebuilding qp.bin");
+
+        // Take the qp.bin cache because it will end up being invalid anyway
+        let qp = self.qp.take().unwrap();
+        let mut archive = ArchiveReader::new(self.open_file(&qp.file)?, qp.tree);
+        let mut builder = ArchiveBuilder::with_archive(&mut archive);
+        for (entry, reader) in files {
+            builder.replace(entry, || Ok(reader));
+        }
+
+        let mut temp = NamedTempFile::new()?;
+        debug!("Writing new qp.bin to {}", temp.path().to_string_lossy());
+        builder.write_to(&mut temp)?;
+        drop(builder);
+        drop(archive);
+
+        temp.rewind()?;
+        self.disc.write(&qp.file, &mut temp)?;
+        Ok(())
+    }
 }
 
 /// A queue of pending file updates.
 pub struct UpdateQueue<'c, 'r, T: ReadWriteSeek> {
     ctx: &'c mut OpenContext<T>,
+    archive_files: Vec<(Arc<FileId>, EntryId, Box<dyn ReadSeek + 'r>)>,
     qp_files: Vec<(EntryId, Box<dyn ReadSeek + 'r>)>,
     iso_files: Vec<(EntryId, Box<dyn ReadSeek + 'r>)>,
     fs_files: Vec<(Arc<Path>, Box<dyn ReadSeek + 'r>)>,
@@ -460,13 +1030,14 @@ pub struct UpdateQueue<'c, 'r, T: ReadWriteSeek> {
 
 impl<'c, 'r, T: ReadWriteSeek> UpdateQueue<'c, 'r, T> {
     fn new(ctx: &'c mut OpenContext<T>) -> Self {
-        Self { ctx, qp_files: vec![], iso_files: vec![], fs_files: vec![] }
+        Self { ctx, archive_files: vec![], qp_files: vec![], iso_files: vec![], fs_files: vec![] }
     }
 
     /// Enqueues `file` to be written from data in `reader`.
     #[must_use]
-    pub fn write_file(self, file: &FileId, reader: impl ReadSeek + 'r) -> Self {
-        self.write_impl(file, Box::from(reader))
+    pub fn write_file(mut self, file: &FileId, reader: impl ReadSeek + 'r) -> Self {
+        self.write_impl(file, Box::from(reader));
+        self
     }
 
     /// Enqueues the file at `path` to be written from data in `reader`.
@@ -528,25 +1099,40 @@ impl<'c, 'r, T: ReadWriteSeek> UpdateQueue<'c, 'r, T> {
         self.serialize_file(file, stage)
     }
 
-    fn write_impl(mut self, file: &FileId, reader: Box<dyn ReadSeek + 'r>) -> Self {
+    fn write_impl(&mut self, file: &FileId, reader: Box<dyn ReadSeek + 'r>) {
         match file {
             &FileId::Iso(entry) => self.iso_files.push((entry, reader)),
             &FileId::Qp(entry) => self.qp_files.push((entry, reader)),
             FileId::File(path) => self.fs_files.push((Arc::clone(path), reader)),
+            FileId::Archive(archive, entry) => {
+                self.archive_files.push((Arc::clone(archive), *entry, reader));
+            }
         }
-        self
     }
 
     /// Commits all pending file updates.
     pub fn commit(mut self) -> Result<()> {
-        // qp.bin is updated first since it's part of the disc
-        if !self.qp_files.is_empty() {
-            self.commit_qp()?;
-        }
-        // The disc is updated next because it's part of the local filesystem
-        if !self.iso_files.is_empty() {
-            self.commit_iso()?;
+        // Archives are rebuilt first because they can be stored anywhere else. This only reads
+        // from the disc, so it doesn't need to be part of the transaction.
+        let result = self.commit_archives();
+        self.ctx.archives.clear();
+        result?;
+
+        let qp_files = mem::take(&mut self.qp_files);
+        let iso_files = mem::take(&mut self.iso_files);
+        if !qp_files.is_empty() || !iso_files.is_empty() {
+            self.ctx.transaction(|ctx| {
+                // qp.bin is updated first since it's part of the disc
+                if !qp_files.is_empty() {
+                    ctx.write_qp_files(qp_files)?;
+                }
+                for (entry, mut reader) in iso_files {
+                    ctx.disc.write(&FileId::Iso(entry), &mut *reader)?;
+                }
+                Ok(())
+            })?;
         }
+
         // Finally, update local files
         if !self.fs_files.is_empty() {
             self.commit_fs()?;
@@ -554,34 +1140,49 @@ impl<'c, 'r, T: ReadWriteSeek> UpdateQueue<'c, 'r, T> {
         Ok(())
     }
 
-    fn commit_qp(&mut self) -> Result<()> {
-        self.ctx.load_qp()?;
-        debug!("Rebuilding qp.bin");
+    fn commit_archives(&mut self) -> Result<()> {
+        // Rebuild the most deeply-nested archives first so that each rebuilt archive can be written
+        // into the archive containing it
+        while let Some(depth) = self.archive_files.iter().map(|(a, _, _)| a.archive_depth()).max() {
+            let (deepest, rest): (Vec<_>, Vec<_>) =
+                self.archive_files.drain(..).partition(|(a, _, _)| a.archive_depth() == depth);
+            self.archive_files = rest;
+            let mut archives: Vec<(Arc<FileId>, Vec<_>)> = vec![];
+            for (archive, entry, reader) in deepest {
+                match archives.iter_mut().find(|(a, _)| *a == archive) {
+                    Some((_, files)) => files.push((entry, reader)),
+                    None => archives.push((archive, vec![(entry, reader)])),
+                }
+            }
+            for (archive, files) in archives {
+                let rebuilt = self.rebuild_archive(&archive, files)?;
+                self.write_impl(&archive, Box::from(rebuilt));
+            }
+        }
+        Ok(())
+    }
 
-        // Take the qp.bin cache because it will end up being invalid anyway
-        let qp = self.ctx.qp.take().unwrap();
-        let mut archive = ArchiveReader::new(self.ctx.open_file(&qp.file)?, qp.tree);
+    fn rebuild_archive(
+        &mut self,
+        file: &FileId,
+        files: Vec<(EntryId, Box<dyn ReadSeek + 'r>)>,
+    ) -> Result<NamedTempFile> {
+        let name = self.ctx.query_file(file)?.name;
+        debug!("Rebuilding {}", name);
+        let tree = self.ctx.load_archive(file)?.clone();
+        let mut archive = ArchiveReader::new(self.ctx.open_file(file)?, tree);
         let mut builder = ArchiveBuilder::with_archive(&mut archive);
-        for (entry, reader) in self.qp_files.drain(..) {
-            builder.replace(entry, || reader);
+        for (entry, reader) in files {
+            builder.replace(entry, || Ok(reader));
         }
 
         let mut temp = NamedTempFile::new()?;
-        debug!("Writing new qp.bin to {}", temp.path().to_string_lossy());
+        debug!("Writing new {} to {}", name, temp.path().to_string_lossy());
         builder.write_to(&mut temp)?;
         drop(builder);
         drop(archive);
-
         temp.rewind()?;
-        self.ctx.disc.write(&qp.file, &mut temp)?;
-        Ok(())
-    }
-
-    fn commit_iso(&mut self) -> Result<()> {
-        for (entry, mut reader) in self.iso_files.drain(..) {
-            self.ctx.disc.write(&FileId::Iso(entry), &mut *reader)?;
-        }
-        Ok(())
+        Ok(temp)
     }
 
     fn commit_fs(&mut self) -> Result<()> {
@@ -594,3 +1195,221 @@ impl<'c, 'r, T: ReadWriteSeek> UpdateQueue<'c, 'r, T> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{make_test_archive, make_test_disc, make_test_iso, TEST_DISC_SIZE};
+    use unplug::dvd::{DirectoryEntry, FileEntry};
+
+    /// Writes a test disc to a temporary file as a `format` image.
+    fn make_test_image(format: ImageFormat) -> Result<NamedTempFile> {
+        let disc = make_test_disc(&[("a.bin", b"aaaa")]);
+        let mut file = NamedTempFile::new()?;
+        write_image(format, &mut disc.as_slice(), TEST_DISC_SIZE.into(), file.as_file_mut())?;
+        Ok(file)
+    }
+
+    fn read_disc_file(path: &Path, name: &str) -> Result<Vec<u8>> {
+        let mut ctx = Context::Iso(path.to_owned()).open_read()?;
+        let mut data = vec![];
+        ctx.open_disc_file_at(name)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Builds a test ISO containing an archive which has another archive nested inside it.
+    fn make_nested_archive_iso() -> NamedTempFile {
+        let inner = make_test_archive(&[("z.bin", b"zzzz")]);
+        let outer = make_test_archive(&[("x/y.bin", b"yyyy"), ("inner.arc", inner.as_slice())]);
+        make_test_iso(&[("a.arc", outer.as_slice()), ("b.bin", b"bbbb")])
+    }
+
+    #[test]
+    fn test_split_archive_path() {
+        let mut tree = FileTree::new();
+        let root = tree.root();
+        let dir = tree.insert(root, DirectoryEntry::new("dir").into());
+        tree.insert(dir, FileEntry::new("a.arc", 0, 0).into());
+        tree.insert(root, FileEntry::new("b.bin", 0, 0).into());
+        assert_eq!(split_archive_path(&tree, "dir/a.arc/y.bin"), Some(("dir/a.arc", "y.bin")));
+        assert_eq!(
+            split_archive_path(&tree, "dir/a.arc/inner.arc/z.bin"),
+            Some(("dir/a.arc", "inner.arc/z.bin"))
+        );
+        // Paths to entries in the tree are never split
+        assert_eq!(split_archive_path(&tree, "dir/a.arc"), None);
+        assert_eq!(split_archive_path(&tree, "b.bin"), None);
+        assert_eq!(split_archive_path(&tree, "dir"), None);
+        assert_eq!(split_archive_path(&tree, "dir/c.bin/y.bin"), None);
+    }
+
+    #[test]
+    fn test_archive_file_at() -> Result<()> {
+        let iso = make_nested_archive_iso();
+        let mut ctx = Context::Iso(iso.path().to_owned()).open_read()?;
+        assert!(matches!(ctx.file_at("dvd:a.arc")?, FileId::Iso(_)));
+        assert!(matches!(ctx.file_at("dvd:b.bin")?, FileId::Iso(_)));
+        assert_eq!(ctx.file_at("dvd:a.arc/x/y.bin")?.archive_depth(), 1);
+        assert_eq!(ctx.file_at("dvd:a.arc/inner.arc/z.bin")?.archive_depth(), 2);
+        assert!(ctx.file_at("dvd:a.arc/x").is_err());
+        assert!(ctx.file_at("dvd:a.arc/missing.bin").is_err());
+        assert!(ctx.file_at("dvd:a.arc/inner.arc/missing.bin").is_err());
+        drop(ctx);
+
+        assert_eq!(read_disc_file(iso.path(), "a.arc/x/y.bin")?, b"yyyy");
+        assert_eq!(read_disc_file(iso.path(), "a.arc/inner.arc/z.bin")?, b"zzzz");
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_nested_archive_file() -> Result<()> {
+        let iso = make_nested_archive_iso();
+        let mut ctx = Context::Iso(iso.path().to_owned()).open_read_write()?;
+        ctx.begin_update()
+            .write_file_at("dvd:a.arc/inner.arc/z.bin", Cursor::new(b"new data"))?
+            .commit()?;
+        drop(ctx);
+
+        assert_eq!(read_disc_file(iso.path(), "a.arc/inner.arc/z.bin")?, b"new data");
+        assert_eq!(read_disc_file(iso.path(), "a.arc/x/y.bin")?, b"yyyy");
+        assert_eq!(read_disc_file(iso.path(), "b.bin")?, b"bbbb");
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit_decoded_image() -> Result<()> {
+        let image = make_test_image(ImageFormat::Ciso)?;
+        let mut ctx = Context::Iso(image.path().to_owned()).open_read_write()?;
+        ctx.create_disc_file_at("b.bin", Cursor::new(b"bbbb"))?;
+        drop(ctx);
+
+        let mut file = File::open(image.path())?;
+        assert_eq!(ImageFormat::detect(&mut file)?, ImageFormat::Ciso);
+        assert_eq!(read_disc_file(image.path(), "a.bin")?, b"aaaa");
+        assert_eq!(read_disc_file(image.path(), "b.bin")?, b"bbbb");
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit_decoded_image_failed_transaction() -> Result<()> {
+        let image = make_test_image(ImageFormat::Gcz)?;
+        let original = fs::read(image.path())?;
+        let mut ctx = Context::Iso(image.path().to_owned()).open_read_write()?;
+        let result: Result<()> = ctx.transaction(|ctx| {
+            ctx.create_disc_file_at("b.bin", Cursor::new(b"bbbb"))?;
+            bail!("oops");
+        });
+        assert!(result.is_err());
+        // The context can still be used and has the original files
+        assert!(ctx.disc_files()?.at("b.bin").is_err());
+        let mut data = vec![];
+        ctx.open_disc_file_at("a.bin")?.read_to_end(&mut data)?;
+        assert_eq!(data, b"aaaa");
+        drop(ctx);
+        assert_eq!(fs::read(image.path())?, original);
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit_iso_failed_transaction() -> Result<()> {
+        let iso = make_test_iso(&[("a.bin", b"aaaa")]);
+        let mut ctx = Context::Iso(iso.path().to_owned()).open_read_write()?;
+        let result: Result<()> = ctx.transaction(|ctx| {
+            ctx.create_disc_file_at("b.bin", Cursor::new(b"bbbb"))?;
+            bail!("oops");
+        });
+        assert!(result.is_err());
+        // The disc is reloaded and stays locked, so it can still be edited
+        assert!(ctx.disc_files()?.at("b.bin").is_err());
+        assert!(open_disc_read_write(iso.path()).is_err());
+        ctx.create_disc_file_at("c.bin", Cursor::new(b"cccc"))?;
+        drop(ctx);
+        assert_eq!(read_disc_file(iso.path(), "a.bin")?, b"aaaa");
+        assert_eq!(read_disc_file(iso.path(), "c.bin")?, b"cccc");
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit_decoded_image_without_commit() -> Result<()> {
+        let image = make_test_image(ImageFormat::Ciso)?;
+        let original = fs::read(image.path())?;
+        let (mut disc, handle) = open_disc_read_write(image.path())?;
+        handle.begin();
+        disc.create_file_at("b.bin", Cursor::new(b"bbbb"))?;
+        drop(disc);
+        drop(handle);
+        assert_eq!(fs::read(image.path())?, original);
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit_disc_rollback() -> Result<()> {
+        let iso = make_test_iso(&[("a.bin", b"aaaa")]);
+        let original = fs::read(iso.path())?;
+        let result: Result<()> = edit_disc(iso.path(), |disc| {
+            disc.replace_file_at("a.bin", Cursor::new(b"bbbbbbbb"))?;
+            disc.create_file_at("b.bin", Cursor::new(b"bbbb"))?;
+            bail!("oops");
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(iso.path())?, original);
+        assert!(!journal::journal_path(iso.path()).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_recovers_journal() -> Result<()> {
+        let iso = make_test_iso(&[("a.bin", b"aaaa")]);
+        let original = fs::read(iso.path())?;
+        let (mut disc, handle) = open_disc_read_write(iso.path())?;
+        handle.begin();
+        disc.replace_file_at("a.bin", Cursor::new(b"bbbb"))?;
+        let journal = journal::journal_path(iso.path());
+        assert!(journal.exists());
+
+        // The image can't be read while it is still being edited
+        assert!(open_disc_read(iso.path()).is_err());
+        assert!(journal.exists());
+
+        // Simulate the process being killed in the middle of the transaction
+        drop(disc);
+        drop(handle);
+        assert_eq!(read_disc_file(iso.path(), "a.bin")?, b"aaaa");
+        assert!(!journal.exists());
+        assert_eq!(fs::read(iso.path())?, original);
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit_overlay_rollback() -> Result<()> {
+        let iso = make_test_iso(&[("a.bin", b"aaaa")]);
+        let dir = tempfile::tempdir()?;
+        let overlay = dir.path().join("overlay");
+        let context = Context::ProjectOverlay {
+            name: "test".to_owned(),
+            base: iso.path().to_owned(),
+            path: overlay.clone(),
+        };
+        let mut ctx = context.clone().open_read_write()?;
+        ctx.create_disc_file_at("b.bin", Cursor::new(b"bbbb"))?;
+        let result: Result<()> = ctx.transaction(|ctx| {
+            ctx.create_disc_file_at("c.bin", Cursor::new(b"cccc"))?;
+            ctx.remove_disc_entry_at("a.bin")?;
+            bail!("oops");
+        });
+        assert!(result.is_err());
+        // The overlay can still be used and only has the committed changes
+        assert!(ctx.disc_files()?.at("a.bin").is_ok());
+        assert!(ctx.disc_files()?.at("c.bin").is_err());
+        drop(ctx);
+
+        let mut ctx = context.open_read()?;
+        assert!(ctx.disc_files()?.at("a.bin").is_ok());
+        assert!(ctx.disc_files()?.at("c.bin").is_err());
+        let mut data = vec![];
+        ctx.open_disc_file_at("b.bin")?.read_to_end(&mut data)?;
+        assert_eq!(data, b"bbbb");
+        assert_eq!(fs::read_dir(overlay.join("files"))?.count(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,520 @@
+use crate::commands::globals::metadata_json;
+use crate::commands::stage::stage_objects_json;
+use crate::context::{FileId, OpenContext};
+use crate::fst::file_paths;
+use anyhow::Result;
+use log::{debug, warn};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::cmp;
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+use unplug::audio::transport::ssm::BankSample;
+use unplug::common::io::BUFFER_SIZE;
+use unplug::common::ReadSeek;
+use unplug::data::Stage as StageId;
+use unplug::dvd::dol::SectionKind;
+use unplug::dvd::Dol;
+use unplug::event::Script;
+use unplug::globals::Libs;
+use unplug::stage::Stage;
+
+/// Path to display for main.dol.
+const DOL_PATH: &str = "sys/main.dol";
+/// Path to qp.bin within the disc.
+const QP_PATH: &str = "qp.bin";
+/// Path to the stage directory within qp.bin.
+const QP_STAGE_DIR: &str = "bin/e/";
+
+type DiscContext = OpenContext<Box<dyn ReadSeek>>;
+
+/// Describes how a file or value changed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// The file or value only exists in the new disc.
+    Added,
+    /// The file or value only exists in the old disc.
+    Removed,
+    /// The file or value exists in both discs but is different.
+    Changed,
+}
+
+impl ChangeKind {
+    /// Returns the character used to represent the change in text output.
+    fn symbol(self) -> char {
+        match self {
+            Self::Added => '+',
+            Self::Removed => '-',
+            Self::Changed => '~',
+        }
+    }
+}
+
+/// A difference between two values inside a file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Difference {
+    /// The path to the value, e.g. `objects[3].position.x`.
+    pub path: String,
+    pub change: ChangeKind,
+    /// The old value, if it is available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    /// The new value, if it is available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Value>,
+}
+
+impl Difference {
+    fn new(path: impl Into<String>, old: Option<&Value>, new: Option<&Value>) -> Self {
+        let change = match (old, new) {
+            (Some(_), None) => ChangeKind::Removed,
+            (None, Some(_)) => ChangeKind::Added,
+            _ => ChangeKind::Changed,
+        };
+        Self { path: path.into(), change, old: old.cloned(), new: new.cloned() }
+    }
+}
+
+/// A difference between two files.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDiff {
+    /// The path to the file. Files inside qp.bin are prefixed with `qp.bin/`.
+    pub path: String,
+    pub change: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_size: Option<u64>,
+    /// Differences inside the file, if its format is recognized.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<Difference>,
+}
+
+/// The differences between two discs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DiscDiff {
+    pub files: Vec<FileDiff>,
+}
+
+impl DiscDiff {
+    /// Returns true if the discs are identical.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Returns the number of files which had a change of kind `change`.
+    pub fn count(&self, change: ChangeKind) -> usize {
+        self.files.iter().filter(|f| f.change == change).count()
+    }
+
+    /// Writes a human-readable description of the differences to `out`.
+    pub fn write_text(&self, mut out: impl Write) -> Result<()> {
+        for file in &self.files {
+            write!(out, "{} {}", file.change.symbol(), file.path)?;
+            match (file.old_size, file.new_size) {
+                (Some(old), Some(new)) if old != new => write!(out, " ({} -> {} bytes)", old, new)?,
+                (Some(size), None) | (None, Some(size)) => write!(out, " ({} bytes)", size)?,
+                _ => (),
+            }
+            writeln!(out)?;
+            for detail in &file.details {
+                write!(out, "    {} {}", detail.change.symbol(), detail.path)?;
+                match (&detail.old, &detail.new) {
+                    (Some(old), Some(new)) => write!(out, ": {} -> {}", old, new)?,
+                    (Some(value), None) | (None, Some(value)) => write!(out, ": {}", value)?,
+                    (None, None) => (),
+                }
+                writeln!(out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Recursively compares two JSON values and appends any differences to `out`. Paths are built
+/// starting from `path`.
+pub fn diff_values(path: &str, old: &Value, new: &Value, out: &mut Vec<Difference>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let keys = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+            for key in keys {
+                let key_path =
+                    if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match (old.get(key), new.get(key)) {
+                    (Some(old), Some(new)) => diff_values(&key_path, old, new, out),
+                    (old, new) => out.push(Difference::new(key_path, old, new)),
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for i in 0..cmp::max(old.len(), new.len()) {
+                let index_path = format!("{}[{}]", path, i);
+                match (old.get(i), new.get(i)) {
+                    (Some(old), Some(new)) => diff_values(&index_path, old, new, out),
+                    (old, new) => out.push(Difference::new(index_path, old, new)),
+                }
+            }
+        }
+        _ if old != new => out.push(Difference::new(path, Some(old), Some(new))),
+        _ => (),
+    }
+}
+
+/// Compares the blocks in two scripts and appends any differences to `out`.
+fn diff_scripts(old: &Script, new: &Script, out: &mut Vec<Difference>) {
+    let (old, new) = (old.blocks(), new.blocks());
+    for i in 0..cmp::max(old.len(), new.len()) {
+        let path = format!("script.blocks[{}]", i);
+        let change = match (old.get(i), new.get(i)) {
+            (Some(old), Some(new)) if old == new => continue,
+            (Some(_), Some(_)) => ChangeKind::Changed,
+            (Some(_), None) => ChangeKind::Removed,
+            (None, _) => ChangeKind::Added,
+        };
+        out.push(Difference { path, change, old: None, new: None });
+    }
+}
+
+/// Summarizes a sound bank sample for comparison.
+fn sample_json(sample: &BankSample) -> Value {
+    json!({
+        "rate": sample.rate,
+        "channels": sample.channels.len(),
+        "size": sample.channels.iter().map(|c| c.data.len()).sum::<usize>(),
+    })
+}
+
+/// Returns the name to use for an executable section.
+fn section_name(kind: SectionKind, index: usize) -> String {
+    match kind {
+        SectionKind::Text => format!("text[{}]", index),
+        SectionKind::Data => format!("data[{}]", index),
+    }
+}
+
+/// Summarizes an executable's layout for comparison.
+fn dol_json(dol: &Dol) -> Value {
+    let mut sections = serde_json::Map::new();
+    for (kind, index, section) in dol.sections() {
+        let size = section.data.len();
+        sections
+            .insert(section_name(kind, index), json!({ "address": section.address, "size": size }));
+    }
+    json!({
+        "entryPoint": dol.entry_point,
+        "bssAddress": dol.bss_address,
+        "bssSize": dol.bss_size,
+        "sections": sections,
+    })
+}
+
+/// Where a file being compared is stored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Location {
+    Disc,
+    Qp,
+}
+
+impl Location {
+    /// Returns the path to display for the file at `path`.
+    fn display_path(self, path: &str) -> String {
+        match self {
+            Self::Disc => path.to_owned(),
+            Self::Qp => format!("{}/{}", QP_PATH, path),
+        }
+    }
+}
+
+/// One of the discs being compared.
+struct Side<'a> {
+    ctx: &'a mut DiscContext,
+    libs: Option<Libs>,
+}
+
+impl<'a> Side<'a> {
+    fn new(ctx: &'a mut DiscContext) -> Self {
+        Self { ctx, libs: None }
+    }
+
+    /// Returns the paths of the files stored in `location`.
+    fn list(&mut self, location: Location) -> Result<BTreeSet<String>> {
+        let tree = match location {
+            Location::Disc => self.ctx.disc_files()?,
+            Location::Qp => self.ctx.qp_files()?,
+        };
+        Ok(file_paths(tree))
+    }
+
+    /// Gets the ID of the file at `path` in `location`.
+    fn file(&mut self, location: Location, path: &str) -> Result<FileId> {
+        match location {
+            Location::Disc => self.ctx.disc_file_at(path),
+            Location::Qp => self.ctx.qp_file_at(path),
+        }
+    }
+
+    /// Reads `file` as a stage file.
+    fn read_stage(&mut self, file: &FileId) -> Result<Stage> {
+        if self.libs.is_none() {
+            self.libs = Some(self.ctx.read_globals()?.read_libs()?);
+        }
+        self.ctx.read_stage_file(self.libs.as_ref().unwrap(), file)
+    }
+
+    /// Reads `file` as a music file and summarizes it for comparison.
+    fn read_music(&mut self, file: &FileId) -> Result<Value> {
+        let hps = self.ctx.open_music_file(file)?;
+        let cues = hps.cues().map(|c| json!({ "name": &*c.name, "start": c.start }));
+        Ok(json!({
+            "sampleRate": hps.sample_rate(),
+            "channels": hps.channels(),
+            "loopStart": hps.loop_start(),
+            "cues": cues.collect::<Vec<_>>(),
+        }))
+    }
+}
+
+/// Compares files between two discs.
+struct Differ<'a> {
+    old: Side<'a>,
+    new: Side<'a>,
+    files: Vec<FileDiff>,
+}
+
+impl Differ<'_> {
+    /// Compares the discs' main.dol files.
+    fn diff_dol(&mut self) -> Result<()> {
+        let old = self.old.ctx.read_dol()?;
+        let new = self.new.ctx.read_dol()?;
+        if old == new {
+            return Ok(());
+        }
+        let mut details = vec![];
+        diff_values("", &dol_json(&old), &dol_json(&new), &mut details);
+        for (kind, index, old_section) in old.sections() {
+            if let Some(new_section) = new.section(kind, index)? {
+                if old_section.data.len() == new_section.data.len()
+                    && old_section.data != new_section.data
+                {
+                    let path = format!("sections.{}.data", section_name(kind, index));
+                    details.push(Difference::new(path, None, None));
+                }
+            }
+        }
+        self.files.push(FileDiff {
+            path: DOL_PATH.to_owned(),
+            change: ChangeKind::Changed,
+            old_size: Some(old.header()?.file_size().into()),
+            new_size: Some(new.header()?.file_size().into()),
+            details,
+        });
+        Ok(())
+    }
+
+    /// Compares all of the files in `location`.
+    fn diff_files(&mut self, location: Location) -> Result<()> {
+        let old_paths = self.old.list(location)?;
+        let new_paths = self.new.list(location)?;
+        for path in old_paths.union(&new_paths) {
+            let display_path = location.display_path(path);
+            let old = match old_paths.contains(path) {
+                true => Some(self.old.file(location, path)?),
+                false => None,
+            };
+            let new = match new_paths.contains(path) {
+                true => Some(self.new.file(location, path)?),
+                false => None,
+            };
+            let old_size = old.as_ref().map(|f| self.old.ctx.query_file(f)).transpose()?;
+            let new_size = new.as_ref().map(|f| self.new.ctx.query_file(f)).transpose()?;
+            let (old_size, new_size) = (old_size.map(|i| i.size), new_size.map(|i| i.size));
+            let (change, details) = match (old, new) {
+                (Some(old), Some(new)) => {
+                    debug!("Comparing {}", display_path);
+                    if old_size == new_size && self.contents_equal(&old, &new)? {
+                        continue;
+                    }
+                    let details = match self.diff_contents(location, path, &old, &new) {
+                        Ok(details) => details,
+                        Err(e) => {
+                            warn!("Could not compare the contents of {}: {:#}", display_path, e);
+                            vec![]
+                        }
+                    };
+                    (ChangeKind::Changed, details)
+                }
+                (Some(_), None) => (ChangeKind::Removed, vec![]),
+                _ => (ChangeKind::Added, vec![]),
+            };
+            self.files.push(FileDiff { path: display_path, change, old_size, new_size, details });
+            if change == ChangeKind::Changed
+                && location == Location::Disc
+                && path.eq_ignore_ascii_case(QP_PATH)
+            {
+                self.diff_files(Location::Qp)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if two files with the same size have identical contents.
+    fn contents_equal(&mut self, old: &FileId, new: &FileId) -> Result<bool> {
+        let mut remaining = self.old.ctx.query_file(old)?.size;
+        let mut old_reader = self.old.ctx.open_file(old)?;
+        let mut new_reader = self.new.ctx.open_file(new)?;
+        let mut old_buf = vec![0; BUFFER_SIZE];
+        let mut new_buf = vec![0; BUFFER_SIZE];
+        while remaining > 0 {
+            let len = cmp::min(remaining, BUFFER_SIZE as u64) as usize;
+            old_reader.read_exact(&mut old_buf[..len])?;
+            new_reader.read_exact(&mut new_buf[..len])?;
+            if old_buf[..len] != new_buf[..len] {
+                return Ok(false);
+            }
+            remaining -= len as u64;
+        }
+        Ok(true)
+    }
+
+    /// Compares the contents of two files with a recognized format.
+    fn diff_contents(
+        &mut self,
+        location: Location,
+        path: &str,
+        old: &FileId,
+        new: &FileId,
+    ) -> Result<Vec<Difference>> {
+        let lower = path.to_lowercase();
+        let mut out = vec![];
+        if location == Location::Qp && lower == StageId::QP_GLOBALS_PATH {
+            let old = metadata_json(self.old.ctx.read_globals()?.read_metadata()?)?;
+            let new = metadata_json(self.new.ctx.read_globals()?.read_metadata()?)?;
+            diff_values("", &old, &new, &mut out);
+        } else if location == Location::Qp
+            && lower.starts_with(QP_STAGE_DIR)
+            && lower.ends_with(".bin")
+        {
+            let old = self.old.read_stage(old)?;
+            let new = self.new.read_stage(new)?;
+            diff_values("", &stage_objects_json(&old)?, &stage_objects_json(&new)?, &mut out);
+            diff_scripts(&old.script, &new.script, &mut out);
+        } else if location == Location::Disc && lower.ends_with(".hps") {
+            let old = self.old.read_music(old)?;
+            let new = self.new.read_music(new)?;
+            diff_values("", &old, &new, &mut out);
+        } else if location == Location::Disc && lower.ends_with(".ssm") {
+            let old = self.old.ctx.read_bank_file(old)?;
+            let new = self.new.ctx.read_bank_file(new)?;
+            let old = old.samples().collect::<Vec<_>>();
+            let new = new.samples().collect::<Vec<_>>();
+            for i in 0..cmp::max(old.len(), new.len()) {
+                let sample_path = format!("samples[{}]", i);
+                match (old.get(i), new.get(i)) {
+                    (Some(old), Some(new)) if old != new => {
+                        let count = out.len();
+                        diff_values(&sample_path, &sample_json(old), &sample_json(new), &mut out);
+                        if out.len() == count {
+                            // Only the audio data changed
+                            out.push(Difference::new(sample_path + ".data", None, None));
+                        }
+                    }
+                    (Some(_), Some(_)) => (),
+                    (old, new) => {
+                        let (old, new) = (old.map(|s| sample_json(s)), new.map(|s| sample_json(s)));
+                        out.push(Difference::new(sample_path, old.as_ref(), new.as_ref()));
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Compares the files on two discs, including the contents of qp.bin and of files whose format is
+/// recognized.
+pub fn diff_discs(old: &mut DiscContext, new: &mut DiscContext) -> Result<DiscDiff> {
+    let mut differ = Differ { old: Side::new(old), new: Side::new(new), files: vec![] };
+    differ.diff_dol()?;
+    differ.diff_files(Location::Disc)?;
+    Ok(DiscDiff { files: differ.files })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_values() {
+        let old = json!({
+            "objects": [
+                { "id": 0, "position": { "x": 1.0, "y": 2.0 } },
+                { "id": 1, "position": { "x": 3.0, "y": 4.0 } },
+            ],
+            "name": "foo",
+            "removed": true,
+        });
+        let new = json!({
+            "objects": [
+                { "id": 0, "position": { "x": 1.0, "y": 2.5 } },
+            ],
+            "name": "bar",
+            "added": 1,
+        });
+        let mut out = vec![];
+        diff_values("", &old, &new, &mut out);
+        let expected = vec![
+            Difference::new("added", None, Some(&json!(1))),
+            Difference::new("name", Some(&json!("foo")), Some(&json!("bar"))),
+            Difference::new("objects[0].position.y", Some(&json!(2.0)), Some(&json!(2.5))),
+            Difference::new("objects[1]", Some(&old["objects"][1]), None),
+            Difference::new("removed", Some(&json!(true)), None),
+        ];
+        assert_eq!(out, expected);
+        assert_eq!(out[0].change, ChangeKind::Added);
+        assert_eq!(out[1].change, ChangeKind::Changed);
+        assert_eq!(out[3].change, ChangeKind::Removed);
+
+        out.clear();
+        diff_values("", &old, &old, &mut out);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_write_text() -> Result<()> {
+        let diff = DiscDiff {
+            files: vec![
+                FileDiff {
+                    path: "qp.bin/bin/e/ahk.bin".into(),
+                    change: ChangeKind::Changed,
+                    old_size: Some(100),
+                    new_size: Some(120),
+                    details: vec![
+                        Difference::new("objects[3].data", Some(&json!(1)), Some(&json!(2))),
+                        Difference::new("script.blocks[7]", None, None),
+                    ],
+                },
+                FileDiff {
+                    path: "new.txt".into(),
+                    change: ChangeKind::Added,
+                    old_size: None,
+                    new_size: Some(5),
+                    details: vec![],
+                },
+            ],
+        };
+        let mut text = vec![];
+        diff.write_text(&mut text)?;
+        let expected = "\
+~ qp.bin/bin/e/ahk.bin (100 -> 120 bytes)
+    ~ objects[3].data: 1 -> 2
+    ~ script.blocks[7]
++ new.txt (5 bytes)
+";
+        assert_eq!(String::from_utf8(text)?, expected);
+        assert_eq!(diff.count(ChangeKind::Added), 1);
+        assert_eq!(diff.count(ChangeKind::Changed), 1);
+        Ok(())
+    }
+}
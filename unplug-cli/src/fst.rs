@@ -1,12 +1,21 @@
 use crate::args::list::Options;
 use anyhow::{bail, Result};
 use log::info;
+use std::collections::BTreeSet;
 use std::fs::{self, File};
 use std::path::Path;
 use unicase::UniCase;
 use unplug::common::io::copy_buffered;
 use unplug::dvd::{Entry, EntryId, FileTree, Glob, OpenFile};
 
+/// Returns the paths of all of the files in `tree`, without a leading slash.
+pub fn file_paths(tree: &FileTree) -> BTreeSet<String> {
+    tree.recurse()
+        .filter(|&(_, id)| tree[id].is_file())
+        .map(|(path, _)| path.trim_start_matches('/').to_owned())
+        .collect()
+}
+
 /// Lists files in an FST matched by `glob`.
 pub fn list_files(tree: &FileTree, args: &Options, glob: &Glob) -> Result<()> {
     let get_file = |(p, e)| tree[e].file().map(|f| (p, f));
@@ -34,13 +43,15 @@ pub fn list_files(tree: &FileTree, args: &Options, glob: &Glob) -> Result<()> {
     Ok(())
 }
 
-/// Extracts a file from a disc or archive.
+/// Extracts a file from a disc or archive. If `decompress` is set, Yaz0 and Yay0 files are
+/// decompressed as they are extracted.
 pub fn extract_file(
     source: &mut dyn OpenFile,
     entry: EntryId,
     entry_path: &str,
     out_dir: &Path,
     out_name: Option<&str>,
+    decompress: bool,
     io_buf: &mut [u8],
 ) -> Result<()> {
     let file = source.query_file(entry);
@@ -50,7 +61,10 @@ pub fn extract_file(
         Entry::File(_) => {
             info!("Extracting {}", entry_path);
             let mut writer = File::create(&out_path)?;
-            let mut reader = source.open_file(entry)?;
+            let mut reader = match decompress {
+                true => source.open_file_decompressed(entry)?,
+                false => source.open_file(entry)?,
+            };
             copy_buffered(&mut reader, &mut writer, io_buf)?;
         }
         Entry::Directory(dir) => {
@@ -59,7 +73,7 @@ pub fn extract_file(
                 let child_file = source.query_file(child);
                 let child_path =
                     format!("{}/{}", entry_path.trim_end_matches('/'), child_file.name());
-                extract_file(source, child, &child_path, &out_path, None, io_buf)?;
+                extract_file(source, child, &child_path, &out_path, None, decompress, io_buf)?;
             }
         }
     }
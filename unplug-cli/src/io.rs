@@ -1,8 +1,9 @@
 use anyhow::Result;
 use std::convert::{AsRef, TryInto};
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, Cursor, Read, Seek, SeekFrom, Stdout, Write};
 use std::path::Path;
+use unplug::common::{Compression, ReadSeek};
 
 /// A cursor around a byte slice.
 pub type MemoryCursor = Cursor<Box<[u8]>>;
@@ -49,6 +50,15 @@ pub fn copy_into_memory(mut reader: impl Read + Seek) -> io::Result<MemoryCursor
     Ok(Cursor::new(buf.into_boxed_slice()))
 }
 
+/// Opens the file at `path` for reading. If `compression` is set, the file's data is compressed in
+/// memory and the returned reader reads the compressed data.
+pub fn open_compressed(path: &Path, compression: Option<Compression>) -> Result<Box<dyn ReadSeek>> {
+    Ok(match compression {
+        Some(compression) => Box::new(Cursor::new(compression.compress(&fs::read(path)?))),
+        None => Box::new(File::open(path)?),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
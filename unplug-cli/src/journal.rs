@@ -0,0 +1,330 @@
+use anyhow::{bail, Result};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use log::{debug, info, warn};
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::{self, File, TryLockError};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Magic number at the start of a journal file.
+const JOURNAL_MAGIC: [u8; 8] = *b"UNPLUGJL";
+/// The size of the blocks which the journal saves. Saving large blocks means the journal has to be
+/// synced less often.
+const BLOCK_SIZE: u64 = 0x10_0000;
+
+/// Returns the path of the journal file for the file at `path`.
+pub fn journal_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".journal");
+    name.into()
+}
+
+/// Restores the original contents of `file` which are saved in the journal at `journal_path` and
+/// then deletes the journal.
+fn recover_file(file: &mut File, journal_path: &Path) -> Result<()> {
+    warn!("Recovering from an interrupted write using {}", journal_path.display());
+    let mut journal = BufReader::new(File::open(journal_path)?);
+    let mut magic = [0u8; 8];
+    let header = journal.read_exact(&mut magic).and_then(|()| journal.read_u64::<LE>());
+    match header {
+        // The header is synced along with the first block, so if it is incomplete then nothing was
+        // written to the file
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => (),
+        Err(e) => return Err(e.into()),
+        Ok(_) if magic != JOURNAL_MAGIC => warn!("The journal is invalid and will be ignored"),
+        Ok(original_len) => {
+            let mut buf = vec![];
+            let mut num_blocks = 0;
+            // A block is only written to the file after it is completely saved to the journal, so
+            // a truncated block can be ignored
+            while let Some((offset, len)) = read_block_header(&mut journal)? {
+                buf.resize(len as usize, 0);
+                match journal.read_exact(&mut buf) {
+                    Ok(()) => (),
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                    Err(e) => return Err(e.into()),
+                }
+                debug!("Restoring {:#x} bytes at {:#x}", len, offset);
+                file.seek(SeekFrom::Start(offset))?;
+                file.write_all(&buf)?;
+                num_blocks += 1;
+            }
+            file.set_len(original_len)?;
+            file.sync_all()?;
+            info!("Restored {} block(s)", num_blocks);
+        }
+    }
+    fs::remove_file(journal_path)?;
+    Ok(())
+}
+
+/// Reads the offset and size of the next block in a journal, returning `None` at the end.
+fn read_block_header(journal: &mut impl Read) -> io::Result<Option<(u64, u32)>> {
+    let header = journal.read_u64::<LE>().and_then(|o| Ok((o, journal.read_u32::<LE>()?)));
+    match header {
+        Ok(header) => Ok(Some(header)),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// A file whose writes can be journaled.
+struct JournaledFile {
+    file: File,
+    journal_path: PathBuf,
+    /// True if a transaction is in progress.
+    active: bool,
+    /// The journal file, if anything has been written during the transaction.
+    journal: Option<BufWriter<File>>,
+    /// The length of the file when the journal was created.
+    original_len: u64,
+    /// The indices of the blocks which have been saved to the journal.
+    saved: HashSet<u64>,
+}
+
+impl JournaledFile {
+    /// Saves the original contents of the blocks overlapping a region to the journal if they have
+    /// not been saved yet.
+    fn save_blocks(&mut self, offset: u64, len: u64) -> io::Result<()> {
+        if !self.active || len == 0 {
+            return Ok(());
+        }
+        let first = offset / BLOCK_SIZE;
+        let last = (offset + len - 1) / BLOCK_SIZE;
+        let blocks = (first..=last).filter(|b| !self.saved.contains(b)).collect::<Vec<_>>();
+        if blocks.is_empty() {
+            return Ok(());
+        }
+
+        let pos = self.file.stream_position()?;
+        if self.journal.is_none() {
+            self.original_len = self.file.seek(SeekFrom::End(0))?;
+            debug!("Creating journal {}", self.journal_path.display());
+            let mut journal = BufWriter::new(File::create(&self.journal_path)?);
+            journal.write_all(&JOURNAL_MAGIC)?;
+            journal.write_u64::<LE>(self.original_len)?;
+            self.journal = Some(journal);
+        }
+        let journal = self.journal.as_mut().unwrap();
+        let mut buf = vec![];
+        for block in blocks {
+            // Data past the original end of the file does not need to be saved because recovery
+            // truncates the file
+            let start = block * BLOCK_SIZE;
+            let end = (start + BLOCK_SIZE).min(self.original_len);
+            if start < end {
+                buf.resize((end - start) as usize, 0);
+                self.file.seek(SeekFrom::Start(start))?;
+                self.file.read_exact(&mut buf)?;
+                journal.write_u64::<LE>(start)?;
+                journal.write_u32::<LE>(buf.len() as u32)?;
+                journal.write_all(&buf)?;
+            }
+            self.saved.insert(block);
+        }
+        journal.flush()?;
+        journal.get_ref().sync_data()?;
+        self.file.seek(SeekFrom::Start(pos))?;
+        Ok(())
+    }
+
+    /// Ends the current transaction and deletes the journal.
+    fn finish(&mut self) -> Result<()> {
+        if self.journal.take().is_some() {
+            self.file.sync_all()?;
+            fs::remove_file(&self.journal_path)?;
+        }
+        self.active = false;
+        self.saved.clear();
+        Ok(())
+    }
+}
+
+/// A handle to a file whose writes can be made crash-safe using a write-ahead journal.
+///
+/// While a transaction is in progress, the original contents of every region which is written to
+/// are first saved to a journal file next to the file. If the transaction does not finish, the
+/// journal is used to roll the file back the next time it is opened. Clones of a handle share the
+/// same file.
+#[derive(Clone)]
+pub struct JournalHandle {
+    inner: Arc<Mutex<JournaledFile>>,
+}
+
+impl JournalHandle {
+    /// Wraps `file`, which was opened from `path`. The file is locked until every handle to it is
+    /// dropped so that no other process can edit it or recover its journal while it is in use. If
+    /// the file has a journal from an interrupted transaction, it is recovered first.
+    pub fn open(path: &Path, mut file: File) -> Result<Self> {
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => {
+                bail!("{} is being edited by another process", path.display())
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        let journal_path = journal_path(path);
+        if journal_path.exists() {
+            recover_file(&mut file, &journal_path)?;
+            file.rewind()?;
+        }
+        let inner = JournaledFile {
+            file,
+            journal_path,
+            active: false,
+            journal: None,
+            original_len: 0,
+            saved: HashSet::new(),
+        };
+        Ok(Self { inner: Arc::new(Mutex::new(inner)) })
+    }
+
+    /// Begins a transaction. Writes will be journaled until `commit()` or `rollback()` is called.
+    pub fn begin(&self) {
+        self.lock().active = true;
+    }
+
+    /// Ends the current transaction, keeping all of the changes made during it.
+    pub fn commit(&self) -> Result<()> {
+        self.lock().finish()
+    }
+
+    /// Ends the current transaction and restores the original contents of everything written
+    /// during it.
+    pub fn rollback(&self) -> Result<()> {
+        let mut inner = self.lock();
+        if inner.journal.take().is_some() {
+            let journal_path = inner.journal_path.clone();
+            recover_file(&mut inner.file, &journal_path)?;
+        }
+        inner.finish()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, JournaledFile> {
+        self.inner.lock().unwrap()
+    }
+}
+
+impl Read for JournalHandle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.lock().file.read(buf)
+    }
+}
+
+impl Write for JournalHandle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inner = self.lock();
+        let offset = inner.file.stream_position()?;
+        inner.save_blocks(offset, buf.len() as u64)?;
+        inner.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().file.flush()
+    }
+}
+
+impl Seek for JournalHandle {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.lock().file.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use tempfile::NamedTempFile;
+
+    /// Creates a temporary file which is a few blocks long.
+    fn create_file() -> Result<(NamedTempFile, Vec<u8>)> {
+        let original = (0..(BLOCK_SIZE * 3 + 0x100)).map(|i| i as u8).collect::<Vec<_>>();
+        let mut file = NamedTempFile::new()?;
+        file.write_all(&original)?;
+        Ok((file, original))
+    }
+
+    fn open(path: &Path) -> Result<JournalHandle> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        JournalHandle::open(path, file)
+    }
+
+    /// Writes to a block in the middle, across a block boundary, and past the end of the file.
+    fn write_changes(handle: &mut JournalHandle) -> Result<()> {
+        handle.seek(SeekFrom::Start(0x100))?;
+        handle.write_all(&[0xaa; 0x100])?;
+        handle.seek(SeekFrom::Start(BLOCK_SIZE * 2 - 0x10))?;
+        handle.write_all(&[0xbb; 0x20])?;
+        handle.seek(SeekFrom::End(0))?;
+        handle.write_all(&[0xcc; 0x100])?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit() -> Result<()> {
+        let (file, original) = create_file()?;
+        let mut handle = open(file.path())?;
+        handle.begin();
+        write_changes(&mut handle)?;
+        assert!(journal_path(file.path()).exists());
+        handle.commit()?;
+        assert!(!journal_path(file.path()).exists());
+
+        let data = fs::read(file.path())?;
+        assert_eq!(data.len(), original.len() + 0x100);
+        assert_eq!(data[0x100..0x200], [0xaa; 0x100]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollback() -> Result<()> {
+        let (file, original) = create_file()?;
+        let mut handle = open(file.path())?;
+        handle.begin();
+        write_changes(&mut handle)?;
+        handle.rollback()?;
+        assert!(!journal_path(file.path()).exists());
+        assert_eq!(fs::read(file.path())?, original);
+
+        // Writes outside of a transaction are not journaled
+        handle.seek(SeekFrom::Start(0))?;
+        handle.write_all(&[0xdd])?;
+        assert!(!journal_path(file.path()).exists());
+        assert_eq!(fs::read(file.path())?[0], 0xdd);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lock() -> Result<()> {
+        let (file, _) = create_file()?;
+        let handle = open(file.path())?;
+        assert!(open(file.path()).is_err());
+        drop(handle);
+        open(file.path())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_interrupted() -> Result<()> {
+        let (file, original) = create_file()?;
+        let mut handle = open(file.path())?;
+        handle.begin();
+        write_changes(&mut handle)?;
+        drop(handle);
+        assert!(journal_path(file.path()).exists());
+
+        // Truncate the journal in the middle of the last block to simulate a crash while it was
+        // being written
+        let journal = journal_path(file.path());
+        let len = fs::metadata(&journal)?.len();
+        OpenOptions::new().write(true).open(&journal)?.set_len(len - 1)?;
+        drop(open(file.path())?);
+        assert!(!journal.exists());
+        let data = fs::read(file.path())?;
+        assert_eq!(data.len(), original.len());
+        assert_eq!(data[..(BLOCK_SIZE as usize)], original[..(BLOCK_SIZE as usize)]);
+        Ok(())
+    }
+}
@@ -3,10 +3,16 @@ pub mod commands;
 pub mod common;
 pub mod config;
 pub mod context;
+pub mod diff;
 pub mod fst;
 pub mod id;
 pub mod io;
+pub mod journal;
 pub mod json;
 pub mod msg;
+pub mod overlay;
 pub mod playback;
 pub mod terminal;
+#[cfg(test)]
+mod test;
+pub mod verify;
@@ -0,0 +1,503 @@
+use crate::context::{edit_disc, open_disc_read};
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+use unplug::common::io::{copy_buffered, BUFFER_SIZE};
+use unplug::common::ReadSeek;
+use unplug::dvd::{DirectoryEntry, DiscStream, Dol, EntryId, FileEntry, FileTree, OpenFile};
+
+/// The version of the overlay format which is written and accepted.
+const OVERLAY_VERSION: u32 = 1;
+/// The name of the manifest file inside an overlay directory.
+const MANIFEST_NAME: &str = "overlay.json";
+/// The name of the directory containing file data inside an overlay directory.
+const DATA_DIR_NAME: &str = "files";
+
+/// Describes how an overlay differs from its base disc. Paths are relative to the disc root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OverlayManifest {
+    pub version: u32,
+    /// Files and directories on the base disc which were removed.
+    pub removed: BTreeSet<String>,
+    /// Directories which are not on the base disc.
+    pub dirs: BTreeSet<String>,
+    /// Files which were changed or added, mapped to the numbers of their data files.
+    pub files: BTreeMap<String, u32>,
+}
+
+impl OverlayManifest {
+    /// Returns true if the manifest does not have any changes.
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.dirs.is_empty() && self.files.is_empty()
+    }
+}
+
+/// Converts a path yielded by `FileTree::recurse()` into a manifest path.
+fn manifest_path(path: &str) -> String {
+    path.trim_matches('/').to_owned()
+}
+
+/// A disc which stores all changes in a separate overlay directory on top of a read-only base
+/// disc. The base disc is never written to.
+pub struct OverlayDisc {
+    /// The base disc.
+    base: DiscStream<Box<dyn ReadSeek>>,
+    /// The path to the base disc image.
+    base_path: PathBuf,
+    /// The path to the overlay directory.
+    dir: PathBuf,
+    /// The base file tree with the overlay's changes applied.
+    files: FileTree,
+    /// Maps files in the tree to the numbers of the overlay data files holding their contents.
+    /// Files which are not in this map are read from the base disc.
+    data: HashMap<EntryId, u32>,
+    /// The number to use for the next data file.
+    next_data: u32,
+    /// True if a transaction is in progress and changes should not be saved yet.
+    in_transaction: bool,
+}
+
+impl OverlayDisc {
+    /// Opens the overlay in `dir` on top of the disc image at `base_path`. The overlay directory
+    /// does not need to exist yet.
+    pub fn open(base_path: &Path, dir: &Path) -> Result<Self> {
+        let base = open_disc_read(base_path)?;
+        let files = base.files().clone();
+        let mut overlay = Self {
+            base,
+            base_path: base_path.to_owned(),
+            dir: dir.to_owned(),
+            files,
+            data: HashMap::new(),
+            next_data: 0,
+            in_transaction: false,
+        };
+        if let Some(manifest) = overlay.read_manifest()? {
+            overlay.load(&manifest)?;
+        }
+        overlay.next_data = overlay.find_data_numbers()?.last().map_or(0, |n| n + 1);
+        Ok(overlay)
+    }
+
+    /// Returns the base disc.
+    pub fn base(&self) -> &DiscStream<Box<dyn ReadSeek>> {
+        &self.base
+    }
+
+    /// Returns the file tree with the overlay's changes applied.
+    pub fn files(&self) -> &FileTree {
+        &self.files
+    }
+
+    /// Returns the disc's game ID (e.g. `GGTE01`).
+    pub fn game_id(&self) -> String {
+        self.base.game_id()
+    }
+
+    /// Reads the disc's main.dol.
+    pub fn read_dol(&mut self) -> Result<Dol> {
+        Ok(self.base.read_dol()?)
+    }
+
+    /// Opens the disc's main.dol for reading.
+    pub fn open_dol(&mut self) -> Result<Box<dyn ReadSeek + '_>> {
+        Ok(self.base.open_dol()?.1)
+    }
+
+    /// Opens the file with ID `id` for reading.
+    pub fn open_file(&mut self, id: EntryId) -> Result<Box<dyn ReadSeek + '_>> {
+        match self.data.get(&id) {
+            Some(&number) => Ok(Box::new(File::open(self.data_path(number))?)),
+            None => Ok(self.base.open_file(id)?),
+        }
+    }
+
+    /// Overwrites the file with ID `id` using data read from `reader`.
+    pub fn replace_file(&mut self, id: EntryId, reader: &mut dyn ReadSeek) -> Result<()> {
+        self.files.file(id)?;
+        let (number, size) = self.write_data(reader)?;
+        self.files.file_mut(id)?.size = size;
+        self.data.insert(id, number);
+        self.save()
+    }
+
+    /// Creates a new file at `path` using data read from `reader`.
+    pub fn create_file_at(&mut self, path: &str, mut reader: impl ReadSeek) -> Result<EntryId> {
+        let (parent, name) = self.files.parent_and_name(path)?;
+        let (number, size) = self.write_data(&mut reader)?;
+        let id = self.files.try_insert(parent, FileEntry::new(name, 0, size).into())?;
+        self.data.insert(id, number);
+        self.save()?;
+        Ok(id)
+    }
+
+    /// Creates a new empty directory at `path`.
+    pub fn create_dir_at(&mut self, path: &str) -> Result<EntryId> {
+        let (parent, name) = self.files.parent_and_name(path)?;
+        let id = self.files.try_insert(parent, DirectoryEntry::new(name).into())?;
+        self.save()?;
+        Ok(id)
+    }
+
+    /// Removes the file or directory at `path`.
+    pub fn remove_at(&mut self, path: &str) -> Result<()> {
+        let id = self.files.at(path)?;
+        let ids = self.files.recurse_from(id).map(|(_, id)| id).collect::<Vec<_>>();
+        self.files.remove(id)?;
+        for id in ids {
+            self.data.remove(&id);
+        }
+        self.save()
+    }
+
+    /// Moves the file or directory at `path` to `new_path`.
+    pub fn rename_at(&mut self, path: &str, new_path: &str) -> Result<()> {
+        let id = self.files.at(path)?;
+        let (new_parent, new_name) = self.files.parent_and_name(new_path)?;
+        self.files.rename(id, new_parent, new_name)?;
+        // The base disc can only provide files at their original paths, so anything which moved
+        // needs to be copied into the overlay
+        let moved = self.files.recurse_from(id).map(|(_, id)| id).collect::<Vec<_>>();
+        for id in moved {
+            if self.files[id].is_file() && !self.data.contains_key(&id) {
+                let mut reader = self.base.open_file(id)?;
+                let (number, _) = write_data(&self.dir, self.next_data, &mut reader)?;
+                self.next_data += 1;
+                self.data.insert(id, number);
+            }
+        }
+        self.save()
+    }
+
+    /// Builds a manifest describing the current changes.
+    pub fn manifest(&self) -> OverlayManifest {
+        let base = self.base.files();
+        let mut manifest = OverlayManifest { version: OVERLAY_VERSION, ..Default::default() };
+        for (path, id) in base.recurse() {
+            let path = manifest_path(&path);
+            if path.is_empty()
+                || manifest.removed.iter().any(|r| path.starts_with(&format!("{}/", r)))
+            {
+                continue;
+            }
+            match self.files.at(&path) {
+                Ok(merged) if self.files[merged].is_dir() == base[id].is_dir() => (),
+                _ => {
+                    manifest.removed.insert(path);
+                }
+            }
+        }
+        for (path, id) in self.files.recurse() {
+            let path = manifest_path(&path);
+            if path.is_empty() {
+                continue;
+            }
+            if self.files[id].is_dir() {
+                let in_base = base.at(&path).is_ok_and(|b| base[b].is_dir());
+                if !in_base || manifest.removed.contains(&path) {
+                    manifest.dirs.insert(path);
+                }
+            } else if let Some(&number) = self.data.get(&id) {
+                manifest.files.insert(path, number);
+            }
+        }
+        manifest
+    }
+
+    /// Returns true if the overlay has any changes.
+    pub fn is_modified(&self) -> bool {
+        !self.manifest().is_empty()
+    }
+
+    /// Deletes all of the overlay's changes.
+    pub fn discard(&mut self) -> Result<()> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        self.files = self.base.files().clone();
+        self.data.clear();
+        self.next_data = 0;
+        Ok(())
+    }
+
+    /// Begins a transaction. Changes will not be saved to the overlay directory until `commit()` is
+    /// called.
+    pub fn begin(&mut self) {
+        self.in_transaction = true;
+    }
+
+    /// Ends the current transaction and saves all of the changes made during it.
+    pub fn commit(&mut self) -> Result<()> {
+        self.in_transaction = false;
+        self.save()
+    }
+
+    /// Ends the current transaction and throws away all of the changes made during it. The file
+    /// tree is reloaded from the saved manifest.
+    pub fn rollback(&mut self) -> Result<()> {
+        self.in_transaction = false;
+        self.files = self.base.files().clone();
+        self.data.clear();
+        let manifest = self.read_manifest()?.unwrap_or_default();
+        self.load(&manifest)?;
+        self.delete_unused_data(&manifest)
+    }
+
+    /// Writes a new disc image to `output` which combines the base disc with the overlay's changes.
+    pub fn bake(&self, output: &Path) -> Result<()> {
+        let manifest = self.manifest();
+        let dir =
+            output.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+        let temp = NamedTempFile::new_in(dir)?;
+        info!("Copying {}", self.base_path.display());
+        fs::copy(&self.base_path, temp.path())?;
+        edit_disc(temp.path(), |disc| {
+            for path in &manifest.removed {
+                info!("Removing {}", path);
+                disc.remove_at(path)?;
+            }
+            for path in &manifest.dirs {
+                info!("Creating {}", path);
+                disc.create_dir_at(path)?;
+            }
+            for (path, &number) in &manifest.files {
+                let reader = File::open(self.data_path(number))?;
+                match disc.files().at(path) {
+                    Ok(id) => {
+                        info!("Writing {}", path);
+                        disc.replace_file(id, reader)?;
+                    }
+                    Err(_) => {
+                        info!("Adding {}", path);
+                        disc.create_file_at(path, reader)?;
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        temp.persist(output)?;
+        Ok(())
+    }
+
+    /// Applies the changes in `manifest` to the file tree.
+    fn load(&mut self, manifest: &OverlayManifest) -> Result<()> {
+        for path in &manifest.removed {
+            match self.files.at(path) {
+                Ok(id) => {
+                    self.files.remove(id)?;
+                }
+                Err(_) => warn!("Overlay removes {}, but it is not on the base disc", path),
+            }
+        }
+        for path in &manifest.dirs {
+            let (parent, name) = self.files.parent_and_name(path)?;
+            self.files.try_insert(parent, DirectoryEntry::new(name).into())?;
+        }
+        for (path, &number) in &manifest.files {
+            let size = data_size(&self.data_path(number))?;
+            let id = match self.files.at(path) {
+                Ok(id) => {
+                    self.files.file_mut(id)?.size = size;
+                    id
+                }
+                Err(_) => {
+                    let (parent, name) = self.files.parent_and_name(path)?;
+                    self.files.try_insert(parent, FileEntry::new(name, 0, size).into())?
+                }
+            };
+            self.data.insert(id, number);
+        }
+        Ok(())
+    }
+
+    /// Reads the manifest from the overlay directory if it has one.
+    fn read_manifest(&self) -> Result<Option<OverlayManifest>> {
+        let manifest_path = self.dir.join(MANIFEST_NAME);
+        if !manifest_path.exists() {
+            return Ok(None);
+        }
+        let reader = BufReader::new(File::open(&manifest_path)?);
+        let manifest: OverlayManifest = serde_json::from_reader(reader)?;
+        if manifest.version != OVERLAY_VERSION {
+            bail!("{} has an unsupported version ({})", self.dir.display(), manifest.version);
+        }
+        Ok(Some(manifest))
+    }
+
+    /// Writes the manifest and then deletes data files which it no longer references. This does
+    /// nothing while a transaction is in progress.
+    fn save(&mut self) -> Result<()> {
+        if self.in_transaction {
+            return Ok(());
+        }
+        let manifest = self.manifest();
+        fs::create_dir_all(&self.dir)?;
+        let mut temp = NamedTempFile::new_in(&self.dir)?;
+        let mut writer = BufWriter::new(&mut temp);
+        serde_json::to_writer_pretty(&mut writer, &manifest)?;
+        writer.flush()?;
+        drop(writer);
+        // The manifest must be on disk before it replaces the old one or else a crash could lose it
+        temp.as_file().sync_all()?;
+        temp.persist(self.dir.join(MANIFEST_NAME))?;
+        self.delete_unused_data(&manifest)
+    }
+
+    /// Deletes data files which `manifest` does not reference.
+    fn delete_unused_data(&self, manifest: &OverlayManifest) -> Result<()> {
+        let used = manifest.files.values().copied().collect::<BTreeSet<_>>();
+        for number in self.find_data_numbers()? {
+            if !used.contains(&number) {
+                debug!("Deleting unused overlay data file {}", number);
+                fs::remove_file(self.data_path(number))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies `reader` into a new data file and returns its number and size.
+    fn write_data(&mut self, reader: &mut dyn ReadSeek) -> Result<(u32, u32)> {
+        let result = write_data(&self.dir, self.next_data, reader)?;
+        self.next_data += 1;
+        Ok(result)
+    }
+
+    /// Returns the path to the data file numbered `number`.
+    fn data_path(&self, number: u32) -> PathBuf {
+        data_path(&self.dir, number)
+    }
+
+    /// Returns the numbers of all data files in the overlay directory in ascending order.
+    fn find_data_numbers(&self) -> Result<Vec<u32>> {
+        let data_dir = self.dir.join(DATA_DIR_NAME);
+        if !data_dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut numbers = vec![];
+        for entry in fs::read_dir(data_dir)? {
+            if let Some(number) = entry?.file_name().to_str().and_then(|n| n.parse().ok()) {
+                numbers.push(number);
+            }
+        }
+        numbers.sort_unstable();
+        Ok(numbers)
+    }
+}
+
+/// Returns the path to the data file numbered `number` in the overlay directory `dir`.
+fn data_path(dir: &Path, number: u32) -> PathBuf {
+    dir.join(DATA_DIR_NAME).join(number.to_string())
+}
+
+/// Returns the size of a data file, making sure that it can fit on a disc.
+fn data_size(path: &Path) -> Result<u32> {
+    let size = fs::metadata(path)?.len();
+    u32::try_from(size).map_err(|_| anyhow!("{} is too large", path.display()))
+}
+
+/// Copies `reader` into the data file numbered `number` in `dir` and returns its number and size.
+fn write_data(dir: &Path, number: u32, reader: &mut dyn ReadSeek) -> Result<(u32, u32)> {
+    let path = data_path(dir, number);
+    fs::create_dir_all(path.parent().unwrap())?;
+    debug!("Writing overlay data file {}", path.display());
+    let mut writer = BufWriter::new(File::create(&path)?);
+    copy_buffered(reader, &mut writer, &mut [0u8; BUFFER_SIZE])?;
+    writer.flush()?;
+    drop(writer);
+    Ok((number, data_size(&path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::make_test_iso;
+    use std::io::{Cursor, Read};
+
+    /// Opens an overlay in `dir` on top of a test disc and makes one of each kind of change to it.
+    fn make_test_overlay(dir: &Path) -> Result<(NamedTempFile, OverlayDisc)> {
+        let iso = make_test_iso(&[
+            ("a.bin", b"aaaa"),
+            ("d/b.bin", b"bbbb"),
+            ("d/c.bin", b"cccc"),
+            ("e.bin", b"eeee"),
+        ]);
+        let mut overlay = OverlayDisc::open(iso.path(), dir)?;
+        assert!(!overlay.is_modified());
+        overlay.rename_at("d/b.bin", "moved.bin")?;
+        overlay.remove_at("d/c.bin")?;
+        overlay.remove_at("e.bin")?;
+        overlay.create_dir_at("e.bin")?;
+        overlay.create_file_at("e.bin/f.bin", Cursor::new(b"ffff"))?;
+        let id = overlay.files().at("a.bin")?;
+        overlay.replace_file(id, &mut Cursor::new(b"AAAAAAAA"))?;
+        Ok((iso, overlay))
+    }
+
+    fn read_file(source: &mut dyn OpenFile, path: &str) -> Result<Vec<u8>> {
+        let mut data = vec![];
+        source.open_file_at(path)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn read_overlay_file(overlay: &mut OverlayDisc, path: &str) -> Result<Vec<u8>> {
+        let mut data = vec![];
+        let id = overlay.files().at(path)?;
+        overlay.open_file(id)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    #[test]
+    fn test_manifest_round_trip() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let overlay_dir = dir.path().join("overlay");
+        let (iso, overlay) = make_test_overlay(&overlay_dir)?;
+        let manifest = overlay.manifest();
+        let removed = ["d/b.bin", "d/c.bin", "e.bin"];
+        assert_eq!(manifest.removed, BTreeSet::from(removed.map(String::from)));
+        assert_eq!(manifest.dirs, BTreeSet::from(["e.bin".to_owned()]));
+        let files =
+            [("a.bin".to_owned(), 2), ("e.bin/f.bin".to_owned(), 1), ("moved.bin".to_owned(), 0)];
+        assert_eq!(manifest.files, BTreeMap::from(files));
+        drop(overlay);
+
+        let mut overlay = OverlayDisc::open(iso.path(), &overlay_dir)?;
+        assert_eq!(overlay.manifest(), manifest);
+        assert_eq!(read_overlay_file(&mut overlay, "a.bin")?, b"AAAAAAAA");
+        assert_eq!(read_overlay_file(&mut overlay, "moved.bin")?, b"bbbb");
+        assert_eq!(read_overlay_file(&mut overlay, "e.bin/f.bin")?, b"ffff");
+        assert!(overlay.files().at("d/b.bin").is_err());
+        assert!(overlay.files().at("d/c.bin").is_err());
+        assert!(overlay.files()[overlay.files().at("e.bin")?].is_dir());
+        assert_eq!(fs::read_dir(overlay_dir.join(DATA_DIR_NAME))?.count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bake() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (iso, overlay) = make_test_overlay(&dir.path().join("overlay"))?;
+        let output = dir.path().join("baked.iso");
+        overlay.bake(&output)?;
+
+        let mut disc = open_disc_read(&output)?;
+        assert_eq!(read_file(&mut disc, "a.bin")?, b"AAAAAAAA");
+        assert_eq!(read_file(&mut disc, "moved.bin")?, b"bbbb");
+        assert_eq!(read_file(&mut disc, "e.bin/f.bin")?, b"ffff");
+        assert!(disc.files().at("d/b.bin").is_err());
+        assert!(disc.files().at("d/c.bin").is_err());
+        assert!(disc.files()[disc.files().at("e.bin")?].is_dir());
+
+        // The base disc must not be touched
+        let mut base = open_disc_read(iso.path())?;
+        assert_eq!(read_file(&mut base, "a.bin")?, b"aaaa");
+        assert_eq!(read_file(&mut base, "e.bin")?, b"eeee");
+        Ok(())
+    }
+}
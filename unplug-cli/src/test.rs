@@ -0,0 +1,73 @@
+use std::io::{Cursor, Write};
+use tempfile::NamedTempFile;
+use unplug::common::WriteStructExt;
+use unplug::dvd::{ArchiveBuilder, DiscBuilder, DiscHeader, DolHeader};
+
+/// Size of the discs created by `make_test_disc()`.
+pub const TEST_DISC_SIZE: u32 = 0x100000;
+
+/// Size of bi2.bin.
+const BI2_SIZE: usize = 0x2000;
+/// Size of the apploader header.
+const APPLOADER_HEADER_SIZE: usize = 0x20;
+
+/// Builds a small GGTE01 disc image containing `files`, which are pairs of paths and contents.
+/// Directories are created as needed.
+pub fn make_test_disc(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut header = DiscHeader::new();
+    header.game_code = *b"GGTE";
+    header.maker_code = *b"01";
+    header.disc_size = TEST_DISC_SIZE;
+    let mut boot = vec![];
+    boot.write_struct(&header).unwrap();
+    let mut dol = vec![];
+    dol.write_struct(&DolHeader::new()).unwrap();
+    dol.resize(0x100, 0);
+
+    let mut builder = DiscBuilder::new(
+        &mut boot.as_slice(),
+        &mut [0u8; BI2_SIZE].as_slice(),
+        &mut [0u8; APPLOADER_HEADER_SIZE].as_slice(),
+        &mut dol.as_slice(),
+    )
+    .unwrap();
+    for &(path, data) in files {
+        let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let mut parent = builder.files().root();
+        for component in dir.split('/').filter(|c| !c.is_empty()) {
+            parent = match builder.files().child(parent, component) {
+                Some(child) => child,
+                None => builder.create_dir(parent, component).unwrap(),
+            };
+        }
+        builder.create_file(parent, name, move || Ok(Cursor::new(data))).unwrap();
+    }
+    let mut cursor = Cursor::new(vec![]);
+    builder.write_to(&mut cursor).unwrap();
+    cursor.into_inner()
+}
+
+/// Writes a disc image built by `make_test_disc()` to a temporary file.
+pub fn make_test_iso(files: &[(&str, &[u8])]) -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(&make_test_disc(files)).unwrap();
+    file.flush().unwrap();
+    file
+}
+
+/// Builds a U8 archive containing `files`, which are pairs of paths and contents. Directories are
+/// created as needed.
+pub fn make_test_archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut builder = ArchiveBuilder::new();
+    for &(path, data) in files {
+        if let Some((dir, _)) = path.rsplit_once('/') {
+            if builder.files().at(dir).is_err() {
+                builder.add_dir_at(dir).unwrap();
+            }
+        }
+        builder.add_file_at(path, move || Ok(Cursor::new(data))).unwrap();
+    }
+    let mut cursor = Cursor::new(vec![]);
+    builder.write_to(&mut cursor).unwrap();
+    cursor.into_inner()
+}
@@ -0,0 +1,147 @@
+use crate::common::FileHash;
+use crate::context::OpenContext;
+use anyhow::{bail, Result};
+use log::{debug, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display};
+use std::io::{Seek, SeekFrom};
+use unicase::UniCase;
+use unplug::common::ReadSeek;
+use unplug::data::{CleanFile, CLEAN_GAME_ID};
+
+/// Path to display for main.dol.
+const DOL_PATH: &str = "sys/main.dol";
+
+/// Describes how a file differs from a clean copy of the game.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    /// The file's contents do not match the clean file.
+    Modified,
+    /// The file does not exist on the disc.
+    Missing,
+    /// The file's data extends past the end of the disc image.
+    Corrupt,
+    /// The file is not on a clean disc.
+    Added,
+}
+
+impl Display for FileStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Modified => "modified",
+            Self::Missing => "missing",
+            Self::Corrupt => "corrupt",
+            Self::Added => "added",
+        })
+    }
+}
+
+/// The results of verifying a disc.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    /// The number of files which were checked.
+    pub checked: usize,
+    /// Each file which does not match a clean disc, sorted by path.
+    pub files: BTreeMap<String, FileStatus>,
+}
+
+impl VerifyReport {
+    /// Returns true if every file matches a clean disc.
+    pub fn is_clean(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Returns the number of files with status `status`.
+    pub fn count(&self, status: FileStatus) -> usize {
+        self.files.values().filter(|&&s| s == status).count()
+    }
+}
+
+/// Bails if clean hashes are not available for the disc in `ctx`.
+fn check_game<T: ReadSeek>(ctx: &OpenContext<T>) -> Result<()> {
+    if CleanFile::all().is_empty() {
+        bail!("unplug was built without clean file hashes");
+    }
+    let game_id = ctx.game_id()?;
+    if game_id != CLEAN_GAME_ID {
+        bail!("Only {} can be verified, but the ISO is {}", CLEAN_GAME_ID, game_id);
+    }
+    Ok(())
+}
+
+/// Returns the paths and sizes of all files on the disc, including main.dol.
+fn list_files<T: ReadSeek>(ctx: &mut OpenContext<T>) -> Result<Vec<(String, u64)>> {
+    let mut files = vec![(DOL_PATH.to_owned(), ctx.open_dol()?.seek(SeekFrom::End(0))?)];
+    let tree = ctx.disc_files()?;
+    for (path, id) in tree.recurse() {
+        if let Some(file) = tree[id].file() {
+            files.push((path.trim_start_matches('/').to_owned(), file.size.into()));
+        }
+    }
+    Ok(files)
+}
+
+/// Compares the files on the disc with the known hashes for a clean disc. If `quick` is true, only
+/// the file sizes and main.dol are checked.
+fn check_files<T: ReadSeek>(ctx: &mut OpenContext<T>, quick: bool) -> Result<VerifyReport> {
+    check_game(ctx)?;
+    let mut report = VerifyReport::default();
+    let mut found = HashSet::new();
+    for (path, size) in list_files(ctx)? {
+        report.checked += 1;
+        let Some(clean) = CleanFile::find(&path) else {
+            report.files.insert(path, FileStatus::Added);
+            continue;
+        };
+        found.insert(UniCase::ascii(clean.path));
+        if size != clean.size {
+            report.files.insert(path, FileStatus::Modified);
+            continue;
+        }
+        if quick && path != DOL_PATH {
+            continue;
+        }
+        debug!("Hashing {}", path);
+        let reader = match path.as_str() {
+            DOL_PATH => ctx.open_dol()?,
+            _ => ctx.open_disc_file_at(&path)?,
+        };
+        let hash = FileHash::of(reader)?;
+        if hash.size < size {
+            report.files.insert(path, FileStatus::Corrupt);
+        } else if hash.hash != clean.hash {
+            report.files.insert(path, FileStatus::Modified);
+        }
+    }
+    for clean in CleanFile::all() {
+        if !found.contains(&UniCase::ascii(clean.path)) {
+            report.files.insert(clean.path.to_owned(), FileStatus::Missing);
+        }
+    }
+    Ok(report)
+}
+
+/// Hashes every file on the disc and compares them with a clean disc.
+pub fn verify_disc<T: ReadSeek>(ctx: &mut OpenContext<T>) -> Result<VerifyReport> {
+    check_files(ctx, false)
+}
+
+/// Logs a warning if the disc is known to not be clean. This only checks main.dol and the sizes of
+/// files, so it is much faster than `verify_disc()` but cannot detect every change.
+pub fn warn_if_unclean<T: ReadSeek>(ctx: &mut OpenContext<T>) -> Result<()> {
+    if let Err(e) = check_game(ctx) {
+        debug!("Skipping clean disc check: {:#}", e);
+        return Ok(());
+    }
+    let report = check_files(ctx, true)?;
+    if !report.is_clean() {
+        warn!(
+            "The ISO has {} file(s) which differ from a clean copy of the game. \
+             Use `iso verify` for details.",
+            report.files.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use phf::phf_map;
+use unicase::UniCase;
+
+/// The ID of the game which clean file hashes are available for.
+pub const CLEAN_GAME_ID: &str = "GGTE01";
+
+/// The size and hash of a file on a clean copy of the game.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct CleanFile {
+    /// The path to the file on the disc. The executable is stored as `sys/main.dol`.
+    pub path: &'static str,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The SeaHash of the file's contents.
+    pub hash: u64,
+}
+
+impl CleanFile {
+    /// Returns all of the files on a clean disc, sorted by path.
+    pub fn all() -> &'static [CleanFile] {
+        CLEAN_FILES
+    }
+
+    /// Finds the clean file at `path` (case-insensitive).
+    pub fn find(path: impl AsRef<str>) -> Option<&'static CleanFile> {
+        LOOKUP.get(&UniCase::ascii(path.as_ref()))
+    }
+}
+
+macro_rules! declare_clean_files {
+    {
+        $($path:tt => { $size:literal, $hash:literal }),*
+        $(,)*
+    } => {
+        const CLEAN_FILES: &[CleanFile] = &[
+            $(CleanFile { path: $path, size: $size, hash: $hash }),*
+        ];
+
+        static LOOKUP: phf::Map<UniCase<&'static str>, CleanFile> = phf_map! {
+            $(UniCase::ascii($path) => CleanFile { path: $path, size: $size, hash: $hash }),*
+        };
+    }
+}
+
+// Generated using unplug-datagen
+include!("gen/clean_files.inc.rs");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_clean_file() {
+        for file in CleanFile::all() {
+            assert_eq!(CleanFile::find(file.path), Some(file));
+            assert_eq!(CleanFile::find(file.path.to_uppercase()), Some(file));
+        }
+        assert_eq!(CleanFile::find("foo.bin"), None);
+    }
+}
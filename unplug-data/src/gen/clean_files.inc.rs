@@ -0,0 +1,5 @@
+// Generated with unplug-datagen. DO NOT EDIT.
+// To regenerate: cargo run -p unplug-datagen -- <iso path>
+
+declare_clean_files! {
+}
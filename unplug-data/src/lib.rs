@@ -1,5 +1,6 @@
 mod animation;
 mod atc;
+mod clean_file;
 mod item;
 mod music;
 mod object;
@@ -14,6 +15,7 @@ mod suit;
 
 pub use animation::{Animation, ObjectAnimations};
 pub use atc::Atc;
+pub use clean_file::{CleanFile, CLEAN_GAME_ID};
 pub use item::{Item, ItemFlags};
 pub use music::Music;
 pub use object::{Object, ObjectClass, ObjectFlags, RawObjectPlacement};
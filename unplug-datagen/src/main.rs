@@ -5,6 +5,7 @@ use lazy_static::lazy_static;
 use log::{debug, error, info, trace, warn};
 use num_enum::TryFromPrimitive;
 use regex::{Regex, RegexSet};
+use seahash::SeaHasher;
 use simplelog::{Color, ColorChoice, ConfigBuilder, Level, LevelFilter, TermLogger, TerminalMode};
 use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
@@ -14,6 +15,7 @@ use std::ffi::CString;
 use std::fmt::Write as FmtWrite;
 use std::fmt::{self, Debug};
 use std::fs::{self, File};
+use std::hash::Hasher;
 use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::process;
@@ -21,31 +23,39 @@ use time::macros::format_description;
 use unplug::audio::metadata::SfxPlaylist;
 use unplug::audio::transport::Brsar;
 use unplug::common::{NonNoneList, ReadFrom, ReadOptionFrom, ReadSeek};
-use unplug::dvd::{ArchiveReader, DiscStream, DolHeader, OpenFile};
+use unplug::dvd::{ArchiveReader, DiscStream, DolHeader, OpenFile, SymbolMap};
 use unplug::globals::metadata::{Atc, Item, Stage, Suit};
 use unplug::globals::{GlobalsReader, Metadata};
 use unplug::stage::Actor;
 
 const MAIN_OBJECTS_ADDR: u32 = 0x8021c70c;
+const MAIN_OBJECTS_SYMBOL: &str = "ObjectTable";
 const NUM_MAIN_OBJECTS: usize = 1162;
 
 const INTERNAL_OBJECTS_ADDR: u32 = 0x80223690;
+const INTERNAL_OBJECTS_SYMBOL: &str = "InternalObjectTable";
 const NUM_INTERNAL_OBJECTS: usize = 36;
 const INTERNAL_OBJECTS_BASE_ID: i32 = 10000;
 
 const SPAWNABLES_ADDR: u32 = 0x80223a80;
+const SPAWNABLES_SYMBOL: &str = "SpawnableTable";
 const NUM_SPAWNABLES: usize = 47;
 
 const NUM_SUITS: usize = 8;
 const SUIT_ITEMS_ADDR: u32 = 0x8020a17c;
+const SUIT_ITEMS_SYMBOL: &str = "SuitItemTable";
+/// Address of the code in the suit menu initialization function which loads suit IDs. This is in
+/// the middle of the function, so it cannot be located using a symbol.
 const SUIT_ORDER_ADDR: u32 = 0x800bc318;
 const ADDI_OPCODE: u32 = 14;
 const STRIP_SUIT_LABEL: &str = "Suit";
 
 const STAGE_LIST_ADDR: u32 = 0x802244e4;
+const STAGE_LIST_SYMBOL: &str = "StageList";
 const FIRST_DEV_STAGE: i32 = 100;
 
 const MUSIC_LIST_ADDR: u32 = 0x802108a8;
+const MUSIC_LIST_SYMBOL: &str = "MusicList";
 const NUM_MUSIC: usize = 109;
 const MUSIC_EXT: &str = ".hps";
 
@@ -97,6 +107,9 @@ const SOUND_BANK_PREFIX: &str = "sfx_";
 const SOUND_BANK_EXT: &str = ".ssm";
 const SOUND_PLAYLIST_PATH: &str = "qp/sfx_sample.sem";
 
+const DOL_PATH: &str = "sys/main.dol";
+const HASH_BUFFER_SIZE: usize = 0x10000;
+
 const UNPLUG_DATA_PATH: &str = "unplug-data";
 const SRC_DIR_NAME: &str = "src";
 const TEST_FILE_NAME: &str = "lib.rs";
@@ -137,6 +150,10 @@ const MUSIC_FILE_NAME: &str = "music.inc.rs";
 const MUSIC_HEADER: &str = "declare_music! {\n";
 const MUSIC_FOOTER: &str = "}\n";
 
+const CLEAN_FILES_FILE_NAME: &str = "clean_files.inc.rs";
+const CLEAN_FILES_HEADER: &str = "declare_clean_files! {\n";
+const CLEAN_FILES_FOOTER: &str = "}\n";
+
 const SFX_GROUPS_FILE_NAME: &str = "sfx_groups.inc.rs";
 const SFX_GROUPS_HEADER: &str = "declare_sfx_groups! {\n";
 const SFX_GROUPS_FOOTER: &str = "}\n";
@@ -246,11 +263,14 @@ struct Options {
     iso: PathBuf,
     /// Path to an extracted data partition from New Play Control! Chibi-Robo.
     npc: Option<PathBuf>,
+    /// Path to a symbol map for the ISO's main.dol.
+    symbols: Option<PathBuf>,
 }
 
 fn parse_options() -> Result<Options> {
     let mut iso: Option<PathBuf> = None;
     let mut npc: Option<PathBuf> = None;
+    let mut symbols: Option<PathBuf> = None;
     let mut args = env::args().skip(1);
     while let Some(arg) = args.next() {
         match arg.as_ref() {
@@ -262,18 +282,61 @@ fn parse_options() -> Result<Options> {
                 let path = args.next().ok_or_else(|| anyhow!("--npc: path expected"))?;
                 npc = Some(PathBuf::from(path));
             }
+            "--symbols" => {
+                let path = args.next().ok_or_else(|| anyhow!("--symbols: path expected"))?;
+                symbols = Some(PathBuf::from(path));
+            }
             arg if iso.is_none() => iso = Some(PathBuf::from(arg)),
             arg => bail!("unrecognized argument: {}", arg),
         }
     }
-    Ok(Options { iso: iso.ok_or_else(|| anyhow!("ISO path expected"))?, npc })
+    Ok(Options { iso: iso.ok_or_else(|| anyhow!("ISO path expected"))?, npc, symbols })
 }
 
 fn usage() {
-    eprintln!("Usage: cargo run -p unplug-datagen -- <iso> [--npc <path>]");
+    eprintln!("Usage: cargo run -p unplug-datagen -- <iso> [--npc <path>] [--symbols <path>]");
     eprintln!("\nTo generate animation and sound names, --npc must be provided");
     eprintln!("along with a path to the data partition extracted from the Wii");
     eprintln!("release of the game (R24J01).");
+    eprintln!("\n--symbols can be used to provide a symbol map for main.dol. Tables");
+    eprintln!("will be located by symbol name if the map defines them.");
+}
+
+/// Addresses of the tables in main.dol which data is generated from.
+struct Tables {
+    main_objects: u32,
+    internal_objects: u32,
+    spawnables: u32,
+    suit_items: u32,
+    stage_list: u32,
+    music_list: u32,
+}
+
+impl Tables {
+    /// Locates the tables using `symbols`, falling back on the built-in addresses for tables that
+    /// the map does not define.
+    fn locate(symbols: &SymbolMap) -> Self {
+        let locate = |name: &str, default: u32| match symbols.address_of(name) {
+            Ok(address) => {
+                debug!("Found {} at {:#x}", name, address);
+                address
+            }
+            Err(_) => {
+                if !symbols.is_empty() {
+                    warn!("{} is not in the symbol map, using {:#x}", name, default);
+                }
+                default
+            }
+        };
+        Self {
+            main_objects: locate(MAIN_OBJECTS_SYMBOL, MAIN_OBJECTS_ADDR),
+            internal_objects: locate(INTERNAL_OBJECTS_SYMBOL, INTERNAL_OBJECTS_ADDR),
+            spawnables: locate(SPAWNABLES_SYMBOL, SPAWNABLES_ADDR),
+            suit_items: locate(SUIT_ITEMS_SYMBOL, SUIT_ITEMS_ADDR),
+            stage_list: locate(STAGE_LIST_SYMBOL, STAGE_LIST_ADDR),
+            music_list: locate(MUSIC_LIST_SYMBOL, MUSIC_LIST_ADDR),
+        }
+    }
 }
 
 /// The raw representation of an object in the executable.
@@ -684,9 +747,10 @@ struct Spawnable {
 fn read_spawnables(
     dol: &DolHeader,
     reader: &mut (impl Read + Seek),
+    tables: &Tables,
     objects: &[ObjectDefinition],
 ) -> Result<Vec<Spawnable>> {
-    let offset = dol.address_to_offset(SPAWNABLES_ADDR)? as u64;
+    let offset = dol.address_to_offset(tables.spawnables)? as u64;
     reader.seek(SeekFrom::Start(offset))?;
     let mut spawnables = Vec::with_capacity(NUM_SPAWNABLES);
     let mut label_counts: HashMap<String, usize> = HashMap::new();
@@ -816,6 +880,7 @@ struct SuitDefinition {
 fn read_suits(
     dol: &DolHeader,
     reader: &mut (impl Read + Seek),
+    tables: &Tables,
     globals: &[Suit],
     items: &[ItemDefinition],
 ) -> Result<Vec<SuitDefinition>> {
@@ -836,7 +901,7 @@ fn read_suits(
     }
 
     // Now we can actually read the item IDs. Again, these are ordered by UI position.
-    let items_offset = dol.address_to_offset(SUIT_ITEMS_ADDR)? as u64;
+    let items_offset = dol.address_to_offset(tables.suit_items)? as u64;
     reader.seek(SeekFrom::Start(items_offset))?;
     let mut item_ids = [0; NUM_SUITS];
     reader.read_u16_into::<BE>(&mut item_ids)?;
@@ -903,11 +968,12 @@ struct StageDefinition {
 fn read_stages(
     dol: &DolHeader,
     reader: &mut (impl Read + Seek),
+    tables: &Tables,
     globals: &[Stage],
 ) -> Result<Vec<StageDefinition>> {
     // The stage list has a null-terminated list of main stages followed by a null-terminated list
     // of developer stages.
-    let stages_offset = dol.address_to_offset(STAGE_LIST_ADDR)? as u64;
+    let stages_offset = dol.address_to_offset(tables.stage_list)? as u64;
     reader.seek(SeekFrom::Start(stages_offset))?;
     let mut stages = NonNoneList::<RawStageDefinition>::read_from(reader)?.into_vec();
     let dev_stages = NonNoneList::<RawStageDefinition>::read_from(reader)?.into_vec();
@@ -964,6 +1030,50 @@ impl<R: Read + ?Sized> ReadFrom<R> for RawMusicDefinition {
     }
 }
 
+/// Representation of a disc file's hash which is written to the generated source.
+struct CleanFileDefinition {
+    path: String,
+    size: u64,
+    hash: u64,
+}
+
+/// Hashes the contents of `reader` and returns the number of bytes read and the hash.
+fn hash_file(mut reader: impl Read) -> Result<(u64, u64)> {
+    let mut hasher = SeaHasher::new();
+    let mut buf = vec![0; HASH_BUFFER_SIZE];
+    let mut size = 0;
+    loop {
+        let len = reader.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        hasher.write(&buf[..len]);
+        size += len as u64;
+    }
+    Ok((size, hasher.finish()))
+}
+
+/// Hashes main.dol and every file in the disc's file tree.
+fn read_clean_files(disc: &mut DiscStream<impl ReadSeek>) -> Result<Vec<CleanFileDefinition>> {
+    let mut files = vec![];
+    let (_, dol_reader) = disc.open_dol()?;
+    let (size, hash) = hash_file(dol_reader)?;
+    files.push(CleanFileDefinition { path: DOL_PATH.to_owned(), size, hash });
+    let tree = disc.files();
+    let paths = tree
+        .recurse()
+        .filter(|&(_, id)| tree[id].is_file())
+        .map(|(path, id)| (path.trim_start_matches('/').to_owned(), id))
+        .collect::<Vec<_>>();
+    for (path, id) in paths {
+        debug!("Hashing {}", path);
+        let (size, hash) = hash_file(disc.open_file(id)?)?;
+        files.push(CleanFileDefinition { path, size, hash });
+    }
+    files.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
 /// Representation of a music file which is written to the generated source.
 struct MusicDefinition {
     id: u8,
@@ -973,8 +1083,12 @@ struct MusicDefinition {
 }
 
 /// Reads the music list from the executable.
-fn read_music(dol: &DolHeader, reader: &mut (impl Read + Seek)) -> Result<Vec<MusicDefinition>> {
-    let music_offset = dol.address_to_offset(MUSIC_LIST_ADDR)? as u64;
+fn read_music(
+    dol: &DolHeader,
+    reader: &mut (impl Read + Seek),
+    tables: &Tables,
+) -> Result<Vec<MusicDefinition>> {
+    let music_offset = dol.address_to_offset(tables.music_list)? as u64;
     reader.seek(SeekFrom::Start(music_offset))?;
     let mut raw_music = [RawMusicDefinition::default(); NUM_MUSIC];
     RawMusicDefinition::read_all_from(reader, &mut raw_music)?;
@@ -1082,7 +1196,7 @@ fn build_sfx(
         if !collection.groups.is_empty() {
             let group_index = collection.groups[0].index;
             let group = &brsar.groups[group_index as usize];
-            let group_name = brsar.symbol(group.name_index);
+            let group_name = brsar.symbol(group.header.name_index);
             let bank_name = SFX_GROUPS.iter().find(|&&(_, g)| g == group_name);
             if let Some(&(name, _)) = bank_name {
                 let group_def = groups.iter().find(|b| b.name == name).unwrap();
@@ -1299,6 +1413,17 @@ fn write_music(mut writer: impl Write, music: &[MusicDefinition]) -> Result<()>
     Ok(())
 }
 
+/// Writes the clean file hash list to the generated file.
+fn write_clean_files(mut writer: impl Write, files: &[CleanFileDefinition]) -> Result<()> {
+    write!(writer, "{}{}", GEN_HEADER, CLEAN_FILES_HEADER)?;
+    for file in files {
+        writeln!(writer, "    \"{}\" => {{ {}, 0x{:016x} }},", file.path, file.size, file.hash)?;
+    }
+    write!(writer, "{}", CLEAN_FILES_FOOTER)?;
+    writer.flush()?;
+    Ok(())
+}
+
 /// Writes the list of SFX groups to the generated file.
 fn write_sfx_groups(mut writer: impl Write, groups: &[SfxGroupDefinition]) -> Result<()> {
     write!(writer, "{}{}", GEN_HEADER, SFX_GROUPS_HEADER)?;
@@ -1440,14 +1565,23 @@ fn run_app() -> Result<()> {
         globals.read_metadata()?
     };
 
+    let symbols = match &options.symbols {
+        Some(path) => {
+            info!("Reading symbol map");
+            SymbolMap::read_from(&mut BufReader::new(File::open(path)?))?
+        }
+        None => SymbolMap::new(),
+    };
+    let tables = Tables::locate(&symbols);
+
     info!("Opening main.dol");
     let (dol, dol_reader) = iso.open_dol()?;
     let mut dol_reader = BufReader::new(dol_reader);
 
     info!("Reading object tables");
-    let mut objects = read_objects(&dol, &mut dol_reader, MAIN_OBJECTS_ADDR, NUM_MAIN_OBJECTS)?;
+    let mut objects = read_objects(&dol, &mut dol_reader, tables.main_objects, NUM_MAIN_OBJECTS)?;
     let internal_objects =
-        read_objects(&dol, &mut dol_reader, INTERNAL_OBJECTS_ADDR, NUM_INTERNAL_OBJECTS)?;
+        read_objects(&dol, &mut dol_reader, tables.internal_objects, NUM_INTERNAL_OBJECTS)?;
 
     info!("Generating object data");
     for mut object in internal_objects {
@@ -1459,7 +1593,7 @@ fn run_app() -> Result<()> {
     fixup_labels(&mut objects);
 
     info!("Reading spawnables");
-    let spawnables = read_spawnables(&dol, &mut dol_reader, &objects)?;
+    let spawnables = read_spawnables(&dol, &mut dol_reader, &tables, &objects)?;
 
     info!("Generating item data");
     let items = build_items(&objects, &metadata.items);
@@ -1468,15 +1602,18 @@ fn run_app() -> Result<()> {
     let atcs = build_atcs(&metadata.atcs);
 
     info!("Reading suit data");
-    let suits = read_suits(&dol, &mut dol_reader, &metadata.suits, &items)?;
+    let suits = read_suits(&dol, &mut dol_reader, &tables, &metadata.suits, &items)?;
 
     info!("Reading stage data");
-    let stages = read_stages(&dol, &mut dol_reader, &metadata.stages)?;
+    let stages = read_stages(&dol, &mut dol_reader, &tables, &metadata.stages)?;
 
     info!("Reading music data");
-    let music = read_music(&dol, &mut dol_reader)?;
+    let music = read_music(&dol, &mut dol_reader, &tables)?;
     drop(dol_reader);
 
+    info!("Hashing disc files");
+    let clean_files = read_clean_files(&mut iso)?;
+
     let actors = {
         let mut qp = ArchiveReader::open(iso.open_file_at(QP_PATH)?)?;
         let mut results = BTreeMap::new();
@@ -1556,6 +1693,11 @@ fn run_app() -> Result<()> {
     let music_writer = BufWriter::new(File::create(music_path)?);
     write_music(music_writer, &music)?;
 
+    let clean_files_path = out_dir.join(CLEAN_FILES_FILE_NAME);
+    info!("Writing {}", clean_files_path.display());
+    let clean_files_writer = BufWriter::new(File::create(clean_files_path)?);
+    write_clean_files(clean_files_writer, &clean_files)?;
+
     let sfx_groups_path = out_dir.join(SFX_GROUPS_FILE_NAME);
     info!("Writing {}", sfx_groups_path.display());
     let sfx_groups_writer = BufWriter::new(File::create(sfx_groups_path)?);
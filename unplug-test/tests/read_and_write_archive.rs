@@ -48,11 +48,11 @@ fn test_read_and_write_archive_replace() -> Result<()> {
     info!("Rebuilding archive");
     let mut temp = tempfile()?;
     ArchiveBuilder::with_archive(&mut original)
-        .replace_at("bin/e/stage01.bin", || Cursor::new(b"stage01"))?
-        .replace_at("bin/e/stage02.bin", || Cursor::new(b"stage02"))?
-        .replace_at("bin/e/stage03.bin", || Cursor::new(b"stage03"))?
-        .replace_at("bin/e/stage04.bin", || Cursor::new(b"stage04"))?
-        .replace_at("bin/e/stage05.bin", || Cursor::new(b"stage05"))?
+        .replace_at("bin/e/stage01.bin", || Ok(Cursor::new(b"stage01")))?
+        .replace_at("bin/e/stage02.bin", || Ok(Cursor::new(b"stage02")))?
+        .replace_at("bin/e/stage03.bin", || Ok(Cursor::new(b"stage03")))?
+        .replace_at("bin/e/stage04.bin", || Ok(Cursor::new(b"stage04")))?
+        .replace_at("bin/e/stage05.bin", || Ok(Cursor::new(b"stage05")))?
         .write_to(&mut temp)?;
 
     info!("Comparing files");
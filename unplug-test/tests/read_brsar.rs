@@ -1,8 +1,10 @@
 use anyhow::Result;
 use log::info;
 use std::fs::File;
-use std::io::BufReader;
-use unplug::audio::transport::Brsar;
+use std::io::{BufReader, Cursor};
+use unplug::audio::transport::rwav::Codec;
+use unplug::audio::transport::{Brsar, Rwav};
+use unplug::audio::ReadSamples;
 use unplug::common::ReadFrom;
 use unplug_test as common;
 
@@ -40,6 +42,64 @@ fn test_read_brsar() -> Result<()> {
     let collection = &brsar.collections[UFO_COLLECTION_INDEX];
     assert_eq!(collection.groups[0].index, UFO_GROUP_INDEX as u32);
     let group = &brsar.groups[UFO_GROUP_INDEX];
-    assert_eq!(brsar.symbol(group.name_index), UFO_GROUP_NAME);
+    assert_eq!(brsar.symbol(group.header.name_index), UFO_GROUP_NAME);
+
+    // Check that the sound's wave data can be found and decoded
+    assert_eq!(brsar.find_sound(UFO_SOUND_NAME), Some(UFO_SOUND_INDEX));
+    let rwav = brsar.read_wave(&mut reader, UFO_SOUND_INDEX)?;
+    assert_eq!(rwav.tag.name, UFO_SOUND_NAME);
+    let samples = rwav.decoder().read_all_samples()?;
+    assert!(samples.len > 0);
+    Ok(())
+}
+
+#[test]
+fn test_replace_wave() -> Result<()> {
+    common::init_logging();
+
+    let brsar_path = match common::brsar_path() {
+        Some(path) => path,
+        None => return Ok(()), // Skip test if no path is set
+    };
+    info!("Reading {}", brsar_path);
+    let mut reader = BufReader::new(File::open(&brsar_path)?);
+    let mut brsar = Brsar::read_from(&mut reader)?;
+
+    // Pick a sound stored after the UFO sound so that we can check that it still gets found
+    let later_index = brsar
+        .sounds
+        .iter()
+        .position(|s| {
+            let collection = &brsar.collections[s.collection_index as usize];
+            s.wave_info.is_some() && collection.groups[0].index > UFO_GROUP_INDEX as u32
+        })
+        .unwrap();
+    let later_rwav = brsar.read_wave(&mut reader, later_index)?;
+    let later_samples = later_rwav.decoder().read_all_samples()?;
+
+    info!("Re-encoding {}", UFO_SOUND_NAME);
+    let old_rwav = brsar.read_wave(&mut reader, UFO_SOUND_INDEX)?;
+    let mut pcm = old_rwav.decoder().read_all_samples()?.into_reader(UFO_SOUND_NAME);
+    let new_rwav = Rwav::from_pcm(&mut pcm, Codec::Pcm16)?;
+    let mut writer = Cursor::new(vec![]);
+    brsar.replace_wave(&mut reader, &mut writer, UFO_SOUND_INDEX, &new_rwav)?;
+
+    info!("Reading rebuilt BRSAR");
+    writer.set_position(0);
+    let rebuilt = Brsar::read_from(&mut writer)?;
+    assert_eq!(rebuilt.sounds.len(), EXPECTED_NUM_SOUNDS);
+    assert_eq!(rebuilt.collections.len(), EXPECTED_NUM_COLLECTIONS);
+    assert_eq!(rebuilt.groups.len(), EXPECTED_NUM_GROUPS);
+    for (group, expected) in rebuilt.groups.iter().zip(&brsar.groups) {
+        assert_eq!(group.header.rwar_offset, expected.header.rwar_offset);
+        assert_eq!(group.header.rwar_size, expected.header.rwar_size);
+    }
+
+    let rwav = rebuilt.read_wave(&mut writer, UFO_SOUND_INDEX)?;
+    assert_eq!(rwav.format, new_rwav.format);
+    assert_eq!(rwav.end_address, new_rwav.end_address);
+    let rwav = rebuilt.read_wave(&mut writer, later_index)?;
+    let samples = rwav.decoder().read_all_samples()?;
+    assert_eq!(samples.data, later_samples.data);
     Ok(())
 }
@@ -1,8 +1,8 @@
 use anyhow::Result;
 use log::info;
-use std::io::BufReader;
+use std::io::{BufReader, Cursor};
 use unplug::audio::metadata::sem::{Action, Command, SfxPlaylist, SoundMaterial};
-use unplug::common::ReadFrom;
+use unplug::common::{ReadFrom, WriteTo};
 use unplug::dvd::OpenFile;
 use unplug_test as common;
 
@@ -29,6 +29,14 @@ fn test_read_playlist() -> Result<()> {
         }
     );
 
+    info!("Rebuilding the playlist");
+    let mut writer = Cursor::new(vec![]);
+    playlist.write_to(&mut writer)?;
+    writer.set_position(0);
+    let rebuilt = SfxPlaylist::read_from(&mut writer)?;
+    assert_eq!(rebuilt.group_indexes, playlist.group_indexes);
+    assert_eq!(rebuilt.sounds, playlist.sounds);
+
     Ok(())
 }
 
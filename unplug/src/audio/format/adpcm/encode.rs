@@ -69,6 +69,7 @@ pub struct EncoderBuilder<'r, 's: 'r> {
     reader: Box<dyn ReadSamples<'s, Format = PcmS16Le> + 'r>,
     mono_block_size: usize,
     stereo_block_size: usize,
+    split_at_loop: bool,
     on_progress: Option<ProgressCallback<'r>>,
 }
 
@@ -84,6 +85,7 @@ impl<'r, 's: 'r> EncoderBuilder<'r, 's> {
             reader,
             mono_block_size: usize::MAX,
             stereo_block_size: usize::MAX,
+            split_at_loop: false,
             on_progress: None,
         }
     }
@@ -110,6 +112,16 @@ impl<'r, 's: 'r> EncoderBuilder<'r, 's> {
         self
     }
 
+    /// Sets whether a new block should begin at the stream's loop point, if it has one. Loop points
+    /// are only able to jump to the start of a block, so this lets the stream loop seamlessly.
+    /// Blocks must start on a frame boundary, so if the loop point is not on one, silence is added
+    /// to the beginning of the stream and every cue is shifted to match.
+    #[must_use]
+    pub fn split_at_loop(mut self, split: bool) -> Self {
+        self.split_at_loop = split;
+        self
+    }
+
     /// Sets a callback to run for progress updates. If the total amount of work is unknown, the
     /// callback will still be invoked with a `None` hint.
     #[must_use]
@@ -144,8 +156,12 @@ impl<'r, 's: 'r> EncoderBuilder<'r, 's> {
 
     fn build_mono(mut self) -> Result<EncoderPair<'s>> {
         let cues = self.reader.cues().collect::<Vec<_>>();
-        let mut channel =
-            EncoderChannel::new(self.mono_block_size, cues, self.reader.tag().clone());
+        let mut channel = EncoderChannel::new(
+            self.mono_block_size,
+            self.split_at_loop,
+            cues,
+            self.reader.tag().clone(),
+        );
         Self::update_progress(self.on_progress.as_mut(), self.reader.progress());
         while let Some(samples) = self.reader.read_samples()? {
             channel.add_samples(samples);
@@ -159,10 +175,18 @@ impl<'r, 's: 'r> EncoderBuilder<'r, 's> {
         let splitter = self.reader.split_channels();
         let mut left = splitter.left();
         let mut right = splitter.right();
-        let mut left_channel =
-            EncoderChannel::new(self.stereo_block_size, cues.clone(), left.tag().clone());
-        let mut right_channel =
-            EncoderChannel::new(self.stereo_block_size, cues, right.tag().clone());
+        let mut left_channel = EncoderChannel::new(
+            self.stereo_block_size,
+            self.split_at_loop,
+            cues.clone(),
+            left.tag().clone(),
+        );
+        let mut right_channel = EncoderChannel::new(
+            self.stereo_block_size,
+            self.split_at_loop,
+            cues,
+            right.tag().clone(),
+        );
         let mut on_progress = self.on_progress;
         Self::update_progress(on_progress.as_mut(), left.progress());
         while let (Some(l), Some(r)) = (left.read_samples()?, right.read_samples()?) {
@@ -186,6 +210,7 @@ impl<'r, 's: 'r> EncoderBuilder<'r, 's> {
 /// Implementation detail for `EncoderBuilder` which builds an encoder for a single channel.
 struct EncoderChannel<'s> {
     block_size: usize,
+    split_at_loop: bool,
     cues: Vec<Cue>,
     tag: SourceTag,
     samples: Vec<Samples<'s, PcmS16Le>>,
@@ -193,8 +218,8 @@ struct EncoderChannel<'s> {
 }
 
 impl<'s> EncoderChannel<'s> {
-    fn new(block_size: usize, cues: Vec<Cue>, tag: SourceTag) -> Self {
-        Self { block_size, cues, tag, samples: vec![], coeff: Default::default() }
+    fn new(block_size: usize, split_at_loop: bool, cues: Vec<Cue>, tag: SourceTag) -> Self {
+        Self { block_size, split_at_loop, cues, tag, samples: vec![], coeff: Default::default() }
     }
 
     fn add_samples(&mut self, samples: Samples<'s, PcmS16Le>) {
@@ -205,10 +230,33 @@ impl<'s> EncoderChannel<'s> {
         self.samples.push(samples);
     }
 
-    fn into_encoder(self) -> Encoder<'s, 's> {
+    fn into_encoder(mut self) -> Encoder<'s, 's> {
         let state = Info { coefficients: self.coeff.finish(), ..Default::default() };
+        let loop_start = self.cues.iter().find(|c| c.is_loop()).map(|c| c.start);
+        let split = loop_start.filter(|_| self.split_at_loop).map(|start| {
+            let frame = SAMPLES_PER_FRAME as u64;
+            let padding = (frame - start % frame) % frame;
+            if padding > 0 {
+                self.pad_start(padding as usize);
+            }
+            start + padding
+        });
         let reader = ReadSampleList::with_cues(self.samples, self.cues, self.tag);
-        Encoder::with_block_size(reader, state, self.block_size)
+        let mut encoder = Encoder::with_block_size(reader, state, self.block_size);
+        if let Some(start) = split {
+            encoder.split_block_at(start);
+        }
+        encoder
+    }
+
+    /// Inserts `len` samples of silence at the beginning of the channel and shifts the cues after
+    /// them.
+    fn pad_start(&mut self, len: usize) {
+        let (channels, rate) = self.samples.first().map_or((1, 0), |s| (s.channels, s.rate));
+        self.samples.insert(0, Samples::from_pcm(vec![0i16; len * channels], channels, rate));
+        for cue in &mut self.cues {
+            cue.start += len as u64;
+        }
     }
 }
 
@@ -256,6 +304,8 @@ pub struct Encoder<'r, 's> {
     samples_encoded: u64,
     /// The total number of samples which will need to be encoded.
     total_samples: Option<NonZeroU64>,
+    /// The index of a sample which must begin a new block.
+    split: Option<u64>,
     /// The current frame which needs to be filled before it can be encoded.
     frame: ArrayVec<i16, SAMPLES_PER_FRAME>,
     /// A buffer for samples which have been read but not encoded yet.
@@ -293,6 +343,13 @@ impl<'r, 's> Encoder<'r, 's> {
         self.state
     }
 
+    /// Ensures that a new block begins at `sample`, which is rounded down to the nearest frame. This
+    /// must be called before any samples are read.
+    pub fn split_block_at(&mut self, sample: u64) {
+        let frame = SAMPLES_PER_FRAME as u64;
+        self.split = Some(sample / frame * frame).filter(|&s| s > 0);
+    }
+
     fn with_block_size_impl(
         reader: Box<dyn ReadSamples<'s, Format = PcmS16Le> + 'r>,
         state: Info,
@@ -307,6 +364,7 @@ impl<'r, 's> Encoder<'r, 's> {
             sample_rate: 0,
             samples_encoded: 0,
             total_samples,
+            split: None,
             frame: ArrayVec::new(),
             buffer: VecDeque::new(),
             sample_offset: 0,
@@ -321,6 +379,22 @@ impl<'r, 's> Encoder<'r, 's> {
         self.samples_encoded += samples.len() as u64;
     }
 
+    /// Returns the number of whole frames which can be encoded before reaching the split point.
+    fn frames_until_split(&self) -> usize {
+        match self.split {
+            Some(split) if split > self.samples_encoded => {
+                ((split - self.samples_encoded) / SAMPLES_PER_FRAME as u64) as usize
+            }
+            _ => usize::MAX,
+        }
+    }
+
+    /// Returns `true` if the current block is full or has reached the split point.
+    fn block_is_complete(&self) -> bool {
+        self.block.data.len() == self.block_size
+            || (self.split == Some(self.samples_encoded) && !self.block.data.is_empty())
+    }
+
     fn extend_frame(&mut self, samples: &[i16]) {
         self.frame.try_extend_from_slice(samples).expect("cannot fit samples in the frame buffer");
     }
@@ -344,10 +418,11 @@ impl<'r, 's> Encoder<'r, 's> {
             }
 
             // Encode as many whole frames as possible until we either run out or complete a block
-            while samples.len >= SAMPLES_PER_FRAME && self.block.data.len() < self.block_size {
+            while samples.len >= SAMPLES_PER_FRAME && !self.block_is_complete() {
                 let remaining_frames = (self.block_size - self.block.data.len()) / BYTES_PER_FRAME;
                 let available_frames = samples.len / SAMPLES_PER_FRAME;
-                let num_frames = remaining_frames.min(available_frames);
+                let num_frames =
+                    remaining_frames.min(available_frames).min(self.frames_until_split());
                 let num_samples = num_frames * SAMPLES_PER_FRAME;
                 self.encode(&samples.data[offset..(offset + num_samples)]);
                 offset += num_samples;
@@ -355,8 +430,8 @@ impl<'r, 's> Encoder<'r, 's> {
             }
 
             debug_assert!(self.block.data.len() <= self.block_size);
-            if self.block.data.len() == self.block_size {
-                // A full block is complete
+            if self.block_is_complete() {
+                // The block is complete
                 let block = mem::take(&mut self.block);
                 self.block.initial_state = self.state;
                 if samples.len > 0 {
@@ -597,4 +672,36 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_encode_split_block() -> Result<()> {
+        const BLOCK_SIZE: usize = 0x8000;
+        const SPLIT: u64 = 1000;
+        const EXPECTED_BLOCK_LENGTHS: &[usize] = &[0x238, 0x8000, 0x8000, 0x8000, 0x345];
+
+        let data = test::open_test_wav();
+        let mut samples = vec![];
+        for chunk in data.chunks(1000) {
+            samples.push(Samples::from_pcm(chunk, 2, 44100));
+        }
+
+        let splitter = ReadSampleList::new(samples, "test").split_channels();
+        let state = Info { coefficients: test::TEST_WAV_LEFT_COEFFICIENTS, ..Default::default() };
+        let mut encoder = Encoder::with_block_size(splitter.left(), state, BLOCK_SIZE);
+        encoder.split_block_at(SPLIT);
+
+        let mut blocks = vec![];
+        while let Some(block) = encoder.read_samples()? {
+            blocks.push(block);
+        }
+        let lengths = blocks.iter().map(|b| b.data.len()).collect::<Vec<_>>();
+        assert_eq!(lengths, EXPECTED_BLOCK_LENGTHS);
+        // The split is rounded down to a frame boundary
+        assert_eq!(blocks[0].len / 16 * 14, (SPLIT as usize) / 14 * 14);
+
+        // Splitting must not change the encoded data
+        let encoded = blocks.iter().flat_map(|b| b.data.iter().copied()).collect::<Vec<_>>();
+        assert_eq!(encoded, test::TEST_WAV_LEFT_DSP);
+        Ok(())
+    }
 }
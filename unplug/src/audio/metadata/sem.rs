@@ -1,16 +1,16 @@
 use crate::audio::{Error, Result};
-use crate::common::ReadFrom;
-use byteorder::{ReadBytesExt, BE};
+use crate::common::{ReadFrom, WriteTo};
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use std::convert::TryFrom;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Read, Seek, SeekFrom, Write};
 use tracing::debug;
 
 /// The file header.
 #[derive(Debug, Clone, Default)]
 struct Header {
-    _unk_00: u32, // zero
-    _unk_04: u32, // zero
+    unk_00: u32, // zero
+    unk_04: u32, // zero
     /// The base index for each sound group.
     group_indexes: Vec<u32>,
     /// The file offsets for each sound material.
@@ -21,8 +21,8 @@ impl<R: Read + ?Sized> ReadFrom<R> for Header {
     type Error = Error;
     fn read_from(reader: &mut R) -> Result<Self> {
         let mut header = Self {
-            _unk_00: reader.read_u32::<BE>()?,
-            _unk_04: reader.read_u32::<BE>()?,
+            unk_00: reader.read_u32::<BE>()?,
+            unk_04: reader.read_u32::<BE>()?,
             ..Default::default()
         };
         let num_groups = reader.read_u32::<BE>()?;
@@ -37,6 +37,23 @@ impl<R: Read + ?Sized> ReadFrom<R> for Header {
     }
 }
 
+impl<W: Write + ?Sized> WriteTo<W> for Header {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        writer.write_u32::<BE>(self.unk_00)?;
+        writer.write_u32::<BE>(self.unk_04)?;
+        writer.write_u32::<BE>(self.group_indexes.len() as u32)?;
+        for &index in &self.group_indexes {
+            writer.write_u32::<BE>(index)?;
+        }
+        writer.write_u32::<BE>(self.sound_offsets.len() as u32)?;
+        for &offset in &self.sound_offsets {
+            writer.write_u32::<BE>(offset)?;
+        }
+        Ok(())
+    }
+}
+
 /// An action command.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive, IntoPrimitive)]
 #[repr(u8)]
@@ -76,6 +93,56 @@ pub enum Command {
     Unk28 = 28,
 }
 
+/// Describes how an action's delay and data are packed into the low 24 bits of its opcode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Encoding {
+    /// `----dd` (delay only)
+    NoData,
+    /// `ddxxxx` (delay + 16-bit data)
+    Data16,
+    /// `--ddxx` (delay + 8-bit data)
+    Data8,
+    /// `----xx` (8-bit data only)
+    NoDelay,
+}
+
+impl Command {
+    /// Returns how the command's operands are encoded.
+    fn encoding(self) -> Encoding {
+        match self {
+            Self::None | Self::End | Self::End2 => Encoding::NoData,
+
+            Self::Sample
+            | Self::Unk2
+            | Self::Unk3
+            | Self::Unk12
+            | Self::Unk13
+            | Self::Unk23
+            | Self::Unk24
+            | Self::Unk25
+            | Self::Unk26 => Encoding::Data16,
+
+            Self::Unk4
+            | Self::Unk5
+            | Self::Unk6
+            | Self::Unk7
+            | Self::Unk8
+            | Self::Unk9
+            | Self::Unk10
+            | Self::Unk11
+            | Self::Unk16
+            | Self::Unk17
+            | Self::Unk18
+            | Self::Unk19
+            | Self::Unk20
+            | Self::Unk21
+            | Self::Unk22 => Encoding::Data8,
+
+            Self::Unk27 | Self::Unk28 => Encoding::NoDelay,
+        }
+    }
+}
+
 /// An action to perform as part of a sound effect.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Action {
@@ -97,45 +164,34 @@ impl<R: Read + ?Sized> ReadFrom<R> for Action {
             Ok(c) => c,
             Err(_) => return Err(Error::UnrecognizedPlaylistCommand(code)),
         };
-        let (delay, data) = match command {
-            // No data
-            Command::None | Command::End | Command::End2 => (op & 0xff, 0),
-
-            // 16-bit data
-            Command::Sample
-            | Command::Unk2
-            | Command::Unk3
-            | Command::Unk12
-            | Command::Unk13
-            | Command::Unk23
-            | Command::Unk24
-            | Command::Unk25
-            | Command::Unk26 => ((op >> 16) & 0xff, op & 0xffff),
-
-            // 8-bit data
-            Command::Unk4
-            | Command::Unk5
-            | Command::Unk6
-            | Command::Unk7
-            | Command::Unk8
-            | Command::Unk9
-            | Command::Unk10
-            | Command::Unk11
-            | Command::Unk16
-            | Command::Unk17
-            | Command::Unk18
-            | Command::Unk19
-            | Command::Unk20
-            | Command::Unk21
-            | Command::Unk22 => ((op >> 8) & 0xff, op & 0xff),
-
-            // No delay
-            Command::Unk27 | Command::Unk28 => (0, op & 0xff),
+        let (delay, data) = match command.encoding() {
+            Encoding::NoData => (op & 0xff, 0),
+            Encoding::Data16 => ((op >> 16) & 0xff, op & 0xffff),
+            Encoding::Data8 => ((op >> 8) & 0xff, op & 0xff),
+            Encoding::NoDelay => (0, op & 0xff),
         };
         Ok(Self { command, delay: delay as u8, data: data as u16 })
     }
 }
 
+impl<W: Write + ?Sized> WriteTo<W> for Action {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        let code = u32::from(u8::from(self.command)) << 24;
+        let delay = u32::from(self.delay);
+        let data = u32::from(self.data);
+        let op = match self.command.encoding() {
+            Encoding::NoData if data == 0 => code | delay,
+            Encoding::Data16 => code | (delay << 16) | data,
+            Encoding::Data8 if data <= 0xff => code | (delay << 8) | data,
+            Encoding::NoDelay if delay == 0 && data <= 0xff => code | data,
+            _ => return Err(Error::InvalidPlaylistAction(*self)),
+        };
+        writer.write_u32::<BE>(op)?;
+        Ok(())
+    }
+}
+
 /// A set of actions describing how to play a sound effect.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SoundMaterial {
@@ -166,6 +222,23 @@ impl<R: Read + ?Sized> ReadFrom<R> for SoundMaterial {
     }
 }
 
+impl<W: Write + ?Sized> WriteTo<W> for SoundMaterial {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        // The material must end with exactly one end command or else reading it back will either
+        // run off the end or cut it short
+        let end =
+            self.actions.iter().position(|a| matches!(a.command, Command::End | Command::End2));
+        if end.is_none() || end != Some(self.actions.len() - 1) {
+            return Err(Error::UnterminatedSoundMaterial);
+        }
+        for action in &self.actions {
+            action.write_to(writer)?;
+        }
+        Ok(())
+    }
+}
+
 /// A sysdolphin sound effect playlist file (.sem) which defines "sound materials" that are divided
 /// into groups for each sample bank. Sound effects are instantiated from sound materials, and an
 /// SFX ID is a concatenation of a 16-bit group index and a 16-bit material index.
@@ -197,6 +270,29 @@ impl<R: Read + Seek + ?Sized> ReadFrom<R> for SfxPlaylist {
     }
 }
 
+impl<W: Write + ?Sized> WriteTo<W> for SfxPlaylist {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        // Sound materials are stored back-to-back immediately after the header
+        let mut header = Header {
+            group_indexes: self.group_indexes.clone(),
+            sound_offsets: Vec::with_capacity(self.sounds.len()),
+            ..Default::default()
+        };
+        let header_size = (4 + self.group_indexes.len() + self.sounds.len()) * 4;
+        let mut offset = header_size as u32;
+        for sound in &self.sounds {
+            header.sound_offsets.push(offset);
+            offset += (sound.actions.len() * 4) as u32;
+        }
+        header.write_to(writer)?;
+        for sound in &self.sounds {
+            sound.write_to(writer)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -225,4 +321,75 @@ mod tests {
         assert_eq!(actual, expected);
         Ok(())
     }
+
+    #[test]
+    fn test_write_action() -> Result<()> {
+        let cases: &[(Action, [u8; 4])] = &[
+            (Action { command: Command::End, delay: 0x56, data: 0 }, [0x0e, 0x00, 0x00, 0x56]),
+            (
+                Action { command: Command::Sample, delay: 0x12, data: 0x3456 },
+                [0x01, 0x12, 0x34, 0x56],
+            ),
+            (Action { command: Command::Unk4, delay: 0x34, data: 0x56 }, [0x04, 0x00, 0x34, 0x56]),
+            (Action { command: Command::Unk28, delay: 0, data: 0x56 }, [0x1c, 0x00, 0x00, 0x56]),
+        ];
+        for (action, expected) in cases {
+            let mut writer = Cursor::new(vec![]);
+            action.write_to(&mut writer)?;
+            assert_eq!(writer.into_inner(), expected);
+        }
+
+        let invalid = [
+            Action { command: Command::End, delay: 0, data: 1 },
+            Action { command: Command::Unk4, delay: 0, data: 0x100 },
+            Action { command: Command::Unk28, delay: 1, data: 0 },
+        ];
+        for action in invalid {
+            let result = action.write_to(&mut Cursor::new(vec![]));
+            assert!(matches!(result, Err(Error::InvalidPlaylistAction(a)) if a == action));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_and_read_playlist() -> Result<()> {
+        let sample = |id| Action { command: Command::Sample, delay: 0, data: id };
+        let end = Action { command: Command::End, delay: 0, data: 0 };
+        let playlist = SfxPlaylist {
+            group_indexes: vec![0, 2],
+            sounds: vec![
+                SoundMaterial { actions: vec![sample(1), end] },
+                SoundMaterial {
+                    actions: vec![
+                        Action { command: Command::Sample, delay: 20, data: 2 },
+                        Action { command: Command::Unk6, delay: 0, data: 0x99 },
+                        sample(3),
+                        Action { command: Command::End2, delay: 0, data: 0 },
+                    ],
+                },
+                SoundMaterial { actions: vec![end] },
+            ],
+        };
+
+        let mut writer = Cursor::new(vec![]);
+        playlist.write_to(&mut writer)?;
+        let bytes = writer.into_inner();
+        assert_eq!(bytes.len(), 0x24 + 7 * 4);
+        assert_eq!(&bytes[0x14..0x24], &[0, 0, 0, 3, 0, 0, 0, 0x24, 0, 0, 0, 0x2c, 0, 0, 0, 0x3c]);
+
+        let actual = SfxPlaylist::read_from(&mut Cursor::new(bytes))?;
+        assert_eq!(actual.group_indexes, playlist.group_indexes);
+        assert_eq!(actual.sounds, playlist.sounds);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_unterminated_material() {
+        let sample = Action { command: Command::Sample, delay: 0, data: 1 };
+        let end = Action { command: Command::End, delay: 0, data: 0 };
+        for actions in [vec![], vec![sample], vec![end, sample], vec![end, end]] {
+            let result = SoundMaterial { actions }.write_to(&mut Cursor::new(vec![]));
+            assert!(matches!(result, Err(Error::UnterminatedSoundMaterial)));
+        }
+    }
 }
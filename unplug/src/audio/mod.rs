@@ -11,6 +11,7 @@ pub use format::{Format, FormatTag};
 pub use sample::{ReadSamples, SampleFilter, Samples, SourceChannel, SourceTag};
 
 use lewton::VorbisError;
+use metadata::sem::Action;
 use minimp3_fixed as minimp3;
 use std::io;
 use std::num::NonZeroU64;
@@ -68,6 +69,9 @@ pub enum Error {
     #[error("invalid HPS magic")]
     InvalidHpsMagic,
 
+    #[error("invalid playlist action: {0:?}")]
+    InvalidPlaylistAction(Action),
+
     #[error("invalid RIFF data")]
     InvalidRiff,
 
@@ -86,6 +90,9 @@ pub enum Error {
     #[error("samples are not aligned on a frame boundary")]
     NotFrameAligned,
 
+    #[error("BRSAR sound is not a wave sound: {0}")]
+    NotWaveSound(String),
+
     #[error("libsamplerate error {0}: {1}")]
     ResampleInternal(i32, String),
 
@@ -95,6 +102,9 @@ pub enum Error {
     #[error("audio stream is not stereo")]
     StreamNotStereo,
 
+    #[error("sound material does not end with an end command")]
+    UnterminatedSoundMaterial,
+
     #[error("unrecognized playlist command: {0}")]
     UnrecognizedPlaylistCommand(u8),
 
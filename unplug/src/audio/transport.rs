@@ -8,7 +8,7 @@ pub mod ssm;
 pub mod wav;
 
 pub use brsar::Brsar;
-pub use flac::FlacReader;
+pub use flac::{FlacReader, FlacWriter};
 pub use hps::{HpsReader, HpsWriter, PcmHpsWriter};
 pub use mp3::Mp3Reader;
 pub use ogg::OggReader;
@@ -1,23 +1,52 @@
-// NOTE: This is intentionally incomplete and only implemented enough to retrieve sound names from
-// New Play Control's cb_robo.brsar. Fully supporting this format is not useful.
+// NOTE: This is intentionally incomplete and only implemented enough to retrieve sound names and
+// wave sounds from New Play Control's cb_robo.brsar and to replace wave data in it. Fully
+// supporting this format is not useful.
 
+use super::Rwav;
 use crate::audio::{Error, Result};
-use crate::common::{ReadFrom, Region};
-use byteorder::{ReadBytesExt, BE};
+use crate::common::io::fill;
+use crate::common::{align, ReadFrom, ReadSeek, Region, WriteTo};
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt, BE};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
 use std::any;
+use std::convert::TryFrom;
 use std::ffi::CString;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 use tracing::{debug, error};
 
 const BRSAR_MAGIC: u32 = 0x52534152; // 'RSAR'
 const BRSAR_VERSION: u16 = 0x0104;
 
+const RWSD_MAGIC: u32 = 0x52575344; // 'RWSD'
+/// The first RWSD version which stores wave data in an RWAR instead of a WAVE section.
+const RWSD_MIN_VERSION: u16 = 0x0103;
+
+const RWAR_MAGIC: u32 = 0x52574152; // 'RWAR'
+const FILE_MAGIC: u32 = 0x46494c45; // 'FILE'
+const TABL_MAGIC: u32 = 0x5441424c; // 'TABL'
+const DATA_MAGIC: u32 = 0x44415441; // 'DATA'
+
+/// The size of an entry in an RWAR's TABL section.
+const RWAR_ENTRY_SIZE: u32 = 0xc;
+/// The alignment of sections and files in an RWAR.
+const RWAR_ALIGN: u32 = 0x20;
+
 const BIG_ENDIAN: u16 = 0xfeff;
 
 const SECTION_HEADER_SIZE: u64 = 0x8;
 
 /// Reads a count followed by a list of tagged offsets to structs.
 fn read_list<T, R, E>(reader: &mut R) -> Result<Vec<T>>
+where
+    T: ReadFrom<R, Error = E>,
+    R: Read + Seek,
+    Error: From<E>,
+{
+    Ok(read_list_with_offsets(reader)?.into_iter().map(|(_, item)| item).collect())
+}
+
+/// Like `read_list()`, but also returns the offset that each struct was read from.
+fn read_list_with_offsets<T, R, E>(reader: &mut R) -> Result<Vec<(u64, T)>>
 where
     T: ReadFrom<R, Error = E>,
     R: Read + Seek,
@@ -32,7 +61,7 @@ where
     for offset in offsets {
         if let Some(offset) = offset.offset() {
             reader.seek(SeekFrom::Start(offset))?;
-            results.push(T::read_from(reader)?);
+            results.push((offset, T::read_from(reader)?));
         } else {
             error!("Invalid {} offset: {:?}", any::type_name::<T>(), offset);
             return Err(Error::InvalidBrsar);
@@ -41,6 +70,65 @@ where
     Ok(results)
 }
 
+/// Reads the header at the beginning of an embedded RWSD or RWAR file. Returns the file version and
+/// an `(offset, size)` pair for each section.
+fn read_file_header(reader: &mut impl Read, magic: u32) -> Result<(u16, Vec<(u32, u32)>)> {
+    if reader.read_u32::<BE>()? != magic {
+        error!("Invalid magic in BRSAR file: expected {:#x}", magic);
+        return Err(Error::InvalidBrsar);
+    }
+    if reader.read_u16::<BE>()? != BIG_ENDIAN {
+        error!("Only big-endian BRSAR files are supported");
+        return Err(Error::InvalidBrsar);
+    }
+    let version = reader.read_u16::<BE>()?;
+    let _file_size = reader.read_u32::<BE>()?;
+    let _header_size = reader.read_u16::<BE>()?;
+    let num_sections = reader.read_u16::<BE>()?;
+    let mut sections = Vec::with_capacity(num_sections as usize);
+    for _ in 0..num_sections {
+        sections.push((reader.read_u32::<BE>()?, reader.read_u32::<BE>()?));
+    }
+    Ok((version, sections))
+}
+
+/// Writes the header for an embedded RWSD or RWAR file. `sections` is an `(offset, size)` pair for
+/// each section.
+fn write_file_header(
+    writer: &mut (impl Write + ?Sized),
+    magic: u32,
+    version: u16,
+    file_size: u32,
+    sections: &[(u32, u32)],
+) -> Result<()> {
+    let header_size = file_header_size(sections.len());
+    writer.write_u32::<BE>(magic)?;
+    writer.write_u16::<BE>(BIG_ENDIAN)?;
+    writer.write_u16::<BE>(version)?;
+    writer.write_u32::<BE>(file_size)?;
+    writer.write_u16::<BE>(header_size as u16)?;
+    writer.write_u16::<BE>(sections.len() as u16)?;
+    for &(offset, size) in sections {
+        writer.write_u32::<BE>(offset)?;
+        writer.write_u32::<BE>(size)?;
+    }
+    fill(writer, 0, u64::from(header_size) - 0x10 - 8 * sections.len() as u64)?;
+    Ok(())
+}
+
+/// Calculates the aligned size of a file header with `num_sections` sections.
+fn file_header_size(num_sections: usize) -> u32 {
+    align(0x10 + 8 * num_sections as u32, 0x20)
+}
+
+/// Opens a region over the data in the section at `offset`, skipping its header.
+fn open_section<R: Read + Seek>(mut reader: R, offset: u32) -> Result<Region<R>> {
+    reader.seek(SeekFrom::Start(offset.into()))?;
+    let header = SectionHeader::read_from(&mut reader)?;
+    let start_offset = reader.stream_position()?;
+    Ok(Region::new(reader, start_offset, u64::from(header.size) - SECTION_HEADER_SIZE))
+}
+
 /// A nullable offset which is tagged with a type value.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum TaggedOffset {
@@ -77,6 +165,20 @@ impl<R: Read + ?Sized> ReadFrom<R> for TaggedOffset {
     }
 }
 
+impl<W: Write + ?Sized> WriteTo<W> for TaggedOffset {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        let (ty, val) = match *self {
+            Self::Pointer(ptr) => (0, ptr),
+            Self::Relative(off) => (1, off),
+        };
+        writer.write_u8(ty)?;
+        writer.write_u24::<BE>(0)?;
+        writer.write_u32::<BE>(val)?;
+        Ok(())
+    }
+}
+
 /// The file header.
 #[derive(Debug, Copy, Clone)]
 struct FileHeader {
@@ -101,7 +203,7 @@ struct FileHeader {
     /// Size of the INFO section.
     _info_size: u32,
     /// Offset to the FILE section.
-    _file_offset: u32,
+    file_offset: u32,
     /// Size of the FILE section.
     _file_size: u32,
 }
@@ -142,7 +244,7 @@ impl<R: Read + ?Sized> ReadFrom<R> for FileHeader {
             _symb_size: reader.read_u32::<BE>()?,
             info_offset: reader.read_u32::<BE>()?,
             _info_size: reader.read_u32::<BE>()?,
-            _file_offset: reader.read_u32::<BE>()?,
+            file_offset: reader.read_u32::<BE>()?,
             _file_size: reader.read_u32::<BE>()?,
         })
     }
@@ -255,6 +357,40 @@ impl<R: Read + ?Sized> ReadFrom<R> for InfoHeader {
     }
 }
 
+/// The kinds of sounds which can be stored in a BRSAR.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive, IntoPrimitive)]
+#[repr(u8)]
+pub enum SoundKind {
+    /// A sequence (RSEQ).
+    Sequence = 1,
+    /// A stream stored in an external file (RSTM).
+    Stream = 2,
+    /// A wave sound (RWSD).
+    Wave = 3,
+}
+
+/// Additional information for wave sounds.
+#[derive(Debug, Copy, Clone)]
+pub struct WaveSoundInfo {
+    /// The index of the sound within its collection's RWSD.
+    pub index: u32,
+    pub alloc_track: u32,
+    pub channel_priority: u8,
+    pub release_priority_fix: u8,
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for WaveSoundInfo {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        Ok(Self {
+            index: reader.read_u32::<BE>()?,
+            alloc_track: reader.read_u32::<BE>()?,
+            channel_priority: reader.read_u8()?,
+            release_priority_fix: reader.read_u8()?,
+        })
+    }
+}
+
 /// Defines a sound.
 #[derive(Debug, Copy, Clone)]
 pub struct Sound {
@@ -274,6 +410,15 @@ pub struct Sound {
     pub pan_mode: u8,
     pub pan_curve: u8,
     pub actor_player_index: u8,
+    /// Additional information if this is a wave sound.
+    pub wave_info: Option<WaveSoundInfo>,
+}
+
+impl Sound {
+    /// Returns the kind of sound this is, if it is recognized.
+    pub fn sound_kind(&self) -> Option<SoundKind> {
+        SoundKind::try_from(self.kind).ok()
+    }
 }
 
 impl<R: Read + ?Sized> ReadFrom<R> for Sound {
@@ -299,6 +444,7 @@ impl<R: Read + ?Sized> ReadFrom<R> for Sound {
             pan_mode: reader.read_u8()?,
             pan_curve: reader.read_u8()?,
             actor_player_index: reader.read_u8()?,
+            wave_info: None,
         };
         let _pad = reader.read_u8()?;
         Ok(sound)
@@ -361,9 +507,9 @@ impl Collection {
     }
 }
 
-/// Defines a group.
+/// The header for a group entry.
 #[derive(Debug, Copy, Clone)]
-pub struct Group {
+pub struct GroupHeader {
     pub name_index: i32,
     pub unk_04: i32,
     pub unk_08: u32,
@@ -372,10 +518,10 @@ pub struct Group {
     pub rwsd_size: u32,
     pub rwar_offset: u32,
     pub rwar_size: u32,
-    pub unk_20: TaggedOffset,
+    pub items: TaggedOffset,
 }
 
-impl<R: Read + ?Sized> ReadFrom<R> for Group {
+impl<R: Read + ?Sized> ReadFrom<R> for GroupHeader {
     type Error = Error;
     fn read_from(reader: &mut R) -> Result<Self> {
         Ok(Self {
@@ -387,17 +533,87 @@ impl<R: Read + ?Sized> ReadFrom<R> for Group {
             rwsd_size: reader.read_u32::<BE>()?,
             rwar_offset: reader.read_u32::<BE>()?,
             rwar_size: reader.read_u32::<BE>()?,
-            unk_20: TaggedOffset::read_from(reader)?,
+            items: TaggedOffset::read_from(reader)?,
         })
     }
 }
 
+/// Locates a collection's data within a group.
+#[derive(Debug, Copy, Clone)]
+pub struct GroupItem {
+    /// The index of the collection.
+    pub collection_index: u32,
+    /// The offset of the collection's RWSD relative to the group's RWSD data.
+    pub rwsd_offset: u32,
+    pub rwsd_size: u32,
+    /// The offset of the collection's RWAR relative to the group's RWAR data.
+    pub rwar_offset: u32,
+    pub rwar_size: u32,
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for GroupItem {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        Ok(Self {
+            collection_index: reader.read_u32::<BE>()?,
+            rwsd_offset: reader.read_u32::<BE>()?,
+            rwsd_size: reader.read_u32::<BE>()?,
+            rwar_offset: reader.read_u32::<BE>()?,
+            rwar_size: reader.read_u32::<BE>()?,
+        })
+    }
+}
+
+/// Defines a group.
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub header: GroupHeader,
+    pub items: Vec<GroupItem>,
+}
+
+impl Group {
+    /// Reads the group's items. Also returns the offset that each item was read from.
+    fn read_from(reader: &mut (impl Read + Seek), header: GroupHeader) -> Result<(Self, Vec<u64>)> {
+        let items = if let Some(offset) = header.items.offset() {
+            reader.seek(SeekFrom::Start(offset))?;
+            read_list_with_offsets::<GroupItem, _, _>(reader)?
+        } else {
+            vec![]
+        };
+        let (offsets, items) = items.into_iter().unzip();
+        Ok((Self { header, items }, offsets))
+    }
+}
+
+/// The offsets of INFO section structs which must be updated if data in the FILE section moves.
+#[derive(Debug, Clone, Default)]
+struct InfoLayout {
+    /// The offset of each `CollectionHeader`.
+    collections: Vec<u64>,
+    /// The offset of each `GroupHeader`.
+    groups: Vec<u64>,
+    /// The offset of each `GroupItem` in each group.
+    group_items: Vec<Vec<u64>>,
+}
+
+impl InfoLayout {
+    /// Adds `base` to every offset.
+    fn rebase(&mut self, base: u64) {
+        let items = self.group_items.iter_mut().flatten();
+        for offset in self.collections.iter_mut().chain(&mut self.groups).chain(items) {
+            *offset += base;
+        }
+    }
+}
+
 /// The INFO section, containing definitions for everything in the BRSAR.
 #[derive(Debug, Clone)]
 struct InfoSection {
     sounds: Vec<Sound>,
     collections: Vec<Collection>,
     groups: Vec<Group>,
+    /// Offsets are relative to the start of the section data.
+    layout: InfoLayout,
 }
 
 impl<R: Read + Seek + ?Sized> ReadFrom<R> for InfoSection {
@@ -411,31 +627,46 @@ impl<R: Read + Seek + ?Sized> ReadFrom<R> for InfoSection {
             Region::new(reader, start_offset, section_header.size as u64 - SECTION_HEADER_SIZE);
         let header = InfoHeader::read_from(&mut section)?;
 
-        let sounds = if let Some(offset) = header.sounds.offset() {
+        let mut sounds: Vec<Sound> = if let Some(offset) = header.sounds.offset() {
             section.seek(SeekFrom::Start(offset))?;
             read_list(&mut section)?
         } else {
             vec![]
         };
+        for sound in &mut sounds {
+            // The detail offset has the same layout as a `TaggedOffset`
+            if sound.sound_kind() == Some(SoundKind::Wave) && sound.unk_18 == 1 {
+                section.seek(SeekFrom::Start(sound.offset2.into()))?;
+                sound.wave_info = Some(WaveSoundInfo::read_from(&mut section)?);
+            }
+        }
 
+        let mut layout = InfoLayout::default();
         let mut collections = vec![];
         if let Some(offset) = header.collections.offset() {
             section.seek(SeekFrom::Start(offset))?;
-            let headers: Vec<CollectionHeader> = read_list(&mut section)?;
+            let headers: Vec<(u64, CollectionHeader)> = read_list_with_offsets(&mut section)?;
             collections.reserve_exact(headers.len());
-            for header in headers {
+            for (offset, header) in headers {
                 collections.push(Collection::read_from(&mut section, header)?);
+                layout.collections.push(offset);
             }
         }
 
-        let groups = if let Some(offset) = header.groups.offset() {
+        let mut groups = vec![];
+        if let Some(offset) = header.groups.offset() {
             section.seek(SeekFrom::Start(offset))?;
-            read_list(&mut section)?
-        } else {
-            vec![]
-        };
+            let headers: Vec<(u64, GroupHeader)> = read_list_with_offsets(&mut section)?;
+            groups.reserve_exact(headers.len());
+            for (offset, header) in headers {
+                let (group, item_offsets) = Group::read_from(&mut section, header)?;
+                groups.push(group);
+                layout.groups.push(offset);
+                layout.group_items.push(item_offsets);
+            }
+        }
 
-        Ok(Self { sounds, collections, groups })
+        Ok(Self { sounds, collections, groups, layout })
     }
 }
 
@@ -458,6 +689,420 @@ impl Brsar {
             ""
         }
     }
+
+    /// Finds a sound by name, ignoring case.
+    pub fn find_sound(&self, name: &str) -> Option<usize> {
+        self.sounds.iter().position(|s| self.symbol(s.name_index).eq_ignore_ascii_case(name))
+    }
+
+    /// Reads the wave data for the sound at `index`. `reader` must be positioned over the same
+    /// BRSAR data that this was read from.
+    pub fn read_wave(&self, reader: &mut dyn ReadSeek, index: usize) -> Result<Rwav> {
+        let (name, collection_index, wave_index) = self.find_wave(reader, index)?;
+        let link = &self.collections[collection_index].groups[0];
+        let (group, item) = self.group_item(name, link)?;
+        let rwar_offset = u64::from(group.rwar_offset) + u64::from(item.rwar_offset);
+        let mut rwar = Region::new(&mut *reader, rwar_offset, item.rwar_size.into());
+        let mut rwav = open_rwar_file(&mut rwar, wave_index)?;
+        Rwav::open(&mut rwav, name)
+    }
+
+    /// Rebuilds the BRSAR data in `reader` with the wave data for the sound at `index` replaced by
+    /// `rwav` and writes the result to `writer`. Every group which contains the sound is updated,
+    /// and the offsets and sizes in the INFO and FILE sections are adjusted to match. This BRSAR is
+    /// updated to describe the new data.
+    pub fn replace_wave(
+        &mut self,
+        reader: &mut dyn ReadSeek,
+        writer: &mut dyn Write,
+        index: usize,
+        rwav: &Rwav,
+    ) -> Result<()> {
+        let (name, collection_index, wave_index) = self.find_wave(reader, index)?;
+        let mut rwav_data = vec![];
+        rwav.write_to(&mut rwav_data)?;
+
+        reader.rewind()?;
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+
+        // We need to know where everything in the INFO section is in order to patch it
+        let (file_offset, layout) = {
+            let mut cursor = Cursor::new(&data);
+            let header = FileHeader::read_from(&mut cursor)?;
+            cursor.seek(SeekFrom::Start(header.info_offset.into()))?;
+            let mut layout = InfoSection::read_from(&mut cursor)?.layout;
+            layout.rebase(u64::from(header.info_offset) + SECTION_HEADER_SIZE);
+            (header.file_offset, layout)
+        };
+        let file_magic = data.get((file_offset as usize)..(file_offset as usize + 4));
+        if file_magic != Some(&FILE_MAGIC.to_be_bytes()) {
+            error!("Invalid FILE section offset: {:#x}", file_offset);
+            return Err(Error::InvalidBrsar);
+        }
+
+        // Each group has its own copy of the collection's RWAR, so all of them need to be rebuilt
+        let mut splices = vec![];
+        for link in &self.collections[collection_index].groups {
+            let (group, item) = self.group_item(name, link)?;
+            let start = group.rwar_offset as usize + item.rwar_offset as usize;
+            let end = start + item.rwar_size as usize;
+            if start < file_offset as usize || end > data.len() {
+                error!("RWAR data for sound \"{}\" is out of bounds: {:?}", name, item);
+                return Err(Error::InvalidBrsar);
+            }
+            if splices.iter().any(|s: &Splice| s.start == start) {
+                continue;
+            }
+            let rwar = rebuild_rwar(&data[start..end], wave_index, &rwav_data)?;
+            debug!(
+                "Rebuilt RWAR for sound \"{}\" in group {}: {:#x} -> {:#x}",
+                name,
+                link.index,
+                end - start,
+                rwar.len()
+            );
+            splices.push(Splice { start, end, data: rwar });
+        }
+        splices.sort_unstable_by_key(|s| s.start);
+        if splices.windows(2).any(|w| w[0].end > w[1].start) {
+            error!("RWAR data for sound \"{}\" overlaps", name);
+            return Err(Error::InvalidBrsar);
+        }
+
+        let mut out = Vec::with_capacity(data.len());
+        let mut offset = 0;
+        for splice in &splices {
+            out.extend(&data[offset..splice.start]);
+            out.extend(&splice.data);
+            offset = splice.end;
+        }
+        out.extend(&data[offset..]);
+
+        // Moves an offset in the original data to where it is in the rebuilt data
+        let relocate = |offset: u32| -> Result<u32> {
+            let mut new_offset = i64::from(offset);
+            for splice in splices.iter().filter(|s| s.end <= offset as usize) {
+                new_offset += splice.data.len() as i64 - (splice.end - splice.start) as i64;
+            }
+            u32::try_from(new_offset).map_err(|_| Error::InvalidBrsar)
+        };
+
+        for ((group, group_offset), item_offsets) in
+            self.groups.iter_mut().zip(&layout.groups).zip(&layout.group_items)
+        {
+            let header = &mut group.header;
+            let (rwsd_start, rwar_start) = (header.rwsd_offset, header.rwar_offset);
+            for (item, &item_offset) in group.items.iter_mut().zip(item_offsets) {
+                let rwsd = relocate_range(relocate, rwsd_start, item.rwsd_offset, item.rwsd_size)?;
+                let rwar = relocate_range(relocate, rwar_start, item.rwar_offset, item.rwar_size)?;
+                (item.rwsd_offset, item.rwsd_size) = rwsd;
+                (item.rwar_offset, item.rwar_size) = rwar;
+                let pos = item_offset as usize;
+                BE::write_u32_into(
+                    &[rwsd.0, rwsd.1, rwar.0, rwar.1],
+                    &mut out[(pos + 0x4)..(pos + 0x14)],
+                );
+            }
+            let rwsd = relocate_range(relocate, 0, header.rwsd_offset, header.rwsd_size)?;
+            let rwar = relocate_range(relocate, 0, header.rwar_offset, header.rwar_size)?;
+            (header.rwsd_offset, header.rwsd_size) = rwsd;
+            (header.rwar_offset, header.rwar_size) = rwar;
+            let pos = *group_offset as usize;
+            BE::write_u32_into(
+                &[rwsd.0, rwsd.1, rwar.0, rwar.1],
+                &mut out[(pos + 0x10)..(pos + 0x20)],
+            );
+        }
+
+        let collection = &mut self.collections[collection_index];
+        collection.header.audio_length = splices[0].data.len() as u32;
+        let pos = layout.collections[collection_index] as usize;
+        BE::write_u32(&mut out[(pos + 0x4)..(pos + 0x8)], collection.header.audio_length);
+
+        // The FILE section is always last, so it gets all of the size difference
+        let total_size = u32::try_from(out.len()).map_err(|_| Error::InvalidBrsar)?;
+        let file_size = total_size - file_offset;
+        let file_offset = file_offset as usize;
+        BE::write_u32(&mut out[0x8..0xc], total_size);
+        BE::write_u32(&mut out[0x24..0x28], file_size);
+        BE::write_u32(&mut out[(file_offset + 0x4)..(file_offset + 0x8)], file_size);
+
+        writer.write_all(&out)?;
+        Ok(())
+    }
+
+    /// Looks up the wave sound at `index`. Returns the sound's name, the index of its collection,
+    /// and the index of its wave in the collection's RWAR.
+    fn find_wave(&self, reader: &mut dyn ReadSeek, index: usize) -> Result<(&str, usize, u32)> {
+        let sound = match self.sounds.get(index) {
+            Some(s) => s,
+            None => {
+                error!("Invalid BRSAR sound index: {}", index);
+                return Err(Error::InvalidBrsar);
+            }
+        };
+        let name = self.symbol(sound.name_index);
+        let wave_info = sound.wave_info.ok_or_else(|| Error::NotWaveSound(name.to_owned()))?;
+
+        // Sounds belong to collections and collections are stored in groups
+        let collection_index = sound.collection_index as usize;
+        let collection = self.collections.get(collection_index);
+        let link = match collection.and_then(|c| c.groups.first()) {
+            Some(link) => link,
+            None => {
+                error!("Sound \"{}\" is not in a group", name);
+                return Err(Error::InvalidBrsar);
+            }
+        };
+        let (group, item) = self.group_item(name, link)?;
+        debug!("Reading sound \"{}\" from group {}: {:?}", name, link.index, item);
+
+        let rwsd_offset = u64::from(group.rwsd_offset) + u64::from(item.rwsd_offset);
+        let mut rwsd = Region::new(&mut *reader, rwsd_offset, item.rwsd_size.into());
+        let wave_index = read_rwsd_wave_index(&mut rwsd, wave_info.index)?;
+        Ok((name, collection_index, wave_index))
+    }
+
+    /// Looks up the group header and item that a collection group link points to. `name` is the
+    /// name of the sound being looked up.
+    fn group_item(
+        &self,
+        name: &str,
+        link: &CollectionGroupLink,
+    ) -> Result<(&GroupHeader, &GroupItem)> {
+        let group = self.groups.get(link.index as usize);
+        match group.and_then(|g| Some((g, g.items.get(link.sub_index as usize)?))) {
+            Some((group, item)) => Ok((&group.header, item)),
+            None => {
+                error!("Invalid group link for sound \"{}\": {:?}", name, link);
+                Err(Error::InvalidBrsar)
+            }
+        }
+    }
+}
+
+/// Replaces a range of bytes in a BRSAR file.
+struct Splice {
+    start: usize,
+    end: usize,
+    data: Vec<u8>,
+}
+
+/// Relocates a range of data given by an offset and size relative to `base`. Returns the new
+/// relative offset and size.
+fn relocate_range(
+    relocate: impl Fn(u32) -> Result<u32>,
+    base: u32,
+    offset: u32,
+    size: u32,
+) -> Result<(u32, u32)> {
+    let start = base + offset;
+    let new_start = relocate(start)?;
+    let new_end = relocate(start + size)?;
+    Ok((new_start - relocate(base)?, new_end - new_start))
+}
+
+/// An entry in an RWSD's DATA section.
+#[derive(Debug, Copy, Clone)]
+struct WaveSoundData {
+    _info: TaggedOffset,
+    _tracks: TaggedOffset,
+    notes: TaggedOffset,
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for WaveSoundData {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        Ok(Self {
+            _info: TaggedOffset::read_from(reader)?,
+            _tracks: TaggedOffset::read_from(reader)?,
+            notes: TaggedOffset::read_from(reader)?,
+        })
+    }
+}
+
+/// A note in a wave sound.
+#[derive(Debug, Copy, Clone)]
+struct NoteInfo {
+    /// The index of the wave to play.
+    wave_index: u32,
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for NoteInfo {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        Ok(Self { wave_index: reader.read_u32::<BE>()? })
+    }
+}
+
+/// Reads RWSD data and returns the index of the wave played by the sound at `index`.
+fn read_rwsd_wave_index(reader: &mut (impl Read + Seek), index: u32) -> Result<u32> {
+    let (version, sections) = read_file_header(reader, RWSD_MAGIC)?;
+    if version < RWSD_MIN_VERSION {
+        error!("RWSD version {:#x} is not supported", version);
+        return Err(Error::InvalidBrsar);
+    }
+    let &(data_offset, _) = sections.first().ok_or(Error::InvalidBrsar)?;
+    let mut data = open_section(reader, data_offset)?;
+    let sounds: Vec<WaveSoundData> = read_list(&mut data)?;
+    let sound = match sounds.get(index as usize) {
+        Some(s) => s,
+        None => {
+            error!("Invalid RWSD sound index: {}", index);
+            return Err(Error::InvalidBrsar);
+        }
+    };
+    let notes: Vec<NoteInfo> = match sound.notes.offset() {
+        Some(offset) => {
+            data.seek(SeekFrom::Start(offset))?;
+            read_list(&mut data)?
+        }
+        None => vec![],
+    };
+    match notes.first() {
+        Some(note) => Ok(note.wave_index),
+        None => {
+            error!("RWSD sound {} does not have any notes", index);
+            Err(Error::InvalidBrsar)
+        }
+    }
+}
+
+/// An entry in an RWAR's TABL section.
+#[derive(Debug, Copy, Clone)]
+struct RwarEntry {
+    /// The offset of the file relative to the DATA section.
+    offset: TaggedOffset,
+    size: u32,
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for RwarEntry {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        Ok(Self { offset: TaggedOffset::read_from(reader)?, size: reader.read_u32::<BE>()? })
+    }
+}
+
+impl<W: Write + ?Sized> WriteTo<W> for RwarEntry {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        self.offset.write_to(writer)?;
+        writer.write_u32::<BE>(self.size)?;
+        Ok(())
+    }
+}
+
+/// The contents of an RWAR's TABL section.
+#[derive(Debug, Clone)]
+struct RwarTable {
+    /// The RWAR file version.
+    version: u16,
+    /// The offset of the DATA section.
+    data_offset: u32,
+    entries: Vec<RwarEntry>,
+}
+
+impl RwarTable {
+    /// Gets the absolute offset and size of the file at `index` within the RWAR.
+    fn file(&self, index: u32) -> Result<(u64, u64)> {
+        let entry = match self.entries.get(index as usize) {
+            Some(e) => e,
+            None => {
+                error!("Invalid RWAR file index: {}", index);
+                return Err(Error::InvalidBrsar);
+            }
+        };
+        match entry.offset.offset() {
+            Some(offset) => Ok((u64::from(self.data_offset) + offset, entry.size.into())),
+            None => {
+                error!("Invalid RWAR file offset: {:?}", entry.offset);
+                Err(Error::InvalidBrsar)
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> ReadFrom<R> for RwarTable {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        let (version, sections) = read_file_header(reader, RWAR_MAGIC)?;
+        let (&(table_offset, _), &(data_offset, _)) = match sections.as_slice() {
+            [table, data, ..] => (table, data),
+            _ => {
+                error!("RWAR data must have at least 2 sections");
+                return Err(Error::InvalidBrsar);
+            }
+        };
+        let mut table = open_section(reader, table_offset)?;
+        let count = table.read_u32::<BE>()? as usize;
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            entries.push(RwarEntry::read_from(&mut table)?);
+        }
+        Ok(Self { version, data_offset, entries })
+    }
+}
+
+/// Reads RWAR data and opens a region over the RWAV file at `index`.
+fn open_rwar_file<R: Read + Seek>(mut reader: R, index: u32) -> Result<Region<R>> {
+    let table = RwarTable::read_from(&mut reader)?;
+    let (offset, size) = table.file(index)?;
+    Ok(Region::new(reader, offset, size))
+}
+
+/// Rebuilds RWAR data with the file at `index` replaced by `file`.
+fn rebuild_rwar(rwar: &[u8], index: u32, file: &[u8]) -> Result<Vec<u8>> {
+    let table = RwarTable::read_from(&mut Cursor::new(rwar))?;
+    table.file(index)?;
+    let mut files = Vec::with_capacity(table.entries.len());
+    for i in 0..(table.entries.len() as u32) {
+        if i == index {
+            files.push(file);
+        } else {
+            let (offset, size) = table.file(i)?;
+            match rwar.get((offset as usize)..((offset + size) as usize)) {
+                Some(data) => files.push(data),
+                None => {
+                    error!("RWAR file {} is out of bounds", i);
+                    return Err(Error::InvalidBrsar);
+                }
+            }
+        }
+    }
+
+    // Files are stored after the DATA section header, which is padded to a full alignment block
+    let table_size =
+        align(SECTION_HEADER_SIZE as u32 + 4 + RWAR_ENTRY_SIZE * files.len() as u32, RWAR_ALIGN);
+    let mut entries = Vec::with_capacity(files.len());
+    let mut data_size = RWAR_ALIGN;
+    for file in &files {
+        let size = file.len() as u32;
+        entries.push(RwarEntry { offset: TaggedOffset::Relative(data_size), size });
+        data_size += align(size, RWAR_ALIGN);
+    }
+
+    let table_offset = file_header_size(2);
+    let data_offset = table_offset + table_size;
+    let file_size = data_offset + data_size;
+    let mut writer = Vec::with_capacity(file_size as usize);
+    let sections = [(table_offset, table_size), (data_offset, data_size)];
+    write_file_header(&mut writer, RWAR_MAGIC, table.version, file_size, &sections)?;
+
+    writer.write_u32::<BE>(TABL_MAGIC)?;
+    writer.write_u32::<BE>(table_size)?;
+    writer.write_u32::<BE>(entries.len() as u32)?;
+    RwarEntry::write_all_to(&mut writer, &entries)?;
+    writer.resize(data_offset as usize, 0);
+
+    writer.write_u32::<BE>(DATA_MAGIC)?;
+    writer.write_u32::<BE>(data_size)?;
+    for file in files {
+        writer.resize(align(writer.len(), RWAR_ALIGN as usize), 0);
+        writer.extend(file);
+    }
+    writer.resize(file_size as usize, 0);
+    Ok(writer)
 }
 
 impl<R: Read + Seek + ?Sized> ReadFrom<R> for Brsar {
@@ -485,3 +1130,270 @@ impl<R: Read + Seek + ?Sized> ReadFrom<R> for Brsar {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::format::{Format, PcmS16Le};
+    use crate::audio::transport::rwav::Codec;
+    use crate::audio::{ReadSamples, Samples};
+    use crate::test::TEST_RWAV;
+
+    fn write_header(buf: &mut Vec<u8>, magic: &[u8], version: u16, sections: &[(u32, u32)]) {
+        buf.extend(magic);
+        buf.write_u16::<BE>(BIG_ENDIAN).unwrap();
+        buf.write_u16::<BE>(version).unwrap();
+        buf.write_u32::<BE>(0).unwrap();
+        buf.write_u16::<BE>(0x20).unwrap();
+        buf.write_u16::<BE>(sections.len() as u16).unwrap();
+        for &(offset, size) in sections {
+            buf.write_u32::<BE>(offset).unwrap();
+            buf.write_u32::<BE>(size).unwrap();
+        }
+        buf.resize(align(buf.len(), 0x20), 0);
+    }
+
+    /// Wraps section data in a section header and pads it to a multiple of 0x20 bytes.
+    fn make_section(magic: &[u8], mut body: Vec<u8>) -> Vec<u8> {
+        body.resize((body.len() + 8 + 0x1f) & !0x1f, 0);
+        let mut section = magic.to_vec();
+        section.write_u32::<BE>(body.len() as u32).unwrap();
+        section.extend(&body[..(body.len() - 8)]);
+        section
+    }
+
+    fn write_offset(buf: &mut Vec<u8>, offset: u32) {
+        buf.write_u32::<BE>(0x01000000).unwrap();
+        buf.write_u32::<BE>(offset).unwrap();
+    }
+
+    fn write_null(buf: &mut Vec<u8>) {
+        buf.write_u32::<BE>(0).unwrap();
+        buf.write_u32::<BE>(0).unwrap();
+    }
+
+    fn write_u32s(buf: &mut Vec<u8>, values: &[u32]) {
+        for &value in values {
+            buf.write_u32::<BE>(value).unwrap();
+        }
+    }
+
+    fn make_symb() -> Vec<u8> {
+        let names: &[&[u8]] = &[b"SE_TEST\0", b"SEQ_TEST\0", b"GROUP_TEST\0"];
+        let mut body = vec![];
+        write_u32s(&mut body, &[0x14, 0, 0, 0, 0, names.len() as u32]);
+        let mut offset = 0x18 + 4 * names.len();
+        for name in names {
+            body.write_u32::<BE>(offset as u32).unwrap();
+            offset += name.len();
+        }
+        for name in names {
+            body.extend(*name);
+        }
+        make_section(b"SYMB", body)
+    }
+
+    /// Writes a sound entry.
+    fn write_sound(body: &mut Vec<u8>, name_index: u32, kind: u8, info_offset: u32) {
+        write_u32s(body, &[name_index, 0, 0]);
+        write_null(body);
+        body.extend([0x7f, 0x40, kind, 0, 1, 0, 0, 0]);
+        write_u32s(body, &[info_offset, 0, 0, 0]);
+    }
+
+    fn make_info(rwsd: (u32, u32), rwar: (u32, u32)) -> Vec<u8> {
+        let mut body = vec![];
+        write_offset(&mut body, 0x30); // sounds
+        write_null(&mut body);
+        write_null(&mut body);
+        write_offset(&mut body, 0xa8); // collections
+        write_offset(&mut body, 0xe4); // groups
+        write_null(&mut body);
+
+        // 0x30: sounds
+        body.write_u32::<BE>(2).unwrap();
+        write_offset(&mut body, 0x44);
+        write_offset(&mut body, 0x70);
+        write_sound(&mut body, 0, SoundKind::Wave.into(), 0x9c);
+        write_sound(&mut body, 1, SoundKind::Sequence.into(), 0);
+        // 0x9c: wave sound info
+        write_u32s(&mut body, &[0, 1, 0x40000000]);
+
+        // 0xa8: collections
+        body.write_u32::<BE>(1).unwrap();
+        write_offset(&mut body, 0xb4);
+        write_u32s(&mut body, &[rwsd.1, rwar.1, 0]);
+        write_null(&mut body);
+        write_offset(&mut body, 0xd0);
+        // 0xd0: collection group links
+        body.write_u32::<BE>(1).unwrap();
+        write_offset(&mut body, 0xdc);
+        write_u32s(&mut body, &[0, 0]);
+
+        // 0xe4: groups
+        body.write_u32::<BE>(1).unwrap();
+        write_offset(&mut body, 0xf0);
+        write_u32s(&mut body, &[2, 0, 0, 0, rwsd.0, rwsd.1, rwar.0, rwar.1]);
+        write_offset(&mut body, 0x118);
+        // 0x118: group items
+        body.write_u32::<BE>(1).unwrap();
+        write_offset(&mut body, 0x124);
+        write_u32s(&mut body, &[0, 0, rwsd.1, 0, rwar.1]);
+        make_section(b"INFO", body)
+    }
+
+    fn make_rwsd() -> Vec<u8> {
+        let mut body = vec![];
+        body.write_u32::<BE>(1).unwrap();
+        write_offset(&mut body, 0xc);
+        write_null(&mut body);
+        write_null(&mut body);
+        write_offset(&mut body, 0x24);
+        // 0x24: notes
+        body.write_u32::<BE>(1).unwrap();
+        write_offset(&mut body, 0x30);
+        body.write_u32::<BE>(1).unwrap(); // wave_index
+        let data = make_section(b"DATA", body);
+
+        let mut rwsd = vec![];
+        write_header(&mut rwsd, b"RWSD", RWSD_MIN_VERSION, &[(0x20, data.len() as u32)]);
+        rwsd.extend(data);
+        rwsd
+    }
+
+    fn make_rwar() -> Vec<u8> {
+        let mut table = vec![];
+        table.write_u32::<BE>(2).unwrap();
+        write_offset(&mut table, 0x20);
+        table.write_u32::<BE>(0x20).unwrap();
+        write_offset(&mut table, 0x40);
+        table.write_u32::<BE>(TEST_RWAV.len() as u32).unwrap();
+        let table = make_section(b"TABL", table);
+
+        let mut data = vec![0; 0x38];
+        data.extend(TEST_RWAV);
+        let data = make_section(b"DATA", data);
+
+        let data_offset = 0x20 + table.len() as u32;
+        let sections = [(0x20, table.len() as u32), (data_offset, data.len() as u32)];
+        let mut rwar = vec![];
+        write_header(&mut rwar, b"RWAR", 0x0100, &sections);
+        rwar.extend(table);
+        rwar.extend(data);
+        rwar
+    }
+
+    /// Builds a BRSAR containing a wave sound and a sequence.
+    fn make_brsar() -> Vec<u8> {
+        let symb = make_symb();
+        let rwsd = make_rwsd();
+        let rwar = make_rwar();
+        let info_offset = 0x40 + symb.len() as u32;
+        // The size of the INFO section doesn't depend on the offsets in it
+        let info_len = make_info((0, 0), (0, 0)).len() as u32;
+        let file_offset = info_offset + info_len;
+        let rwsd_offset = file_offset + 0x20;
+        let rwar_offset = rwsd_offset + rwsd.len() as u32;
+        let info = make_info((rwsd_offset, rwsd.len() as u32), (rwar_offset, rwar.len() as u32));
+
+        let mut file = vec![0; 0x18];
+        file.extend(rwsd);
+        file.extend(rwar);
+        let file = make_section(b"FILE", file);
+
+        let mut brsar = vec![];
+        let sections =
+            [(0x40, symb.len() as u32), (info_offset, info_len), (file_offset, file.len() as u32)];
+        write_header(&mut brsar, b"RSAR", BRSAR_VERSION, &sections);
+        brsar.extend(symb);
+        brsar.extend(info);
+        brsar.extend(file);
+        brsar
+    }
+
+    #[test]
+    fn test_read_brsar() -> Result<()> {
+        let mut reader = Cursor::new(make_brsar());
+        let brsar = Brsar::read_from(&mut reader)?;
+        assert_eq!(brsar.symbols, &["SE_TEST", "SEQ_TEST", "GROUP_TEST"]);
+        assert_eq!(brsar.sounds.len(), 2);
+        assert_eq!(brsar.collections.len(), 1);
+        assert_eq!(brsar.groups.len(), 1);
+
+        assert_eq!(brsar.find_sound("se_test"), Some(0));
+        assert_eq!(brsar.find_sound("SEQ_TEST"), Some(1));
+        assert_eq!(brsar.find_sound("foo"), None);
+
+        let wave = &brsar.sounds[0];
+        assert_eq!(wave.sound_kind(), Some(SoundKind::Wave));
+        assert_eq!(wave.wave_info.map(|i| i.index), Some(0));
+        let sequence = &brsar.sounds[1];
+        assert_eq!(sequence.sound_kind(), Some(SoundKind::Sequence));
+        assert!(sequence.wave_info.is_none());
+
+        let group = &brsar.groups[0];
+        assert_eq!(brsar.symbol(group.header.name_index), "GROUP_TEST");
+        assert_eq!(group.items.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_wave() -> Result<()> {
+        let mut reader = Cursor::new(make_brsar());
+        let brsar = Brsar::read_from(&mut reader)?;
+
+        let rwav = brsar.read_wave(&mut reader, 0)?;
+        assert_eq!(rwav.tag.name, "SE_TEST");
+        assert_eq!(rwav.sample_rate, 44100);
+        assert_eq!(rwav.channels.len(), 2);
+        assert_eq!(rwav.channels[0].data, &TEST_RWAV[0x108..0x118]);
+        assert_eq!(rwav.channels[1].data, &TEST_RWAV[0x118..0x128]);
+
+        assert!(matches!(brsar.read_wave(&mut reader, 1), Err(Error::NotWaveSound(_))));
+        assert!(matches!(brsar.read_wave(&mut reader, 2), Err(Error::InvalidBrsar)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_wave() -> Result<()> {
+        let original = make_brsar();
+        let mut reader = Cursor::new(original.clone());
+        let mut brsar = Brsar::read_from(&mut reader)?;
+        let old_group = brsar.groups[0].clone();
+
+        let pcm = (0..200).collect::<Vec<i16>>();
+        let samples = Samples::<PcmS16Le>::from_pcm(pcm.clone(), 2, 32000);
+        let rwav = Rwav::from_pcm(&mut samples.into_reader("test"), Codec::Pcm16)?;
+        let mut writer = vec![];
+        brsar.replace_wave(&mut reader, &mut writer, 0, &rwav)?;
+        assert!(writer.len() > original.len());
+        assert_eq!(BE::read_u32(&writer[0x8..0xc]) as usize, writer.len());
+
+        let mut reader = Cursor::new(writer);
+        let rebuilt = Brsar::read_from(&mut reader)?;
+        let group = &rebuilt.groups[0];
+        assert_eq!(group.header.rwsd_offset, old_group.header.rwsd_offset);
+        assert_eq!(group.header.rwsd_size, old_group.header.rwsd_size);
+        assert_eq!(group.header.rwar_offset, old_group.header.rwar_offset);
+        assert!(group.header.rwar_size > old_group.header.rwar_size);
+        assert_eq!(group.header.rwar_size, brsar.groups[0].header.rwar_size);
+        assert_eq!(group.items[0].rwar_offset, 0);
+        assert_eq!(group.items[0].rwar_size, group.header.rwar_size);
+        assert_eq!(rebuilt.collections[0].header.audio_length, group.header.rwar_size);
+        assert_eq!(brsar.collections[0].header.audio_length, group.header.rwar_size);
+
+        let rwav = rebuilt.read_wave(&mut reader, 0)?;
+        assert_eq!(rwav.tag.name, "SE_TEST");
+        assert_eq!(rwav.format, Format::PcmS16Be);
+        assert_eq!(rwav.sample_rate, 32000);
+        assert_eq!(rwav.decoder().read_all_samples()?.data, pcm);
+
+        // The other file in the RWAR should still be there
+        let rwar_offset = u64::from(group.header.rwar_offset);
+        let mut rwar = Region::new(&mut reader, rwar_offset, group.header.rwar_size.into());
+        let table = RwarTable::read_from(&mut rwar)?;
+        assert_eq!(table.entries.len(), 2);
+        assert_eq!(table.entries[0].size, 0x20);
+        Ok(())
+    }
+}
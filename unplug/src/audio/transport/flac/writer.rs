@@ -0,0 +1,594 @@
+use super::*;
+use crate::audio::format::PcmS16Le;
+use crate::audio::sample::{PeekSamples, ReadSamples};
+use crate::audio::{Error, ProgressHint, Result};
+use byteorder::{WriteBytesExt, BE, LE};
+use std::io::{Seek, SeekFrom, Write};
+use tracing::{debug, instrument};
+
+const DEFAULT_SOFTWARE_NAME: &str = concat!("unplug v", env!("CARGO_PKG_VERSION"));
+
+const FLAC_MAGIC: &[u8; 4] = b"fLaC";
+
+const BLOCK_TYPE_STREAMINFO: u8 = 0;
+const BLOCK_TYPE_VORBIS_COMMENT: u8 = 4;
+const STREAMINFO_SIZE: u32 = 34;
+
+/// The number of sample frames in each FLAC frame.
+const BLOCK_SIZE: usize = 4096;
+/// The bit depth of the audio data.
+const BITS_PER_SAMPLE: u32 = 16;
+/// The highest order of fixed predictor to try.
+const MAX_FIXED_ORDER: usize = 4;
+/// The highest partition order to try when Rice-coding residuals.
+const MAX_PARTITION_ORDER: u32 = 8;
+/// The highest Rice parameter which can be written using 4-bit parameters (15 is an escape code).
+const MAX_RICE_PARAM: u32 = 14;
+
+/// The highest channel count which FLAC supports.
+const MAX_CHANNELS: usize = 8;
+/// The highest sample rate which can be stored in STREAMINFO.
+const MAX_SAMPLE_RATE: u32 = (1 << 20) - 1;
+
+/// Frame header channel assignments for stereo decorrelation.
+const CHANNELS_LEFT_SIDE: u64 = 8;
+const CHANNELS_RIGHT_SIDE: u64 = 9;
+const CHANNELS_MID_SIDE: u64 = 10;
+
+const CRC16_TABLE: [u16; 256] = crc16_table();
+
+/// Builds the lookup table for the CRC-16 at the end of each frame (polynomial 0x8005).
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Calculates the CRC-16 of a frame.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &b| (crc << 8) ^ CRC16_TABLE[usize::from((crc >> 8) as u8 ^ b)])
+}
+
+/// Calculates the CRC-8 of a frame header (polynomial 0x07).
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0, |mut crc, &b| {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+        crc
+    })
+}
+
+/// Writes big-endian values of arbitrary bit widths into a byte buffer.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits which have not been written to `bytes` yet.
+    acc: u64,
+    /// The number of bits in `acc`.
+    bits: u32,
+}
+
+impl BitWriter {
+    /// Writes the low `bits` bits of `value`. `bits` must not be greater than 32.
+    fn write(&mut self, value: u64, bits: u32) {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return;
+        }
+        self.acc = (self.acc << bits) | (value & ((1 << bits) - 1));
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.acc >> self.bits) as u8);
+        }
+        self.acc &= (1 << self.bits) - 1;
+    }
+
+    /// Writes a two's complement signed value using `bits` bits.
+    fn write_signed(&mut self, value: i32, bits: u32) {
+        self.write(value as i64 as u64, bits);
+    }
+
+    /// Writes `zeros` zero bits followed by a one bit.
+    fn write_unary(&mut self, mut zeros: u32) {
+        while zeros >= 32 {
+            self.write(0, 32);
+            zeros -= 32;
+        }
+        self.write(1, zeros + 1);
+    }
+
+    /// Writes a number using FLAC's extended UTF-8 coding.
+    fn write_utf8(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+        // An n-byte sequence can hold 5n + 1 bits
+        let mut num_bytes = 2;
+        while value >> (5 * num_bytes + 1) != 0 {
+            num_bytes += 1;
+        }
+        let lead = u64::from((0xff00u32 >> num_bytes) as u8);
+        self.write(lead | (value >> (6 * (num_bytes - 1))), 8);
+        for i in (0..(num_bytes - 1)).rev() {
+            self.write(0x80 | ((value >> (6 * i)) & 0x3f), 8);
+        }
+    }
+
+    /// Pads the data with zero bits up to the next byte boundary.
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(0, 8 - self.bits);
+        }
+    }
+}
+
+/// Maps a signed residual onto an unsigned value for Rice coding.
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+/// Calculates the residual of `samples` using the fixed predictor of order `order`.
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<i32> {
+    let s = samples;
+    (order..s.len())
+        .map(|i| match order {
+            0 => s[i],
+            1 => s[i] - s[i - 1],
+            2 => s[i] - 2 * s[i - 1] + s[i - 2],
+            3 => s[i] - 3 * s[i - 1] + 3 * s[i - 2] - s[i - 3],
+            4 => s[i] - 4 * s[i - 1] + 6 * s[i - 2] - 4 * s[i - 3] + s[i - 4],
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Parameters for Rice-coding a residual.
+struct RiceParams {
+    /// The residual is split into `2^partition_order` partitions.
+    partition_order: u32,
+    /// The Rice parameter for each partition.
+    params: Vec<u32>,
+}
+
+/// Chooses the best Rice parameter for a partition of `count` values which sum to `sum`. Returns
+/// the parameter and the estimated number of bits required to encode the partition.
+fn best_rice_param(count: u64, sum: u64) -> (u32, u64) {
+    (0..=MAX_RICE_PARAM)
+        .map(|k| (k, count * u64::from(k + 1) + (sum >> k)))
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap()
+}
+
+/// Chooses how to Rice-code the `residual` of a block of `block_size` samples which was predicted
+/// with order `order`. Returns the parameters and the estimated size in bits.
+fn plan_rice(residual: &[i32], order: usize, block_size: usize) -> (RiceParams, u64) {
+    // The first partition does not include the warm-up samples, so each partition must be bigger
+    let mut max_order = 0;
+    while max_order < MAX_PARTITION_ORDER
+        && block_size.is_multiple_of(1 << (max_order + 1))
+        && (block_size >> (max_order + 1)) > order
+    {
+        max_order += 1;
+    }
+
+    // Calculate the sums for the finest partitioning, then merge them for the coarser ones
+    let partition_size = block_size >> max_order;
+    let mut sums = vec![0u64; 1 << max_order];
+    for (i, &r) in residual.iter().enumerate() {
+        sums[(i + order) / partition_size] += u64::from(zigzag(r));
+    }
+    let mut best: Option<(RiceParams, u64)> = None;
+    for partition_order in (0..=max_order).rev() {
+        let partition_size = (block_size >> partition_order) as u64;
+        let mut params = vec![];
+        let mut total = 0;
+        for (i, &sum) in sums.iter().enumerate() {
+            let count = if i == 0 { partition_size - order as u64 } else { partition_size };
+            let (param, bits) = best_rice_param(count, sum);
+            params.push(param);
+            total += 4 + bits;
+        }
+        if best.as_ref().is_none_or(|(_, bits)| total < *bits) {
+            best = Some((RiceParams { partition_order, params }, total));
+        }
+        sums = sums.chunks(2).map(|c| c.iter().sum()).collect();
+    }
+    best.unwrap()
+}
+
+/// How a subframe is encoded.
+enum SubframeKind {
+    /// Every sample has the same value.
+    Constant,
+    /// Samples are stored without compression.
+    Verbatim,
+    /// Samples are predicted from the previous samples and the residual is Rice-coded.
+    Fixed { order: usize, residual: Vec<i32>, rice: RiceParams },
+}
+
+/// A plan for encoding the samples of one channel in a frame.
+struct Subframe<'a> {
+    samples: &'a [i32],
+    /// The number of bits in each sample.
+    bps: u32,
+    kind: SubframeKind,
+    /// The estimated size of the subframe in bits.
+    bits: u64,
+}
+
+impl<'a> Subframe<'a> {
+    /// Chooses the smallest encoding for `samples`, which have `bps` bits each.
+    fn plan(samples: &'a [i32], bps: u32) -> Self {
+        let len = samples.len() as u64;
+        if samples.iter().all(|&s| s == samples[0]) {
+            return Self { samples, bps, kind: SubframeKind::Constant, bits: u64::from(bps) };
+        }
+        let mut best =
+            Self { samples, bps, kind: SubframeKind::Verbatim, bits: u64::from(bps) * len };
+        for order in 0..=MAX_FIXED_ORDER.min(samples.len() - 1) {
+            let residual = fixed_residual(samples, order);
+            let (rice, rice_bits) = plan_rice(&residual, order, samples.len());
+            // Warm-up samples + coding method + partition order + residual
+            let bits = (order as u64) * u64::from(bps) + 2 + 4 + rice_bits;
+            if bits < best.bits {
+                best.kind = SubframeKind::Fixed { order, residual, rice };
+                best.bits = bits;
+            }
+        }
+        best
+    }
+
+    /// Writes the subframe to `out`.
+    fn write_to(&self, out: &mut BitWriter) {
+        let kind = match &self.kind {
+            SubframeKind::Constant => 0b000000,
+            SubframeKind::Verbatim => 0b000001,
+            SubframeKind::Fixed { order, .. } => 0b001000 | *order as u64,
+        };
+        out.write(0, 1); // Zero padding
+        out.write(kind, 6);
+        out.write(0, 1); // No wasted bits
+        match &self.kind {
+            SubframeKind::Constant => out.write_signed(self.samples[0], self.bps),
+            SubframeKind::Verbatim => {
+                for &sample in self.samples {
+                    out.write_signed(sample, self.bps);
+                }
+            }
+            SubframeKind::Fixed { order, residual, rice } => {
+                for &sample in &self.samples[..*order] {
+                    out.write_signed(sample, self.bps);
+                }
+                // Rice coding with 4-bit parameters
+                out.write(0, 2);
+                out.write(rice.partition_order.into(), 4);
+                let partition_size = self.samples.len() >> rice.partition_order;
+                let mut values = residual.iter();
+                for (i, &param) in rice.params.iter().enumerate() {
+                    let count = if i == 0 { partition_size - order } else { partition_size };
+                    out.write(param.into(), 4);
+                    for &r in values.by_ref().take(count) {
+                        let value = zigzag(r);
+                        out.write_unary(value >> param);
+                        out.write(value.into(), param);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Writes out a FLAC file from sample data and other parameters.
+///
+/// Audio is compressed using fixed linear predictors and stereo decorrelation. Cues are stored in
+/// Vorbis comments so that they can be read back by `FlacReader`.
+pub struct FlacWriter<'r, 's: 'r> {
+    samples: PeekSamples<'s, Box<dyn ReadSamples<'s, Format = PcmS16Le> + 'r>>,
+    channels: usize,
+    sample_rate: u32,
+    software_name: Cow<'static, str>,
+    on_progress: Option<Box<dyn FnMut(Option<ProgressHint>) + 'r>>,
+}
+
+impl<'r, 's: 'r> FlacWriter<'r, 's> {
+    /// Creates a new `FlacWriter` which reads samples from `reader`.
+    pub fn new(reader: impl ReadSamples<'s, Format = PcmS16Le> + 'r) -> Self {
+        Self::new_impl(Box::from(reader))
+    }
+
+    fn new_impl(reader: Box<dyn ReadSamples<'s, Format = PcmS16Le> + 'r>) -> Self {
+        Self {
+            samples: reader.peekable(),
+            channels: 0,
+            sample_rate: 0,
+            software_name: DEFAULT_SOFTWARE_NAME.into(),
+            on_progress: None,
+        }
+    }
+
+    /// Sets the software name to write as the Vorbis comment vendor string.
+    pub fn software_name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.software_name = Cow::Owned(name.into());
+        self
+    }
+
+    /// Sets a callback to run for progress updates. If the total amount of work is unknown, the
+    /// callback will still be invoked with a `None` hint.
+    pub fn on_progress(&mut self, callback: impl FnMut(Option<ProgressHint>) + 'r) -> &mut Self {
+        self.on_progress = Some(Box::from(callback));
+        self
+    }
+
+    /// Encodes the final FLAC file and writes it to `writer`.
+    #[instrument(level = "trace", skip_all)]
+    pub fn write_to(&mut self, mut writer: impl Write + Seek) -> Result<()> {
+        self.update_progress();
+        self.peek_audio_info()?;
+        writer.write_all(FLAC_MAGIC)?;
+        // STREAMINFO needs to be rewritten after the audio is encoded
+        let info_offset = writer.stream_position()?;
+        self.write_stream_info(&mut writer, &StreamStats::default())?;
+        self.write_comments(&mut writer)?;
+        let stats = self.write_frames(&mut writer)?;
+        let end_offset = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(info_offset))?;
+        self.write_stream_info(&mut writer, &stats)?;
+        writer.seek(SeekFrom::Start(end_offset))?;
+        Ok(())
+    }
+
+    /// Writes the STREAMINFO metadata block.
+    fn write_stream_info(&self, writer: &mut impl Write, stats: &StreamStats) -> Result<()> {
+        write_block_header(writer, BLOCK_TYPE_STREAMINFO, false, STREAMINFO_SIZE)?;
+        let mut info = BitWriter::default();
+        info.write(BLOCK_SIZE as u64, 16); // Minimum block size
+        info.write(BLOCK_SIZE as u64, 16); // Maximum block size
+        info.write(stats.min_frame_size.into(), 24);
+        info.write(stats.max_frame_size.into(), 24);
+        info.write(self.sample_rate.into(), 20);
+        info.write(self.channels as u64 - 1, 3);
+        info.write(u64::from(BITS_PER_SAMPLE - 1), 5);
+        info.write(stats.num_samples >> 32, 4);
+        info.write(stats.num_samples, 32);
+        // The MD5 signature is optional
+        info.bytes.extend([0; 16]);
+        writer.write_all(&info.bytes)?;
+        Ok(())
+    }
+
+    /// Writes the VORBIS_COMMENT metadata block with the cues in it.
+    fn write_comments(&self, writer: &mut impl Write) -> Result<()> {
+        let mut cues = self.samples.cues().collect::<Vec<_>>();
+        cues.sort_unstable();
+        cues.dedup();
+        let comments =
+            cues.iter().map(|c| format!("{}={}", CUE_FIELD, format_cue(c))).collect::<Vec<_>>();
+
+        let mut block = vec![];
+        block.write_u32::<LE>(self.software_name.len() as u32)?;
+        block.write_all(self.software_name.as_bytes())?;
+        block.write_u32::<LE>(comments.len() as u32)?;
+        for comment in comments {
+            block.write_u32::<LE>(comment.len() as u32)?;
+            block.write_all(comment.as_bytes())?;
+        }
+        write_block_header(writer, BLOCK_TYPE_VORBIS_COMMENT, true, block.len() as u32)?;
+        writer.write_all(&block)?;
+        Ok(())
+    }
+
+    /// Encodes all of the audio samples into frames.
+    #[instrument(level = "trace", skip_all)]
+    fn write_frames(&mut self, writer: &mut impl Write) -> Result<StreamStats> {
+        let mut stats = StreamStats { min_frame_size: u32::MAX, ..Default::default() };
+        let mut buffer: Vec<i16> = vec![];
+        let mut frame_number = 0;
+        let block_len = BLOCK_SIZE * self.channels;
+        loop {
+            let samples = self.samples.read_samples()?;
+            if let Some(samples) = &samples {
+                if samples.channels != self.channels {
+                    return Err(Error::InconsistentChannels);
+                }
+                if samples.rate != self.sample_rate {
+                    return Err(Error::InconsistentSampleRate);
+                }
+                buffer.extend_from_slice(&samples.data[..samples.len]);
+            }
+            // Encode full blocks, and at the end, whatever is left over
+            let mut offset = 0;
+            while buffer.len() - offset >= block_len || (samples.is_none() && offset < buffer.len())
+            {
+                let end = buffer.len().min(offset + block_len);
+                let frame = self.encode_frame(frame_number, &buffer[offset..end]);
+                writer.write_all(&frame)?;
+                stats.min_frame_size = stats.min_frame_size.min(frame.len() as u32);
+                stats.max_frame_size = stats.max_frame_size.max(frame.len() as u32);
+                stats.num_samples += ((end - offset) / self.channels) as u64;
+                frame_number += 1;
+                offset = end;
+            }
+            buffer.drain(..offset);
+            self.update_progress();
+            if samples.is_none() {
+                break;
+            }
+        }
+        debug!("Wrote {} samples from {:?} to FLAC", stats.num_samples, self.samples.tag());
+        Ok(stats)
+    }
+
+    /// Encodes a frame containing the interleaved `samples`.
+    fn encode_frame(&self, frame_number: u64, samples: &[i16]) -> Vec<u8> {
+        let block_size = samples.len() / self.channels;
+        let channels = (0..self.channels)
+            .map(|c| samples.iter().skip(c).step_by(self.channels).map(|&s| s.into()).collect())
+            .collect::<Vec<Vec<i32>>>();
+
+        // Try every stereo decorrelation mode and pick whichever is smallest
+        let (side, mid): (Vec<i32>, Vec<i32>);
+        let (assignment, subframes) = if self.channels == 2 {
+            let (left, right) = (&channels[0], &channels[1]);
+            side = left.iter().zip(right).map(|(l, r)| l - r).collect();
+            mid = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+            let left = Subframe::plan(left, BITS_PER_SAMPLE);
+            let right = Subframe::plan(right, BITS_PER_SAMPLE);
+            let side = Subframe::plan(&side, BITS_PER_SAMPLE + 1);
+            let mid = Subframe::plan(&mid, BITS_PER_SAMPLE);
+            let independent = left.bits + right.bits;
+            let left_side = left.bits + side.bits;
+            let right_side = side.bits + right.bits;
+            let mid_side = mid.bits + side.bits;
+            let smallest = independent.min(left_side).min(right_side).min(mid_side);
+            if smallest == independent {
+                (1, vec![left, right])
+            } else if smallest == left_side {
+                (CHANNELS_LEFT_SIDE, vec![left, side])
+            } else if smallest == right_side {
+                (CHANNELS_RIGHT_SIDE, vec![side, right])
+            } else {
+                (CHANNELS_MID_SIDE, vec![mid, side])
+            }
+        } else {
+            let subframes = channels.iter().map(|c| Subframe::plan(c, BITS_PER_SAMPLE)).collect();
+            (self.channels as u64 - 1, subframes)
+        };
+
+        let mut out = BitWriter::default();
+        out.write(0x3ffe, 14); // Sync code
+        out.write(0, 1); // Reserved
+        out.write(0, 1); // Fixed block size
+        let (size_code, size_bits) = match block_size {
+            BLOCK_SIZE => (12, 0),
+            1..=256 => (6, 8),
+            _ => (7, 16),
+        };
+        out.write(size_code, 4);
+        out.write(0, 4); // Use the sample rate from STREAMINFO
+        out.write(assignment, 4);
+        out.write(4, 3); // 16 bits per sample
+        out.write(0, 1); // Reserved
+        out.write_utf8(frame_number);
+        out.write(block_size as u64 - 1, size_bits);
+        let crc = crc8(&out.bytes);
+        out.write(crc.into(), 8);
+
+        for subframe in subframes {
+            subframe.write_to(&mut out);
+        }
+        out.align();
+        let crc = crc16(&out.bytes);
+        out.write(crc.into(), 16);
+        out.bytes
+    }
+
+    fn peek_audio_info(&mut self) -> Result<()> {
+        let (channels, rate) = match self.samples.peek_samples()? {
+            Some(s) => (s.channels, s.rate),
+            None => return Err(Error::EmptyStream),
+        };
+        if channels == 0 || channels > MAX_CHANNELS {
+            return Err(Error::InvalidChannelCount(channels as u32));
+        }
+        if rate == 0 || rate > MAX_SAMPLE_RATE {
+            return Err(Error::InvalidSampleRate(rate));
+        }
+        self.channels = channels;
+        self.sample_rate = rate;
+        Ok(())
+    }
+
+    fn update_progress(&mut self) {
+        if let Some(callback) = &mut self.on_progress {
+            callback(self.samples.progress());
+        }
+    }
+}
+
+/// Statistics about an encoded stream which are stored in STREAMINFO.
+#[derive(Default)]
+struct StreamStats {
+    min_frame_size: u32,
+    max_frame_size: u32,
+    num_samples: u64,
+}
+
+/// Writes a metadata block header.
+fn write_block_header(writer: &mut impl Write, kind: u8, last: bool, size: u32) -> Result<()> {
+    writer.write_u8(if last { 0x80 | kind } else { kind })?;
+    writer.write_u24::<BE>(size)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::sample::ReadSampleList;
+    use crate::audio::{Cue, Samples};
+    use crate::test::open_test_wav;
+    use std::io::Cursor;
+
+    fn encode_and_decode(samples: Samples<'static, PcmS16Le>) -> Result<(Vec<i16>, usize)> {
+        let mut cursor = Cursor::new(vec![]);
+        FlacWriter::new(samples.into_reader("test")).write_to(&mut cursor)?;
+        let size = cursor.get_ref().len();
+        cursor.rewind()?;
+        let flac = FlacReader::new(cursor, "test")?;
+        Ok((flac.convert::<PcmS16Le>().read_all_samples()?.data.into_owned(), size))
+    }
+
+    #[test]
+    fn test_write_flac() -> Result<()> {
+        let expected = open_test_wav();
+        let samples = Samples::<PcmS16Le>::from_pcm(expected.clone(), 2, 44100);
+        let (actual, size) = encode_and_decode(samples)?;
+        assert!(actual == expected);
+        assert!(size < expected.len() * 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_flac_mono() -> Result<()> {
+        // Covers constant subframes and a short final block
+        let mut expected = vec![0i16; BLOCK_SIZE + 100];
+        expected.extend((0..5000).map(|i| (i * 37 % 2001 - 1000) as i16));
+        let samples = Samples::<PcmS16Le>::from_pcm(expected.clone(), 1, 22050);
+        let (actual, _) = encode_and_decode(samples)?;
+        assert!(actual == expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_and_read_cues() -> Result<()> {
+        let samples = Samples::<PcmS16Le>::from_pcm((0..8).collect::<Vec<_>>(), 2, 44100);
+        let cues =
+            vec![Cue::new("start", 0), Cue::new_range("range", 1, 2), Cue::new_loop("test", 3)];
+
+        let samples = ReadSampleList::with_cues(vec![samples], cues.clone(), "test");
+        let mut cursor = Cursor::new(vec![]);
+        FlacWriter::new(samples).write_to(&mut cursor)?;
+
+        cursor.rewind()?;
+        let flac = FlacReader::new(cursor, "test")?;
+        let actual = flac.cues().collect::<Vec<_>>();
+        assert_eq!(actual.len(), 3);
+        assert_eq!(&actual[0..2], &cues[0..2]);
+        assert_eq!(actual[2], Cue::new_loop("loop:test", 3));
+        assert_eq!(flac.convert::<PcmS16Le>().read_all_samples()?.data, (0..8).collect::<Vec<_>>());
+        Ok(())
+    }
+}
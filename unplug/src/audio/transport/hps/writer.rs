@@ -143,7 +143,8 @@ impl<'r, 's> PcmHpsWriter<'r, 's> {
     pub fn prepare(self) -> Result<HpsWriter<'s, 's>> {
         let mut builder = EncoderBuilder::new(self.reader)
             .mono_block_size(super::MONO_BLOCK_SIZE)
-            .stereo_block_size(super::STEREO_BLOCK_SIZE);
+            .stereo_block_size(super::STEREO_BLOCK_SIZE)
+            .split_at_loop(true);
         if let Some(on_progress) = self.on_progress {
             builder = builder.on_progress(on_progress);
         }
@@ -540,6 +541,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_hps_from_pcm_loop() -> Result<()> {
+        let data = test::open_test_wav();
+        let data_len = data.len() / 2;
+        let samples = Samples::<PcmS16Le>::from_pcm(data, 2, 44100);
+        let cues = vec![Cue::new_loop("loop", 10000)];
+        let reader = ReadSampleList::with_cues(vec![samples], cues, "test");
+
+        // 10000 is not on a frame boundary, so 10 samples of silence should be added to the
+        // beginning and the first block should end exactly at the loop point
+        let hps = write_and_read_hps(PcmHpsWriter::new(reader).prepare()?)?;
+        assert_eq!(hps.loop_start(), Some(1));
+        assert_eq!(hps.cues().collect::<Vec<_>>(), &[Cue::new_loop("loop", 10010)]);
+
+        const EXPECTED_END_ADDRESSES: &[u32] = &[0x2caf, 0xffff, 0xffff, 0xde54];
+        let blocks = hps.blocks().collect::<Vec<_>>();
+        let end_addresses = blocks.iter().map(|b| b.end_address).collect::<Vec<_>>();
+        assert_eq!(end_addresses, EXPECTED_END_ADDRESSES);
+
+        let decoded = hps.decoder().read_all_samples()?;
+        assert_eq!(decoded.channels, 2);
+        assert_eq!(decoded.len, (data_len + 10) * 2);
+        assert!(decoded.data[..20].iter().all(|&s| s == 0));
+        Ok(())
+    }
+
     #[test]
     fn test_assign_cues() -> Result<()> {
         let mut blocks = vec![];
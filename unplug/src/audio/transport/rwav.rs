@@ -1,29 +1,43 @@
 // rwav is a Wii-specific format, but supporting it allows us to read sounds from the New Play
 // Control release.
 
+use super::ssm::BankSample;
+use crate::audio::cue::{Cue, LOOP_PREFIX};
 use crate::audio::format::adpcm::{self, GcAdpcm};
-use crate::audio::format::{AnyFormat, Format, PcmS16Le};
-use crate::audio::{
-    Cue, Error, ProgressHint, ReadSamples, Result, Samples, SourceChannel, SourceTag,
-};
-use crate::common::{ReadFrom, ReadSeek, Region};
+use crate::audio::format::{AnyFormat, Format, PcmS16Be, PcmS16Le, ReadWriteBytes};
+use crate::audio::{Error, ProgressHint, ReadSamples, Result, Samples, SourceChannel, SourceTag};
+use crate::common::io::fill;
+use crate::common::{align, ReadFrom, ReadSeek, Region, WriteTo};
 use arrayvec::ArrayVec;
-use byteorder::{ReadBytesExt, BE};
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::fmt::{self, Debug};
-use std::io::{self, Read, Seek, SeekFrom};
-use std::iter;
-use tracing::error;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use tracing::{error, instrument};
 
 const RWAV_MAGIC: u32 = 0x52574156; // 'RWAV'
 const RWAV_VERSION: u16 = 0x0102;
 
+const INFO_MAGIC: u32 = 0x494e464f; // 'INFO'
+const DATA_MAGIC: u32 = 0x44415441; // 'DATA'
+
 const BIG_ENDIAN: u16 = 0xfeff;
 
 const SECTION_HEADER_SIZE: u64 = 0x8;
 
+/// The size of the file header.
+const HEADER_SIZE: u32 = 0x20;
+/// The size of the `InfoHeader` struct.
+const INFO_HEADER_SIZE: u32 = 0x1c;
+/// The size of the `ChannelHeader` struct.
+const CHANNEL_HEADER_SIZE: u32 = 0x1c;
+/// The size of the `AdpcmCodecInfo` struct.
+const CODEC_INFO_SIZE: u32 = 0x30;
+/// The alignment of sections and channel data.
+const DATA_ALIGN: u32 = 0x20;
+
 /// Convenience type for an opaque decoder.
 type RwavDecoder<'r, 's> = Box<dyn ReadSamples<'s, Format = PcmS16Le> + 'r>;
 
@@ -91,6 +105,23 @@ impl<R: Read + ?Sized> ReadFrom<R> for FileHeader {
     }
 }
 
+impl<W: Write + ?Sized> WriteTo<W> for FileHeader {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        writer.write_u32::<BE>(self.magic)?;
+        writer.write_u16::<BE>(self.endian)?;
+        writer.write_u16::<BE>(self.version)?;
+        writer.write_u32::<BE>(self.file_size)?;
+        writer.write_u16::<BE>(self.header_size)?;
+        writer.write_u16::<BE>(self.num_sections)?;
+        writer.write_u32::<BE>(self.info_offset)?;
+        writer.write_u32::<BE>(self.info_size)?;
+        writer.write_u32::<BE>(self.data_offset)?;
+        writer.write_u32::<BE>(self.data_size)?;
+        Ok(())
+    }
+}
+
 /// The header at the beginning of each RWAV section.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 struct SectionHeader {
@@ -107,6 +138,15 @@ impl<R: Read + ?Sized> ReadFrom<R> for SectionHeader {
     }
 }
 
+impl<W: Write + ?Sized> WriteTo<W> for SectionHeader {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        writer.write_u32::<BE>(self.magic)?;
+        writer.write_u32::<BE>(self.size)?;
+        Ok(())
+    }
+}
+
 /// RWAV audio codecs.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive, IntoPrimitive)]
 #[repr(u8)]
@@ -126,6 +166,18 @@ impl From<Codec> for Format {
     }
 }
 
+impl TryFrom<Format> for Codec {
+    type Error = Error;
+    fn try_from(format: Format) -> Result<Self> {
+        match format {
+            Format::PcmS8 => Ok(Codec::Pcm8),
+            Format::PcmS16Be => Ok(Codec::Pcm16),
+            Format::GcAdpcm => Ok(Codec::GcAdpcm),
+            other => Err(Error::UnsupportedFormat(other)),
+        }
+    }
+}
+
 /// The header at the beginning of the INFO section data.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 struct InfoHeader {
@@ -137,8 +189,8 @@ struct InfoHeader {
     num_channels: u8,
     /// The sample rate.
     sample_rate: u16,
-    /// The address that decoding should start at.
-    start_address: u32,
+    /// The address that playback loops back to if looping is enabled.
+    loop_address: u32,
     /// The address that decoding should end at.
     end_address: u32,
     /// The offset of the channel list within the INFO section.
@@ -173,7 +225,7 @@ impl<R: Read + ?Sized> ReadFrom<R> for InfoHeader {
             looping,
             num_channels,
             sample_rate,
-            start_address: reader.read_u32::<BE>()?,
+            loop_address: reader.read_u32::<BE>()?,
             end_address: reader.read_u32::<BE>()?,
             channels_offset: reader.read_u32::<BE>()?,
             data_offset: reader.read_u32::<BE>()?,
@@ -182,6 +234,24 @@ impl<R: Read + ?Sized> ReadFrom<R> for InfoHeader {
     }
 }
 
+impl<W: Write + ?Sized> WriteTo<W> for InfoHeader {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        writer.write_u8(self.codec.into())?;
+        writer.write_u8(self.looping.into())?;
+        writer.write_u8(self.num_channels)?;
+        writer.write_u8(0)?;
+        writer.write_u16::<BE>(self.sample_rate)?;
+        writer.write_u16::<BE>(0)?;
+        writer.write_u32::<BE>(self.loop_address)?;
+        writer.write_u32::<BE>(self.end_address)?;
+        writer.write_u32::<BE>(self.channels_offset)?;
+        writer.write_u32::<BE>(self.data_offset)?;
+        writer.write_u32::<BE>(self.unk_18)?;
+        Ok(())
+    }
+}
+
 /// The header for channel data. `channels_offset` in `InfoHeader` points to a list of offsets to
 /// these structures.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -212,6 +282,20 @@ impl<R: Read + ?Sized> ReadFrom<R> for ChannelHeader {
     }
 }
 
+impl<W: Write + ?Sized> WriteTo<W> for ChannelHeader {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        writer.write_u32::<BE>(self.data_offset)?;
+        writer.write_u32::<BE>(self.codec_info_offset)?;
+        writer.write_u32::<BE>(self.unk_08)?;
+        writer.write_u32::<BE>(self.unk_0c)?;
+        writer.write_u32::<BE>(self.unk_10)?;
+        writer.write_u32::<BE>(self.unk_14)?;
+        writer.write_u32::<BE>(self.unk_18)?;
+        Ok(())
+    }
+}
+
 /// Codec info for ADPCM sounds.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 struct AdpcmCodecInfo {
@@ -231,6 +315,16 @@ impl<R: Read + ?Sized> ReadFrom<R> for AdpcmCodecInfo {
     }
 }
 
+impl<W: Write + ?Sized> WriteTo<W> for AdpcmCodecInfo {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        self.adpcm.write_to(writer)?;
+        self.loop_context.write_to(writer)?;
+        writer.write_u16::<BE>(0)?;
+        Ok(())
+    }
+}
+
 /// The INFO section data in an RWAV.
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct InfoSection {
@@ -296,16 +390,13 @@ impl Debug for Channel {
 
 #[derive(Clone)]
 struct DataSection {
-    start_address: u32,
-    end_address: u32,
     channels: ArrayVec<Channel, 2>,
 }
 
 impl DataSection {
     fn read_from(reader: &mut (impl Read + Seek + ?Sized), info: &InfoSection) -> Result<Self> {
         let format = Format::from(info.header.codec);
-        let mut start_address = info.header.start_address;
-        let mut end_address = info.header.end_address;
+        let end_address = info.header.end_address;
 
         // Wrap the reader in a region which locks it to the DATA section. All seeks will now be
         // relative to the start of the DATA section.
@@ -317,16 +408,10 @@ impl DataSection {
         // Read the data for each channel
         let mut channels = ArrayVec::new();
         for (i, channel) in info.channels.iter().enumerate() {
-            // Correct the data offset and addresses in the INFO section so that we don't include
-            // data that never gets decoded. This is not strictly necessary, but it is more
-            // technically correct and ensures we don't read data we don't need.
-            let start_aligned = format.frame_address(start_address as usize);
-            start_address -= start_aligned as u32;
-            end_address -= start_aligned as u32;
-            let start_offset = channel.data_offset as usize + format.address_to_byte(start_aligned);
+            // Decoding always starts at the beginning of the data. The address in the INFO header
+            // is only where the sound loops back to.
             let size = format.address_to_byte_up((end_address + 1) as usize);
-
-            section.seek(SeekFrom::Start(start_offset as u64))?;
+            section.seek(SeekFrom::Start(channel.data_offset.into()))?;
             let mut data = vec![];
             section.by_ref().take(size as u64).read_to_end(&mut data)?;
             if data.len() != size {
@@ -342,7 +427,7 @@ impl DataSection {
             channels.push(Channel { adpcm, loop_context, data });
         }
 
-        Ok(Self { start_address, end_address, channels })
+        Ok(Self { channels })
     }
 }
 
@@ -356,8 +441,8 @@ pub struct Rwav {
     pub looping: bool,
     /// The audio sample rate.
     pub sample_rate: u32,
-    /// The start address of the sample data.
-    pub start_address: u32,
+    /// The address that playback loops back to if looping is enabled.
+    pub loop_address: u32,
     /// The end address of the sample data.
     pub end_address: u32,
     /// The data for each channel in the sound.
@@ -384,13 +469,101 @@ impl Rwav {
             format: info.header.codec.into(),
             looping: info.header.looping,
             sample_rate: info.header.sample_rate as u32,
-            start_address: data.start_address,
-            end_address: data.end_address,
+            loop_address: info.header.loop_address,
+            end_address: info.header.end_address,
             channels: data.channels,
             tag,
         })
     }
 
+    /// Creates a new `Rwav` by encoding mono/stereo PCMS16LE sample data with `codec`. The first
+    /// loop cue in the stream (if any) is used as the loop point.
+    #[instrument(level = "trace", skip(reader))]
+    pub fn from_pcm(
+        reader: &mut dyn ReadSamples<'_, Format = PcmS16Le>,
+        codec: Codec,
+    ) -> Result<Self> {
+        let tag = reader.tag().clone();
+        match codec {
+            Codec::GcAdpcm => Self::from_pcm_adpcm(reader, tag),
+            Codec::Pcm16 => Self::from_pcm_pcm16(reader, tag),
+            Codec::Pcm8 => Err(Error::UnsupportedFormat(Format::PcmS8)),
+        }
+    }
+
+    fn from_pcm_adpcm(
+        reader: &mut dyn ReadSamples<'_, Format = PcmS16Le>,
+        tag: SourceTag,
+    ) -> Result<Self> {
+        // Sample banks use the same ADPCM channel layout, so we can just steal their encoder
+        let sample = BankSample::from_pcm(reader)?;
+        let address = sample.channels[0].address;
+        let channels = sample
+            .channels
+            .into_iter()
+            .map(|c| Channel { adpcm: c.adpcm, loop_context: c.loop_context, data: c.data })
+            .collect();
+        Ok(Self {
+            format: Format::GcAdpcm,
+            looping: address.looping,
+            sample_rate: sample.rate,
+            loop_address: address.loop_address,
+            end_address: address.end_address,
+            channels,
+            tag,
+        })
+    }
+
+    fn from_pcm_pcm16(
+        reader: &mut dyn ReadSamples<'_, Format = PcmS16Le>,
+        tag: SourceTag,
+    ) -> Result<Self> {
+        let samples = reader.read_all_samples()?;
+        if !(1..=2).contains(&samples.channels) {
+            return Err(Error::UnsupportedChannels);
+        }
+        let num_frames = samples.len / samples.channels;
+        if num_frames == 0 {
+            return Err(Error::EmptyStream);
+        }
+        // PCM addresses are just sample numbers
+        let loop_start = reader.cues().find(|c| c.is_loop()).map(|c| c.start);
+        let mut channels = ArrayVec::new();
+        for i in 0..samples.channels {
+            let channel_samples: Vec<i16> =
+                samples.data.iter().skip(i).step_by(samples.channels).copied().collect();
+            let mut data = vec![];
+            PcmS16Be::write_bytes(&mut data, &channel_samples)?;
+            channels.push(Channel {
+                adpcm: Default::default(),
+                loop_context: Default::default(),
+                data,
+            });
+        }
+        Ok(Self {
+            format: Format::PcmS16Be,
+            looping: loop_start.is_some(),
+            sample_rate: samples.rate,
+            loop_address: loop_start.unwrap_or(0) as u32,
+            end_address: (num_frames - 1) as u32,
+            channels,
+            tag,
+        })
+    }
+
+    /// Returns the sample number that playback loops back to, if looping is enabled.
+    pub fn loop_start(&self) -> Option<u64> {
+        if !self.looping {
+            return None;
+        }
+        let address = self.loop_address as usize;
+        let sample = match self.format {
+            Format::GcAdpcm => GcAdpcm::address_to_sample(address),
+            _ => address,
+        };
+        Some(sample as u64)
+    }
+
     /// Creates a `ChannelReader` over a channel in the stream.
     /// ***Panics*** if the channel index is out-of-bounds.
     pub fn reader(&self, channel: usize) -> ChannelReader<'_> {
@@ -400,12 +573,14 @@ impl Rwav {
             (2, 1) => self.tag.clone().for_channel(SourceChannel::Right),
             _ => self.tag.clone(),
         };
+        let loop_cue = self.loop_start().map(|start| Cue::new_loop(LOOP_PREFIX, start));
         ChannelReader {
             channel: Some(&self.channels[channel]),
             format: self.format,
             sample_rate: self.sample_rate,
             end_address: self.end_address,
             tag,
+            loop_cue,
         }
     }
 
@@ -413,7 +588,10 @@ impl Rwav {
     /// ***Panics*** if the channel index is out-of-bounds.
     pub fn channel_decoder(&self, channel: usize) -> RwavDecoder<'_, '_> {
         let reader = self.reader(channel);
-        Box::new(adpcm::Decoder::new(reader.cast()))
+        match self.format {
+            Format::GcAdpcm => Box::new(adpcm::Decoder::new(reader.cast())),
+            _ => reader.convert(),
+        }
     }
 
     /// Creates a decoder which decodes all channels into PCM16 format and joins them.
@@ -435,6 +613,7 @@ pub struct ChannelReader<'a> {
     sample_rate: u32,
     end_address: u32,
     tag: SourceTag,
+    loop_cue: Option<Cue>,
 }
 
 impl<'a> ReadSamples<'a> for ChannelReader<'a> {
@@ -457,6 +636,11 @@ impl<'a> ReadSamples<'a> for ChannelReader<'a> {
                 }
                 .cast(),
             )),
+            Format::PcmS16Be => {
+                let len = self.end_address as usize + 1;
+                let samples = PcmS16Be::read_bytes(&channel.data[..(len * 2)])?;
+                Ok(Some(Samples::<PcmS16Be>::from_pcm(samples, 1, self.sample_rate).cast()))
+            }
             other => {
                 // TODO
                 error!("Sample format not supported yet: {:?}", other);
@@ -488,136 +672,223 @@ impl<'a> ReadSamples<'a> for ChannelReader<'a> {
     }
 
     fn cues(&self) -> Box<dyn Iterator<Item = Cue> + '_> {
-        Box::from(iter::empty())
+        Box::from(self.loop_cue.iter().cloned())
+    }
+}
+
+impl<W: Write + ?Sized> WriteTo<W> for Rwav {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        let codec = Codec::try_from(self.format)?;
+        let num_channels = self.channels.len() as u32;
+        if !(1..=2).contains(&num_channels) {
+            return Err(Error::UnsupportedChannels);
+        }
+        let sample_rate = u16::try_from(self.sample_rate)
+            .map_err(|_| Error::InvalidSampleRate(self.sample_rate))?;
+
+        // The INFO section is laid out as: header, channel offsets, channel headers, codec info.
+        // Offsets within it are relative to the end of the section header.
+        let channels_offset = INFO_HEADER_SIZE;
+        let first_channel_offset = channels_offset + 4 * num_channels;
+        let first_codec_offset = first_channel_offset + CHANNEL_HEADER_SIZE * num_channels;
+        let mut info_end = first_codec_offset;
+        if codec == Codec::GcAdpcm {
+            info_end += CODEC_INFO_SIZE * num_channels;
+        }
+        let info_size = align(SECTION_HEADER_SIZE as u32 + info_end, DATA_ALIGN);
+        let data_size = SECTION_HEADER_SIZE as u32
+            + self.channels.iter().map(|c| align(c.data.len() as u32, DATA_ALIGN)).sum::<u32>();
+
+        let header = FileHeader {
+            magic: RWAV_MAGIC,
+            endian: BIG_ENDIAN,
+            version: RWAV_VERSION,
+            file_size: HEADER_SIZE + info_size + data_size,
+            header_size: HEADER_SIZE as u16,
+            num_sections: 2,
+            info_offset: HEADER_SIZE,
+            info_size,
+            data_offset: HEADER_SIZE + info_size,
+            data_size,
+        };
+        header.write_to(writer)?;
+
+        SectionHeader { magic: INFO_MAGIC, size: info_size }.write_to(writer)?;
+        let info = InfoHeader {
+            codec,
+            looping: self.looping,
+            num_channels: num_channels as u8,
+            sample_rate,
+            loop_address: self.loop_address,
+            end_address: self.end_address,
+            channels_offset,
+            data_offset: info_size - SECTION_HEADER_SIZE as u32,
+            unk_18: 0,
+        };
+        info.write_to(writer)?;
+        for i in 0..num_channels {
+            writer.write_u32::<BE>(first_channel_offset + CHANNEL_HEADER_SIZE * i)?;
+        }
+        let mut data_offset = 0;
+        for (i, channel) in self.channels.iter().enumerate() {
+            let codec_info_offset = match codec {
+                Codec::GcAdpcm => first_codec_offset + CODEC_INFO_SIZE * i as u32,
+                _ => 0,
+            };
+            // These are always 0x01000000 in the game's files
+            ChannelHeader {
+                data_offset,
+                codec_info_offset,
+                unk_08: 0x01000000,
+                unk_0c: 0x01000000,
+                unk_10: 0x01000000,
+                unk_14: 0x01000000,
+                unk_18: 0,
+            }
+            .write_to(writer)?;
+            data_offset += align(channel.data.len() as u32, DATA_ALIGN);
+        }
+        if codec == Codec::GcAdpcm {
+            for channel in &self.channels {
+                let codec_info =
+                    AdpcmCodecInfo { adpcm: channel.adpcm, loop_context: channel.loop_context };
+                codec_info.write_to(writer)?;
+            }
+        }
+        fill(&mut *writer, 0, u64::from(info_size) - SECTION_HEADER_SIZE - u64::from(info_end))?;
+
+        SectionHeader { magic: DATA_MAGIC, size: data_size }.write_to(writer)?;
+        for channel in &self.channels {
+            writer.write_all(&channel.data)?;
+            let padding = align(channel.data.len() as u32, DATA_ALIGN) - channel.data.len() as u32;
+            fill(&mut *writer, 0, padding.into())?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::Cursor;
-
-    #[rustfmt::skip]
-    const RWAV_BYTES: &[u8] = &[
-        0x52, 0x57, 0x41, 0x56, // magic
-        0xfe, 0xff, // endian
-        0x01, 0x02, // version
-        0x00, 0x00, 0x01, 0x28, // file_size
-        0x00, 0x20, // header_size
-        0x00, 0x02, // num_sections
-        0x00, 0x00, 0x00, 0x20, // info_offset
-        0x00, 0x00, 0x00, 0xe0, // info_size
-        0x00, 0x00, 0x01, 0x00, // data_offset
-        0x00, 0x00, 0x00, 0x28, // data_size
-
-        // info section
-        0x49, 0x4e, 0x46, 0x4f, // magic
-        0x00, 0x00, 0x00, 0xe0, // size
-        0x02, // codec
-        0x00, // looping
-        0x02, // num_channels
-        0x00,
-        0xac, 0x44, // sample_rate
-        0x00, 0x00,
-        0x00, 0x00, 0x00, 0x02, // start_address
-        0x00, 0x00, 0x00, 0x1f, // end_address
-        0x00, 0x00, 0x00, 0x1c, // channels_offset
-        0x00, 0x00, 0x00, 0xd8, // data_offset
-        0x00, 0x00, 0x00, 0x00, // unk_18
-
-        // channels
-        0x00, 0x00, 0x00, 0x24, // channels[0] offset
-        0x00, 0x00, 0x00, 0x40, // channels[1] offset
-
-        // channels[0]
-        0x00, 0x00, 0x00, 0x00, // data_offset
-        0x00, 0x00, 0x00, 0x5c, // codec_info_offset
-        0x01, 0x00, 0x00, 0x00, // unk_08
-        0x01, 0x00, 0x00, 0x00, // unk_0c
-        0x01, 0x00, 0x00, 0x00, // unk_10
-        0x01, 0x00, 0x00, 0x00, // unk_14
-        0x00, 0x00, 0x00, 0x00, // unk_18
-
-        // channels[1]
-        0x00, 0x00, 0x00, 0x10, // data_offset
-        0x00, 0x00, 0x00, 0x8c, // codec_info_offset
-        0x01, 0x00, 0x00, 0x00, // unk_08
-        0x01, 0x00, 0x00, 0x00, // unk_0c
-        0x01, 0x00, 0x00, 0x00, // unk_10
-        0x01, 0x00, 0x00, 0x00, // unk_14
-        0x00, 0x00, 0x00, 0x00, // unk_18
-
-        // channels[0].codec_info
-        0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, // coefficients[0..4]
-        0x00, 0x04, 0x00, 0x05, 0x00, 0x06, 0x00, 0x07, // coefficient[4..8]
-        0x00, 0x08, 0x00, 0x09, 0x00, 0x0a, 0x00, 0x0b, // coefficient[8..12]
-        0x00, 0x0c, 0x00, 0x0d, 0x00, 0x0e, 0x00, 0x0f, // coefficient[12..16]
-        0x00, 0x00, // gain
-        0x00, 0x17, 0x00, 0x00, 0x00, 0x00, // context
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // loop_context
-        0x00, 0x00,
-
-        // channels[1].codec_info
-        0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, // coefficients[0..4]
-        0x00, 0x04, 0x00, 0x05, 0x00, 0x06, 0x00, 0x07, // coefficients[4..8]
-        0x00, 0x08, 0x00, 0x09, 0x00, 0x0a, 0x00, 0x0b, // coefficients[8..12]
-        0x00, 0x0c, 0x00, 0x0d, 0x00, 0x0e, 0x00, 0x0f, // coefficients[12..16]
-        0x00, 0x00, // gain
-        0x00, 0x17, 0x00, 0x00, 0x00, 0x00, // context
-        0x00, 0x17, 0x00, 0x00, 0x00, 0x00, // loop_context
-        0x00, 0x00,
-
-        // padding
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-        0x00, 0x00, 0x00, 0x00,
-
-        // data section
-        0x44, 0x41, 0x54, 0x41, // magic
-        0x00, 0x00, 0x00, 0x28, // size
-
-        // channels[0].data
-        0x17, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
-        0x17, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
-
-        // channels[1].data
-        0x17, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
-        0x17, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
-    ];
+    use crate::test::TEST_RWAV;
+    use std::io::{Cursor, Seek};
 
     #[test]
     fn test_read_rwav() -> Result<()> {
-        let rwav = Rwav::open(&mut Cursor::new(RWAV_BYTES), "RWAV_BYTES")?;
+        let rwav = Rwav::open(&mut Cursor::new(TEST_RWAV), "TEST_RWAV")?;
         assert_eq!(rwav.format, Format::GcAdpcm);
         assert!(!rwav.looping);
         assert_eq!(rwav.sample_rate, 44100);
-        assert_eq!(rwav.start_address, 0x2);
+        assert_eq!(rwav.loop_address, 0x2);
         assert_eq!(rwav.end_address, 0x1f);
         assert_eq!(rwav.channels.len(), 2);
 
         let channel0 = &rwav.channels[0];
         let expected_coefficients = (0..16).collect::<Vec<i16>>();
         assert_eq!(channel0.adpcm.coefficients, expected_coefficients.as_slice());
-        assert_eq!(channel0.data, &RWAV_BYTES[0x108..0x118]);
+        assert_eq!(channel0.data, &TEST_RWAV[0x108..0x118]);
 
         let channel1 = &rwav.channels[1];
         assert_eq!(channel1.adpcm.coefficients, expected_coefficients.as_slice());
-        assert_eq!(channel1.data, &RWAV_BYTES[0x118..0x128]);
+        assert_eq!(channel1.data, &TEST_RWAV[0x118..0x128]);
 
         let samples0 = rwav.reader(0).read_samples()?.unwrap();
         let samples0: Samples<'_, GcAdpcm> = samples0.cast();
         assert_eq!(samples0.format(), Format::GcAdpcm);
         assert_eq!(samples0.len, 32);
         assert_eq!(samples0.channels, 1);
-        assert_eq!(samples0.data, &RWAV_BYTES[0x108..0x118]);
+        assert_eq!(samples0.data, &TEST_RWAV[0x108..0x118]);
 
         let samples1 = rwav.reader(1).read_samples()?.unwrap();
         let samples1: Samples<'_, GcAdpcm> = samples1.cast();
         assert_eq!(samples1.format(), Format::GcAdpcm);
         assert_eq!(samples1.len, 32);
         assert_eq!(samples1.channels, 1);
-        assert_eq!(samples1.data, &RWAV_BYTES[0x118..0x128]);
+        assert_eq!(samples1.data, &TEST_RWAV[0x118..0x128]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_and_read_rwav() -> Result<()> {
+        let rwav = Rwav::open(&mut Cursor::new(TEST_RWAV), "TEST_RWAV")?;
+        let mut writer = Cursor::new(vec![]);
+        rwav.write_to(&mut writer)?;
+        let bytes = writer.into_inner();
+        assert_eq!(bytes.len(), 0x148);
+        // We align channel data to 0x20 bytes, so only the second channel's data offset differs
+        assert_eq!(bytes[0x20..0x68], TEST_RWAV[0x20..0x68]);
+        assert_eq!(bytes[0x68..0x6c], [0x00, 0x00, 0x00, 0x20]);
+        assert_eq!(bytes[0x6c..0x100], TEST_RWAV[0x6c..0x100]);
+
+        let rwav2 = Rwav::open(&mut Cursor::new(bytes), "TEST_RWAV")?;
+        assert_eq!(rwav2.format, rwav.format);
+        assert_eq!(rwav2.looping, rwav.looping);
+        assert_eq!(rwav2.sample_rate, rwav.sample_rate);
+        assert_eq!(rwav2.loop_address, rwav.loop_address);
+        assert_eq!(rwav2.end_address, rwav.end_address);
+        assert_eq!(rwav2.channels.len(), 2);
+        for (channel2, channel) in rwav2.channels.iter().zip(&rwav.channels) {
+            assert_eq!(channel2.adpcm, channel.adpcm);
+            assert_eq!(channel2.loop_context, channel.loop_context);
+            assert_eq!(channel2.data, channel.data);
+        }
+        Ok(())
+    }
+
+    /// Makes `len` frames of a stereo sawtooth wave.
+    fn make_samples(len: usize) -> Vec<i16> {
+        (0..(len * 2)).map(|i| ((i / 2) * 256 + (i % 2) * 1000) as i16).collect()
+    }
+
+    #[test]
+    fn test_rwav_from_pcm16() -> Result<()> {
+        let data = make_samples(100);
+        let samples = Samples::<PcmS16Le>::from_pcm(data.clone(), 2, 32000);
+        let mut reader = samples.into_reader("test").with_cues(vec![Cue::new_loop("loop", 20)]);
+        let rwav = Rwav::from_pcm(&mut reader, Codec::Pcm16)?;
+        assert_eq!(rwav.format, Format::PcmS16Be);
+        assert!(rwav.looping);
+        assert_eq!(rwav.loop_address, 20);
+        assert_eq!(rwav.end_address, 99);
+
+        let mut writer = Cursor::new(vec![]);
+        rwav.write_to(&mut writer)?;
+        writer.rewind()?;
+        let rwav = Rwav::open(&mut writer, "test")?;
+        assert_eq!(rwav.format, Format::PcmS16Be);
+        assert_eq!(rwav.sample_rate, 32000);
+        assert_eq!(rwav.loop_start(), Some(20));
+
+        let mut decoder = rwav.decoder();
+        let decoded = decoder.read_all_samples()?;
+        assert_eq!(decoded.channels, 2);
+        assert_eq!(decoded.data, data);
+        assert_eq!(decoder.cues().collect::<Vec<_>>(), &[Cue::new_loop(LOOP_PREFIX, 20)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rwav_from_pcm_adpcm() -> Result<()> {
+        let data = make_samples(100);
+        let samples = Samples::<PcmS16Le>::from_pcm(data, 2, 44100);
+        let mut reader = samples.into_reader("test").with_cues(vec![Cue::new_loop("loop", 42)]);
+        let rwav = Rwav::from_pcm(&mut reader, Codec::GcAdpcm)?;
+
+        let mut writer = Cursor::new(vec![]);
+        rwav.write_to(&mut writer)?;
+        writer.rewind()?;
+        let rwav = Rwav::open(&mut writer, "test")?;
+        assert_eq!(rwav.format, Format::GcAdpcm);
+        assert_eq!(rwav.sample_rate, 44100);
+        assert_eq!(rwav.channels.len(), 2);
+        assert_eq!(rwav.loop_start(), Some(42));
+
+        let mut decoder = rwav.decoder();
+        let decoded = decoder.read_all_samples()?;
+        assert_eq!(decoded.channels, 2);
+        assert_eq!(decoded.len, 200);
+        assert_eq!(decoder.cues().collect::<Vec<_>>(), &[Cue::new_loop(LOOP_PREFIX, 42)]);
         Ok(())
     }
 }
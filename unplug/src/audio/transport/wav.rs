@@ -24,6 +24,7 @@ const ID_LIST: u32 = fourcc(b"LIST");
 const ID_LTXT: u32 = fourcc(b"ltxt");
 const ID_RGN: u32 = fourcc(b"rgn ");
 const ID_RIFF: u32 = fourcc(b"RIFF");
+const ID_SMPL: u32 = fourcc(b"smpl");
 const ID_WAVE: u32 = fourcc(b"WAVE");
 
 const RIFF_ALIGN: u64 = 2;
@@ -31,6 +32,11 @@ const CHUNK_HEADER_SIZE: u64 = 8;
 
 const WAVE_FORMAT_PCM: u16 = 0x1;
 
+/// `smpl` loop type for a normal forward loop.
+const LOOP_FORWARD: u32 = 0;
+/// MIDI note number for middle C.
+const MIDI_MIDDLE_C: u32 = 60;
+
 /// A RIFF chunk header.
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 struct ChunkHeader {
@@ -237,6 +243,103 @@ impl<W: Write + ?Sized> WriteTo<W> for LabelTextChunk {
     }
 }
 
+/// WAVE `smpl` chunk data.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct SamplerChunk {
+    manufacturer: u32,
+    product: u32,
+    /// The duration of a sample in nanoseconds.
+    sample_period: u32,
+    midi_unity_note: u32,
+    midi_pitch_fraction: u32,
+    smpte_format: u32,
+    smpte_offset: u32,
+    loops: Vec<SampleLoop>,
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for SamplerChunk {
+    type Error = io::Error;
+    fn read_from(reader: &mut R) -> io::Result<Self> {
+        let mut chunk = Self {
+            manufacturer: reader.read_u32::<LE>()?,
+            product: reader.read_u32::<LE>()?,
+            sample_period: reader.read_u32::<LE>()?,
+            midi_unity_note: reader.read_u32::<LE>()?,
+            midi_pitch_fraction: reader.read_u32::<LE>()?,
+            smpte_format: reader.read_u32::<LE>()?,
+            smpte_offset: reader.read_u32::<LE>()?,
+            loops: vec![],
+        };
+        let count = reader.read_u32::<LE>()?;
+        // Vendor-specific data follows the loops and can be ignored
+        let _sampler_data = reader.read_u32::<LE>()?;
+        chunk.loops.reserve(count as usize);
+        for _ in 0..count {
+            chunk.loops.push(SampleLoop::read_from(reader)?);
+        }
+        Ok(chunk)
+    }
+}
+
+impl<W: Write + ?Sized> WriteTo<W> for SamplerChunk {
+    type Error = io::Error;
+    fn write_to(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u32::<LE>(self.manufacturer)?;
+        writer.write_u32::<LE>(self.product)?;
+        writer.write_u32::<LE>(self.sample_period)?;
+        writer.write_u32::<LE>(self.midi_unity_note)?;
+        writer.write_u32::<LE>(self.midi_pitch_fraction)?;
+        writer.write_u32::<LE>(self.smpte_format)?;
+        writer.write_u32::<LE>(self.smpte_offset)?;
+        writer.write_u32::<LE>(self.loops.len() as u32)?;
+        writer.write_u32::<LE>(0)?;
+        SampleLoop::write_all_to(writer, &self.loops)?;
+        Ok(())
+    }
+}
+
+/// WAVE sample loop.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+struct SampleLoop {
+    /// The ID of the cue point associated with the loop.
+    cue_point_id: u32,
+    loop_type: u32,
+    /// The index of the first sample in the loop.
+    start: u32,
+    /// The index of the last sample in the loop (inclusive).
+    end: u32,
+    fraction: u32,
+    /// The number of times to play the loop, or 0 for infinite.
+    play_count: u32,
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for SampleLoop {
+    type Error = io::Error;
+    fn read_from(reader: &mut R) -> io::Result<Self> {
+        Ok(Self {
+            cue_point_id: reader.read_u32::<LE>()?,
+            loop_type: reader.read_u32::<LE>()?,
+            start: reader.read_u32::<LE>()?,
+            end: reader.read_u32::<LE>()?,
+            fraction: reader.read_u32::<LE>()?,
+            play_count: reader.read_u32::<LE>()?,
+        })
+    }
+}
+
+impl<W: Write + ?Sized> WriteTo<W> for SampleLoop {
+    type Error = io::Error;
+    fn write_to(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_u32::<LE>(self.cue_point_id)?;
+        writer.write_u32::<LE>(self.loop_type)?;
+        writer.write_u32::<LE>(self.start)?;
+        writer.write_u32::<LE>(self.end)?;
+        writer.write_u32::<LE>(self.fraction)?;
+        writer.write_u32::<LE>(self.play_count)?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -301,4 +404,35 @@ mod tests {
             text: CString::new("fumo").unwrap(),
         });
     }
+
+    #[test]
+    fn test_write_and_read_smpl_chunk() {
+        assert_write_and_read!(SamplerChunk {
+            manufacturer: 1,
+            product: 2,
+            sample_period: 3,
+            midi_unity_note: 4,
+            midi_pitch_fraction: 5,
+            smpte_format: 6,
+            smpte_offset: 7,
+            loops: vec![
+                SampleLoop {
+                    cue_point_id: 8,
+                    loop_type: 9,
+                    start: 10,
+                    end: 11,
+                    fraction: 12,
+                    play_count: 13,
+                },
+                SampleLoop {
+                    cue_point_id: 14,
+                    loop_type: 15,
+                    start: 16,
+                    end: 17,
+                    fraction: 18,
+                    play_count: 19,
+                },
+            ]
+        });
+    }
 }
@@ -1,12 +1,12 @@
 use super::*;
-use crate::audio::cue::{self, Cue, CueKind};
+use crate::audio::cue::{self, Cue, CueKind, LOOP_PREFIX};
 use crate::audio::format::{PcmS16Le, ReadWriteBytes, StaticFormat};
 use crate::audio::{Error, Format, ProgressHint, ReadSamples, Result, Samples, SourceTag};
 use crate::common::{align, ReadFrom, ReadSeek, Region};
 use std::collections::HashMap;
 use std::io::{self, Read, Seek, SeekFrom};
 use std::num::NonZeroU64;
-use tracing::{error, instrument, trace, warn};
+use tracing::{debug, error, instrument, trace, warn};
 
 /// RIFF data reader which can recursively read chunks.
 struct RiffReader<'a> {
@@ -112,6 +112,8 @@ pub struct WavReader<'a> {
     channels: usize,
     /// The audio's sample rate.
     sample_rate: u32,
+    /// The index of the sample frame after the end of the loop in the smpl chunk, if there is one.
+    loop_end: Option<u32>,
 }
 
 impl<'a> WavReader<'a> {
@@ -136,6 +138,7 @@ impl<'a> WavReader<'a> {
             cues: vec![],
             channels: 0,
             sample_rate: 0,
+            loop_end: None,
         };
         wav.read_chunks()?;
         wav.read_format()?;
@@ -143,6 +146,32 @@ impl<'a> WavReader<'a> {
         Ok(wav)
     }
 
+    /// Returns the index of the sample frame after the end of the loop in the smpl chunk, if there
+    /// is one.
+    pub fn loop_end(&self) -> Option<u64> {
+        self.loop_end.map(u64::from)
+    }
+
+    /// Discards any audio after the end of the loop in the smpl chunk. Formats which can only loop
+    /// back from the end of the stream (e.g. HPS) need this to preserve the loop. This must be
+    /// called before any samples are read.
+    pub fn trim_to_loop(&mut self) {
+        let loop_end = match self.loop_end {
+            Some(end) => end,
+            None => return,
+        };
+        let frame_size = (self.channels * 2) as u32;
+        let loop_size = loop_end * frame_size;
+        if loop_size < self.data_remaining {
+            debug!(
+                "Discarding {} samples after the end of the loop at sample {}",
+                (self.data_remaining - loop_size) / frame_size,
+                loop_end - 1
+            );
+            self.data_remaining = loop_size;
+        }
+    }
+
     /// Iterates over the chunks in the WAV data and builds the internal chunk offset map.
     fn read_chunks(&mut self) -> Result<()> {
         while let Some(mut chunk) = self.riff.next_chunk()? {
@@ -197,19 +226,18 @@ impl<'a> WavReader<'a> {
 
     /// Reads cue point information from the cue chunk.
     fn read_cues(&mut self) -> Result<()> {
-        let mut cues: HashMap<u32, Cue> = {
-            let mut chunk = match self.open_chunk(ID_CUE)? {
-                Some(c) => c,
-                None => return Ok(()),
-            };
-            let raw_cues = CueChunk::read_from(&mut chunk)?;
-            trace!("cue chunk: {:?}", raw_cues);
-            raw_cues
-                .points
-                .into_iter()
-                .filter(|c| c.chunk_id == ID_DATA)
-                .map(|c| (c.name, Cue::new("", c.sample_offset as u64)))
-                .collect()
+        let mut cues: HashMap<u32, Cue> = match self.open_chunk(ID_CUE)? {
+            Some(mut chunk) => {
+                let raw_cues = CueChunk::read_from(&mut chunk)?;
+                trace!("cue chunk: {:?}", raw_cues);
+                raw_cues
+                    .points
+                    .into_iter()
+                    .filter(|c| c.chunk_id == ID_DATA)
+                    .map(|c| (c.name, Cue::new("", c.sample_offset as u64)))
+                    .collect()
+            }
+            None => HashMap::new(),
         };
 
         // Cue names have to be pulled from the adtl list
@@ -251,13 +279,60 @@ impl<'a> WavReader<'a> {
             }
         }
 
+        // The smpl chunk takes precedence over any loop cues because it is what most audio software
+        // actually edits
+        let loop_cue = self.read_loop()?.map(|sample_loop| {
+            let start = sample_loop.start as u64;
+            let name = match cues.remove(&sample_loop.cue_point_id) {
+                // If the loop has a cue point, keep its name
+                Some(cue) if cue.start == start => cue.name,
+                Some(cue) => {
+                    cues.insert(sample_loop.cue_point_id, cue);
+                    LOOP_PREFIX.into()
+                }
+                None => LOOP_PREFIX.into(),
+            };
+            cues.retain(|_, c| !c.is_loop());
+            Cue::new_loop(name, start)
+        });
+
         // Sort and deduplicate in case any cues are duplicated for whatever reason
-        self.cues = cues.into_values().collect();
+        self.cues = cues.into_values().chain(loop_cue).collect();
         self.cues.sort_unstable();
         self.cues.dedup();
         Ok(())
     }
 
+    /// Reads the first forward loop from the smpl chunk if there is one.
+    fn read_loop(&mut self) -> Result<Option<SampleLoop>> {
+        let sampler = match self.open_chunk(ID_SMPL)? {
+            Some(mut chunk) => SamplerChunk::read_from(&mut chunk)?,
+            None => return Ok(None),
+        };
+        trace!("smpl chunk: {:?}", sampler);
+        let mut loops = sampler.loops.into_iter().filter(|l| l.loop_type == LOOP_FORWARD);
+        let sample_loop = match loops.next() {
+            Some(l) => l,
+            None => return Ok(None),
+        };
+        if loops.next().is_some() {
+            warn!("WAV data has more than one loop - only the first one will be used");
+        }
+
+        let frame_size = (self.channels * 2) as u32;
+        let num_frames = self.data_remaining / frame_size;
+        if sample_loop.start > sample_loop.end || sample_loop.start >= num_frames {
+            warn!(
+                "Discarding invalid WAV loop from sample {} to {}",
+                sample_loop.start, sample_loop.end
+            );
+            return Ok(None);
+        }
+        // Some programs write the end as an exclusive index, so tolerate it being past the end
+        self.loop_end = Some(sample_loop.end.saturating_add(1).min(num_frames));
+        Ok(Some(sample_loop))
+    }
+
     /// Opens a reader on chunk `id` if it is present.
     fn open_chunk(&mut self, id: u32) -> Result<Option<RiffReader<'_>>> {
         let offset = match self.chunk_offsets.get(&id) {
@@ -311,8 +386,9 @@ impl ReadSamples<'static> for WavReader<'_> {
         if self.data_remaining == 0 {
             return Ok(None);
         }
+        let len = self.data_remaining as u64;
         let samples = if let Some(chunk) = self.open_chunk(ID_DATA)? {
-            PcmS16Le::read_bytes(chunk)?
+            PcmS16Le::read_bytes(chunk.take(len))?
         } else {
             vec![]
         };
@@ -386,4 +462,65 @@ mod tests {
         );
         Ok(())
     }
+
+    #[rustfmt::skip]
+    const LOOP_WAV: &[u8] = &[
+        b'R', b'I', b'F', b'F', 0x78, 0x00, 0x00, 0x00,
+        b'W', b'A', b'V', b'E',
+
+        b'f', b'm', b't', b' ', 0x10, 0x00, 0x00, 0x00,
+        0x01, 0x00, // format_tag
+        0x01, 0x00, // channels
+        0x44, 0xac, 0x00, 0x00, // samples_per_sec
+        0x88, 0x58, 0x01, 0x00, // avg_bytes_per_sec
+        0x02, 0x00, // block_align
+        0x10, 0x00, // bits_per_sample
+
+        b'd', b'a', b't', b'a', 0x10, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, // samples
+        0x04, 0x00, 0x05, 0x00, 0x06, 0x00, 0x07, 0x00,
+
+        b's', b'm', b'p', b'l', 0x3c, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, // manufacturer
+        0x00, 0x00, 0x00, 0x00, // product
+        0x93, 0x58, 0x00, 0x00, // sample_period
+        0x3c, 0x00, 0x00, 0x00, // midi_unity_note
+        0x00, 0x00, 0x00, 0x00, // midi_pitch_fraction
+        0x00, 0x00, 0x00, 0x00, // smpte_format
+        0x00, 0x00, 0x00, 0x00, // smpte_offset
+        0x01, 0x00, 0x00, 0x00, // num_sample_loops
+        0x00, 0x00, 0x00, 0x00, // sampler_data
+        0x00, 0x00, 0x00, 0x00, // cue_point_id
+        0x00, 0x00, 0x00, 0x00, // type
+        0x02, 0x00, 0x00, 0x00, // start
+        0x05, 0x00, 0x00, 0x00, // end
+        0x00, 0x00, 0x00, 0x00, // fraction
+        0x00, 0x00, 0x00, 0x00, // play_count
+    ];
+
+    #[test]
+    fn test_read_wav_loop() -> Result<()> {
+        let mut wav = WavReader::new(Cursor::new(LOOP_WAV), "test")?;
+        let cues = wav.cues().collect::<Vec<_>>();
+        assert_eq!(cues, &[Cue::new_loop("loop", 2)]);
+
+        assert_eq!(wav.loop_end(), Some(6));
+
+        // Samples after the end of the loop are kept unless the reader is trimmed
+        assert_eq!(wav.data_remaining(), Some(8));
+        let samples = wav.read_all_samples()?;
+        assert_eq!(samples.data[..samples.len], [0, 1, 2, 3, 4, 5, 6, 7]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_wav_loop_trimmed() -> Result<()> {
+        let mut wav = WavReader::new(Cursor::new(LOOP_WAV), "test")?;
+        wav.trim_to_loop();
+        assert_eq!(wav.cues().collect::<Vec<_>>(), &[Cue::new_loop("loop", 2)]);
+        assert_eq!(wav.data_remaining(), Some(6));
+        let samples = wav.read_all_samples()?;
+        assert_eq!(samples.data[..samples.len], [0, 1, 2, 3, 4, 5]);
+        Ok(())
+    }
 }
@@ -8,7 +8,7 @@ use byteorder::{WriteBytesExt, LE};
 use std::borrow::Cow;
 use std::io::{self, Seek, SeekFrom, Write};
 use tracing::level_filters::STATIC_MAX_LEVEL;
-use tracing::{debug, instrument, Level};
+use tracing::{debug, instrument, warn, Level};
 
 const DEFAULT_SOFTWARE_NAME: &str = concat!("unplug v", env!("CARGO_PKG_VERSION"));
 
@@ -136,9 +136,12 @@ impl<'r, 's: 'r> WavWriter<'r, 's> {
         let mut riff = RiffWriter::new(writer);
         riff.open_form(ID_WAVE)?;
         self.write_format(&mut riff)?;
-        self.write_cues(&mut riff)?;
+        let loop_cue = self.write_cues(&mut riff)?;
         self.write_info(&mut riff)?;
-        self.write_data(&mut riff)?;
+        let num_samples = self.write_data(&mut riff)?;
+        if let Some((id, start)) = loop_cue {
+            self.write_loop(&mut riff, id, start, num_samples)?;
+        }
         riff.close_form()?;
         Ok(())
     }
@@ -161,11 +164,12 @@ impl<'r, 's: 'r> WavWriter<'r, 's> {
         Ok(())
     }
 
-    /// Writes the `cue ` and `adtl` chunks with cue information.
-    fn write_cues(&self, riff: &mut RiffWriter<impl Write + Seek>) -> Result<()> {
+    /// Writes the `cue ` and `adtl` chunks with cue information. If there is a loop cue, returns
+    /// its ID and start position.
+    fn write_cues(&self, riff: &mut RiffWriter<impl Write + Seek>) -> Result<Option<(u32, u64)>> {
         let mut cues = self.samples.cues().collect::<Vec<_>>();
         if cues.is_empty() {
-            return Ok(());
+            return Ok(None);
         }
         cues.sort_unstable();
         cues.dedup();
@@ -173,6 +177,7 @@ impl<'r, 's: 'r> WavWriter<'r, 's> {
         let mut chunk = CueChunk::default();
         let mut labels: Vec<LabelChunk> = vec![];
         let mut ltxts: Vec<LabelTextChunk> = vec![];
+        let mut loop_cue = None;
         for (i, cue) in cues.into_iter().enumerate() {
             // Each cue gets an entry in the cue chunk and a label in the labl list.
             let id = (i + 1) as u32;
@@ -184,6 +189,9 @@ impl<'r, 's: 'r> WavWriter<'r, 's> {
                 block_start: 0,
                 sample_offset: cue.start as u32,
             });
+            if cue.is_loop() && loop_cue.is_none() {
+                loop_cue = Some((id, cue.start));
+            }
             // Loop cues are stored with the loop prefix so they can be loaded back
             let name = match cue.kind {
                 CueKind::Loop => cue::add_loop_prefix(&cue.name),
@@ -225,6 +233,37 @@ impl<'r, 's: 'r> WavWriter<'r, 's> {
             riff.close_chunk(ID_LTXT)?;
         }
         riff.close_chunk(ID_LIST)?;
+        Ok(loop_cue)
+    }
+
+    /// Writes a `smpl` chunk which loops from `start` to the end of the audio. Most audio software
+    /// only understands loops which are stored in this chunk. `cue_id` is the ID of the loop cue.
+    fn write_loop(
+        &self,
+        riff: &mut RiffWriter<impl Write + Seek>,
+        cue_id: u32,
+        start: u64,
+        num_samples: u64,
+    ) -> Result<()> {
+        if start >= num_samples {
+            warn!("Loop start {} is past the end of the audio - discarding it", start);
+            return Ok(());
+        }
+        let chunk = SamplerChunk {
+            sample_period: 1_000_000_000 / self.sample_rate,
+            midi_unity_note: MIDI_MIDDLE_C,
+            loops: vec![SampleLoop {
+                cue_point_id: cue_id,
+                loop_type: LOOP_FORWARD,
+                start: start as u32,
+                end: (num_samples - 1) as u32,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        riff.open_chunk(ID_SMPL)?;
+        chunk.write_to(riff)?;
+        riff.close_chunk(ID_SMPL)?;
         Ok(())
     }
 
@@ -240,9 +279,9 @@ impl<'r, 's: 'r> WavWriter<'r, 's> {
         Ok(())
     }
 
-    /// Writes the `data` chunk.
+    /// Writes the `data` chunk and returns the number of samples written per channel.
     #[instrument(level = "trace", skip_all)]
-    fn write_data(&mut self, riff: &mut RiffWriter<impl Write + Seek>) -> Result<()> {
+    fn write_data(&mut self, riff: &mut RiffWriter<impl Write + Seek>) -> Result<u64> {
         riff.open_chunk(ID_DATA)?;
         let mut num_samples = 0;
         while let Some(samples) = self.samples.read_samples()? {
@@ -270,7 +309,7 @@ impl<'r, 's: 'r> WavWriter<'r, 's> {
             );
         }
         riff.close_chunk(ID_DATA)?;
-        Ok(())
+        Ok(num_samples as u64)
     }
 
     fn peek_audio_info(&mut self) -> Result<()> {
@@ -376,4 +415,38 @@ mod tests {
         assert_eq!(actual[2], Cue::new_loop("loop:test", 3));
         Ok(())
     }
+
+    #[test]
+    fn test_write_and_read_loop() -> Result<()> {
+        let samples = Samples::<PcmS16Le>::from_pcm((0..8).collect::<Vec<_>>(), 1, 44100);
+        let cues = vec![Cue::new("start", 0), Cue::new_loop("test", 2)];
+
+        let samples = ReadSampleList::with_cues(vec![samples], cues, "test");
+        let mut cursor = Cursor::new(vec![]);
+        WavWriter::new(samples).write_to(&mut cursor)?;
+
+        // The loop should be in a smpl chunk at the end of the file
+        let bytes = cursor.get_ref();
+        let offset = bytes.len() - 0x44;
+        let mut smpl = Cursor::new(&bytes[offset..]);
+        assert_eq!(ChunkHeader::read_from(&mut smpl)?, ChunkHeader { id: ID_SMPL, size: 0x3c });
+        let chunk = SamplerChunk::read_from(&mut smpl)?;
+        assert_eq!(chunk.sample_period, 22675);
+        assert_eq!(
+            chunk.loops,
+            &[SampleLoop {
+                cue_point_id: 2,
+                loop_type: LOOP_FORWARD,
+                start: 2,
+                end: 7,
+                ..Default::default()
+            }]
+        );
+
+        cursor.rewind()?;
+        let wav = WavReader::new(cursor, "test")?;
+        let actual = wav.cues().collect::<Vec<_>>();
+        assert_eq!(actual, &[Cue::new("start", 0), Cue::new_loop("loop:test", 2)]);
+        Ok(())
+    }
 }
@@ -0,0 +1,528 @@
+use super::ReadSeek;
+use byteorder::{ByteOrder, BE};
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use thiserror::Error;
+
+const YAZ0_MAGIC: [u8; 4] = *b"Yaz0";
+const YAY0_MAGIC: [u8; 4] = *b"Yay0";
+const HEADER_SIZE: usize = 0x10;
+
+/// The furthest back a match can reference.
+const MAX_DISTANCE: usize = 0x1000;
+/// The shortest match which can be encoded.
+const MIN_MATCH: usize = 3;
+/// The longest match which can be encoded without an extra length byte.
+const MAX_SHORT_MATCH: usize = 0x11;
+/// The length which is added to the extra length byte.
+const LONG_MATCH_BIAS: usize = 0x12;
+/// The longest match which can be encoded.
+const MAX_MATCH: usize = 0xff + LONG_MATCH_BIAS;
+
+/// The number of bits in a match finder hash.
+const HASH_BITS: u32 = 15;
+/// The maximum number of earlier positions to check when looking for a match.
+const MAX_CHAIN: usize = 256;
+/// Marks the end of a match finder hash chain.
+const CHAIN_END: usize = usize::MAX;
+/// The most that the decoders will preallocate per byte of compressed data. The size in the header
+/// can't be trusted, so the output is allowed to grow past this instead.
+const MAX_PREALLOCATE_RATIO: usize = 16;
+
+/// The result type for compression operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error type for compression operations.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("data is not compressed with a recognized format")]
+    UnrecognizedFormat,
+
+    #[error("compressed data is truncated")]
+    Truncated,
+
+    #[error("invalid back-reference at output offset {0:#x}")]
+    InvalidReference(usize),
+
+    #[error(transparent)]
+    Io(Box<io::Error>),
+}
+
+from_error_boxed!(Error::Io, io::Error);
+
+/// Supported compression formats.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Compression {
+    /// Yaz0, which interleaves flag bytes with the compressed data.
+    Yaz0,
+    /// Yay0, which stores flags, back-references, and literal bytes in separate blocks.
+    Yay0,
+}
+
+impl Compression {
+    /// Looks up a compression format by its case-insensitive name (e.g. `yaz0`).
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Yaz0, Self::Yay0].into_iter().find(|c| c.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the format's name.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Yaz0 => "yaz0",
+            Self::Yay0 => "yay0",
+        }
+    }
+
+    /// Detects the format of compressed data from its first bytes. Returns `None` if the data is
+    /// not compressed.
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        match header.get(..4)? {
+            m if m == YAZ0_MAGIC => Some(Self::Yaz0),
+            m if m == YAY0_MAGIC => Some(Self::Yay0),
+            _ => None,
+        }
+    }
+
+    /// Compresses `data` using this format.
+    ///
+    /// ***Panics*** if `data` is larger than 4 GiB.
+    pub fn compress(self, data: &[u8]) -> Vec<u8> {
+        let tokens = find_tokens(data);
+        match self {
+            Self::Yaz0 => encode_yaz0(data.len(), &tokens),
+            Self::Yay0 => encode_yay0(data.len(), &tokens),
+        }
+    }
+}
+
+/// Detects the compression format of the data in `reader` without changing its position.
+pub fn detect(reader: &mut (impl Read + Seek + ?Sized)) -> io::Result<Option<Compression>> {
+    let start = reader.stream_position()?;
+    let mut header = Vec::with_capacity(4);
+    reader.take(4).read_to_end(&mut header)?;
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(Compression::detect(&header))
+}
+
+/// Decompresses Yaz0 or Yay0 data, detecting the format from its header.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    match Compression::detect(data) {
+        Some(Compression::Yaz0) => decode_yaz0(data),
+        Some(Compression::Yay0) => decode_yay0(data),
+        None => Err(Error::UnrecognizedFormat),
+    }
+}
+
+/// If the data in `reader` is compressed, decompresses it and returns a reader over the
+/// decompressed data. Otherwise, `reader` is returned as-is.
+pub fn open_decompressed<'r>(mut reader: Box<dyn ReadSeek + 'r>) -> Result<Box<dyn ReadSeek + 'r>> {
+    if detect(&mut reader)?.is_none() {
+        return Ok(reader);
+    }
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    Ok(Box::new(Cursor::new(decompress(&data)?)))
+}
+
+/// Reads `len` bytes from `src` at `*pos` and advances `pos`.
+fn take<'a>(src: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8]> {
+    let bytes = src.get(*pos..(*pos + len)).ok_or(Error::Truncated)?;
+    *pos += len;
+    Ok(bytes)
+}
+
+/// Reads a byte from `src` at `*pos` and advances `pos`.
+fn take_byte(src: &[u8], pos: &mut usize) -> Result<u8> {
+    Ok(take(src, pos, 1)?[0])
+}
+
+/// Reads the decompressed size from a header.
+fn decoded_size(src: &[u8]) -> Result<usize> {
+    let header = src.get(..HEADER_SIZE).ok_or(Error::Truncated)?;
+    Ok(BE::read_u32(&header[4..8]) as usize)
+}
+
+/// Allocates an output buffer for decoding `src`, which has a decompressed size of `size`.
+fn output_buffer(src: &[u8], size: usize) -> Vec<u8> {
+    Vec::with_capacity(size.min(src.len().saturating_mul(MAX_PREALLOCATE_RATIO)))
+}
+
+/// Copies `len` bytes starting `distance` bytes back from the end of `out`. The output is never
+/// allowed to grow past `size`.
+fn copy_match(out: &mut Vec<u8>, distance: usize, len: usize, size: usize) -> Result<()> {
+    if distance > out.len() {
+        return Err(Error::InvalidReference(out.len()));
+    }
+    let start = out.len() - distance;
+    let len = len.min(size - out.len());
+    // The regions can overlap, so this has to go byte-by-byte
+    for i in start..(start + len) {
+        out.push(out[i]);
+    }
+    Ok(())
+}
+
+/// Decodes a match's distance and length from a back-reference code, reading an extra length
+/// byte using `extra` if necessary.
+fn decode_match(code: u16, extra: impl FnOnce() -> Result<u8>) -> Result<(usize, usize)> {
+    let distance = usize::from(code & 0xfff) + 1;
+    let len = match code >> 12 {
+        0 => usize::from(extra()?) + LONG_MATCH_BIAS,
+        n => usize::from(n) + 2,
+    };
+    Ok((distance, len))
+}
+
+fn decode_yaz0(src: &[u8]) -> Result<Vec<u8>> {
+    let size = decoded_size(src)?;
+    let mut out = output_buffer(src, size);
+    let mut pos = HEADER_SIZE;
+    let mut flags = 0u8;
+    let mut num_flags = 0;
+    while out.len() < size {
+        if num_flags == 0 {
+            flags = take_byte(src, &mut pos)?;
+            num_flags = 8;
+        }
+        if flags & 0x80 != 0 {
+            out.push(take_byte(src, &mut pos)?);
+        } else {
+            let code = BE::read_u16(take(src, &mut pos, 2)?);
+            let (distance, len) = decode_match(code, || take_byte(src, &mut pos))?;
+            copy_match(&mut out, distance, len, size)?;
+        }
+        flags <<= 1;
+        num_flags -= 1;
+    }
+    Ok(out)
+}
+
+fn decode_yay0(src: &[u8]) -> Result<Vec<u8>> {
+    let size = decoded_size(src)?;
+    let mut out = output_buffer(src, size);
+    let mut flags_pos = HEADER_SIZE;
+    let mut link_pos = BE::read_u32(&src[0x8..0xc]) as usize;
+    let mut chunk_pos = BE::read_u32(&src[0xc..0x10]) as usize;
+    let mut flags = 0u32;
+    let mut num_flags = 0;
+    while out.len() < size {
+        if num_flags == 0 {
+            flags = BE::read_u32(take(src, &mut flags_pos, 4)?);
+            num_flags = 32;
+        }
+        if flags & 0x8000_0000 != 0 {
+            out.push(take_byte(src, &mut chunk_pos)?);
+        } else {
+            let code = BE::read_u16(take(src, &mut link_pos, 2)?);
+            let (distance, len) = decode_match(code, || take_byte(src, &mut chunk_pos))?;
+            copy_match(&mut out, distance, len, size)?;
+        }
+        flags <<= 1;
+        num_flags -= 1;
+    }
+    Ok(out)
+}
+
+/// A piece of compressed data.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[allow(variant_size_differences)]
+enum Token {
+    /// A single uncompressed byte.
+    Literal(u8),
+    /// A copy of `len` bytes starting `distance` bytes back.
+    Match { distance: usize, len: usize },
+}
+
+impl Token {
+    /// Encodes a match as a back-reference code and an optional extra length byte.
+    fn match_code(distance: usize, len: usize) -> (u16, Option<u8>) {
+        let distance = (distance - 1) as u16;
+        if len <= MAX_SHORT_MATCH {
+            (((len - 2) as u16) << 12 | distance, None)
+        } else {
+            (distance, Some((len - LONG_MATCH_BIAS) as u8))
+        }
+    }
+}
+
+/// Finds repeated byte sequences using hash chains.
+struct MatchFinder<'a> {
+    data: &'a [u8],
+    /// The most recent position which has each hash.
+    head: Vec<usize>,
+    /// The previous position with the same hash as each position.
+    prev: Vec<usize>,
+    /// The next position to insert into the hash chains.
+    next: usize,
+}
+
+impl<'a> MatchFinder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            head: vec![CHAIN_END; 1 << HASH_BITS],
+            prev: vec![CHAIN_END; data.len()],
+            next: 0,
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let bytes = &self.data[pos..(pos + MIN_MATCH)];
+        let key = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        (key.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// Inserts all positions before `end` into the hash chains.
+    fn insert_until(&mut self, end: usize) {
+        let end = end.min(self.data.len().saturating_sub(MIN_MATCH - 1));
+        while self.next < end {
+            let hash = self.hash(self.next);
+            self.prev[self.next] = self.head[hash];
+            self.head[hash] = self.next;
+            self.next += 1;
+        }
+    }
+
+    /// Finds the longest match for the data at `pos` and returns its distance and length.
+    fn find(&mut self, pos: usize) -> (usize, usize) {
+        let max_len = MAX_MATCH.min(self.data.len() - pos);
+        if max_len < MIN_MATCH {
+            return (0, 0);
+        }
+        self.insert_until(pos);
+        let target = &self.data[pos..(pos + max_len)];
+        let mut best = (0, 0);
+        let mut candidate = self.head[self.hash(pos)];
+        let mut steps = 0;
+        while candidate != CHAIN_END && pos - candidate <= MAX_DISTANCE && steps < MAX_CHAIN {
+            let len =
+                target.iter().zip(&self.data[candidate..]).take_while(|(a, b)| a == b).count();
+            if len > best.1 {
+                best = (pos - candidate, len);
+                if len == max_len {
+                    break;
+                }
+            }
+            candidate = self.prev[candidate];
+            steps += 1;
+        }
+        best
+    }
+}
+
+/// Splits `data` into literals and matches.
+fn find_tokens(data: &[u8]) -> Vec<Token> {
+    let mut finder = MatchFinder::new(data);
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < data.len() {
+        let (distance, len) = finder.find(pos);
+        // Lazy matching: if the next byte starts a longer match, use a literal for this one
+        if len < MIN_MATCH || finder.find(pos + 1).1 > len {
+            tokens.push(Token::Literal(data[pos]));
+            pos += 1;
+        } else {
+            tokens.push(Token::Match { distance, len });
+            pos += len;
+        }
+    }
+    tokens
+}
+
+/// Writes a compressed data header.
+fn write_header(out: &mut Vec<u8>, magic: [u8; 4], size: usize) {
+    let size = u32::try_from(size).expect("Data size overflow");
+    out.extend(magic);
+    out.extend(size.to_be_bytes());
+}
+
+fn encode_yaz0(size: usize, tokens: &[Token]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE + size);
+    write_header(&mut out, YAZ0_MAGIC, size);
+    out.resize(HEADER_SIZE, 0);
+    for group in tokens.chunks(8) {
+        let flags_pos = out.len();
+        out.push(0);
+        for (i, &token) in group.iter().enumerate() {
+            match token {
+                Token::Literal(b) => {
+                    out[flags_pos] |= 0x80 >> i;
+                    out.push(b);
+                }
+                Token::Match { distance, len } => {
+                    let (code, extra) = Token::match_code(distance, len);
+                    out.extend(code.to_be_bytes());
+                    out.extend(extra);
+                }
+            }
+        }
+    }
+    out
+}
+
+fn encode_yay0(size: usize, tokens: &[Token]) -> Vec<u8> {
+    let mut flags = Vec::with_capacity(tokens.len() / 8 + 4);
+    let mut links = vec![];
+    let mut chunks = vec![];
+    for group in tokens.chunks(32) {
+        let mut group_flags = 0u32;
+        for (i, &token) in group.iter().enumerate() {
+            match token {
+                Token::Literal(b) => {
+                    group_flags |= 0x8000_0000 >> i;
+                    chunks.push(b);
+                }
+                Token::Match { distance, len } => {
+                    let (code, extra) = Token::match_code(distance, len);
+                    links.extend(code.to_be_bytes());
+                    chunks.extend(extra);
+                }
+            }
+        }
+        flags.extend(group_flags.to_be_bytes());
+    }
+
+    let link_offset = HEADER_SIZE + flags.len();
+    let chunk_offset = link_offset + links.len();
+    let mut out = Vec::with_capacity(chunk_offset + chunks.len());
+    write_header(&mut out, YAY0_MAGIC, size);
+    out.extend((link_offset as u32).to_be_bytes());
+    out.extend((chunk_offset as u32).to_be_bytes());
+    out.extend(flags);
+    out.extend(links);
+    out.extend(chunks);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Generates data with a mix of repeated and unique sequences.
+    fn test_data() -> Vec<u8> {
+        let mut data = b"Chibi-Robo! Plug Into Adventure! ".repeat(20);
+        data.extend((0..=255).map(|i: u32| (i * 37 % 251) as u8));
+        data.extend(vec![0xaa; 1000]);
+        data.extend(b"Chibi-Robo!");
+        data
+    }
+
+    #[test]
+    fn test_decompress_yaz0() -> Result<()> {
+        // "abcabcabcd" with a literal run, a short match, and a literal
+        let compressed = [
+            b'Y',
+            b'a',
+            b'z',
+            b'0',
+            0,
+            0,
+            0,
+            10,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0, // header
+            0b1110_1000,
+            b'a',
+            b'b',
+            b'c',
+            0x40,
+            0x02,
+            b'd',
+        ];
+        assert_eq!(decompress(&compressed)?, b"abcabcabcd");
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_yay0() -> Result<()> {
+        let compressed = [
+            b'Y',
+            b'a',
+            b'y',
+            b'0',
+            0,
+            0,
+            0,
+            10,
+            0,
+            0,
+            0,
+            0x14,
+            0,
+            0,
+            0,
+            0x16, // header
+            0b1110_1000,
+            0,
+            0,
+            0, // flags
+            0x40,
+            0x02, // links
+            b'a',
+            b'b',
+            b'c',
+            b'd', // chunks
+        ];
+        assert_eq!(decompress(&compressed)?, b"abcabcabcd");
+        Ok(())
+    }
+
+    #[test]
+    fn test_compress_round_trip() -> Result<()> {
+        let data = test_data();
+        for format in [Compression::Yaz0, Compression::Yay0] {
+            let compressed = format.compress(&data);
+            assert_eq!(Compression::detect(&compressed), Some(format));
+            assert!(compressed.len() < data.len() / 2);
+            assert_eq!(decompress(&compressed)?, data);
+
+            let empty = format.compress(&[]);
+            assert_eq!(decompress(&empty)?, b"");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_invalid() {
+        assert!(matches!(decompress(b"foo"), Err(Error::UnrecognizedFormat)));
+        let mut compressed = Compression::Yaz0.compress(&test_data());
+        compressed.truncate(compressed.len() - 1);
+        assert!(matches!(decompress(&compressed), Err(Error::Truncated)));
+        let bad_reference =
+            [b'Y', b'a', b'z', b'0', 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0];
+        assert!(matches!(decompress(&bad_reference), Err(Error::InvalidReference(0))));
+        // A huge size in the header should fail on the missing data rather than on allocation
+        let huge = [b'Y', b'a', b'y', b'0', 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0x10, 0, 0, 0, 0x10];
+        assert!(matches!(decompress(&huge), Err(Error::Truncated)));
+    }
+
+    #[test]
+    fn test_open_decompressed() -> Result<()> {
+        let data = test_data();
+        let compressed: Box<dyn ReadSeek> =
+            Box::new(Cursor::new(Compression::Yay0.compress(&data)));
+        let mut buf = vec![];
+        open_decompressed(compressed)?.read_to_end(&mut buf)?;
+        assert_eq!(buf, data);
+
+        let plain: Box<dyn ReadSeek> = Box::new(Cursor::new(data.clone()));
+        buf.clear();
+        open_decompressed(plain)?.read_to_end(&mut buf)?;
+        assert_eq!(buf, data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression_names() {
+        assert_eq!(Compression::from_name("YAZ0"), Some(Compression::Yaz0));
+        assert_eq!(Compression::from_name("yay0"), Some(Compression::Yay0));
+        assert_eq!(Compression::from_name("zlib"), None);
+    }
+}
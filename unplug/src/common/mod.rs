@@ -4,11 +4,13 @@ mod math;
 mod read_write;
 mod region;
 
+pub mod compression;
 pub mod endian;
 pub mod io;
 pub mod string_table;
 pub mod text;
 
+pub use compression::Compression;
 pub use i24::I24;
 pub use list::*;
 pub use math::*;
@@ -81,3 +81,79 @@ impl<W: Write + ?Sized> WriteTo<W> for Header {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Compression;
+    use crate::dvd::OpenFile;
+    use std::io::Cursor;
+
+    fn read_file(archive: &mut ArchiveReader<Cursor<Vec<u8>>>, path: &str) -> Vec<u8> {
+        let mut bytes = vec![];
+        archive.open_file_at(path).unwrap().read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_build_archive() -> Result<()> {
+        let mut builder = ArchiveBuilder::new();
+        builder.add_file_at("foo", || Ok(Cursor::new(b"foo".to_vec())))?;
+        builder.add_dir_at("bar")?;
+        builder.add_file_at("bar/baz", || Ok(Cursor::new(b"hello".to_vec())))?;
+        assert!(builder.add_file_at("foo", || Ok(Cursor::new(vec![]))).is_err());
+        assert!(builder.add_file_at("qux/foo", || Ok(Cursor::new(vec![]))).is_err());
+        let mut writer = Cursor::new(vec![]);
+        builder.write_to(&mut writer)?;
+
+        let mut archive = ArchiveReader::open(Cursor::new(writer.into_inner()))?;
+        assert_eq!(read_file(&mut archive, "foo"), b"foo");
+        assert_eq!(read_file(&mut archive, "bar/baz"), b"hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_compressed_file() -> Result<()> {
+        let mut builder = ArchiveBuilder::new();
+        let compressed = Compression::Yaz0.compress(b"hello hello hello");
+        builder.add_file_at("foo", move || Ok(Cursor::new(compressed)))?;
+        builder.add_file_at("bar", || Ok(Cursor::new(b"Yaz".to_vec())))?;
+        let mut writer = Cursor::new(vec![]);
+        builder.write_to(&mut writer)?;
+
+        let mut archive = ArchiveReader::open(Cursor::new(writer.into_inner()))?;
+        let mut bytes = vec![];
+        archive.open_file_decompressed_at("foo")?.read_to_end(&mut bytes)?;
+        assert_eq!(bytes, b"hello hello hello");
+        bytes.clear();
+        archive.open_file_decompressed_at("bar")?.read_to_end(&mut bytes)?;
+        assert_eq!(bytes, b"Yaz");
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit_archive() -> Result<()> {
+        let mut builder = ArchiveBuilder::new();
+        builder.add_file_at("foo", || Ok(Cursor::new(b"foo".to_vec())))?;
+        builder.add_dir_at("bar")?;
+        builder.add_file_at("bar/baz", || Ok(Cursor::new(b"hello".to_vec())))?;
+        let mut writer = Cursor::new(vec![]);
+        builder.write_to(&mut writer)?;
+
+        let mut archive = ArchiveReader::open(Cursor::new(writer.into_inner()))?;
+        let mut builder = ArchiveBuilder::with_archive(&mut archive);
+        builder.remove_at("bar")?;
+        builder.add_file_at("qux", || Ok(Cursor::new(b"qux".to_vec())))?;
+        assert!(builder.remove_at("bar/baz").is_err());
+        let mut writer = Cursor::new(vec![]);
+        builder.write_to(&mut writer)?;
+        drop(builder);
+
+        let mut archive = ArchiveReader::open(Cursor::new(writer.into_inner()))?;
+        assert_eq!(read_file(&mut archive, "foo"), b"foo");
+        assert_eq!(read_file(&mut archive, "qux"), b"qux");
+        assert!(archive.files.at("bar").is_err());
+        assert!(archive.files.at("bar/baz").is_err());
+        Ok(())
+    }
+}
@@ -1,27 +1,27 @@
 use super::{ArchiveReader, Header, Result};
 use crate::common::io::pad;
 use crate::common::{ReadSeek, WriteTo};
-use crate::dvd::fst::{self, EntryId, FileTree, FstEntryKind};
+use crate::dvd::fst::{self, DirectoryEntry, EntryId, FileEntry, FileTree, FstEntryKind};
 use crate::dvd::OpenFile;
 use slotmap::SecondaryMap;
 use std::cell::RefCell;
 use std::convert::TryFrom;
-use std::io::{BufWriter, Seek, Write};
+use std::io::{self, BufWriter, Seek, Write};
 use std::rc::Rc;
 use tracing::trace;
 
 const ARCHIVE_ALIGN: u64 = 0x20;
 
-/// An `OpenFile` wrapper around a closure which returns a reader for a file.
+/// An `OpenFile` wrapper around a closure which opens a reader for a file.
 struct Opener<R, F>(Option<F>)
 where
     R: ReadSeek,
-    F: FnOnce() -> R;
+    F: FnOnce() -> io::Result<R>;
 
 impl<R, F> OpenFile for Opener<R, F>
 where
     R: ReadSeek,
-    F: FnOnce() -> R,
+    F: FnOnce() -> io::Result<R>,
 {
     fn query_file(&self, _id: EntryId) -> &fst::Entry {
         unimplemented!()
@@ -29,7 +29,7 @@ where
 
     fn open_file(&mut self, _id: EntryId) -> fst::Result<Box<dyn ReadSeek + '_>> {
         let func = self.0.take().unwrap();
-        Ok(Box::from(func()))
+        Ok(Box::from(func()?))
     }
 
     fn into_file<'s>(self, _id: EntryId) -> fst::Result<Box<dyn ReadSeek + 's>>
@@ -37,12 +37,12 @@ where
         Self: 's,
     {
         let func = self.0.unwrap();
-        Ok(Box::from(func()))
+        Ok(Box::from(func()?))
     }
 
     fn open_file_at(&mut self, _path: &str) -> fst::Result<Box<dyn ReadSeek + '_>> {
         let func = self.0.take().unwrap();
-        Ok(Box::from(func()))
+        Ok(Box::from(func()?))
     }
 
     fn into_file_at<'s>(self, _path: &str) -> fst::Result<Box<dyn ReadSeek + 's>>
@@ -50,7 +50,7 @@ where
         Self: 's,
     {
         let func = self.0.unwrap();
-        Ok(Box::from(func()))
+        Ok(Box::from(func()?))
     }
 }
 
@@ -91,7 +91,7 @@ impl<'a> ArchiveBuilder<'a> {
     pub fn replace<'s, R, F>(&'s mut self, entry: EntryId, opener: F) -> &'s mut Self
     where
         R: ReadSeek + 'a,
-        F: (FnOnce() -> R) + 'a,
+        F: (FnOnce() -> io::Result<R>) + 'a,
     {
         let boxed: Box<dyn OpenFile> = Box::new(Opener(Some(opener)));
         self.sources.insert(entry, Rc::new(RefCell::new(boxed)));
@@ -103,11 +103,65 @@ impl<'a> ArchiveBuilder<'a> {
     pub fn replace_at<'s, R, F>(&'s mut self, path: &str, opener: F) -> Result<&'s mut Self>
     where
         R: ReadSeek + 'a,
-        F: (FnOnce() -> R) + 'a,
+        F: (FnOnce() -> io::Result<R>) + 'a,
     {
         Ok(self.replace(self.files.at(path)?, opener))
     }
 
+    /// Adds a new file named `name` to the `parent` directory and registers `opener` as its data
+    /// source. When the archive is written out, `opener` will be called to get a reader for the
+    /// file's data.
+    pub fn add_file<R, F>(&mut self, parent: EntryId, name: &str, opener: F) -> Result<EntryId>
+    where
+        R: ReadSeek + 'a,
+        F: (FnOnce() -> io::Result<R>) + 'a,
+    {
+        let id = self.files.try_insert(parent, FileEntry::new(name, 0, 0).into())?;
+        let boxed: Box<dyn OpenFile> = Box::new(Opener(Some(opener)));
+        self.sources.insert(id, Rc::new(RefCell::new(boxed)));
+        Ok(id)
+    }
+
+    /// Adds a new file at `path` and registers `opener` as its data source. The parent directory
+    /// must already exist.
+    pub fn add_file_at<R, F>(&mut self, path: &str, opener: F) -> Result<EntryId>
+    where
+        R: ReadSeek + 'a,
+        F: (FnOnce() -> io::Result<R>) + 'a,
+    {
+        let (parent, name) = self.files.parent_and_name(path)?;
+        self.add_file(parent, name, opener)
+    }
+
+    /// Adds a new empty directory named `name` to the `parent` directory.
+    pub fn add_dir(&mut self, parent: EntryId, name: &str) -> Result<EntryId> {
+        Ok(self.files.try_insert(parent, DirectoryEntry::new(name).into())?)
+    }
+
+    /// Adds a new empty directory at `path`. The parent directory must already exist.
+    pub fn add_dir_at(&mut self, path: &str) -> Result<EntryId> {
+        let (parent, name) = self.files.parent_and_name(path)?;
+        self.add_dir(parent, name)
+    }
+
+    /// Removes the entry with ID `id`. If it is a directory, everything inside it is removed too.
+    pub fn remove(&mut self, id: EntryId) -> Result<()> {
+        let ids = match self.files[id].is_dir() {
+            true => self.files.recurse_from(id).map(|(_, e)| e).collect(),
+            false => vec![id],
+        };
+        self.files.remove(id)?;
+        for id in ids {
+            self.sources.remove(id);
+        }
+        Ok(())
+    }
+
+    /// Removes the entry at `path`. If it is a directory, everything inside it is removed too.
+    pub fn remove_at(&mut self, path: &str) -> Result<()> {
+        self.remove(self.files.at(path)?)
+    }
+
     /// Writes out an archive file.
     pub fn write_to(&self, mut writer: impl Write + Seek) -> Result<()> {
         // Write an empty header we can fill in later
@@ -139,7 +193,7 @@ impl<'a> ArchiveBuilder<'a> {
             trace!("Writing archive entry \"{}\" at {:#x}", self.files[id].name(), start_offset);
             let mut source = self.sources[id].borrow_mut();
             let mut reader = source.open_file(id)?;
-            std::io::copy(reader.as_mut(), &mut writer)?;
+            io::copy(reader.as_mut(), &mut writer)?;
 
             let end_offset = u32::try_from(writer.stream_position()?).expect("File size overflow");
             entry.offset_or_parent = start_offset;
@@ -19,6 +19,9 @@ pub const IMAGE_WIDTH: usize = 96;
 pub const IMAGE_HEIGHT: usize = 32;
 pub const IMAGE_SIZE: usize = IMAGE_WIDTH * IMAGE_HEIGHT;
 
+/// Images are stored in tiles which are 4x4 pixels.
+const TILE_SIZE: usize = 4;
+
 /// The result type for banner operations.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -58,6 +61,75 @@ impl Banner {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Decodes the banner image into 8-bit RGBA pixels stored in row-major order.
+    pub fn image_rgba(&self) -> Result<Vec<u8>> {
+        if self.image.len() != IMAGE_SIZE {
+            return Err(Error::InvalidImageSize);
+        }
+        let mut rgba = vec![0u8; IMAGE_SIZE * 4];
+        for (i, &pixel) in self.image.iter().enumerate() {
+            let index = untile(i);
+            rgba[(index * 4)..(index * 4 + 4)].copy_from_slice(&decode_rgb5a3(pixel));
+        }
+        Ok(rgba)
+    }
+
+    /// Replaces the banner image with 8-bit RGBA pixels stored in row-major order. The image must
+    /// be exactly `IMAGE_WIDTH` x `IMAGE_HEIGHT`.
+    pub fn set_image_rgba(&mut self, rgba: &[u8]) -> Result<()> {
+        if rgba.len() != IMAGE_SIZE * 4 {
+            return Err(Error::InvalidImageSize);
+        }
+        let image = (0..IMAGE_SIZE).map(|i| {
+            let index = untile(i);
+            encode_rgb5a3(rgba[(index * 4)..(index * 4 + 4)].try_into().unwrap())
+        });
+        self.image = image.collect();
+        Ok(())
+    }
+}
+
+/// Converts an index into the tiled image data into an index in row-major order.
+fn untile(index: usize) -> usize {
+    const TILE_PIXELS: usize = TILE_SIZE * TILE_SIZE;
+    const TILES_PER_ROW: usize = IMAGE_WIDTH / TILE_SIZE;
+    let (tile, pixel) = (index / TILE_PIXELS, index % TILE_PIXELS);
+    let x = (tile % TILES_PER_ROW) * TILE_SIZE + pixel % TILE_SIZE;
+    let y = (tile / TILES_PER_ROW) * TILE_SIZE + pixel / TILE_SIZE;
+    y * IMAGE_WIDTH + x
+}
+
+/// Decodes an RGB5A3 pixel into 8-bit RGBA.
+///
+/// If the high bit is set, the pixel is opaque and has 5 bits per color channel. Otherwise, it
+/// has 3 bits of alpha and 4 bits per color channel.
+pub fn decode_rgb5a3(pixel: u16) -> [u8; 4] {
+    let expand5 = |x: u16| ((x << 3) | (x >> 2)) as u8;
+    let expand4 = |x: u16| (x * 0x11) as u8;
+    if pixel & 0x8000 != 0 {
+        let r = (pixel >> 10) & 0x1f;
+        let g = (pixel >> 5) & 0x1f;
+        let b = pixel & 0x1f;
+        [expand5(r), expand5(g), expand5(b), 0xff]
+    } else {
+        let a = (pixel >> 12) & 0x7;
+        let r = (pixel >> 8) & 0xf;
+        let g = (pixel >> 4) & 0xf;
+        let b = pixel & 0xf;
+        [expand4(r), expand4(g), expand4(b), ((a << 5) | (a << 2) | (a >> 1)) as u8]
+    }
+}
+
+/// Encodes an 8-bit RGBA color as an RGB5A3 pixel. Colors which are nearly opaque are stored
+/// opaque so that they can keep more color precision.
+pub fn encode_rgb5a3(rgba: [u8; 4]) -> u16 {
+    let [r, g, b, a] = rgba.map(u16::from);
+    if a >= 0xe0 {
+        0x8000 | ((r >> 3) << 10) | ((g >> 3) << 5) | (b >> 3)
+    } else {
+        ((a >> 5) << 12) | ((r >> 4) << 8) | ((g >> 4) << 4) | (b >> 4)
+    }
 }
 
 #[allow(clippy::missing_fields_in_debug)]
@@ -184,6 +256,34 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_rgb5a3() {
+        assert_eq!(decode_rgb5a3(0xffff), [0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(decode_rgb5a3(0xfc00), [0xff, 0x00, 0x00, 0xff]);
+        assert_eq!(decode_rgb5a3(0x0000), [0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(decode_rgb5a3(0x40f0), [0x00, 0xff, 0x00, 0x92]);
+        for pixel in (0..=0xffff).filter(|p| p & 0x8000 != 0 || p & 0x7000 != 0x7000) {
+            assert_eq!(encode_rgb5a3(decode_rgb5a3(pixel)), pixel, "{:#06x}", pixel);
+        }
+    }
+
+    #[test]
+    fn test_banner_image_rgba() -> Result<()> {
+        let mut banner = Banner { image: vec![0x8000; IMAGE_SIZE].into(), ..Default::default() };
+        // Put a white pixel at (5, 1), which is in the second tile
+        let mut rgba = banner.image_rgba()?;
+        let index = (IMAGE_WIDTH + 5) * 4;
+        rgba[index..(index + 4)].copy_from_slice(&[0xff; 4]);
+        banner.set_image_rgba(&rgba)?;
+        let tiled = 16 + 4 + 1;
+        assert_eq!(banner.image[tiled], 0xffff);
+        assert_eq!(banner.image.iter().filter(|&&p| p != 0x8000).count(), 1);
+        assert_eq!(banner.image_rgba()?, rgba);
+
+        assert!(matches!(banner.set_image_rgba(&[0; 4]), Err(Error::InvalidImageSize)));
+        Ok(())
+    }
+
     #[test]
     fn test_parse_banner() -> Result<()> {
         let mut reader = Cursor::new(TEST_BANNER);
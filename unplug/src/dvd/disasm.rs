@@ -0,0 +1,826 @@
+use super::dol::{Dol, Error, Result};
+use byteorder::{ByteOrder, BE};
+use std::fmt;
+use Operand::*;
+
+/// Names of the bits in a condition register field.
+const CR_BITS: [&str; 4] = ["lt", "gt", "eq", "so"];
+/// Names of the conditions tested by conditional branches, indexed by CR bit. The second entry is
+/// the condition used when the bit must be clear.
+const BRANCH_CONDITIONS: [(&str, &str); 4] =
+    [("lt", "ge"), ("gt", "le"), ("eq", "ne"), ("so", "ns")];
+
+/// An operand of an instruction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operand {
+    /// A general-purpose register.
+    Gpr(u8),
+    /// A floating-point register.
+    Fpr(u8),
+    /// A condition register field.
+    Cr(u8),
+    /// A bit in the condition register.
+    CrBit(u8),
+    /// A special-purpose register.
+    Spr(u16),
+    /// A segment register.
+    Sr(u8),
+    /// A signed immediate value.
+    Simm(i32),
+    /// An unsigned immediate value.
+    Uimm(u32),
+    /// A small number such as a shift amount or a mask bit.
+    Num(u32),
+    /// A memory operand made up of a displacement and a base register.
+    Offset { offset: i32, base: u8 },
+    /// The absolute address of a branch target.
+    Target(u32),
+}
+
+impl Operand {
+    /// Returns the name of a special-purpose register, if it has one.
+    pub fn spr_name(spr: u16) -> Option<&'static str> {
+        Some(match spr {
+            1 => "xer",
+            8 => "lr",
+            9 => "ctr",
+            18 => "dsisr",
+            19 => "dar",
+            22 => "dec",
+            25 => "sdr1",
+            26 => "srr0",
+            27 => "srr1",
+            272 => "sprg0",
+            273 => "sprg1",
+            274 => "sprg2",
+            275 => "sprg3",
+            282 => "ear",
+            284 => "tbl",
+            285 => "tbu",
+            287 => "pvr",
+            912 => "gqr0",
+            913 => "gqr1",
+            914 => "gqr2",
+            915 => "gqr3",
+            916 => "gqr4",
+            917 => "gqr5",
+            918 => "gqr6",
+            919 => "gqr7",
+            920 => "hid2",
+            921 => "wpar",
+            922 => "dma_u",
+            923 => "dma_l",
+            1008 => "hid0",
+            1009 => "hid1",
+            1010 => "iabr",
+            1013 => "dabr",
+            1017 => "l2cr",
+            _ => return None,
+        })
+    }
+}
+
+/// Formats a signed number in hexadecimal.
+fn fmt_signed_hex(f: &mut fmt::Formatter<'_>, value: i32) -> fmt::Result {
+    if value < 0 {
+        write!(f, "-{:#x}", value.unsigned_abs())
+    } else {
+        write!(f, "{:#x}", value)
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Gpr(r) => write!(f, "r{}", r),
+            Self::Fpr(r) => write!(f, "f{}", r),
+            Self::Cr(cr) => write!(f, "cr{}", cr),
+            Self::CrBit(bit) => match bit / 4 {
+                0 => f.write_str(CR_BITS[bit as usize % 4]),
+                cr => write!(f, "4*cr{}+{}", cr, CR_BITS[bit as usize % 4]),
+            },
+            Self::Spr(spr) => match Self::spr_name(spr) {
+                Some(name) => f.write_str(name),
+                None => write!(f, "{}", spr),
+            },
+            Self::Sr(sr) => write!(f, "{}", sr),
+            Self::Simm(value) => fmt_signed_hex(f, value),
+            Self::Uimm(value) => write!(f, "{:#x}", value),
+            Self::Num(value) => write!(f, "{}", value),
+            Self::Offset { offset, base } => {
+                fmt_signed_hex(f, offset)?;
+                write!(f, "(r{})", base)
+            }
+            Self::Target(address) => write!(f, "{:#x}", address),
+        }
+    }
+}
+
+/// A disassembled Gekko instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    /// The address the instruction is loaded at.
+    pub address: u32,
+    /// The raw instruction word.
+    pub code: u32,
+    /// The instruction's mnemonic. Simplified mnemonics (e.g. `li`, `mr`, `blr`) are used where
+    /// possible. Words which are not valid instructions use `.long`.
+    pub mnemonic: String,
+    /// The instruction's operands, in the order they are written in assembly.
+    pub operands: Vec<Operand>,
+}
+
+impl Instruction {
+    /// Disassembles the instruction word `code` located at `address`.
+    pub fn decode(address: u32, code: u32) -> Self {
+        let (mnemonic, operands) = Decoder { address, code }
+            .decode()
+            .unwrap_or_else(|| (".long".into(), vec![Uimm(code)]));
+        Self { address, code, mnemonic, operands }
+    }
+
+    /// Returns the address this instruction branches to, if it is a direct branch.
+    pub fn branch_target(&self) -> Option<u32> {
+        self.operands.iter().find_map(|op| match op {
+            Target(address) => Some(*address),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.operands.is_empty() {
+            return f.write_str(&self.mnemonic);
+        }
+        write!(f, "{:<9} ", self.mnemonic)?;
+        for (i, operand) in self.operands.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", operand)?;
+        }
+        Ok(())
+    }
+}
+
+/// Disassembles `len` bytes of code loaded at `address` in `dol`. The range must be inside a
+/// single section. `address` is rounded down to a multiple of 4.
+pub fn disassemble(dol: &Dol, address: u32, len: u32) -> Result<Vec<Instruction>> {
+    let start = address & !3;
+    let end = address.checked_add(len).and_then(|end| end.checked_next_multiple_of(4));
+    let end = end.ok_or(Error::UnmappedAddress(address))?;
+    let bytes = dol.read(start, end - start)?;
+    Ok(bytes
+        .chunks_exact(4)
+        .zip((start..).step_by(4))
+        .map(|(word, address)| Instruction::decode(address, BE::read_u32(word)))
+        .collect())
+}
+
+type Decoded = Option<(String, Vec<Operand>)>;
+
+/// Decodes the fields of an instruction word.
+struct Decoder {
+    address: u32,
+    code: u32,
+}
+
+impl Decoder {
+    fn bits(&self, start: u32, len: u32) -> u32 {
+        (self.code >> (32 - start - len)) & ((1 << len) - 1)
+    }
+
+    fn opcode(&self) -> u32 {
+        self.bits(0, 6)
+    }
+
+    fn d(&self) -> u8 {
+        self.bits(6, 5) as u8
+    }
+
+    fn a(&self) -> u8 {
+        self.bits(11, 5) as u8
+    }
+
+    fn b(&self) -> u8 {
+        self.bits(16, 5) as u8
+    }
+
+    fn c(&self) -> u8 {
+        self.bits(21, 5) as u8
+    }
+
+    fn crf_d(&self) -> u8 {
+        self.bits(6, 3) as u8
+    }
+
+    fn crf_s(&self) -> u8 {
+        self.bits(11, 3) as u8
+    }
+
+    fn simm(&self) -> i32 {
+        self.code as i16 as i32
+    }
+
+    fn uimm(&self) -> u32 {
+        self.code & 0xffff
+    }
+
+    fn rc(&self) -> bool {
+        self.code & 1 != 0
+    }
+
+    fn oe(&self) -> bool {
+        self.bits(21, 1) != 0
+    }
+
+    /// The 10-bit extended opcode of X-form instructions.
+    fn xo(&self) -> u32 {
+        self.bits(21, 10)
+    }
+
+    /// The 5-bit extended opcode of A-form instructions.
+    fn xo_a(&self) -> u32 {
+        self.bits(26, 5)
+    }
+
+    fn spr(&self) -> u16 {
+        (self.bits(11, 5) | (self.bits(16, 5) << 5)) as u16
+    }
+
+    fn offset(&self) -> Operand {
+        Offset { offset: self.simm(), base: self.a() }
+    }
+
+    /// Returns `name` with a `.` appended if the instruction records its result in CR0.
+    fn dot(&self, name: &str) -> String {
+        if self.rc() {
+            format!("{}.", name)
+        } else {
+            name.into()
+        }
+    }
+
+    fn decode(&self) -> Decoded {
+        let (d, a, b) = (self.d(), self.a(), self.b());
+        let ins = |name: &str, operands: Vec<Operand>| Some((name.to_owned(), operands));
+        match self.opcode() {
+            3 => ins("twi", vec![Num(d.into()), Gpr(a), Simm(self.simm())]),
+            4 => self.decode_paired(),
+            7 => ins("mulli", vec![Gpr(d), Gpr(a), Simm(self.simm())]),
+            8 => ins("subfic", vec![Gpr(d), Gpr(a), Simm(self.simm())]),
+            10 => ins("cmplwi", self.with_crf(vec![Gpr(a), Uimm(self.uimm())])),
+            11 => ins("cmpwi", self.with_crf(vec![Gpr(a), Simm(self.simm())])),
+            12 => ins("addic", vec![Gpr(d), Gpr(a), Simm(self.simm())]),
+            13 => ins("addic.", vec![Gpr(d), Gpr(a), Simm(self.simm())]),
+            14 if a == 0 => ins("li", vec![Gpr(d), Simm(self.simm())]),
+            14 => ins("addi", vec![Gpr(d), Gpr(a), Simm(self.simm())]),
+            15 if a == 0 => ins("lis", vec![Gpr(d), Uimm(self.uimm())]),
+            15 => ins("addis", vec![Gpr(d), Gpr(a), Uimm(self.uimm())]),
+            16 => {
+                let disp = (self.code & 0xfffc) as i16 as i32;
+                let aa = self.code & 2 != 0;
+                let target = if aa { disp as u32 } else { self.address.wrapping_add(disp as u32) };
+                let (mut name, mut operands) = self.branch_conditional("")?;
+                if aa {
+                    name.push('a');
+                }
+                operands.push(Target(target));
+                Some((name, operands))
+            }
+            17 if self.code & 2 != 0 => ins("sc", vec![]),
+            18 => {
+                let disp = ((self.code & 0x03fffffc) << 6) as i32 >> 6;
+                let aa = self.code & 2 != 0;
+                let target = if aa { disp as u32 } else { self.address.wrapping_add(disp as u32) };
+                let name = match (self.code & 1 != 0, aa) {
+                    (false, false) => "b",
+                    (true, false) => "bl",
+                    (false, true) => "ba",
+                    (true, true) => "bla",
+                };
+                ins(name, vec![Target(target)])
+            }
+            19 => self.decode_19(),
+            20 => Some((
+                self.dot("rlwimi"),
+                vec![Gpr(a), Gpr(d), Num(b.into()), Num(self.c().into()), Num(self.bits(26, 5))],
+            )),
+            21 => self.decode_rlwinm(),
+            23 => {
+                let (mb, me) = (self.c(), self.bits(26, 5));
+                if mb == 0 && me == 31 {
+                    Some((self.dot("rotlw"), vec![Gpr(a), Gpr(d), Gpr(b)]))
+                } else {
+                    Some((self.dot("rlwnm"), vec![Gpr(a), Gpr(d), Gpr(b), Num(mb.into()), Num(me)]))
+                }
+            }
+            24 if self.code == 0x60000000 => ins("nop", vec![]),
+            24 => ins("ori", vec![Gpr(a), Gpr(d), Uimm(self.uimm())]),
+            25 => ins("oris", vec![Gpr(a), Gpr(d), Uimm(self.uimm())]),
+            26 => ins("xori", vec![Gpr(a), Gpr(d), Uimm(self.uimm())]),
+            27 => ins("xoris", vec![Gpr(a), Gpr(d), Uimm(self.uimm())]),
+            28 => ins("andi.", vec![Gpr(a), Gpr(d), Uimm(self.uimm())]),
+            29 => ins("andis.", vec![Gpr(a), Gpr(d), Uimm(self.uimm())]),
+            31 => self.decode_31(),
+            op @ 32..=47 => {
+                let name = [
+                    "lwz", "lwzu", "lbz", "lbzu", "stw", "stwu", "stb", "stbu", "lhz", "lhzu",
+                    "lha", "lhau", "sth", "sthu", "lmw", "stmw",
+                ][op as usize - 32];
+                ins(name, vec![Gpr(d), self.offset()])
+            }
+            op @ 48..=55 => {
+                let name = ["lfs", "lfsu", "lfd", "lfdu", "stfs", "stfsu", "stfd", "stfdu"]
+                    [op as usize - 48];
+                ins(name, vec![Fpr(d), self.offset()])
+            }
+            op @ (56 | 57 | 60 | 61) => {
+                let name = match op {
+                    56 => "psq_l",
+                    57 => "psq_lu",
+                    60 => "psq_st",
+                    _ => "psq_stu",
+                };
+                let offset = ((self.code & 0xfff) << 20) as i32 >> 20;
+                ins(
+                    name,
+                    vec![
+                        Fpr(d),
+                        Offset { offset, base: a },
+                        Num(self.bits(16, 1)),
+                        Num(self.bits(17, 3)),
+                    ],
+                )
+            }
+            59 => self.decode_59(),
+            63 => self.decode_63(),
+            _ => None,
+        }
+    }
+
+    /// Prepends the CR field operand of a compare instruction if it is not CR0.
+    fn with_crf(&self, mut operands: Vec<Operand>) -> Vec<Operand> {
+        if self.crf_d() != 0 {
+            operands.insert(0, Cr(self.crf_d()));
+        }
+        operands
+    }
+
+    /// Builds the mnemonic and condition operands for a conditional branch. `suffix` is appended
+    /// to the base mnemonic before the link bit is applied.
+    fn branch_conditional(&self, suffix: &str) -> Decoded {
+        let (bo, bi) = (self.bits(6, 5), self.bits(11, 5));
+        let mut operands = vec![];
+        let mut name = if bo & 0x14 == 0x14 {
+            "b".to_owned()
+        } else if bo & 0x14 == 0x04 {
+            let (when_set, when_clear) = BRANCH_CONDITIONS[bi as usize % 4];
+            if bi >= 4 {
+                operands.push(Cr((bi / 4) as u8));
+            }
+            format!("b{}", if bo & 0x08 != 0 { when_set } else { when_clear })
+        } else if bo & 0x14 == 0x10 {
+            if bo & 0x02 != 0 { "bdz" } else { "bdnz" }.to_owned()
+        } else {
+            operands.push(Num(bo));
+            operands.push(CrBit(bi as u8));
+            "bc".to_owned()
+        };
+        name.push_str(suffix);
+        if self.code & 1 != 0 {
+            name.push('l');
+        }
+        Some((name, operands))
+    }
+
+    fn decode_19(&self) -> Decoded {
+        let (d, a, b) = (self.d(), self.a(), self.b());
+        let cr_op = |name: &str| Some((name.to_owned(), vec![CrBit(d), CrBit(a), CrBit(b)]));
+        match self.xo() {
+            0 => Some(("mcrf".into(), vec![Cr(self.crf_d()), Cr(self.crf_s())])),
+            16 => self.branch_conditional("lr"),
+            33 => cr_op("crnor"),
+            50 => Some(("rfi".into(), vec![])),
+            129 => cr_op("crandc"),
+            150 => Some(("isync".into(), vec![])),
+            193 => cr_op("crxor"),
+            225 => cr_op("crnand"),
+            257 => cr_op("crand"),
+            289 => cr_op("creqv"),
+            417 => cr_op("crorc"),
+            449 => cr_op("cror"),
+            528 => self.branch_conditional("ctr"),
+            _ => None,
+        }
+    }
+
+    fn decode_rlwinm(&self) -> Decoded {
+        let (s, a) = (Gpr(self.d()), Gpr(self.a()));
+        let (sh, mb, me) = (self.b() as u32, self.c() as u32, self.bits(26, 5));
+        let (name, operands) = if mb == 0 && me == 31 {
+            ("rotlwi", vec![a, s, Num(sh)])
+        } else if mb == 0 && me == 31 - sh {
+            ("slwi", vec![a, s, Num(sh)])
+        } else if me == 31 && sh == 32 - mb {
+            ("srwi", vec![a, s, Num(mb)])
+        } else if sh == 0 && me == 31 {
+            ("clrlwi", vec![a, s, Num(mb)])
+        } else if sh == 0 && mb == 0 {
+            ("clrrwi", vec![a, s, Num(31 - me)])
+        } else {
+            ("rlwinm", vec![a, s, Num(sh), Num(mb), Num(me)])
+        };
+        Some((self.dot(name), operands))
+    }
+
+    fn decode_31(&self) -> Decoded {
+        let (d, a, b) = (self.d(), self.a(), self.b());
+        let ins = |name: &str, operands: Vec<Operand>| Some((name.to_owned(), operands));
+        let indexed = |name: &str| ins(name, vec![Gpr(d), Gpr(a), Gpr(b)]);
+        let indexed_float = |name: &str| ins(name, vec![Fpr(d), Gpr(a), Gpr(b)]);
+        let logical = |name: &str| Some((self.dot(name), vec![Gpr(a), Gpr(d), Gpr(b)]));
+        let unary = |name: &str| Some((self.dot(name), vec![Gpr(a), Gpr(d)]));
+        let cache = |name: &str| ins(name, vec![Gpr(a), Gpr(b)]);
+        match self.xo() {
+            0 => ins("cmpw", self.with_crf(vec![Gpr(a), Gpr(b)])),
+            4 if d == 31 => ins("trap", vec![]),
+            4 => ins("tw", vec![Num(d.into()), Gpr(a), Gpr(b)]),
+            19 => ins("mfcr", vec![Gpr(d)]),
+            20 => indexed("lwarx"),
+            23 => indexed("lwzx"),
+            24 => logical("slw"),
+            26 => unary("cntlzw"),
+            28 => logical("and"),
+            32 => ins("cmplw", self.with_crf(vec![Gpr(a), Gpr(b)])),
+            54 => cache("dcbst"),
+            55 => indexed("lwzux"),
+            60 => logical("andc"),
+            83 => ins("mfmsr", vec![Gpr(d)]),
+            86 => cache("dcbf"),
+            87 => indexed("lbzx"),
+            119 => indexed("lbzux"),
+            124 if d == b => unary("not"),
+            124 => logical("nor"),
+            144 => {
+                let crm = self.bits(12, 8);
+                if crm == 0xff {
+                    ins("mtcr", vec![Gpr(d)])
+                } else {
+                    ins("mtcrf", vec![Uimm(crm), Gpr(d)])
+                }
+            }
+            146 => ins("mtmsr", vec![Gpr(d)]),
+            150 => indexed("stwcx."),
+            151 => indexed("stwx"),
+            183 => indexed("stwux"),
+            210 => ins("mtsr", vec![Sr(self.bits(12, 4) as u8), Gpr(d)]),
+            215 => indexed("stbx"),
+            242 => ins("mtsrin", vec![Gpr(d), Gpr(b)]),
+            246 => cache("dcbtst"),
+            247 => indexed("stbux"),
+            278 => cache("dcbt"),
+            279 => indexed("lhzx"),
+            284 => logical("eqv"),
+            306 => ins("tlbie", vec![Gpr(b)]),
+            310 => indexed("eciwx"),
+            311 => indexed("lhzux"),
+            316 => logical("xor"),
+            339 => match self.spr() {
+                1 => ins("mfxer", vec![Gpr(d)]),
+                8 => ins("mflr", vec![Gpr(d)]),
+                9 => ins("mfctr", vec![Gpr(d)]),
+                spr => ins("mfspr", vec![Gpr(d), Spr(spr)]),
+            },
+            343 => indexed("lhax"),
+            370 => ins("tlbia", vec![]),
+            371 => match self.spr() {
+                268 => ins("mftb", vec![Gpr(d)]),
+                269 => ins("mftbu", vec![Gpr(d)]),
+                tbr => ins("mftb", vec![Gpr(d), Num(tbr.into())]),
+            },
+            375 => indexed("lhaux"),
+            407 => indexed("sthx"),
+            412 => logical("orc"),
+            438 => indexed("ecowx"),
+            439 => indexed("sthux"),
+            444 if d == b => unary("mr"),
+            444 => logical("or"),
+            467 => match self.spr() {
+                1 => ins("mtxer", vec![Gpr(d)]),
+                8 => ins("mtlr", vec![Gpr(d)]),
+                9 => ins("mtctr", vec![Gpr(d)]),
+                spr => ins("mtspr", vec![Spr(spr), Gpr(d)]),
+            },
+            470 => cache("dcbi"),
+            476 => logical("nand"),
+            512 => ins("mcrxr", vec![Cr(self.crf_d())]),
+            533 => indexed("lswx"),
+            534 => indexed("lwbrx"),
+            535 => indexed_float("lfsx"),
+            536 => logical("srw"),
+            566 => ins("tlbsync", vec![]),
+            567 => indexed_float("lfsux"),
+            595 => ins("mfsr", vec![Gpr(d), Sr(self.bits(12, 4) as u8)]),
+            597 => ins("lswi", vec![Gpr(d), Gpr(a), Num(b.into())]),
+            598 => ins("sync", vec![]),
+            599 => indexed_float("lfdx"),
+            631 => indexed_float("lfdux"),
+            659 => ins("mfsrin", vec![Gpr(d), Gpr(b)]),
+            661 => indexed("stswx"),
+            662 => indexed("stwbrx"),
+            663 => indexed_float("stfsx"),
+            695 => indexed_float("stfsux"),
+            725 => ins("stswi", vec![Gpr(d), Gpr(a), Num(b.into())]),
+            727 => indexed_float("stfdx"),
+            759 => indexed_float("stfdux"),
+            790 => indexed("lhbrx"),
+            792 => logical("sraw"),
+            824 => Some((self.dot("srawi"), vec![Gpr(a), Gpr(d), Num(b.into())])),
+            854 => ins("eieio", vec![]),
+            918 => indexed("sthbrx"),
+            922 => unary("extsh"),
+            954 => unary("extsb"),
+            982 => cache("icbi"),
+            983 => indexed_float("stfiwx"),
+            1014 => cache("dcbz"),
+            _ => self.decode_31_arithmetic(),
+        }
+    }
+
+    /// Decodes XO-form integer arithmetic instructions, which have an overflow-enable bit.
+    fn decode_31_arithmetic(&self) -> Decoded {
+        let (d, a, b) = (self.d(), self.a(), self.b());
+        let (name, unary, has_oe) = match self.bits(22, 9) {
+            8 => ("subfc", false, true),
+            10 => ("addc", false, true),
+            11 => ("mulhwu", false, false),
+            40 => ("subf", false, true),
+            75 => ("mulhw", false, false),
+            104 => ("neg", true, true),
+            136 => ("subfe", false, true),
+            138 => ("adde", false, true),
+            200 => ("subfze", true, true),
+            202 => ("addze", true, true),
+            232 => ("subfme", true, true),
+            234 => ("addme", true, true),
+            235 => ("mullw", false, true),
+            266 => ("add", false, true),
+            459 => ("divwu", false, true),
+            491 => ("divw", false, true),
+            _ => return None,
+        };
+        if self.oe() && !has_oe {
+            return None;
+        }
+        let name = if self.oe() { format!("{}o", name) } else { name.to_owned() };
+        let operands = if unary { vec![Gpr(d), Gpr(a)] } else { vec![Gpr(d), Gpr(a), Gpr(b)] };
+        Some((self.dot(&name), operands))
+    }
+
+    /// Decodes the A-form floating-point instructions shared by opcodes 4, 59, and 63.
+    fn decode_float_arithmetic(&self, name: &str, xo: u32) -> Decoded {
+        let (d, a, b, c) = (Fpr(self.d()), Fpr(self.a()), Fpr(self.b()), Fpr(self.c()));
+        let operands = match xo {
+            // Divide, subtract, add
+            18 | 20 | 21 => vec![d, a, b],
+            // Reciprocal estimates
+            24 | 26 => vec![d, b],
+            // Multiply
+            25 => vec![d, a, c],
+            // Select and multiply-add
+            23 | 28..=31 => vec![d, a, c, b],
+            _ => return None,
+        };
+        Some((self.dot(name), operands))
+    }
+
+    fn decode_59(&self) -> Decoded {
+        let xo = self.xo_a();
+        let name = match xo {
+            18 => "fdivs",
+            20 => "fsubs",
+            21 => "fadds",
+            24 => "fres",
+            25 => "fmuls",
+            28 => "fmsubs",
+            29 => "fmadds",
+            30 => "fnmsubs",
+            31 => "fnmadds",
+            _ => return None,
+        };
+        self.decode_float_arithmetic(name, xo)
+    }
+
+    fn decode_63(&self) -> Decoded {
+        let xo = self.xo_a();
+        let name = match xo {
+            18 => Some("fdiv"),
+            20 => Some("fsub"),
+            21 => Some("fadd"),
+            23 => Some("fsel"),
+            25 => Some("fmul"),
+            26 => Some("frsqrte"),
+            28 => Some("fmsub"),
+            29 => Some("fmadd"),
+            30 => Some("fnmsub"),
+            31 => Some("fnmadd"),
+            _ => None,
+        };
+        if let Some(name) = name {
+            return self.decode_float_arithmetic(name, xo);
+        }
+
+        let (d, a, b) = (self.d(), self.a(), self.b());
+        let compare = |name: &str| Some((name.to_owned(), vec![Cr(self.crf_d()), Fpr(a), Fpr(b)]));
+        let unary = |name: &str| Some((self.dot(name), vec![Fpr(d), Fpr(b)]));
+        match self.xo() {
+            0 => compare("fcmpu"),
+            12 => unary("frsp"),
+            14 => unary("fctiw"),
+            15 => unary("fctiwz"),
+            32 => compare("fcmpo"),
+            38 => Some((self.dot("mtfsb1"), vec![CrBit(d)])),
+            40 => unary("fneg"),
+            64 => Some(("mcrfs".into(), vec![Cr(self.crf_d()), Cr(self.crf_s())])),
+            70 => Some((self.dot("mtfsb0"), vec![CrBit(d)])),
+            72 => unary("fmr"),
+            134 => Some((self.dot("mtfsfi"), vec![Cr(self.crf_d()), Num(self.bits(16, 4))])),
+            136 => unary("fnabs"),
+            264 => unary("fabs"),
+            583 => Some((self.dot("mffs"), vec![Fpr(d)])),
+            711 => Some((self.dot("mtfsf"), vec![Uimm(self.bits(7, 8)), Fpr(b)])),
+            _ => None,
+        }
+    }
+
+    /// Decodes the Gekko's paired-single instructions.
+    fn decode_paired(&self) -> Decoded {
+        let xo = self.xo_a();
+        let name = match xo {
+            18 => Some("ps_div"),
+            20 => Some("ps_sub"),
+            21 => Some("ps_add"),
+            23 => Some("ps_sel"),
+            24 => Some("ps_res"),
+            25 => Some("ps_mul"),
+            26 => Some("ps_rsqrte"),
+            28 => Some("ps_msub"),
+            29 => Some("ps_madd"),
+            30 => Some("ps_nmsub"),
+            31 => Some("ps_nmadd"),
+            _ => None,
+        };
+        if let Some(name) = name {
+            return self.decode_float_arithmetic(name, xo);
+        }
+
+        let (d, a, b, c) = (Fpr(self.d()), Fpr(self.a()), Fpr(self.b()), Fpr(self.c()));
+        let ps = |name: &str, operands: Vec<Operand>| Some((self.dot(name), operands));
+        match xo {
+            10 => return ps("ps_sum0", vec![d, a, c, b]),
+            11 => return ps("ps_sum1", vec![d, a, c, b]),
+            12 => return ps("ps_muls0", vec![d, a, c]),
+            13 => return ps("ps_muls1", vec![d, a, c]),
+            14 => return ps("ps_madds0", vec![d, a, c, b]),
+            15 => return ps("ps_madds1", vec![d, a, c, b]),
+            _ => (),
+        }
+
+        let indexed_name = match self.bits(25, 6) {
+            6 => Some("psq_lx"),
+            7 => Some("psq_stx"),
+            38 => Some("psq_lux"),
+            39 => Some("psq_stux"),
+            _ => None,
+        };
+        if let Some(name) = indexed_name {
+            let operands =
+                vec![d, Gpr(self.a()), Gpr(self.b()), Num(self.bits(21, 1)), Num(self.bits(22, 3))];
+            return Some((name.to_owned(), operands));
+        }
+
+        let compare = |name: &str| Some((name.to_owned(), vec![Cr(self.crf_d()), a, b]));
+        match self.xo() {
+            0 => compare("ps_cmpu0"),
+            32 => compare("ps_cmpo0"),
+            40 => ps("ps_neg", vec![d, b]),
+            64 => compare("ps_cmpu1"),
+            72 => ps("ps_mr", vec![d, b]),
+            96 => compare("ps_cmpo1"),
+            136 => ps("ps_nabs", vec![d, b]),
+            264 => ps("ps_abs", vec![d, b]),
+            528 => ps("ps_merge00", vec![d, a, b]),
+            560 => ps("ps_merge01", vec![d, a, b]),
+            592 => ps("ps_merge10", vec![d, a, b]),
+            624 => ps("ps_merge11", vec![d, a, b]),
+            1014 => Some(("dcbz_l".into(), vec![Gpr(self.a()), Gpr(self.b())])),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dvd::dol::DolSection;
+
+    fn disasm(address: u32, code: u32) -> String {
+        Instruction::decode(address, code).to_string()
+    }
+
+    #[test]
+    fn test_integer() {
+        assert_eq!(disasm(0, 0x7c0802a6), "mflr      r0");
+        assert_eq!(disasm(0, 0x9421ffe0), "stwu      r1, -0x20(r1)");
+        assert_eq!(disasm(0, 0x93e1001c), "stw       r31, 0x1c(r1)");
+        assert_eq!(disasm(0, 0x7c7f1b78), "mr        r31, r3");
+        assert_eq!(disasm(0, 0x38600001), "li        r3, 0x1");
+        assert_eq!(disasm(0, 0x3c608021), "lis       r3, 0x8021");
+        assert_eq!(disasm(0, 0x3863c70c), "addi      r3, r3, -0x38f4");
+        assert_eq!(disasm(0, 0x2c030000), "cmpwi     r3, 0x0");
+        assert_eq!(disasm(0, 0x2b830005), "cmplwi    cr7, r3, 0x5");
+        assert_eq!(disasm(0, 0x7c032000), "cmpw      r3, r4");
+        assert_eq!(disasm(0, 0x60000000), "nop");
+        assert_eq!(disasm(0, 0x7c632214), "add       r3, r3, r4");
+        assert_eq!(disasm(0, 0x7c632615), "addo.     r3, r3, r4");
+        assert_eq!(disasm(0, 0x7c6300d0), "neg       r3, r3");
+        assert_eq!(disasm(0, 0x7c0903a6), "mtctr     r0");
+        assert_eq!(disasm(0, 0x7c70fba6), "mtspr     hid0, r3");
+        assert_eq!(disasm(0, 0x7c72e2a6), "mfspr     r3, gqr2");
+        assert_eq!(disasm(0, 0x5463103a), "slwi      r3, r3, 2");
+        assert_eq!(disasm(0, 0x5463f87e), "srwi      r3, r3, 1");
+        assert_eq!(disasm(0, 0x5463063e), "clrlwi    r3, r3, 24");
+        assert_eq!(disasm(0, 0x54630ffe), "srwi      r3, r3, 31");
+        assert_eq!(disasm(0, 0x546307bc), "rlwinm    r3, r3, 0, 30, 30");
+        assert_eq!(disasm(0, 0x5060442e), "rlwimi    r0, r3, 8, 16, 23");
+        assert_eq!(disasm(0, 0x7c630775), "extsb.    r3, r3");
+        assert_eq!(disasm(0, 0x7c0004ac), "sync");
+        assert_eq!(disasm(0, 0x7c001fec), "dcbz      r0, r3");
+        assert_eq!(disasm(0, 0x7fe00008), "trap");
+    }
+
+    #[test]
+    fn test_branches() {
+        assert_eq!(disasm(0x80003100, 0x4e800020), "blr");
+        assert_eq!(disasm(0x80003100, 0x4e800421), "bctrl");
+        assert_eq!(disasm(0x80003100, 0x48000101), "bl        0x80003200");
+        assert_eq!(disasm(0x80003100, 0x4bffff00), "b         0x80003000");
+        assert_eq!(disasm(0x80003100, 0x48000012), "ba        0x10");
+        assert_eq!(disasm(0x80003100, 0x41820010), "beq       0x80003110");
+        assert_eq!(disasm(0x80003100, 0x409efff0), "bne       cr7, 0x800030f0");
+        assert_eq!(disasm(0x80003100, 0x4180000c), "blt       0x8000310c");
+        assert_eq!(disasm(0x80003100, 0x4200fff0), "bdnz      0x800030f0");
+        assert_eq!(disasm(0x80003100, 0x4d820020), "beqlr");
+        assert_eq!(disasm(0x80003100, 0x4c800420), "bgectr");
+        assert_eq!(disasm(0x80003100, 0x4cc63182), "crxor     4*cr1+eq, 4*cr1+eq, 4*cr1+eq");
+        assert_eq!(Instruction::decode(0x80003100, 0x48000101).branch_target(), Some(0x80003200));
+        assert_eq!(Instruction::decode(0x80003100, 0x4e800020).branch_target(), None);
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(disasm(0, 0xc02d8000), "lfs       f1, -0x8000(r13)");
+        assert_eq!(disasm(0, 0xfc200890), "fmr       f1, f1");
+        assert_eq!(disasm(0, 0xec2100b2), "fmuls     f1, f1, f2");
+        assert_eq!(disasm(0, 0xec22183a), "fmadds    f1, f2, f0, f3");
+        assert_eq!(disasm(0, 0xfc011000), "fcmpu     cr0, f1, f2");
+        assert_eq!(disasm(0, 0xfc00001e), "fctiwz    f0, f0");
+        assert_eq!(disasm(0, 0xfc20082a), "fadd      f1, f0, f1");
+    }
+
+    #[test]
+    fn test_paired_singles() {
+        assert_eq!(disasm(0, 0xe0230008), "psq_l     f1, 0x8(r3), 0, 0");
+        assert_eq!(disasm(0, 0xf0238ffc), "psq_st    f1, -0x4(r3), 1, 0");
+        assert_eq!(disasm(0, 0x1022102a), "ps_add    f1, f2, f2");
+        assert_eq!(disasm(0, 0x10220032), "ps_mul    f1, f2, f0");
+        assert_eq!(disasm(0, 0x102200d4), "ps_sum0   f1, f2, f3, f0");
+        assert_eq!(disasm(0, 0x10221420), "ps_merge00 f1, f2, f2");
+        assert_eq!(disasm(0, 0x10200090), "ps_mr     f1, f0");
+        assert_eq!(disasm(0, 0x1002c70c), "psq_lx    f0, r2, r24, 1, 6");
+        assert_eq!(disasm(0, 0x100307ec), "dcbz_l    r3, r0");
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(disasm(0, 0), ".long     0x0");
+        assert_eq!(disasm(0, 0xfc00002c), ".long     0xfc00002c");
+        assert_eq!(disasm(0, 0x7c00002c), ".long     0x7c00002c");
+    }
+
+    #[test]
+    fn test_disassemble_dol() -> Result<()> {
+        let mut dol = Dol::new();
+        let code = [0x94, 0x21, 0xff, 0xe0, 0x4e, 0x80, 0x00, 0x20];
+        dol.text[0] = Some(DolSection::new(0x80003100, code.to_vec()));
+        let instructions = disassemble(&dol, 0x80003102, 6)?;
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].address, 0x80003100);
+        assert_eq!(instructions[0].code, 0x9421ffe0);
+        assert_eq!(instructions[1].mnemonic, "blr");
+        assert!(disassemble(&dol, 0x80003100, 0xc).is_err());
+        Ok(())
+    }
+}
@@ -1,25 +1,27 @@
+mod builder;
+mod header;
+
+pub use builder::DiscBuilder;
+pub use header::{ApploaderHeader, DiscHeader, DiscHeaderInfo, RegionCode};
+
+use header::{APPLOADER_HEADER_SIZE, BI2_SIZE};
+
 use super::banner::{self, Banner};
-use super::dol::{self, DolHeader};
+use super::dol::{self, Dol, DolHeader};
 use super::fst::{
-    self, EditFile, Entry, EntryId, FileStringTable, FileTree, FstEntryKind, OpenFile,
+    self, DirectoryEntry, EditFile, Entry, EntryId, FileEntry, FileStringTable, FileTree,
+    FstEntryKind, OpenFile,
 };
-use crate::common::io::{copy_within, fill};
-use crate::common::{
-    self, text, FixedText, ReadFrom, ReadSeek, ReadStructExt, ReadWriteSeek, Region,
-    WriteStructExt, WriteTo,
-};
-use byteorder::{ReadBytesExt, WriteBytesExt, BE};
-use encoding_rs::mem::decode_latin1;
+use crate::common::io::{copy_buffered, copy_within, fill, BUFFER_SIZE};
+use crate::common::{self, text, ReadSeek, ReadStructExt, ReadWriteSeek, Region, WriteStructExt};
 use std::cmp;
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::io::{self, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
 use thiserror::Error;
 use tracing::{debug, trace};
 
-const GCN_MAGIC: u32 = 0xc2339f3d;
-const WII_MAGIC: u32 = 0x5d1c9ea3;
-
 /// Size of the game name in the disc header.
 const GAME_NAME_SIZE: usize = 0x3e0;
 
@@ -28,6 +30,15 @@ const GAME_NAME_SIZE: usize = 0x3e0;
 // Aligning files also leaves some room for adjacent files to grow
 const DVD_OPTIMAL_ALIGN: u32 = 0x8000;
 
+/// Alignment of files which are packed together by `DiscStream::compact()`.
+const COMPACT_ALIGN: u32 = 4;
+
+/// Offset of the disc header information (bi2.bin).
+const BI2_OFFSET: u64 = 0x440;
+
+/// Offset of the apploader (apploader.img).
+const APPLOADER_OFFSET: u64 = 0x2440;
+
 /// Path to the banner file that the GameCube looks for.
 const BANNER_PATH: &str = "opening.bnr";
 
@@ -47,9 +58,21 @@ pub enum Error {
     #[error("not enough space available in disc (need {0:#x} bytes)")]
     NotEnoughSpace(u32),
 
+    #[error("disc data is too large (offset {0:#x} is past 4 GiB)")]
+    OffsetOverflow(u64),
+
     #[error("Wii DVDs are not supported")]
     WiiNotSupported,
 
+    #[error("invalid apploader")]
+    InvalidApploader,
+
+    #[error("invalid main.dol")]
+    InvalidDol,
+
+    #[error("invalid game ID: {0:?}")]
+    InvalidGameId(String),
+
     #[error(transparent)]
     Banner(Box<banner::Error>),
 
@@ -72,107 +95,14 @@ from_error_boxed!(Error::Dol, dol::Error);
 from_error_boxed!(Error::Io, io::Error);
 from_error_boxed!(Error::Text, text::Error);
 
-#[derive(Debug, Clone, Default, PartialEq, Eq)]
-struct DiscHeader {
-    game_code: [u8; 4],
-    maker_code: [u8; 2],
-    disc_id: u8,
-    version: u8,
-    audio_streaming: u8,
-    stream_buffer_size: u8,
-    unused_00a: [u8; 0x0e],
-    wii_magic: u32,
-    gcn_magic: u32,
-    game_name: FixedText<GAME_NAME_SIZE>,
-    debug_monitor_offset: u32,
-    debug_monitor_addr: u32,
-    unused_408: [u8; 0x18],
-    dol_offset: u32,
-    fst_offset: u32,
-    fst_size: u32,
-    fst_max_size: u32,
-    user_position: u32,
-    user_size: u32,
-    disc_size: u32,
-    unused_43c: u32,
-}
-
-impl DiscHeader {
-    /// Constructs a new `DiscHeader` with all fields initialized to zero.
-    fn new() -> Self {
-        Self::default()
-    }
-}
-
-impl<R: Read + ?Sized> ReadFrom<R> for DiscHeader {
-    type Error = Error;
-    fn read_from(reader: &mut R) -> Result<Self> {
-        let mut header = Self::new();
-        reader.read_exact(&mut header.game_code)?;
-        reader.read_exact(&mut header.maker_code)?;
-        header.disc_id = reader.read_u8()?;
-        header.version = reader.read_u8()?;
-        header.audio_streaming = reader.read_u8()?;
-        header.stream_buffer_size = reader.read_u8()?;
-        reader.read_exact(&mut header.unused_00a)?;
-        header.wii_magic = reader.read_u32::<BE>()?;
-        if header.wii_magic == WII_MAGIC {
-            return Err(Error::WiiNotSupported);
-        }
-        header.gcn_magic = reader.read_u32::<BE>()?;
-        if header.gcn_magic != GCN_MAGIC {
-            return Err(Error::InvalidMagic);
-        }
-        header.game_name = reader.read_struct()?;
-        header.debug_monitor_offset = reader.read_u32::<BE>()?;
-        header.debug_monitor_addr = reader.read_u32::<BE>()?;
-        reader.read_exact(&mut header.unused_408)?;
-        header.dol_offset = reader.read_u32::<BE>()?;
-        header.fst_offset = reader.read_u32::<BE>()?;
-        header.fst_size = reader.read_u32::<BE>()?;
-        header.fst_max_size = reader.read_u32::<BE>()?;
-        header.user_position = reader.read_u32::<BE>()?;
-        header.user_size = reader.read_u32::<BE>()?;
-        header.disc_size = reader.read_u32::<BE>()?;
-        header.unused_43c = reader.read_u32::<BE>()?;
-        Ok(header)
-    }
-}
-
-impl<W: Write + ?Sized> WriteTo<W> for DiscHeader {
-    type Error = Error;
-    fn write_to(&self, writer: &mut W) -> Result<()> {
-        writer.write_all(&self.game_code)?;
-        writer.write_all(&self.maker_code)?;
-        writer.write_u8(self.disc_id)?;
-        writer.write_u8(self.version)?;
-        writer.write_u8(self.audio_streaming)?;
-        writer.write_u8(self.stream_buffer_size)?;
-        writer.write_all(&self.unused_00a)?;
-        writer.write_u32::<BE>(self.wii_magic)?;
-        writer.write_u32::<BE>(self.gcn_magic)?;
-        writer.write_struct(&self.game_name)?;
-        writer.write_u32::<BE>(self.debug_monitor_offset)?;
-        writer.write_u32::<BE>(self.debug_monitor_addr)?;
-        writer.write_all(&self.unused_408)?;
-        writer.write_u32::<BE>(self.dol_offset)?;
-        writer.write_u32::<BE>(self.fst_offset)?;
-        writer.write_u32::<BE>(self.fst_size)?;
-        writer.write_u32::<BE>(self.fst_max_size)?;
-        writer.write_u32::<BE>(self.user_position)?;
-        writer.write_u32::<BE>(self.user_size)?;
-        writer.write_u32::<BE>(self.disc_size)?;
-        writer.write_u32::<BE>(self.unused_43c)?;
-        Ok(())
-    }
-}
-
 /// A stream for reading and manipulating GameCube DVD data (e.g. a .iso).
 pub struct DiscStream<S: ReadSeek> {
     header: Box<DiscHeader>,
     free_regions: Vec<DiscRegion>,
     stream: S,
     files: FileTree,
+    /// The region containing main.dol if it has been moved after the FST.
+    relocated_dol: Option<DiscRegion>,
 }
 
 impl<S: ReadSeek> DiscStream<S> {
@@ -185,9 +115,21 @@ impl<S: ReadSeek> DiscStream<S> {
         debug!("Found FST at {:#x} (size = {:#x})", header.fst_offset, header.fst_size);
         stream.seek(SeekFrom::Start(header.fst_offset as u64))?;
         let fst = stream.by_ref().take(header.fst_size as u64).read_struct::<FileStringTable>()?;
-        let free_regions = Self::find_free_regions(&header, &fst);
+        let relocated_dol = Self::find_relocated_dol(&mut stream, &header)?;
+        let free_regions = Self::find_free_regions(&header, &fst, relocated_dol);
         let files = FileTree::from_fst(&fst)?;
-        Ok(Self { header: header.into(), free_regions, stream: stream.into_inner(), files })
+        Ok(Self {
+            header: header.into(),
+            free_regions,
+            stream: stream.into_inner(),
+            files,
+            relocated_dol,
+        })
+    }
+
+    /// Consumes this `DiscStream` and returns the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
     }
 
     /// Returns a reference to the inner file tree.
@@ -197,10 +139,7 @@ impl<S: ReadSeek> DiscStream<S> {
 
     /// Returns the disc's game ID (e.g. "GGTE01").
     pub fn game_id(&self) -> String {
-        let mut game_id = [0u8; 6];
-        game_id[..4].copy_from_slice(&self.header.game_code);
-        game_id[4..].copy_from_slice(&self.header.maker_code);
-        decode_latin1(&game_id).into()
+        self.header.game_id()
     }
 
     /// Returns the disc's game name.
@@ -208,6 +147,39 @@ impl<S: ReadSeek> DiscStream<S> {
         self.header.game_name.decode_replacing().into_owned()
     }
 
+    /// Returns the disc header (boot.bin).
+    pub fn header(&self) -> &DiscHeader {
+        &self.header
+    }
+
+    /// Reads the disc header information (bi2.bin).
+    pub fn read_header_info(&mut self) -> Result<DiscHeaderInfo> {
+        self.stream.seek(SeekFrom::Start(BI2_OFFSET))?;
+        BufReader::new(&mut self.stream).read_struct()
+    }
+
+    /// Reads the apploader's header.
+    pub fn read_apploader_header(&mut self) -> Result<ApploaderHeader> {
+        self.stream.seek(SeekFrom::Start(APPLOADER_OFFSET))?;
+        self.stream.read_struct()
+    }
+
+    /// Returns a stream that can be used to read the disc header (boot.bin).
+    pub fn open_boot(&mut self) -> Result<Box<dyn ReadSeek + '_>> {
+        Ok(Box::new(Region::new(&mut self.stream, 0, BI2_OFFSET)))
+    }
+
+    /// Returns a stream that can be used to read the disc header information (bi2.bin).
+    pub fn open_bi2(&mut self) -> Result<Box<dyn ReadSeek + '_>> {
+        Ok(Box::new(Region::new(&mut self.stream, BI2_OFFSET, BI2_SIZE as u64)))
+    }
+
+    /// Returns a stream that can be used to read the apploader (apploader.img).
+    pub fn open_apploader(&mut self) -> Result<Box<dyn ReadSeek + '_>> {
+        let len = self.read_apploader_header()?.total_size();
+        Ok(Box::new(Region::new(&mut self.stream, APPLOADER_OFFSET, len)))
+    }
+
     /// Returns the DOL header and a stream that can be used to read the main.dol file.
     pub fn open_dol(&mut self) -> Result<(DolHeader, Box<dyn ReadSeek + '_>)> {
         let start = self.header.dol_offset as u64;
@@ -218,6 +190,12 @@ impl<S: ReadSeek> DiscStream<S> {
         Ok((header, region))
     }
 
+    /// Reads the disc's main.dol.
+    pub fn read_dol(&mut self) -> Result<Dol> {
+        let (_, reader) = self.open_dol()?;
+        Ok(BufReader::new(reader).read_struct()?)
+    }
+
     /// Returns a list of unused areas in the disc sorted by offset.
     pub fn free_regions(&self) -> &[DiscRegion] {
         &self.free_regions
@@ -233,6 +211,54 @@ impl<S: ReadSeek> DiscStream<S> {
         self.header.user_size + self.header.disc_size
     }
 
+    /// Returns the offset of the end of the last in-use region on the disc.
+    pub fn used_end(&self) -> u32 {
+        match self.free_regions.last() {
+            Some(r) if r.end() == self.total_size() => r.offset,
+            _ => self.total_size(),
+        }
+    }
+
+    /// Writes a copy of the disc to `writer` with every free region filled with zeros. If `trim`
+    /// is true, the copy ends at `used_end()` instead of being padded out to the full disc size.
+    /// Returns the number of bytes written.
+    pub fn write_scrubbed(&mut self, mut writer: impl Write, trim: bool) -> Result<u64> {
+        let end = if trim { self.used_end() } else { self.total_size() };
+        let mut buf = vec![0u8; BUFFER_SIZE].into_boxed_slice();
+        let mut offset = 0;
+        for free in &self.free_regions {
+            if free.offset >= end {
+                break;
+            }
+            Self::copy_used(&mut self.stream, &mut writer, offset, free.offset, &mut buf)?;
+            let free_end = cmp::min(free.end(), end);
+            trace!("Scrubbing {:#x} bytes at {:#x}", free_end - free.offset, free.offset);
+            fill(&mut writer, 0, (free_end - free.offset) as u64)?;
+            offset = free_end;
+        }
+        Self::copy_used(&mut self.stream, &mut writer, offset, end, &mut buf)?;
+        Ok(end as u64)
+    }
+
+    /// Copies the data in `stream` between `start` and `end` to `writer`.
+    fn copy_used(
+        stream: &mut S,
+        writer: &mut impl Write,
+        start: u32,
+        end: u32,
+        buf: &mut [u8],
+    ) -> Result<()> {
+        if start >= end {
+            return Ok(());
+        }
+        let len = (end - start) as u64;
+        stream.seek(SeekFrom::Start(start as u64))?;
+        if copy_buffered(&mut stream.take(len), writer, buf)? != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(())
+    }
+
     /// Reads the disc's banner file.
     pub fn read_banner(&mut self) -> Result<Banner> {
         let mut reader = BufReader::new(self.open_file_at(BANNER_PATH)?);
@@ -279,8 +305,16 @@ impl<S: ReadSeek> DiscStream<S> {
     ///
     /// The returned region may be larger than the requested size if more free space is available.
     pub fn allocate(&self, size: u32, align: u32) -> Result<DiscRegion> {
-        self.free_regions
-            .iter()
+        Self::allocate_from(self.free_regions.iter().copied(), size, align)
+    }
+
+    /// Like `allocate()`, but searches `regions` instead of the disc's free list.
+    fn allocate_from(
+        regions: impl Iterator<Item = DiscRegion>,
+        size: u32,
+        align: u32,
+    ) -> Result<DiscRegion> {
+        regions
             .filter_map(|r| {
                 let aligned_offset = common::align(r.offset, align);
                 let padding = aligned_offset - r.offset;
@@ -299,7 +333,26 @@ impl<S: ReadSeek> DiscStream<S> {
     }
 
     /// Calculates a list of unused areas in the disc sorted by offset.
-    fn find_free_regions(header: &DiscHeader, fst: &FileStringTable) -> Vec<DiscRegion> {
+    /// Returns the region containing main.dol if it is stored after the FST. Normally it comes
+    /// before the FST, but `write_dol()` moves it if it grows too large.
+    fn find_relocated_dol(
+        reader: &mut (impl Read + Seek),
+        header: &DiscHeader,
+    ) -> Result<Option<DiscRegion>> {
+        if header.dol_offset < header.fst_offset + header.fst_size {
+            return Ok(None);
+        }
+        reader.seek(SeekFrom::Start(header.dol_offset as u64))?;
+        let dol_header = reader.read_struct::<DolHeader>()?;
+        debug!("Found relocated main.dol at {:#x}", header.dol_offset);
+        Ok(Some(DiscRegion::new(header.dol_offset, dol_header.file_size())))
+    }
+
+    fn find_free_regions(
+        header: &DiscHeader,
+        fst: &FileStringTable,
+        relocated_dol: Option<DiscRegion>,
+    ) -> Vec<DiscRegion> {
         // Collect all of the regions used by each file
         let mut used_regions: Vec<_> = fst
             .entries
@@ -310,6 +363,7 @@ impl<S: ReadSeek> DiscStream<S> {
 
         // Assume that everything up to and including the FST is unusable
         used_regions.push(DiscRegion::new(0, header.fst_offset + header.fst_size));
+        used_regions.extend(relocated_dol);
 
         // Add a zero-size region for the end of the disc so that we cover free space at the end
         let disc_end = header.user_size + header.disc_size;
@@ -440,6 +494,189 @@ impl<S: ReadWriteSeek> DiscStream<S> {
         self.replace_file(self.files.at(path)?, reader)
     }
 
+    /// Creates a new file named `name` in the `parent` directory and fills it with the contents of
+    /// `reader`. Returns the ID of the new file.
+    pub fn create_file(
+        &mut self,
+        parent: EntryId,
+        name: &str,
+        reader: impl Read + Seek,
+    ) -> Result<EntryId> {
+        // Start with an empty file so that the FST has room to grow before we allocate any data
+        debug!("Creating file {}", name);
+        let entry = FileEntry::new(name, 0, 0);
+        let id = self.update_file_tree(|files| files.try_insert(parent, entry.into()))?;
+        if let Err(e) = self.replace_file(id, reader) {
+            self.update_file_tree(|files| files.remove(id))?;
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    /// Creates a new file at `path` and fills it with the contents of `reader`. The parent
+    /// directory must already exist. Returns the ID of the new file.
+    pub fn create_file_at(&mut self, path: &str, reader: impl Read + Seek) -> Result<EntryId> {
+        let (parent, name) = self.files.parent_and_name(path)?;
+        self.create_file(parent, name, reader)
+    }
+
+    /// Creates a new empty directory named `name` in the `parent` directory and returns its ID.
+    pub fn create_dir(&mut self, parent: EntryId, name: &str) -> Result<EntryId> {
+        debug!("Creating directory {}", name);
+        let entry = DirectoryEntry::new(name);
+        self.update_file_tree(|files| files.try_insert(parent, entry.into()))
+    }
+
+    /// Creates a new empty directory at `path` and returns its ID. The parent directory must
+    /// already exist.
+    pub fn create_dir_at(&mut self, path: &str) -> Result<EntryId> {
+        let (parent, name) = self.files.parent_and_name(path)?;
+        self.create_dir(parent, name)
+    }
+
+    /// Removes the entry `id` from the disc. If it is a directory, all of its descendants will be
+    /// removed as well. The space used by removed files is zeroed out and becomes free.
+    pub fn remove(&mut self, id: EntryId) -> Result<()> {
+        debug!("Removing {}", self.files[id].name());
+        let removed = self.update_file_tree(|files| files.remove(id))?;
+        for file in removed {
+            // Files can share data, so only wipe data which isn't referenced anymore
+            if file.size > 0 && self.is_free(file.offset, file.size) {
+                debug!("Clearing {:#x} bytes at {:#x}", file.size, file.offset);
+                self.stream.seek(SeekFrom::Start(file.offset as u64))?;
+                fill(&mut self.stream, 0, file.size as u64)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the entry at `path` from the disc. See `remove()`.
+    pub fn remove_at(&mut self, path: &str) -> Result<()> {
+        self.remove(self.files.at(path)?)
+    }
+
+    /// Moves the entry `id` into the `new_parent` directory and renames it to `new_name`. File
+    /// data is not moved.
+    pub fn rename(&mut self, id: EntryId, new_parent: EntryId, new_name: &str) -> Result<()> {
+        debug!("Renaming {} to {}", self.files[id].name(), new_name);
+        self.update_file_tree(|files| files.rename(id, new_parent, new_name))
+    }
+
+    /// Moves the entry at `path` to `new_path`. The parent directory of `new_path` must already
+    /// exist.
+    pub fn rename_at(&mut self, path: &str, new_path: &str) -> Result<()> {
+        let id = self.files.at(path)?;
+        let (new_parent, new_name) = self.files.parent_and_name(new_path)?;
+        self.rename(id, new_parent, new_name)
+    }
+
+    /// Rearranges file data so that files are stored contiguously in FST order after the FST,
+    /// merging all of the free space into a single region at the end of the disc. Returns the
+    /// offset of the end of the used data, which the disc can safely be truncated to.
+    pub fn compact(&mut self) -> Result<u32> {
+        // Entries can share data, so group them by the region they point to and move each group
+        // as a unit
+        let (_, ids) = self.files.to_fst()?;
+        let mut groups: Vec<(DiscRegion, Vec<EntryId>)> = vec![];
+        let mut group_indexes = HashMap::new();
+        for id in ids {
+            let file = match self.files[id].file() {
+                Some(file) if file.size > 0 => file,
+                _ => continue,
+            };
+            let region = DiscRegion::new(file.offset, file.size);
+            let index = *group_indexes.entry((region.offset, region.size)).or_insert_with(|| {
+                groups.push((region, vec![]));
+                groups.len() - 1
+            });
+            groups[index].1.push(id);
+        }
+
+        // Leave some room after the FST so that it can still grow
+        let fst_end = self.header.fst_offset + self.header.fst_size;
+        let mut end = common::align(fst_end, DVD_OPTIMAL_ALIGN);
+        for i in 0..groups.len() {
+            let (placed, unplaced) = groups.split_at_mut(i + 1);
+            let (region, entries) = &mut placed[i];
+            let mut target = DiscRegion::new(common::align(end, COMPACT_ALIGN), region.size);
+            if let Some(dol) = self.relocated_dol.filter(|dol| dol.overlaps(&target)) {
+                target.offset = common::align(dol.end(), COMPACT_ALIGN);
+            }
+            end = target.end();
+            if region.offset == target.offset {
+                continue;
+            }
+
+            // Everything before the target has already been placed, so anything else inside it
+            // belongs to a later group and needs to be moved out of the way first
+            for (other, other_entries) in unplaced.iter_mut().filter(|(r, _)| r.overlaps(&target)) {
+                // Recompute the free list so that space taken or released by earlier relocations
+                // in this pass can't be handed out twice
+                self.refresh_free_regions()?;
+                let free = self.free_regions.iter().flat_map(|r| r.subtract(&target));
+                let new_offset = Self::allocate_from(free, other.size, COMPACT_ALIGN)?.offset;
+                self.relocate(*other, new_offset, other_entries)?;
+                other.offset = new_offset;
+            }
+
+            self.relocate(*region, target.offset, entries)?;
+            *region = target;
+        }
+        Ok(self.relocated_dol.map_or(end, |dol| end.max(dol.end())))
+    }
+
+    /// Moves the data in `old` to `new_offset` and points `entries` at it. Unlike
+    /// `move_and_resize_file()`, the old and new regions are allowed to overlap.
+    fn relocate(&mut self, old: DiscRegion, new_offset: u32, entries: &[EntryId]) -> Result<()> {
+        trace!("Relocating {:?} to {:#x}", old, new_offset);
+        copy_within(&mut self.stream, old.offset as u64, old.size as u64, new_offset as u64)?;
+        let original = self.files.clone();
+        for &id in entries {
+            self.files.file_mut(id)?.offset = new_offset;
+        }
+        if let Err(e) = self.commit_file_tree() {
+            self.files = original;
+            return Err(e);
+        }
+
+        // Wipe whatever part of the old region is no longer in use
+        let new = DiscRegion::new(new_offset, old.size);
+        for unused in old.subtract(&new) {
+            self.stream.seek(SeekFrom::Start(unused.offset as u64))?;
+            fill(&mut self.stream, 0, unused.size as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Edits the file tree using `f` and then commits it. If anything fails, the file tree is
+    /// restored to its original state.
+    fn update_file_tree<T, F>(&mut self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut FileTree) -> fst::Result<T>,
+    {
+        let original = self.files.clone();
+        let result = f(&mut self.files).map_err(Error::from).and_then(|value| {
+            self.commit_file_tree()?;
+            Ok(value)
+        });
+        if result.is_err() {
+            self.files = original;
+        }
+        result
+    }
+
+    /// Recalculates the free list from the current file tree.
+    fn refresh_free_regions(&mut self) -> Result<()> {
+        let (fst, _) = self.files.to_fst()?;
+        self.free_regions = Self::find_free_regions(&self.header, &fst, self.relocated_dol);
+        Ok(())
+    }
+
+    /// Returns true if the region of `size` bytes at `offset` is entirely unused.
+    fn is_free(&self, offset: u32, size: u32) -> bool {
+        self.free_regions.iter().any(|r| offset >= r.offset && offset + size <= r.offset + r.size)
+    }
+
     /// Rebuilds the disc's File String Table (FST), writes it out, and updates internal state.
     pub fn commit_file_tree(&mut self) -> Result<()> {
         debug!("Rebuilding disc FST");
@@ -469,15 +706,79 @@ impl<S: ReadWriteSeek> DiscStream<S> {
         if disk_size != self.header.fst_size {
             let mut new_header = self.header.clone();
             new_header.fst_size = disk_size;
-            self.stream.rewind()?;
-            let mut buf = BufWriter::new(&mut self.stream);
-            buf.write_struct(&*new_header)?;
-            buf.flush()?;
-            self.header = new_header;
+            self.update_header(new_header)?;
         }
 
         // Everything succeeded, so update our free list
-        self.free_regions = Self::find_free_regions(&self.header, &fst);
+        self.free_regions = Self::find_free_regions(&self.header, &fst, self.relocated_dol);
+        Ok(())
+    }
+
+    /// Writes `dol` to the disc as main.dol. If it does not fit in the space used by the current
+    /// executable, it is moved to a free region after the FST.
+    pub fn write_dol(&mut self, dol: &Dol) -> Result<()> {
+        let mut bytes = Cursor::new(vec![]);
+        bytes.write_struct(dol)?;
+        let bytes = bytes.into_inner();
+        let size = u32::try_from(bytes.len()).map_err(|_| Error::NotEnoughSpace(u32::MAX))?;
+
+        let old_offset = self.header.dol_offset;
+        let old_size = self.open_dol()?.0.file_size();
+        let max_size = match self.relocated_dol {
+            Some(dol) => self.max_region_size(dol.offset, dol.size),
+            None => self.header.fst_offset.saturating_sub(old_offset),
+        };
+        let new_offset = if size <= max_size {
+            old_offset
+        } else {
+            self.allocate(size, DVD_OPTIMAL_ALIGN)?.offset
+        };
+
+        debug!("Writing main.dol at {:#x} (size = {:#x})", new_offset, size);
+        self.stream.seek(SeekFrom::Start(new_offset as u64))?;
+        self.stream.write_all(&bytes)?;
+        if new_offset != old_offset {
+            let mut new_header = self.header.clone();
+            new_header.dol_offset = new_offset;
+            self.update_header(new_header)?;
+
+            // The header points to the new executable now, so the old one can be wiped
+            self.stream.seek(SeekFrom::Start(old_offset as u64))?;
+            fill(&mut self.stream, 0, old_size as u64)?;
+        } else if size < old_size {
+            fill(&mut self.stream, 0, (old_size - size) as u64)?;
+        }
+
+        let fst_end = self.header.fst_offset + self.header.fst_size;
+        self.relocated_dol = (new_offset >= fst_end).then(|| DiscRegion::new(new_offset, size));
+        self.refresh_free_regions()
+    }
+
+    /// Writes a new disc header (boot.bin). The fields which describe the disc layout are ignored
+    /// and the current values are kept.
+    pub fn write_header(&mut self, header: &DiscHeader) -> Result<()> {
+        let mut new_header = Box::new(header.clone());
+        new_header.copy_layout(&self.header);
+        self.update_header(new_header)
+    }
+
+    /// Writes new disc header information (bi2.bin).
+    pub fn write_header_info(&mut self, info: &DiscHeaderInfo) -> Result<()> {
+        self.stream.seek(SeekFrom::Start(BI2_OFFSET))?;
+        let mut buf = BufWriter::new(&mut self.stream);
+        buf.write_struct(info)?;
+        buf.flush()?;
+        Ok(())
+    }
+
+    /// Writes `header` to the start of the disc and makes it the current header.
+    fn update_header(&mut self, header: Box<DiscHeader>) -> Result<()> {
+        self.stream.rewind()?;
+        let mut buf = BufWriter::new(&mut self.stream);
+        buf.write_struct(&*header)?;
+        buf.flush()?;
+        drop(buf);
+        self.header = header;
         Ok(())
     }
 
@@ -542,6 +843,25 @@ impl DiscRegion {
     pub fn new(offset: u32, size: u32) -> Self {
         Self { offset, size }
     }
+
+    /// Returns the offset of the end of the region.
+    pub fn end(&self) -> u32 {
+        self.offset + self.size
+    }
+
+    /// Returns true if the region overlaps `other`.
+    pub fn overlaps(&self, other: &DiscRegion) -> bool {
+        self.offset < other.end() && other.offset < self.end()
+    }
+
+    /// Returns the parts of the region which are not covered by `other`.
+    fn subtract(&self, other: &DiscRegion) -> impl Iterator<Item = DiscRegion> {
+        let before =
+            DiscRegion::new(self.offset, other.offset.clamp(self.offset, self.end()) - self.offset);
+        let after_start = other.end().clamp(self.offset, self.end());
+        let after = DiscRegion::new(after_start, self.end() - after_start);
+        [before, after].into_iter().filter(|r| r.size > 0)
+    }
 }
 
 impl fmt::Debug for DiscRegion {
@@ -553,33 +873,347 @@ impl fmt::Debug for DiscRegion {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::assert_write_and_read;
-    use text::Text;
+    use crate::common::io::pad;
+
+    /// Size of the disc created by `make_test_disc()`.
+    const TEST_DISC_SIZE: u32 = 0x40000;
+
+    /// Builds a small disc image containing a single file named "foo".
+    fn make_test_disc() -> Result<Cursor<Vec<u8>>> {
+        let mut files = FileTree::new();
+        files.insert(files.root(), FileEntry::new("foo", 0x8000, 4).into());
+        let (fst, _) = files.to_fst()?;
+        let mut header = DiscHeader::new();
+        header.game_code = *b"GGTE";
+        header.maker_code = *b"01";
+        header.fst_offset = 0x440;
+        header.fst_size = fst.disk_size();
+        header.fst_max_size = fst.disk_size();
+        header.disc_size = TEST_DISC_SIZE;
+
+        let mut cursor = Cursor::new(vec![]);
+        cursor.write_struct(&header)?;
+        cursor.write_struct(&fst)?;
+        pad(&mut cursor, 0x8000, 0)?;
+        cursor.write_all(b"test")?;
+        fill(&mut cursor, 0, (TEST_DISC_SIZE - 0x8004).into())?;
+        Ok(cursor)
+    }
+
+    fn read_file(disc: &mut DiscStream<impl ReadSeek>, path: &str) -> Result<Vec<u8>> {
+        let mut bytes = vec![];
+        disc.open_file_at(path)?.read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
 
     #[test]
-    fn test_write_and_read_disc_header() {
-        assert_write_and_read!(DiscHeader {
-            game_code: *b"GGTE",
-            maker_code: *b"01",
-            disc_id: 2,
-            version: 3,
-            audio_streaming: 4,
-            stream_buffer_size: 5,
-            unused_00a: [6; 0x0e],
-            wii_magic: 0,
-            gcn_magic: GCN_MAGIC,
-            game_name: Text::from_bytes("test").unwrap(),
-            debug_monitor_offset: 8,
-            debug_monitor_addr: 9,
-            unused_408: [10; 0x18],
-            dol_offset: 11,
-            fst_offset: 12,
-            fst_size: 13,
-            fst_max_size: 14,
-            user_position: 15,
-            user_size: 16,
-            disc_size: 17,
-            unused_43c: 18,
-        });
+    fn test_create_and_remove_files() -> Result<()> {
+        let mut disc = DiscStream::open(make_test_disc()?)?;
+        disc.create_dir_at("bar")?;
+        disc.create_file_at("bar/baz", Cursor::new(b"hello"))?;
+        disc.create_file_at("qux", Cursor::new(b""))?;
+        assert!(matches!(
+            disc.create_file_at("foo", Cursor::new(b"")),
+            Err(Error::Fst(e)) if matches!(*e, fst::Error::AlreadyExists(_))
+        ));
+        assert!(disc.create_file_at("missing/baz", Cursor::new(b"")).is_err());
+
+        let mut disc = DiscStream::open(disc.stream)?;
+        assert_eq!(read_file(&mut disc, "foo")?, b"test");
+        assert_eq!(read_file(&mut disc, "bar/baz")?, b"hello");
+        assert_eq!(read_file(&mut disc, "qux")?, b"");
+        let baz = disc.files().file_at("bar/baz")?.clone();
+        assert_eq!(baz.offset % DVD_OPTIMAL_ALIGN, 0);
+
+        disc.remove_at("bar")?;
+        assert!(disc.files().at("bar").is_err());
+        assert!(disc.files().at("bar/baz").is_err());
+        assert!(disc.is_free(baz.offset, baz.size));
+        assert!(matches!(
+            disc.remove_at("/"),
+            Err(Error::Fst(e)) if matches!(*e, fst::Error::RootNotAllowed)
+        ));
+
+        let mut disc = DiscStream::open(disc.stream)?;
+        assert!(disc.files().at("bar").is_err());
+        assert_eq!(read_file(&mut disc, "foo")?, b"test");
+        let fst_end = disc.header.fst_offset + disc.header.fst_size;
+        assert_eq!(
+            disc.free_regions(),
+            [
+                DiscRegion::new(fst_end, 0x8000 - fst_end),
+                DiscRegion::new(0x8004, TEST_DISC_SIZE - 0x8004)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_disc() -> Result<()> {
+        let mut header = DiscHeader::new();
+        header.game_code = *b"GGTE";
+        header.maker_code = *b"01";
+        header.disc_size = TEST_DISC_SIZE;
+        let mut boot = Cursor::new(vec![]);
+        boot.write_struct(&header)?;
+        let bi2 = vec![0xbb; BI2_SIZE];
+        let mut apploader = vec![0; APPLOADER_HEADER_SIZE];
+        apploader[0x17] = 0x4;
+        apploader.extend(b"code");
+        let mut dol_header = DolHeader::new();
+        dol_header.text_offsets[0] = 0x100;
+        dol_header.text_sizes[0] = 4;
+        let mut dol = Cursor::new(vec![]);
+        dol.write_struct(&dol_header)?;
+        pad(&mut dol, 0x100, 0)?;
+        dol.write_all(b"main")?;
+
+        let mut builder = DiscBuilder::new(
+            &mut boot.into_inner().as_slice(),
+            &mut bi2.as_slice(),
+            &mut apploader.as_slice(),
+            &mut dol.into_inner().as_slice(),
+        )?;
+        let root = builder.files().root();
+        let bar = builder.create_dir(root, "bar")?;
+        builder.create_file(bar, "baz", || Ok(Cursor::new(b"hello")))?;
+        builder.create_file(root, "foo", || Ok(Cursor::new(b"test")))?;
+        assert!(builder.create_file(root, "foo", || Ok(Cursor::new(b""))).is_err());
+        let mut cursor = Cursor::new(vec![]);
+        builder.write_to(&mut cursor)?;
+        assert_eq!(cursor.get_ref().len(), TEST_DISC_SIZE as usize);
+
+        let mut disc = DiscStream::open(cursor)?;
+        assert_eq!(disc.game_id(), "GGTE01");
+        assert_eq!(read_file(&mut disc, "foo")?, b"test");
+        assert_eq!(read_file(&mut disc, "bar/baz")?, b"hello");
+        assert_eq!(disc.files().file_at("foo")?.offset % DVD_OPTIMAL_ALIGN, 0);
+
+        let mut bytes = vec![];
+        disc.open_bi2()?.read_to_end(&mut bytes)?;
+        assert_eq!(bytes, bi2);
+        bytes.clear();
+        disc.open_apploader()?.read_to_end(&mut bytes)?;
+        assert_eq!(bytes, apploader);
+        bytes.clear();
+        let (dol_header2, mut dol_reader) = disc.open_dol()?;
+        dol_reader.read_to_end(&mut bytes)?;
+        assert_eq!(dol_header2, dol_header);
+        assert_eq!(&bytes[0x100..], b"main");
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_dol() -> Result<()> {
+        let mut header = DiscHeader::new();
+        header.disc_size = TEST_DISC_SIZE;
+        let mut boot = vec![];
+        boot.write_struct(&header)?;
+        let mut dol = Dol::new();
+        dol.text[0] = Some(dol::DolSection::new(0x80003100, b"main".to_vec()));
+        let mut dol_bytes = vec![];
+        dol_bytes.write_struct(&dol)?;
+        let mut builder = DiscBuilder::new(
+            &mut boot.as_slice(),
+            &mut [0u8; BI2_SIZE].as_slice(),
+            &mut [0u8; APPLOADER_HEADER_SIZE].as_slice(),
+            &mut dol_bytes.as_slice(),
+        )?;
+        let root = builder.files().root();
+        builder.create_file(root, "foo", || Ok(Cursor::new(b"test")))?;
+        let mut cursor = Cursor::new(vec![]);
+        builder.write_to(&mut cursor)?;
+
+        // A small change fits in place
+        let mut disc = DiscStream::open(cursor)?;
+        let old_offset = disc.header.dol_offset;
+        dol.patch(0x80003100, b"MAIN")?;
+        disc.write_dol(&dol)?;
+        assert_eq!(disc.header.dol_offset, old_offset);
+        assert_eq!(disc.read_dol()?, dol);
+
+        // Growing it past the FST forces it to move
+        dol.add_section(dol::SectionKind::Data, 0x80004000, vec![0xdd; 0x9000])?;
+        disc.write_dol(&dol)?;
+        let new_offset = disc.header.dol_offset;
+        assert!(new_offset > disc.header.fst_offset);
+        assert_eq!(new_offset % DVD_OPTIMAL_ALIGN, 0);
+        let region = DiscRegion::new(new_offset, dol.header()?.file_size());
+        assert!(!disc.free_regions().iter().any(|r| r.overlaps(&region)));
+
+        // Reopening the disc should find it again, and compacting must not overwrite it
+        let mut disc = DiscStream::open(disc.stream)?;
+        assert_eq!(disc.relocated_dol, Some(region));
+        assert_eq!(disc.read_dol()?, dol);
+        disc.create_file_at("bar", Cursor::new(vec![0xee; 0x10000]))?;
+        let end = disc.compact()?;
+        assert!(end >= region.end());
+        assert_eq!(disc.read_dol()?, dol);
+        assert_eq!(read_file(&mut disc, "foo")?, b"test");
+        assert_eq!(read_file(&mut disc, "bar")?, [0xee; 0x10000]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact() -> Result<()> {
+        let mut disc = DiscStream::open(make_test_disc()?)?;
+        disc.create_file_at("bar", Cursor::new(vec![1; 0x9000]))?;
+        disc.create_file_at("baz", Cursor::new(vec![2; 0x10]))?;
+        disc.create_file_at("qux", Cursor::new(vec![3; 0x100]))?;
+        // Grow foo so that it moves to the end and leaves a hole behind
+        disc.replace_file_at("foo", Cursor::new(vec![4; 0x8001]))?;
+        disc.remove_at("baz")?;
+        assert!(disc.free_regions().len() > 2);
+
+        let end = disc.compact()?;
+        let mut disc = DiscStream::open(disc.stream)?;
+        assert_eq!(read_file(&mut disc, "foo")?, vec![4; 0x8001]);
+        assert_eq!(read_file(&mut disc, "bar")?, vec![1; 0x9000]);
+        assert_eq!(read_file(&mut disc, "qux")?, vec![3; 0x100]);
+
+        // Files should be packed in FST order with the free space at the end
+        let offsets = ["foo", "bar", "qux"].map(|p| disc.files().file_at(p).unwrap().offset);
+        assert_eq!(offsets, [0x8000, 0x10004, 0x19004]);
+        assert_eq!(end, 0x19104);
+        let fst_end = disc.header.fst_offset + disc.header.fst_size;
+        assert_eq!(
+            disc.free_regions(),
+            [
+                DiscRegion::new(fst_end, 0x8000 - fst_end),
+                DiscRegion::new(0x10001, 3),
+                DiscRegion::new(end, TEST_DISC_SIZE - end)
+            ]
+        );
+        assert!(disc.stream.get_ref()[end as usize..].iter().all(|&b| b == 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_evicts_multiple_groups() -> Result<()> {
+        let mut disc = DiscStream::open(make_test_disc()?)?;
+        disc.create_file_at("bar", Cursor::new(vec![1; 0x10]))?;
+        disc.create_file_at("baz", Cursor::new(vec![2; 0x10]))?;
+        // Grow foo so that its compacted location covers both bar and baz
+        disc.replace_file_at("foo", Cursor::new(vec![3; 0x10001]))?;
+        let bar = disc.files().file_at("bar")?.offset;
+        let baz = disc.files().file_at("baz")?.offset;
+        let target = DiscRegion::new(0x8000, 0x10001);
+        assert!(target.overlaps(&DiscRegion::new(bar, 0x10)));
+        assert!(target.overlaps(&DiscRegion::new(baz, 0x10)));
+
+        let end = disc.compact()?;
+        let mut disc = DiscStream::open(disc.stream)?;
+        assert_eq!(read_file(&mut disc, "foo")?, vec![3; 0x10001]);
+        assert_eq!(read_file(&mut disc, "bar")?, vec![1; 0x10]);
+        assert_eq!(read_file(&mut disc, "baz")?, vec![2; 0x10]);
+        let offsets = ["foo", "bar", "baz"].map(|p| disc.files().file_at(p).unwrap().offset);
+        assert_eq!(offsets, [0x8000, 0x18004, 0x18014]);
+        assert_eq!(end, 0x18024);
+        assert!(disc.stream.get_ref()[end as usize..].iter().all(|&b| b == 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_scrubbed() -> Result<()> {
+        let mut disc = DiscStream::open(make_test_disc()?)?;
+        disc.create_file_at("bar", Cursor::new(vec![1; 0x10]))?;
+        let bar_offset = disc.files().file_at("bar")?.offset;
+        // Leave junk in the space between the files and at the end of the disc
+        let junk_offsets = [0x8004, (bar_offset + 0x10) as usize];
+        for offset in junk_offsets {
+            disc.stream.get_mut()[offset..(offset + 4)].copy_from_slice(b"junk");
+        }
+
+        let mut scrubbed = Cursor::new(vec![]);
+        assert_eq!(disc.write_scrubbed(&mut scrubbed, false)?, TEST_DISC_SIZE as u64);
+        assert_eq!(scrubbed.get_ref().len(), TEST_DISC_SIZE as usize);
+        for offset in junk_offsets {
+            assert_eq!(&scrubbed.get_ref()[offset..(offset + 4)], [0; 4]);
+        }
+
+        let mut trimmed = Cursor::new(vec![]);
+        assert_eq!(disc.write_scrubbed(&mut trimmed, true)?, (bar_offset + 0x10) as u64);
+        assert_eq!(
+            trimmed.get_ref().as_slice(),
+            &scrubbed.get_ref()[..(bar_offset + 0x10) as usize]
+        );
+
+        let mut trimmed = DiscStream::open(trimmed)?;
+        assert_eq!(read_file(&mut trimmed, "foo")?, b"test");
+        assert_eq!(read_file(&mut trimmed, "bar")?, [1; 0x10]);
+        assert_eq!(trimmed.used_end(), bar_offset + 0x10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_subtract_region() {
+        let region = DiscRegion::new(0x10, 0x10);
+        let subtract =
+            |offset, size| region.subtract(&DiscRegion::new(offset, size)).collect::<Vec<_>>();
+        assert_eq!(subtract(0, 0x10), [region]);
+        assert_eq!(subtract(0x20, 0x10), [region]);
+        assert_eq!(subtract(0x10, 0x10), []);
+        assert_eq!(subtract(0x14, 0x4), [DiscRegion::new(0x10, 0x4), DiscRegion::new(0x18, 0x8)]);
+        assert_eq!(subtract(0x8, 0x10), [DiscRegion::new(0x18, 0x8)]);
+        assert_eq!(subtract(0x18, 0x10), [DiscRegion::new(0x10, 0x8)]);
+    }
+
+    #[test]
+    fn test_write_header() -> Result<()> {
+        let mut disc = DiscStream::open(make_test_disc()?)?;
+        let mut header = disc.header().clone();
+        header.set_game_id("GGTP01")?;
+        header.version = 1;
+        header.fst_offset = 0;
+        disc.write_header(&header)?;
+
+        let mut disc = DiscStream::open(disc.stream)?;
+        assert_eq!(disc.game_id(), "GGTP01");
+        assert_eq!(disc.header().version, 1);
+        assert_eq!(disc.header().fst_offset, 0x440);
+        assert_eq!(read_file(&mut disc, "foo")?, b"test");
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_header_info() -> Result<()> {
+        let mut header = DiscHeader::new();
+        header.disc_size = TEST_DISC_SIZE;
+        let mut boot = vec![];
+        boot.write_struct(&header)?;
+        let mut dol_bytes = vec![];
+        dol_bytes.write_struct(&Dol::new())?;
+        let builder = DiscBuilder::new(
+            &mut boot.as_slice(),
+            &mut [0u8; BI2_SIZE].as_slice(),
+            &mut [0u8; APPLOADER_HEADER_SIZE].as_slice(),
+            &mut dol_bytes.as_slice(),
+        )?;
+        let mut cursor = Cursor::new(vec![]);
+        builder.write_to(&mut cursor)?;
+
+        let mut disc = DiscStream::open(cursor)?;
+        let mut info = disc.read_header_info()?;
+        assert_eq!(info.region, RegionCode::Japan);
+        info.region = RegionCode::Pal;
+        info.simulated_memory_size = 0x1800000;
+        disc.write_header_info(&info)?;
+        assert_eq!(disc.read_header_info()?, info);
+        assert_eq!(disc.read_apploader_header()?.total_size(), APPLOADER_HEADER_SIZE as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename() -> Result<()> {
+        let mut disc = DiscStream::open(make_test_disc()?)?;
+        disc.create_dir_at("bar")?;
+        disc.rename_at("foo", "bar/baz")?;
+        assert!(disc.rename_at("bar", "bar/qux").is_err());
+        assert!(disc.rename_at("bar/baz", "bar/..").is_err());
+
+        let mut disc = DiscStream::open(disc.stream)?;
+        assert!(disc.files().at("foo").is_err());
+        assert_eq!(read_file(&mut disc, "bar/baz")?, b"test");
+        Ok(())
     }
 }
@@ -0,0 +1,162 @@
+use super::{
+    DiscHeader, Error, Result, APPLOADER_HEADER_SIZE, APPLOADER_OFFSET, BI2_OFFSET, BI2_SIZE,
+    DVD_OPTIMAL_ALIGN,
+};
+use crate::common::io::{fill, pad};
+use crate::common::{ReadSeek, ReadStructExt, WriteStructExt};
+use crate::dvd::dol::DolHeader;
+use crate::dvd::fst::{DirectoryEntry, EntryId, FileEntry, FileTree, FstEntryKind};
+use slotmap::SecondaryMap;
+use std::convert::TryFrom;
+use std::io::{self, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use tracing::{debug, trace};
+
+/// Alignment of main.dol and the FST on a built disc.
+const SYSTEM_ALIGN: u64 = 0x100;
+
+/// A closure which opens a reader for a file's data.
+type Opener<'a> = Box<dyn FnOnce() -> io::Result<Box<dyn ReadSeek + 'a>> + 'a>;
+
+/// Builds complete disc images from system files and a file tree.
+pub struct DiscBuilder<'a> {
+    /// The disc header (boot.bin). The DOL and FST fields will be recalculated.
+    header: Box<DiscHeader>,
+    /// The contents of bi2.bin.
+    bi2: Vec<u8>,
+    /// The contents of apploader.img.
+    apploader: Vec<u8>,
+    /// The contents of main.dol.
+    dol: Vec<u8>,
+    /// The files to put on the disc.
+    files: FileTree,
+    /// Maps file entries in the tree to closures which open readers for them.
+    sources: SecondaryMap<EntryId, Opener<'a>>,
+}
+
+impl<'a> DiscBuilder<'a> {
+    /// Constructs a new `DiscBuilder` with an empty file tree. The system files are read from
+    /// `boot` (boot.bin), `bi2` (bi2.bin), `apploader` (apploader.img), and `dol` (main.dol).
+    pub fn new(
+        boot: &mut dyn Read,
+        bi2: &mut dyn Read,
+        apploader: &mut dyn Read,
+        dol: &mut dyn Read,
+    ) -> Result<Self> {
+        let header = boot.read_struct::<DiscHeader>()?;
+
+        let mut bi2_bytes = vec![0u8; BI2_SIZE];
+        bi2.read_exact(&mut bi2_bytes)?;
+
+        let mut apploader_bytes = vec![];
+        apploader.read_to_end(&mut apploader_bytes)?;
+        if apploader_bytes.len() < APPLOADER_HEADER_SIZE {
+            return Err(Error::InvalidApploader);
+        }
+
+        let mut dol_bytes = vec![];
+        dol.read_to_end(&mut dol_bytes)?;
+        let dol_header = Cursor::new(&dol_bytes).read_struct::<DolHeader>()?;
+        if (dol_header.file_size() as usize) > dol_bytes.len() {
+            return Err(Error::InvalidDol);
+        }
+
+        Ok(Self {
+            header: header.into(),
+            bi2: bi2_bytes,
+            apploader: apploader_bytes,
+            dol: dol_bytes,
+            files: FileTree::new(),
+            sources: SecondaryMap::new(),
+        })
+    }
+
+    /// Returns a reference to the disc's `FileTree`.
+    pub fn files(&self) -> &FileTree {
+        &self.files
+    }
+
+    /// Creates a new empty directory named `name` in the `parent` directory and returns its ID.
+    pub fn create_dir(&mut self, parent: EntryId, name: &str) -> Result<EntryId> {
+        Ok(self.files.try_insert(parent, DirectoryEntry::new(name).into())?)
+    }
+
+    /// Creates a new file named `name` in the `parent` directory and returns its ID. When the disc
+    /// is written out, `opener` will be called to get a reader for the file's data.
+    pub fn create_file<R, F>(&mut self, parent: EntryId, name: &str, opener: F) -> Result<EntryId>
+    where
+        R: ReadSeek + 'a,
+        F: (FnOnce() -> io::Result<R>) + 'a,
+    {
+        let id = self.files.try_insert(parent, FileEntry::new(name, 0, 0).into())?;
+        self.sources.insert(
+            id,
+            Box::new(move || {
+                let reader: Box<dyn ReadSeek + 'a> = Box::new(opener()?);
+                Ok(reader)
+            }),
+        );
+        Ok(id)
+    }
+
+    /// Writes out the disc image. The image will be padded to the full disc size specified by the
+    /// disc header.
+    pub fn write_to(mut self, mut writer: impl Write + Seek) -> Result<()> {
+        // The system files are all at fixed locations except for main.dol
+        writer.rewind()?;
+        let mut buf = BufWriter::new(&mut writer);
+        buf.write_struct(&*self.header)?;
+        buf.seek(SeekFrom::Start(BI2_OFFSET))?;
+        buf.write_all(&self.bi2)?;
+        buf.seek(SeekFrom::Start(APPLOADER_OFFSET))?;
+        buf.write_all(&self.apploader)?;
+        pad(&mut buf, SYSTEM_ALIGN, 0)?;
+        self.header.dol_offset = offset_u32(buf.stream_position()?)?;
+        debug!("Writing main.dol at {:#x}", self.header.dol_offset);
+        buf.write_all(&self.dol)?;
+        pad(&mut buf, SYSTEM_ALIGN, 0)?;
+
+        // The FST's size doesn't depend on file offsets, so leave room for it and fill it in later
+        self.header.fst_offset = offset_u32(buf.stream_position()?)?;
+        let (mut fst, ids) = self.files.to_fst()?;
+        self.header.fst_size = fst.disk_size();
+        self.header.fst_max_size = fst.disk_size();
+        debug!("Writing FST at {:#x} (size = {:#x})", self.header.fst_offset, fst.disk_size());
+        fill(&mut buf, 0, fst.disk_size().into())?;
+
+        for (entry, id) in fst.entries.iter_mut().zip(ids) {
+            if entry.kind != FstEntryKind::File {
+                continue;
+            }
+            pad(&mut buf, DVD_OPTIMAL_ALIGN.into(), 0)?;
+            let start_offset = offset_u32(buf.stream_position()?)?;
+            trace!("Writing disc file \"{}\" at {:#x}", self.files[id].name(), start_offset);
+            let opener = self.sources.remove(id).expect("File does not have a data source");
+            let mut reader = opener()?;
+            io::copy(&mut reader, &mut buf)?;
+            let end_offset = offset_u32(buf.stream_position()?)?;
+            entry.offset_or_parent = start_offset;
+            entry.size_or_next = end_offset - start_offset;
+        }
+
+        // Pad out the disc to its full size
+        let data_end = buf.stream_position()?;
+        let disc_size = u64::from(self.header.user_size) + u64::from(self.header.disc_size);
+        if data_end > disc_size {
+            return Err(Error::NotEnoughSpace(offset_u32(data_end)?));
+        }
+        fill(&mut buf, 0, disc_size - data_end)?;
+
+        // Go back and fill in the header and FST
+        buf.rewind()?;
+        buf.write_struct(&*self.header)?;
+        buf.seek(SeekFrom::Start(self.header.fst_offset.into()))?;
+        buf.write_struct(&fst)?;
+        buf.flush()?;
+        Ok(())
+    }
+}
+
+/// Converts a stream offset to a 32-bit disc offset.
+fn offset_u32(offset: u64) -> Result<u32> {
+    u32::try_from(offset).map_err(|_| Error::OffsetOverflow(offset))
+}
@@ -0,0 +1,460 @@
+use super::{Error, Result, GAME_NAME_SIZE};
+use crate::common::{FixedText, ReadFrom, ReadStructExt, WriteStructExt, WriteTo};
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+use encoding_rs::mem::decode_latin1;
+use std::io::{Read, Write};
+
+pub(super) const GCN_MAGIC: u32 = 0xc2339f3d;
+const WII_MAGIC: u32 = 0x5d1c9ea3;
+
+/// Size of the disc header information (bi2.bin).
+pub(super) const BI2_SIZE: usize = 0x2000;
+/// Size of the fields at the start of bi2.bin which have known meanings.
+const BI2_FIELDS_SIZE: usize = 0x2c;
+
+/// Size of the apploader's header.
+pub(super) const APPLOADER_HEADER_SIZE: usize = 0x20;
+/// Size of the date string in the apploader header.
+const APPLOADER_DATE_SIZE: usize = 0x10;
+
+/// The disc header (boot.bin).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscHeader {
+    /// The four-character game code (e.g. `GGTE`).
+    pub game_code: [u8; 4],
+    /// The two-character maker code (e.g. `01`).
+    pub maker_code: [u8; 2],
+    /// The disc number for games which span multiple discs.
+    pub disc_id: u8,
+    /// The game's revision number.
+    pub version: u8,
+    /// Nonzero if the game streams audio from the disc.
+    pub audio_streaming: u8,
+    /// The size of the audio streaming buffer.
+    pub stream_buffer_size: u8,
+    pub unused_00a: [u8; 0x0e],
+    /// Magic number which identifies Wii discs.
+    pub wii_magic: u32,
+    /// Magic number which identifies GameCube discs.
+    pub gcn_magic: u32,
+    /// The game's internal name.
+    pub game_name: FixedText<GAME_NAME_SIZE>,
+    /// The offset of the debug monitor (dh.bin).
+    pub debug_monitor_offset: u32,
+    /// The address to load the debug monitor at.
+    pub debug_monitor_addr: u32,
+    pub unused_408: [u8; 0x18],
+    /// The offset of main.dol.
+    pub dol_offset: u32,
+    /// The offset of the FST.
+    pub fst_offset: u32,
+    /// The size of the FST.
+    pub fst_size: u32,
+    /// The maximum size of the FST across all discs in the game.
+    pub fst_max_size: u32,
+    /// The offset of the user area.
+    pub user_position: u32,
+    /// The size of the user area.
+    pub user_size: u32,
+    /// The size of the disc minus the user area.
+    pub disc_size: u32,
+    pub unused_43c: u32,
+}
+
+impl DiscHeader {
+    /// Constructs a new `DiscHeader` with all fields except the magic initialized to zero.
+    pub fn new() -> Self {
+        Self {
+            game_code: [0; 4],
+            maker_code: [0; 2],
+            disc_id: 0,
+            version: 0,
+            audio_streaming: 0,
+            stream_buffer_size: 0,
+            unused_00a: [0; 0x0e],
+            wii_magic: 0,
+            gcn_magic: GCN_MAGIC,
+            game_name: FixedText::default(),
+            debug_monitor_offset: 0,
+            debug_monitor_addr: 0,
+            unused_408: [0; 0x18],
+            dol_offset: 0,
+            fst_offset: 0,
+            fst_size: 0,
+            fst_max_size: 0,
+            user_position: 0,
+            user_size: 0,
+            disc_size: 0,
+            unused_43c: 0,
+        }
+    }
+
+    /// Returns the game ID (e.g. "GGTE01"), which is the game code followed by the maker code.
+    pub fn game_id(&self) -> String {
+        let mut game_id = [0u8; 6];
+        game_id[..4].copy_from_slice(&self.game_code);
+        game_id[4..].copy_from_slice(&self.maker_code);
+        decode_latin1(&game_id).into()
+    }
+
+    /// Changes the game code and maker code to match `id`, which must be six ASCII letters or
+    /// digits.
+    pub fn set_game_id(&mut self, id: &str) -> Result<()> {
+        let bytes = id.as_bytes();
+        if bytes.len() != 6 || !bytes.iter().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(Error::InvalidGameId(id.to_owned()));
+        }
+        self.game_code.copy_from_slice(&bytes[..4]);
+        self.maker_code.copy_from_slice(&bytes[4..]);
+        Ok(())
+    }
+
+    /// Copies the fields which describe the disc layout (main.dol, the FST, and the user area)
+    /// from `other`.
+    pub fn copy_layout(&mut self, other: &DiscHeader) {
+        self.dol_offset = other.dol_offset;
+        self.fst_offset = other.fst_offset;
+        self.fst_size = other.fst_size;
+        self.fst_max_size = other.fst_max_size;
+        self.user_position = other.user_position;
+        self.user_size = other.user_size;
+        self.disc_size = other.disc_size;
+    }
+}
+
+impl Default for DiscHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for DiscHeader {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        let mut header = Self::new();
+        reader.read_exact(&mut header.game_code)?;
+        reader.read_exact(&mut header.maker_code)?;
+        header.disc_id = reader.read_u8()?;
+        header.version = reader.read_u8()?;
+        header.audio_streaming = reader.read_u8()?;
+        header.stream_buffer_size = reader.read_u8()?;
+        reader.read_exact(&mut header.unused_00a)?;
+        header.wii_magic = reader.read_u32::<BE>()?;
+        if header.wii_magic == WII_MAGIC {
+            return Err(Error::WiiNotSupported);
+        }
+        header.gcn_magic = reader.read_u32::<BE>()?;
+        if header.gcn_magic != GCN_MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        header.game_name = reader.read_struct()?;
+        header.debug_monitor_offset = reader.read_u32::<BE>()?;
+        header.debug_monitor_addr = reader.read_u32::<BE>()?;
+        reader.read_exact(&mut header.unused_408)?;
+        header.dol_offset = reader.read_u32::<BE>()?;
+        header.fst_offset = reader.read_u32::<BE>()?;
+        header.fst_size = reader.read_u32::<BE>()?;
+        header.fst_max_size = reader.read_u32::<BE>()?;
+        header.user_position = reader.read_u32::<BE>()?;
+        header.user_size = reader.read_u32::<BE>()?;
+        header.disc_size = reader.read_u32::<BE>()?;
+        header.unused_43c = reader.read_u32::<BE>()?;
+        Ok(header)
+    }
+}
+
+impl<W: Write + ?Sized> WriteTo<W> for DiscHeader {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.game_code)?;
+        writer.write_all(&self.maker_code)?;
+        writer.write_u8(self.disc_id)?;
+        writer.write_u8(self.version)?;
+        writer.write_u8(self.audio_streaming)?;
+        writer.write_u8(self.stream_buffer_size)?;
+        writer.write_all(&self.unused_00a)?;
+        writer.write_u32::<BE>(self.wii_magic)?;
+        writer.write_u32::<BE>(self.gcn_magic)?;
+        writer.write_struct(&self.game_name)?;
+        writer.write_u32::<BE>(self.debug_monitor_offset)?;
+        writer.write_u32::<BE>(self.debug_monitor_addr)?;
+        writer.write_all(&self.unused_408)?;
+        writer.write_u32::<BE>(self.dol_offset)?;
+        writer.write_u32::<BE>(self.fst_offset)?;
+        writer.write_u32::<BE>(self.fst_size)?;
+        writer.write_u32::<BE>(self.fst_max_size)?;
+        writer.write_u32::<BE>(self.user_position)?;
+        writer.write_u32::<BE>(self.user_size)?;
+        writer.write_u32::<BE>(self.disc_size)?;
+        writer.write_u32::<BE>(self.unused_43c)?;
+        Ok(())
+    }
+}
+
+/// The region a disc is for, which determines which consoles can boot it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RegionCode {
+    /// NTSC-J
+    Japan,
+    /// NTSC-U
+    Usa,
+    /// PAL
+    Pal,
+    /// NTSC-K
+    Korea,
+    /// An unrecognized region code.
+    Unknown(u32),
+}
+
+impl From<u32> for RegionCode {
+    fn from(code: u32) -> Self {
+        match code {
+            0 => Self::Japan,
+            1 => Self::Usa,
+            2 => Self::Pal,
+            4 => Self::Korea,
+            _ => Self::Unknown(code),
+        }
+    }
+}
+
+impl From<RegionCode> for u32 {
+    fn from(region: RegionCode) -> Self {
+        match region {
+            RegionCode::Japan => 0,
+            RegionCode::Usa => 1,
+            RegionCode::Pal => 2,
+            RegionCode::Korea => 4,
+            RegionCode::Unknown(code) => code,
+        }
+    }
+}
+
+/// Disc header information (bi2.bin).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscHeaderInfo {
+    /// The size of the debug monitor.
+    pub debug_monitor_size: u32,
+    /// The amount of memory to simulate on development hardware.
+    pub simulated_memory_size: u32,
+    /// The offset of the arguments passed to main.dol.
+    pub argument_offset: u32,
+    /// Nonzero if debug features should be enabled.
+    pub debug_flag: u32,
+    /// The offset of the track data used by debug tools.
+    pub track_location: u32,
+    /// The size of the track data used by debug tools.
+    pub track_size: u32,
+    /// The region the disc is for.
+    pub region: RegionCode,
+    /// Unknown.
+    pub unk_01c: u32,
+    /// Nonzero if the disc uses long filenames.
+    pub long_file_names: u32,
+    /// The controller specification the game was built against.
+    pub pad_spec: u32,
+    /// The maximum size of main.dol, or 0 if there is no limit.
+    pub dol_limit: u32,
+    pub unused_02c: Box<[u8]>,
+}
+
+impl DiscHeaderInfo {
+    /// Constructs a new `DiscHeaderInfo` for a USA disc with all other fields set to zero.
+    pub fn new() -> Self {
+        Self {
+            debug_monitor_size: 0,
+            simulated_memory_size: 0,
+            argument_offset: 0,
+            debug_flag: 0,
+            track_location: 0,
+            track_size: 0,
+            region: RegionCode::Usa,
+            unk_01c: 0,
+            long_file_names: 0,
+            pad_spec: 0,
+            dol_limit: 0,
+            unused_02c: vec![0; BI2_SIZE - BI2_FIELDS_SIZE].into_boxed_slice(),
+        }
+    }
+}
+
+impl Default for DiscHeaderInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for DiscHeaderInfo {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        let mut info = Self::new();
+        info.debug_monitor_size = reader.read_u32::<BE>()?;
+        info.simulated_memory_size = reader.read_u32::<BE>()?;
+        info.argument_offset = reader.read_u32::<BE>()?;
+        info.debug_flag = reader.read_u32::<BE>()?;
+        info.track_location = reader.read_u32::<BE>()?;
+        info.track_size = reader.read_u32::<BE>()?;
+        info.region = reader.read_u32::<BE>()?.into();
+        info.unk_01c = reader.read_u32::<BE>()?;
+        info.long_file_names = reader.read_u32::<BE>()?;
+        info.pad_spec = reader.read_u32::<BE>()?;
+        info.dol_limit = reader.read_u32::<BE>()?;
+        reader.read_exact(&mut info.unused_02c)?;
+        Ok(info)
+    }
+}
+
+impl<W: Write + ?Sized> WriteTo<W> for DiscHeaderInfo {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        writer.write_u32::<BE>(self.debug_monitor_size)?;
+        writer.write_u32::<BE>(self.simulated_memory_size)?;
+        writer.write_u32::<BE>(self.argument_offset)?;
+        writer.write_u32::<BE>(self.debug_flag)?;
+        writer.write_u32::<BE>(self.track_location)?;
+        writer.write_u32::<BE>(self.track_size)?;
+        writer.write_u32::<BE>(self.region.into())?;
+        writer.write_u32::<BE>(self.unk_01c)?;
+        writer.write_u32::<BE>(self.long_file_names)?;
+        writer.write_u32::<BE>(self.pad_spec)?;
+        writer.write_u32::<BE>(self.dol_limit)?;
+        writer.write_all(&self.unused_02c)?;
+        Ok(())
+    }
+}
+
+/// The header at the start of the apploader (apploader.img).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApploaderHeader {
+    /// The date the apploader was built (e.g. `2004/02/02`).
+    pub date: FixedText<APPLOADER_DATE_SIZE>,
+    /// The address of the apploader's entry point.
+    pub entry_point: u32,
+    /// The size of the apploader code following the header.
+    pub code_size: u32,
+    /// The size of the data following the code.
+    pub trailer_size: u32,
+    pub unused_01c: u32,
+}
+
+impl ApploaderHeader {
+    /// Constructs a new `ApploaderHeader` with all fields initialized to zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the total size of the apploader including the header.
+    pub fn total_size(&self) -> u64 {
+        APPLOADER_HEADER_SIZE as u64 + u64::from(self.code_size) + u64::from(self.trailer_size)
+    }
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for ApploaderHeader {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        Ok(Self {
+            date: reader.read_struct()?,
+            entry_point: reader.read_u32::<BE>()?,
+            code_size: reader.read_u32::<BE>()?,
+            trailer_size: reader.read_u32::<BE>()?,
+            unused_01c: reader.read_u32::<BE>()?,
+        })
+    }
+}
+
+impl<W: Write + ?Sized> WriteTo<W> for ApploaderHeader {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        writer.write_struct(&self.date)?;
+        writer.write_u32::<BE>(self.entry_point)?;
+        writer.write_u32::<BE>(self.code_size)?;
+        writer.write_u32::<BE>(self.trailer_size)?;
+        writer.write_u32::<BE>(self.unused_01c)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_write_and_read;
+    use crate::common::Text;
+
+    #[test]
+    fn test_write_and_read_disc_header() {
+        assert_write_and_read!(DiscHeader {
+            game_code: *b"GGTE",
+            maker_code: *b"01",
+            disc_id: 2,
+            version: 3,
+            audio_streaming: 4,
+            stream_buffer_size: 5,
+            unused_00a: [6; 0x0e],
+            wii_magic: 0,
+            gcn_magic: GCN_MAGIC,
+            game_name: Text::from_bytes("test").unwrap(),
+            debug_monitor_offset: 8,
+            debug_monitor_addr: 9,
+            unused_408: [10; 0x18],
+            dol_offset: 11,
+            fst_offset: 12,
+            fst_size: 13,
+            fst_max_size: 14,
+            user_position: 15,
+            user_size: 16,
+            disc_size: 17,
+            unused_43c: 18,
+        });
+    }
+
+    #[test]
+    fn test_write_and_read_disc_header_info() {
+        assert_write_and_read!(DiscHeaderInfo {
+            debug_monitor_size: 1,
+            simulated_memory_size: 0x1800000,
+            argument_offset: 3,
+            debug_flag: 4,
+            track_location: 5,
+            track_size: 6,
+            region: RegionCode::Pal,
+            unk_01c: 7,
+            long_file_names: 8,
+            pad_spec: 9,
+            dol_limit: 10,
+            unused_02c: vec![11; BI2_SIZE - BI2_FIELDS_SIZE].into_boxed_slice(),
+        });
+    }
+
+    #[test]
+    fn test_write_and_read_apploader_header() {
+        assert_write_and_read!(ApploaderHeader {
+            date: Text::from_bytes("2004/02/02").unwrap(),
+            entry_point: 0x81200000,
+            code_size: 0x1000,
+            trailer_size: 0x200,
+            unused_01c: 1,
+        });
+    }
+
+    #[test]
+    fn test_game_id() -> Result<()> {
+        let mut header = DiscHeader::new();
+        header.set_game_id("GGTP01")?;
+        assert_eq!(header.game_code, *b"GGTP");
+        assert_eq!(header.maker_code, *b"01");
+        assert_eq!(header.game_id(), "GGTP01");
+        assert!(matches!(header.set_game_id("GGTE0"), Err(Error::InvalidGameId(_))));
+        assert!(matches!(header.set_game_id("GGTE-1"), Err(Error::InvalidGameId(_))));
+        assert_eq!(header.game_id(), "GGTP01");
+        Ok(())
+    }
+
+    #[test]
+    fn test_region_code() {
+        for code in 0..8 {
+            assert_eq!(u32::from(RegionCode::from(code)), code);
+        }
+        assert_eq!(RegionCode::from(2), RegionCode::Pal);
+        assert_eq!(RegionCode::from(3), RegionCode::Unknown(3));
+    }
+}
@@ -1,10 +1,19 @@
-use crate::common::{ReadFrom, WriteTo};
+use crate::common::{self, ReadFrom, ReadStructExt, WriteTo};
 use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt, BE};
+use std::convert::TryFrom;
 use std::io::{self, Read, Write};
+use std::ops::Range;
 use thiserror::Error;
 
-const NUM_TEXT_SECTIONS: usize = 7;
-const NUM_DATA_SECTIONS: usize = 11;
+pub const NUM_TEXT_SECTIONS: usize = 7;
+pub const NUM_DATA_SECTIONS: usize = 11;
+
+/// Size of the DOL header in bytes, including padding.
+const HEADER_SIZE: u32 = 0x100;
+/// Number of bytes in the header which are actually used.
+const HEADER_USED_SIZE: u32 = ((NUM_TEXT_SECTIONS + NUM_DATA_SECTIONS) * 12 + 12) as u32;
+/// Alignment of sections written by `Dol::write_to()`.
+const SECTION_ALIGN: u32 = 0x20;
 
 /// The result type for DOL operations.
 pub type Result<T> = std::result::Result<T, Error>;
@@ -31,6 +40,21 @@ pub enum Error {
     #[error("offset {offset:#x} is past the end of the section ({end:#x})")]
     PastEndOfSection { offset: u32, end: u32 },
 
+    #[error("section at {offset:#x} (size {size:#x}) is outside of the file")]
+    InvalidSection { offset: u32, size: u32 },
+
+    #[error("there are no free {0:?} sections")]
+    NoFreeSection(SectionKind),
+
+    #[error("section at {0:#x} overlaps another section")]
+    OverlappingSection(u32),
+
+    #[error("section at {0:#x} extends past the end of memory")]
+    SectionOverflow(u32),
+
+    #[error("the executable is too large")]
+    TooLarge,
+
     #[error(transparent)]
     Io(Box<io::Error>),
 }
@@ -45,6 +69,13 @@ pub enum DolPointer {
     Bss { offset: u32 },
 }
 
+/// The kinds of sections in an executable.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SectionKind {
+    Text,
+    Data,
+}
+
 /// The header in a Dolphin executable (.dol).
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct DolHeader {
@@ -208,10 +239,256 @@ impl<W: Write + ?Sized> WriteTo<W> for DolHeader {
     }
 }
 
+/// A loaded section of code or data in an executable.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DolSection {
+    /// The memory address the section is loaded to.
+    pub address: u32,
+    /// The section's contents.
+    pub data: Vec<u8>,
+}
+
+impl DolSection {
+    /// Constructs a new `DolSection` which loads `data` to `address`.
+    pub fn new(address: u32, data: Vec<u8>) -> Self {
+        Self { address, data }
+    }
+
+    /// Returns the range of memory addresses covered by the section. Fails if the section extends
+    /// past the end of the address space.
+    pub fn address_range(&self) -> Result<Range<u32>> {
+        let len = u32::try_from(self.data.len()).ok();
+        let end = len.and_then(|len| self.address.checked_add(len));
+        Ok(self.address..end.ok_or(Error::SectionOverflow(self.address))?)
+    }
+}
+
+/// A Dolphin executable (.dol) with all of its sections loaded into memory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dol {
+    /// The text (code) sections. Unused sections are `None`.
+    pub text: [Option<DolSection>; NUM_TEXT_SECTIONS],
+    /// The data sections. Unused sections are `None`.
+    pub data: [Option<DolSection>; NUM_DATA_SECTIONS],
+    pub bss_address: u32,
+    pub bss_size: u32,
+    pub entry_point: u32,
+}
+
+impl Dol {
+    /// Constructs an empty `Dol` with no sections.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the section of `kind` at `index`, if it is in use.
+    pub fn section(&self, kind: SectionKind, index: usize) -> Result<Option<&DolSection>> {
+        match kind {
+            SectionKind::Text => self.text.get(index).ok_or(Error::InvalidTextSection(index)),
+            SectionKind::Data => self.data.get(index).ok_or(Error::InvalidDataSection(index)),
+        }
+        .map(|s| s.as_ref())
+    }
+
+    /// Returns an iterator over all of the sections which are in use.
+    pub fn sections(&self) -> impl Iterator<Item = (SectionKind, usize, &DolSection)> {
+        let text = self.text.iter().enumerate().map(|(i, s)| (SectionKind::Text, i, s));
+        let data = self.data.iter().enumerate().map(|(i, s)| (SectionKind::Data, i, s));
+        text.chain(data).filter_map(|(kind, i, s)| s.as_ref().map(|s| (kind, i, s)))
+    }
+
+    /// Returns the bytes loaded at `address`, which must all be inside the same section.
+    pub fn read(&self, address: u32, len: u32) -> Result<&[u8]> {
+        let (section, start) = self.locate(address, len)?;
+        Ok(&section.data[start..(start + len as usize)])
+    }
+
+    /// Overwrites the bytes loaded at `address` with `bytes`, which must all be inside the same
+    /// section.
+    pub fn patch(&mut self, address: u32, bytes: &[u8]) -> Result<()> {
+        let len = u32::try_from(bytes.len()).map_err(|_| Error::UnmappedAddress(address))?;
+        let (_, start) = self.locate(address, len)?;
+        let section = self.section_at_mut(address).unwrap();
+        section.data[start..(start + bytes.len())].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Adds a new section of `kind` which loads `data` to `address` and returns its index.
+    pub fn add_section(&mut self, kind: SectionKind, address: u32, data: Vec<u8>) -> Result<usize> {
+        let section = DolSection::new(address, data);
+        self.check_overlap(&section, None)?;
+        let slots = match kind {
+            SectionKind::Text => &mut self.text[..],
+            SectionKind::Data => &mut self.data[..],
+        };
+        let index = slots.iter().position(|s| s.is_none()).ok_or(Error::NoFreeSection(kind))?;
+        slots[index] = Some(section);
+        Ok(index)
+    }
+
+    /// Resizes the section of `kind` at `index` to `new_size` bytes. New bytes are filled with
+    /// zeros.
+    pub fn resize_section(&mut self, kind: SectionKind, index: usize, new_size: u32) -> Result<()> {
+        let section = match self.section(kind, index)? {
+            Some(section) => section,
+            None => return Err(Self::invalid_index(kind, index)),
+        };
+        let resized = DolSection::new(section.address, vec![0; new_size as usize]);
+        self.check_overlap(&resized, Some((kind, index)))?;
+        let section = match kind {
+            SectionKind::Text => self.text[index].as_mut(),
+            SectionKind::Data => self.data[index].as_mut(),
+        };
+        section.unwrap().data.resize(new_size as usize, 0);
+        Ok(())
+    }
+
+    /// Calculates the header that the executable will be written with.
+    pub fn header(&self) -> Result<DolHeader> {
+        let mut header = DolHeader::new();
+        header.bss_address = self.bss_address;
+        header.bss_size = self.bss_size;
+        header.entry_point = self.entry_point;
+        let mut offset = HEADER_SIZE;
+        let mut place = |section: &Option<DolSection>| -> Result<(u32, u32, u32)> {
+            match section {
+                Some(s) if !s.data.is_empty() => {
+                    let size = u32::try_from(s.data.len()).map_err(|_| Error::TooLarge)?;
+                    let start = offset;
+                    offset = common::align(
+                        start.checked_add(size).ok_or(Error::TooLarge)?,
+                        SECTION_ALIGN,
+                    );
+                    Ok((start, s.address, size))
+                }
+                _ => Ok((0, 0, 0)),
+            }
+        };
+        for (i, section) in self.text.iter().enumerate() {
+            let (offset, address, size) = place(section)?;
+            header.text_offsets[i] = offset;
+            header.text_addresses[i] = address;
+            header.text_sizes[i] = size;
+        }
+        for (i, section) in self.data.iter().enumerate() {
+            let (offset, address, size) = place(section)?;
+            header.data_offsets[i] = offset;
+            header.data_addresses[i] = address;
+            header.data_sizes[i] = size;
+        }
+        Ok(header)
+    }
+
+    /// Finds the section containing `len` bytes at `address` and returns it along with the offset
+    /// of the address in the section data.
+    fn locate(&self, address: u32, len: u32) -> Result<(&DolSection, usize)> {
+        let section = self.section_at(address).ok_or(Error::UnmappedAddress(address))?;
+        let end = address.checked_add(len).ok_or(Error::UnmappedAddress(address))?;
+        let section_end = section.address_range()?.end;
+        if end > section_end {
+            return Err(Error::UnmappedAddress(section_end));
+        }
+        Ok((section, (address - section.address) as usize))
+    }
+
+    fn section_at(&self, address: u32) -> Option<&DolSection> {
+        self.sections()
+            .map(|(_, _, s)| s)
+            .find(|s| s.address_range().is_ok_and(|r| r.contains(&address)))
+    }
+
+    fn section_at_mut(&mut self, address: u32) -> Option<&mut DolSection> {
+        let text = self.text.iter_mut();
+        let data = self.data.iter_mut();
+        text.chain(data).flatten().find(|s| s.address_range().is_ok_and(|r| r.contains(&address)))
+    }
+
+    /// Fails if `section` would overlap any section besides `exclude`.
+    fn check_overlap(
+        &self,
+        section: &DolSection,
+        exclude: Option<(SectionKind, usize)>,
+    ) -> Result<()> {
+        let range = section.address_range()?;
+        for (_, _, other) in self.sections().filter(|&(kind, i, _)| exclude != Some((kind, i))) {
+            let other = other.address_range()?;
+            if range.start < other.end && other.start < range.end {
+                return Err(Error::OverlappingSection(section.address));
+            }
+        }
+        Ok(())
+    }
+
+    fn invalid_index(kind: SectionKind, index: usize) -> Error {
+        match kind {
+            SectionKind::Text => Error::InvalidTextSection(index),
+            SectionKind::Data => Error::InvalidDataSection(index),
+        }
+    }
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for Dol {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        let header = reader.read_struct::<DolHeader>()?;
+
+        // Sections can be stored in any order, so just read everything
+        let mut bytes = vec![0u8; HEADER_USED_SIZE as usize];
+        let file_size = header.file_size().max(HEADER_SIZE);
+        reader.take((file_size - HEADER_USED_SIZE) as u64).read_to_end(&mut bytes)?;
+        let load = |offset: u32, address: u32, size: u32| -> Result<Option<DolSection>> {
+            if size == 0 {
+                return Ok(None);
+            }
+            let start = offset as usize;
+            let end = start + size as usize;
+            if offset < HEADER_SIZE || end > bytes.len() {
+                return Err(Error::InvalidSection { offset, size });
+            }
+            let section = DolSection::new(address, bytes[start..end].to_vec());
+            section.address_range()?;
+            Ok(Some(section))
+        };
+
+        let mut dol = Self::new();
+        for (i, section) in dol.text.iter_mut().enumerate() {
+            *section =
+                load(header.text_offsets[i], header.text_addresses[i], header.text_sizes[i])?;
+        }
+        for (i, section) in dol.data.iter_mut().enumerate() {
+            *section =
+                load(header.data_offsets[i], header.data_addresses[i], header.data_sizes[i])?;
+        }
+        dol.bss_address = header.bss_address;
+        dol.bss_size = header.bss_size;
+        dol.entry_point = header.entry_point;
+        Ok(dol)
+    }
+}
+
+impl<W: Write + ?Sized> WriteTo<W> for Dol {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        let header = self.header()?;
+        let mut bytes = vec![0u8; header.file_size().max(HEADER_SIZE) as usize];
+        header.write_to(&mut &mut *bytes)?;
+        for (kind, i, section) in self.sections() {
+            let offset = match kind {
+                SectionKind::Text => header.text_offsets[i],
+                SectionKind::Data => header.data_offsets[i],
+            } as usize;
+            bytes[offset..(offset + section.data.len())].copy_from_slice(&section.data);
+        }
+        writer.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::assert_write_and_read;
+    use crate::common::WriteStructExt;
 
     static TEST_HEADER: DolHeader = DolHeader {
         text_offsets: [0x100, 0x2600, 0, 0, 0, 0, 0],
@@ -250,6 +527,110 @@ mod tests {
         Ok(())
     }
 
+    fn make_test_dol() -> Dol {
+        let mut dol = Dol::new();
+        dol.text[0] = Some(DolSection::new(0x80003100, vec![1; 0x24]));
+        dol.data[0] = Some(DolSection::new(0x80004000, vec![2; 0x10]));
+        dol.data[2] = Some(DolSection::new(0x80005000, vec![3; 0x8]));
+        dol.bss_address = 0x80006000;
+        dol.bss_size = 0x100;
+        dol.entry_point = 0x80003100;
+        dol
+    }
+
+    #[test]
+    fn test_write_and_read_dol() -> Result<()> {
+        let dol = make_test_dol();
+        let header = dol.header()?;
+        assert_eq!(header.text_offsets[..2], [0x100, 0]);
+        assert_eq!(header.data_offsets[..3], [0x140, 0, 0x160]);
+        assert_eq!(header.data_addresses[..3], [0x80004000, 0, 0x80005000]);
+        assert_eq!(header.data_sizes[..3], [0x10, 0, 0x8]);
+        assert_eq!(header.file_size(), 0x168);
+        assert_write_and_read!(dol);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_dol_invalid_section() {
+        let mut header = DolHeader::new();
+        header.text_offsets[0] = 0x80;
+        header.text_sizes[0] = 0x100;
+        let mut bytes = vec![];
+        bytes.write_struct(&header).unwrap();
+        bytes.resize(0x180, 0);
+        let result = bytes.as_slice().read_struct::<Dol>();
+        assert!(matches!(result, Err(Error::InvalidSection { offset: 0x80, size: 0x100 })));
+    }
+
+    #[test]
+    fn test_read_dol_section_overflow() {
+        let mut header = DolHeader::new();
+        header.text_offsets[0] = 0x100;
+        header.text_addresses[0] = 0xffffff00;
+        header.text_sizes[0] = 0x200;
+        let mut bytes = vec![];
+        bytes.write_struct(&header).unwrap();
+        bytes.resize(0x300, 0);
+        let result = bytes.as_slice().read_struct::<Dol>();
+        assert!(matches!(result, Err(Error::SectionOverflow(0xffffff00))));
+    }
+
+    #[test]
+    fn test_section_address_range() {
+        let section = DolSection::new(0x80003100, vec![0; 0x100]);
+        assert_eq!(section.address_range().unwrap(), 0x80003100..0x80003200);
+        let section = DolSection::new(0xffffff00, vec![0; 0x100]);
+        assert!(matches!(section.address_range(), Err(Error::SectionOverflow(0xffffff00))));
+    }
+
+    #[test]
+    fn test_patch_dol() -> Result<()> {
+        let mut dol = make_test_dol();
+        dol.patch(0x80003120, &[0xaa; 4])?;
+        assert_eq!(dol.read(0x8000311e, 6)?, [1, 1, 0xaa, 0xaa, 0xaa, 0xaa]);
+        dol.patch(0x80005000, &[0xbb])?;
+        assert_eq!(dol.data[2].as_ref().unwrap().data[..2], [0xbb, 3]);
+        assert!(matches!(dol.patch(0x80003122, &[0; 4]), Err(Error::UnmappedAddress(0x80003124))));
+        assert!(matches!(dol.patch(0x80006000, &[0]), Err(Error::UnmappedAddress(0x80006000))));
+        assert!(dol.read(0x80004010, 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_and_resize_sections() -> Result<()> {
+        let mut dol = make_test_dol();
+        assert_eq!(dol.add_section(SectionKind::Data, 0x80007000, vec![4; 4])?, 1);
+        assert_eq!(dol.add_section(SectionKind::Text, 0x80008000, vec![5; 4])?, 1);
+        assert!(matches!(
+            dol.add_section(SectionKind::Text, 0x80004008, vec![0; 4]),
+            Err(Error::OverlappingSection(0x80004008))
+        ));
+        for i in 2..NUM_TEXT_SECTIONS {
+            dol.add_section(SectionKind::Text, 0x80010000 + (i as u32) * 0x100, vec![0; 4])?;
+        }
+        assert!(matches!(
+            dol.add_section(SectionKind::Text, 0x80020000, vec![]),
+            Err(Error::NoFreeSection(SectionKind::Text))
+        ));
+
+        dol.resize_section(SectionKind::Data, 0, 0x1000)?;
+        assert_eq!(dol.read(0x80004ff0, 0x10)?, [0; 0x10]);
+        assert!(matches!(
+            dol.resize_section(SectionKind::Data, 0, 0x1001),
+            Err(Error::OverlappingSection(0x80004000))
+        ));
+        assert!(matches!(
+            dol.resize_section(SectionKind::Data, 3, 0x10),
+            Err(Error::InvalidDataSection(3))
+        ));
+
+        let mut bytes = vec![];
+        bytes.write_struct(&dol)?;
+        assert_eq!(bytes.as_slice().read_struct::<Dol>()?, dol);
+        Ok(())
+    }
+
     #[test]
     fn test_file_size() {
         assert_eq!(TEST_HEADER.file_size(), 0x25e6a0);
@@ -1,5 +1,5 @@
 use crate::common::{
-    string_table, ReadFrom, ReadSeek, ReadWriteSeek, Region, StringTable, WriteTo,
+    compression, string_table, ReadFrom, ReadSeek, ReadWriteSeek, Region, StringTable, WriteTo,
 };
 use byteorder::{ReadBytesExt, WriteBytesExt, BE};
 use encoding_rs::SHIFT_JIS;
@@ -57,15 +57,28 @@ pub enum Error {
     #[error("entry {0:?} was not found")]
     NotFound(String),
 
+    #[error("entry {0:?} already exists")]
+    AlreadyExists(String),
+
+    #[error("the root directory cannot be removed or renamed")]
+    RootNotAllowed,
+
+    #[error("directory {0:?} cannot be moved inside itself")]
+    MoveIntoSelf(String),
+
     #[error(transparent)]
     Io(Box<io::Error>),
 
     #[error(transparent)]
     StringTable(Box<string_table::Error>),
+
+    #[error(transparent)]
+    Compression(Box<compression::Error>),
 }
 
 from_error_boxed!(Error::Io, io::Error);
 from_error_boxed!(Error::StringTable, string_table::Error);
+from_error_boxed!(Error::Compression, compression::Error);
 
 /// Returns true if a name is safe from directory traversal attacks.
 fn is_name_safe(name: &str) -> bool {
@@ -587,6 +600,39 @@ impl FileTree {
         Err(Error::NotFound(path.to_owned()))
     }
 
+    /// Splits `path` into its parent directory and final component. Returns a `(parent, name)`
+    /// tuple, where `parent` is the ID of the parent directory. The final component does not need
+    /// to exist, which makes this useful for resolving the destination of a new entry.
+    pub fn parent_and_name<'p>(&self, path: &'p str) -> Result<(EntryId, &'p str)> {
+        let trimmed = path.trim_end_matches(['/', '\\']);
+        let (parent_path, name) = match trimmed.rfind(['/', '\\']) {
+            Some(index) => (&trimmed[..index], &trimmed[(index + 1)..]),
+            None => ("", trimmed),
+        };
+        if !is_name_safe(name) {
+            return Err(Error::UnsafeName(name.to_owned()));
+        }
+        let parent = self.at(parent_path)?;
+        if self[parent].is_file() {
+            return Err(Error::ExpectedDirectory(parent));
+        }
+        Ok((parent, name))
+    }
+
+    /// Returns the ID of the child of the `dir` directory named `name`, if any.
+    pub fn child(&self, dir: EntryId, name: &str) -> Option<EntryId> {
+        let dir = self[dir].dir()?;
+        dir.children.iter().copied().find(|&id| self[id].name() == name)
+    }
+
+    /// Returns the ID of the directory containing `id`, or `None` if `id` is the root directory.
+    pub fn parent(&self, id: EntryId) -> Option<EntryId> {
+        self.entries.iter().find_map(|(parent_id, entry)| match entry {
+            Entry::Directory(dir) if dir.children.contains(&id) => Some(parent_id),
+            _ => None,
+        })
+    }
+
     /// Inserts `entry` as a child of the `parent_id` directory and returns its ID.
     pub fn insert(&mut self, parent_id: EntryId, entry: Entry) -> EntryId {
         let id = self.entries.insert(entry);
@@ -598,6 +644,74 @@ impl FileTree {
         id
     }
 
+    /// Inserts `entry` as a child of the `parent_id` directory and returns its ID. Unlike
+    /// `insert()`, this checks that the name is safe and not already taken.
+    pub fn try_insert(&mut self, parent_id: EntryId, entry: Entry) -> Result<EntryId> {
+        if self[parent_id].is_file() {
+            return Err(Error::ExpectedDirectory(parent_id));
+        }
+        let name = entry.name();
+        if !is_name_safe(name) {
+            return Err(Error::UnsafeName(name.to_owned()));
+        }
+        if self.child(parent_id, name).is_some() {
+            return Err(Error::AlreadyExists(name.to_owned()));
+        }
+        Ok(self.insert(parent_id, entry))
+    }
+
+    /// Removes the entry corresponding to `id` along with all of its descendants. Returns the
+    /// removed file entries so that the caller can reclaim their data.
+    pub fn remove(&mut self, id: EntryId) -> Result<Vec<FileEntry>> {
+        let parent_id = self.parent(id).ok_or(Error::RootNotAllowed)?;
+        let parent = self[parent_id].dir_mut().unwrap();
+        parent.children.retain(|&c| c != id);
+
+        let mut removed = vec![];
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            match self.entries.remove(id).unwrap() {
+                Entry::File(file) => removed.push(file),
+                Entry::Directory(dir) => pending.extend(dir.children),
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Moves the entry corresponding to `id` into the `new_parent` directory and renames it to
+    /// `new_name`.
+    pub fn rename(&mut self, id: EntryId, new_parent: EntryId, new_name: &str) -> Result<()> {
+        let old_parent = self.parent(id).ok_or(Error::RootNotAllowed)?;
+        if self[new_parent].is_file() {
+            return Err(Error::ExpectedDirectory(new_parent));
+        }
+        if !is_name_safe(new_name) {
+            return Err(Error::UnsafeName(new_name.to_owned()));
+        }
+        match self.child(new_parent, new_name) {
+            Some(existing) if existing == id => return Ok(()),
+            Some(_) => return Err(Error::AlreadyExists(new_name.to_owned())),
+            None => (),
+        }
+
+        // Walk up from the new parent to make sure we aren't moving a directory inside itself
+        let mut ancestor = Some(new_parent);
+        while let Some(a) = ancestor {
+            if a == id {
+                return Err(Error::MoveIntoSelf(self[id].name().to_owned()));
+            }
+            ancestor = self.parent(a);
+        }
+
+        self[old_parent].dir_mut().unwrap().children.retain(|&c| c != id);
+        self[new_parent].dir_mut().unwrap().children.push(id);
+        match &mut self[id] {
+            Entry::File(file) => file.name = new_name.to_owned(),
+            Entry::Directory(dir) => dir.name = new_name.to_owned(),
+        }
+        Ok(())
+    }
+
     /// Builds a `FileStringTable` from this tree. Returns an `(fst, ids)` tuple, where `ids` is a
     /// list of the `EntryId` corresponding to each FST entry.
     pub fn to_fst(&self) -> Result<(FileStringTable, Vec<EntryId>)> {
@@ -693,6 +807,18 @@ pub trait OpenFile {
     fn into_file_at<'s>(self, path: &str) -> Result<Box<dyn ReadSeek + 's>>
     where
         Self: 's;
+
+    /// Similar to `open_file()`, but if the file is Yaz0 or Yay0 compressed, the returned reader
+    /// reads the decompressed data instead.
+    fn open_file_decompressed(&mut self, id: EntryId) -> Result<Box<dyn ReadSeek + '_>> {
+        Ok(compression::open_decompressed(self.open_file(id)?)?)
+    }
+
+    /// Similar to `open_file_at()`, but if the file is Yaz0 or Yay0 compressed, the returned
+    /// reader reads the decompressed data instead.
+    fn open_file_decompressed_at(&mut self, path: &str) -> Result<Box<dyn ReadSeek + '_>> {
+        Ok(compression::open_decompressed(self.open_file_at(path)?)?)
+    }
 }
 
 impl<T: OpenFile> OpenFile for &mut T {
@@ -966,6 +1092,89 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parent_and_name() -> Result<()> {
+        let files = TEST_TREE.clone();
+        let ghi = files.at("ghi")?;
+        assert_eq!(files.parent_and_name("foo")?, (files.root(), "foo"));
+        assert_eq!(files.parent_and_name("/ghi/foo")?, (ghi, "foo"));
+        assert_eq!(files.parent_and_name("ghi\\foo/")?, (ghi, "foo"));
+        assert!(matches!(files.parent_and_name("abc/foo"), Err(Error::ExpectedDirectory(_))));
+        assert!(matches!(files.parent_and_name("xyz/foo"), Err(Error::NotFound(_))));
+        assert!(matches!(files.parent_and_name("ghi/.."), Err(Error::UnsafeName(_))));
+        assert!(matches!(files.parent_and_name("/"), Err(Error::UnsafeName(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parent() -> Result<()> {
+        assert_eq!(TEST_TREE.parent(TEST_TREE.root()), None);
+        assert_eq!(TEST_TREE.parent(TEST_TREE.at("abc")?), Some(TEST_TREE.root()));
+        assert_eq!(TEST_TREE.parent(TEST_TREE.at("ghi/jkl/mno")?), Some(TEST_TREE.at("ghi/jkl")?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_insert() -> Result<()> {
+        let mut files = TEST_TREE.clone();
+        let ghi = files.at("ghi")?;
+        let foo = files.try_insert(ghi, file("foo"))?;
+        assert_eq!(files.at("ghi/foo")?, foo);
+        assert!(matches!(files.try_insert(ghi, file("foo")), Err(Error::AlreadyExists(_))));
+        assert!(matches!(files.try_insert(ghi, dir("stu")), Err(Error::AlreadyExists(_))));
+        assert!(matches!(files.try_insert(ghi, file("a/b")), Err(Error::UnsafeName(_))));
+        assert!(matches!(files.try_insert(foo, file("bar")), Err(Error::ExpectedDirectory(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove() -> Result<()> {
+        let mut files = TEST_TREE.clone();
+        let removed = files.remove(files.at("abc")?)?;
+        assert_eq!(removed, [FileEntry::new("abc", 1, 2)]);
+        assert!(files.at("abc").is_err());
+
+        let removed = files.remove(files.at("ghi")?)?;
+        assert_eq!(removed, [FileEntry::new("stu", 1, 2)]);
+        assert!(files.at("ghi").is_err());
+        assert_eq!(files.len(), 3);
+
+        assert!(matches!(files.remove(files.root()), Err(Error::RootNotAllowed)));
+        let (fst, _) = files.to_fst()?;
+        assert_eq!(fst.entries.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename() -> Result<()> {
+        let mut files = TEST_TREE.clone();
+        let root = files.root();
+        let abc = files.at("abc")?;
+        let ghi = files.at("ghi")?;
+        let jkl = files.at("ghi/jkl")?;
+
+        files.rename(abc, root, "foo")?;
+        assert_eq!(files.at("foo")?, abc);
+        assert!(files.at("abc").is_err());
+
+        files.rename(abc, jkl, "bar")?;
+        assert_eq!(files.at("ghi/jkl/bar")?, abc);
+        assert!(files.at("foo").is_err());
+
+        files.rename(jkl, root, "jkl")?;
+        assert_eq!(files.at("jkl/bar")?, abc);
+        assert!(files.at("ghi/jkl").is_err());
+
+        assert!(matches!(files.rename(abc, root, "def"), Err(Error::AlreadyExists(_))));
+        assert!(matches!(files.rename(abc, root, ".."), Err(Error::UnsafeName(_))));
+        assert!(matches!(files.rename(root, ghi, "root"), Err(Error::RootNotAllowed)));
+        assert!(matches!(
+            files.rename(jkl, files.at("jkl/mno")?, "x"),
+            Err(Error::MoveIntoSelf(_))
+        ));
+        Ok(())
+    }
+
     #[test]
     fn test_is_name_safe() {
         assert!(!is_name_safe(""));
@@ -0,0 +1,434 @@
+use super::dol::{self, Dol, DolPointer, SectionKind};
+use byteorder::{ByteOrder, BE};
+use thiserror::Error;
+
+/// The memory address where code inserted by C2 codes is loaded. This is the same area that the
+/// Gecko code handler uses, which games do not touch.
+pub const CODE_START: u32 = 0x80001800;
+/// The end of the memory area where code can be inserted.
+pub const CODE_END: u32 = 0x80003000;
+
+/// The result type for Gecko code operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error type for Gecko code operations.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("line {0}: expected a code line in the form \"XXXXXXXX YYYYYYYY\"")]
+    InvalidLine(usize),
+
+    #[error("line {0}: code lines must come after a \"$\" name line")]
+    UnnamedCode(usize),
+
+    #[error("code \"{0}\" is truncated")]
+    Truncated(String),
+
+    #[error("code \"{name}\" uses unsupported code type {code_type:02X}")]
+    UnsupportedCodeType { name: String, code_type: u8 },
+
+    #[error("code \"{name}\" writes to {address:#x}, which is not loaded from main.dol")]
+    UnmappedAddress { name: String, address: u32 },
+
+    #[error("cannot branch from {from:#x} to {to:#x}")]
+    BranchOutOfRange { from: u32, to: u32 },
+
+    #[error("not enough space to insert code (need {0:#x} bytes)")]
+    NotEnoughSpace(u32),
+
+    #[error(transparent)]
+    Dol(Box<dol::Error>),
+}
+
+from_error_boxed!(Error::Dol, dol::Error);
+
+/// A named Gecko code.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeckoCode {
+    /// The code's name, taken from its `$` line.
+    pub name: String,
+    /// The code's lines, each made up of two 32-bit words.
+    pub lines: Vec<[u32; 2]>,
+}
+
+impl GeckoCode {
+    /// Constructs an empty `GeckoCode` named `name`.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), lines: vec![] }
+    }
+
+    /// Parses a list of codes from text. The text can either be a plain list of codes or a
+    /// Dolphin game INI, in which case only the `[Gecko]` section is read. Each code starts with a
+    /// `$` line containing its name. Blank lines and lines beginning with `*` or `#` are ignored.
+    pub fn parse_all(text: &str) -> Result<Vec<Self>> {
+        Ok(parse_codes(text)?.0)
+    }
+
+    /// Parses a list of codes like `parse_all()`, but if the text is a Dolphin game INI with a
+    /// `[Gecko_Enabled]` section, only the codes listed in that section are returned.
+    pub fn parse_enabled(text: &str) -> Result<Vec<Self>> {
+        let (mut codes, enabled) = parse_codes(text)?;
+        if let Some(enabled) = enabled {
+            codes.retain(|c| enabled.iter().any(|n| n == c.name_without_creator()));
+        }
+        Ok(codes)
+    }
+
+    /// Returns the code's name without the `[creator]` suffix that Dolphin leaves out of
+    /// `[Gecko_Enabled]`.
+    fn name_without_creator(&self) -> &str {
+        self.name.split('[').next().unwrap_or_default().trim()
+    }
+}
+
+/// Parses the codes in `text` along with the names in its `[Gecko_Enabled]` section, if it has
+/// one.
+fn parse_codes(text: &str) -> Result<(Vec<GeckoCode>, Option<Vec<String>>)> {
+    let mut codes: Vec<GeckoCode> = vec![];
+    let mut enabled: Option<Vec<String>> = None;
+    let mut in_gecko = true;
+    let mut in_enabled = false;
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('*') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_gecko = section.eq_ignore_ascii_case("Gecko");
+            in_enabled = section.eq_ignore_ascii_case("Gecko_Enabled");
+            if in_enabled {
+                enabled.get_or_insert_with(Vec::new);
+            }
+        } else if in_enabled {
+            if let (Some(names), Some(name)) = (&mut enabled, line.strip_prefix('$')) {
+                names.push(name.trim().to_owned());
+            }
+        } else if !in_gecko {
+            continue;
+        } else if let Some(name) = line.strip_prefix('$') {
+            codes.push(GeckoCode::new(name.trim()));
+        } else {
+            let words = parse_line(line).ok_or(Error::InvalidLine(i + 1))?;
+            codes.last_mut().ok_or(Error::UnnamedCode(i + 1))?.lines.push(words);
+        }
+    }
+    Ok((codes, enabled))
+}
+
+/// Parses a code line consisting of two 8-digit hex words.
+fn parse_line(line: &str) -> Option<[u32; 2]> {
+    let mut words = line.split_whitespace().map(|w| {
+        if w.len() == 8 {
+            u32::from_str_radix(w, 16).ok()
+        } else {
+            None
+        }
+    });
+    let result = [words.next()??, words.next()??];
+    words.next().is_none().then_some(result)
+}
+
+/// Encodes a `b` instruction at `from` which jumps to `to`.
+fn branch(from: u32, to: u32) -> Result<u32> {
+    let delta = to.wrapping_sub(from) as i32;
+    if !(-0x2000000..0x2000000).contains(&delta) || delta & 3 != 0 {
+        return Err(Error::BranchOutOfRange { from, to });
+    }
+    Ok(0x48000000 | (delta as u32 & 0x03fffffc))
+}
+
+/// Statically applies Gecko codes to an executable so that they take effect without a code
+/// handler.
+///
+/// Supported code types are the direct (non-pointer) RAM writes (00, 02, 04, 06), insert ASM
+/// (C2), branches (C6), and terminators (E0, F0). Code inserted by C2 codes is placed in a new
+/// text section starting at `CODE_START`, or appended to it if it already exists.
+pub struct GeckoPatcher<'a> {
+    dol: &'a mut Dol,
+    /// The index of the text section holding inserted code, if there is one.
+    code_section: Option<usize>,
+    /// Inserted code which has not been added to the executable yet.
+    code: Vec<u8>,
+    /// The address that `code` will be loaded to.
+    code_address: u32,
+}
+
+impl<'a> GeckoPatcher<'a> {
+    /// Constructs a new `GeckoPatcher` which patches `dol`.
+    pub fn new(dol: &'a mut Dol) -> Result<Self> {
+        let code_section =
+            dol.text.iter().position(|s| matches!(s, Some(s) if s.address == CODE_START));
+        let code_address = match code_section {
+            Some(i) => dol.text[i].as_ref().unwrap().address_range()?.end,
+            None => CODE_START,
+        };
+        Ok(Self { dol, code_section, code: vec![], code_address })
+    }
+
+    /// Applies `code` to the executable. If this fails, the executable may be partially patched.
+    pub fn apply(&mut self, code: &GeckoCode) -> Result<()> {
+        let truncated = || Error::Truncated(code.name.clone());
+        let mut lines = code.lines.iter();
+        while let Some(&[first, second]) = lines.next() {
+            let code_type = (first >> 24) as u8 & 0xfe;
+            let address = 0x80000000 | (first & 0x01ffffff);
+            match code_type {
+                0x00 | 0x02 => {
+                    // Fill writes repeat the value `count + 1` times
+                    let count = (second >> 16) as usize + 1;
+                    let bytes = if code_type == 0x00 {
+                        vec![second as u8; count]
+                    } else {
+                        (second as u16).to_be_bytes().repeat(count)
+                    };
+                    self.write(code, address, &bytes)?;
+                }
+                0x04 => self.write(code, address, &second.to_be_bytes())?,
+                0x06 => {
+                    let len = second as usize;
+                    if len > lines.len() * 8 {
+                        return Err(truncated());
+                    }
+                    let mut bytes = Vec::with_capacity(len.next_multiple_of(8));
+                    while bytes.len() < len {
+                        let &[a, b] = lines.next().ok_or_else(truncated)?;
+                        bytes.extend(a.to_be_bytes());
+                        bytes.extend(b.to_be_bytes());
+                    }
+                    bytes.truncate(len);
+                    self.write(code, address, &bytes)?;
+                }
+                0xc2 => {
+                    let num_lines = second as usize;
+                    if num_lines == 0 || num_lines > lines.len() {
+                        return Err(truncated());
+                    }
+                    let mut asm = vec![0u8; num_lines * 8];
+                    for chunk in asm.chunks_exact_mut(8) {
+                        let &[a, b] = lines.next().ok_or_else(truncated)?;
+                        BE::write_u32(&mut chunk[..4], a);
+                        BE::write_u32(&mut chunk[4..], b);
+                    }
+                    self.insert_asm(code, address, asm)?;
+                }
+                0xc6 => {
+                    let instruction = branch(address, second)?;
+                    self.write(code, address, &instruction.to_be_bytes())?;
+                }
+                // Terminators are only meaningful to the code handler
+                0xe0 | 0xf0 => (),
+                _ => {
+                    let name = code.name.clone();
+                    return Err(Error::UnsupportedCodeType {
+                        name,
+                        code_type: (first >> 24) as u8,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds any inserted code to the executable. This must be called after all codes have been
+    /// applied.
+    pub fn finish(self) -> Result<()> {
+        if self.code.is_empty() {
+            return Ok(());
+        }
+        match self.code_section {
+            Some(index) => {
+                let section = self.dol.text[index].as_mut().unwrap();
+                section.data.extend(self.code);
+            }
+            None => {
+                self.dol.add_section(SectionKind::Text, CODE_START, self.code)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes `bytes` to `address` in the executable. Writes to addresses which are not loaded
+    /// from the file (e.g. BSS) cannot be applied statically.
+    fn write(&mut self, code: &GeckoCode, address: u32, bytes: &[u8]) -> Result<()> {
+        let header = self.dol.header()?;
+        let unmapped = || Error::UnmappedAddress { name: code.name.clone(), address };
+        match header.locate_address(address) {
+            Ok(DolPointer::Text { .. } | DolPointer::Data { .. }) => (),
+            Ok(DolPointer::Bss { .. }) | Err(dol::Error::UnmappedAddress(_)) => {
+                return Err(unmapped())
+            }
+            Err(e) => return Err(e.into()),
+        }
+        match self.dol.patch(address, bytes) {
+            Ok(()) => Ok(()),
+            Err(dol::Error::UnmappedAddress(_)) => Err(unmapped()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Inserts `asm` into the inserted code area and replaces the instruction at `address` with a
+    /// branch to it. The last instruction in `asm` is replaced with a branch back to the
+    /// instruction after `address`, the same as the code handler does.
+    fn insert_asm(&mut self, code: &GeckoCode, address: u32, mut asm: Vec<u8>) -> Result<()> {
+        let start = self.code_address + self.code.len() as u32;
+        let end = start + asm.len() as u32;
+        if end > CODE_END {
+            return Err(Error::NotEnoughSpace(end - self.code_address));
+        }
+        let return_offset = asm.len() - 4;
+        BE::write_u32(&mut asm[return_offset..], branch(end - 4, address + 4)?);
+        self.write(code, address, &branch(address, start)?.to_be_bytes())?;
+        self.code.extend(asm);
+        Ok(())
+    }
+}
+
+/// Convenience function for applying a list of codes to `dol`.
+pub fn apply_codes(dol: &mut Dol, codes: &[GeckoCode]) -> Result<()> {
+    let mut patcher = GeckoPatcher::new(dol)?;
+    for code in codes {
+        patcher.apply(code)?;
+    }
+    patcher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dvd::dol::DolSection;
+
+    /// The `ori r0, r0, 0` instruction.
+    const NOP: u32 = 0x60000000;
+
+    const TEST_CODES: &str = "\
+[Gecko]
+$Write Stuff [someone]
+04003104 12345678
+00003110 0002aabb
+* This is a note
+02003120 0001ccdd
+06003130 00000005
+01020304 05ffffff
+$Insert ASM
+C2003100 00000002
+38600001 60000000
+38800002 00000000
+E0000000 80008000
+[Gecko_Enabled]
+$Write Stuff
+";
+
+    fn make_test_dol() -> Dol {
+        let mut dol = Dol::new();
+        dol.text[0] = Some(DolSection::new(0x80003100, vec![0; 0x100]));
+        dol.bss_address = 0x80004000;
+        dol.bss_size = 0x100;
+        dol
+    }
+
+    fn read_u32(dol: &Dol, address: u32) -> u32 {
+        BE::read_u32(dol.read(address, 4).unwrap())
+    }
+
+    #[test]
+    fn test_parse_codes() -> Result<()> {
+        let codes = GeckoCode::parse_all(TEST_CODES)?;
+        assert_eq!(codes.len(), 2);
+        assert_eq!(codes[0].name, "Write Stuff [someone]");
+        assert_eq!(codes[0].lines.len(), 5);
+        assert_eq!(codes[0].lines[4], [0x01020304, 0x05ffffff]);
+        assert_eq!(codes[1].name, "Insert ASM");
+        assert_eq!(codes[1].lines.len(), 4);
+
+        assert!(matches!(GeckoCode::parse_all("$A\n0400000 00000000"), Err(Error::InvalidLine(2))));
+        assert!(matches!(GeckoCode::parse_all("04000000 00000000"), Err(Error::UnnamedCode(1))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_enabled_codes() -> Result<()> {
+        let codes = GeckoCode::parse_enabled(TEST_CODES)?;
+        assert_eq!(codes.len(), 1);
+        assert_eq!(codes[0].name, "Write Stuff [someone]");
+
+        // Without a [Gecko_Enabled] section, every code is enabled
+        let (all, _) = TEST_CODES.split_once("[Gecko_Enabled]").unwrap();
+        assert_eq!(GeckoCode::parse_enabled(all)?.len(), 2);
+        // An empty section disables everything
+        assert!(GeckoCode::parse_enabled(&format!("{all}[Gecko_Enabled]\n"))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_writes() -> Result<()> {
+        let codes = GeckoCode::parse_all(TEST_CODES)?;
+        let mut dol = make_test_dol();
+        apply_codes(&mut dol, &codes[..1])?;
+        assert_eq!(read_u32(&dol, 0x80003104), 0x12345678);
+        assert_eq!(dol.read(0x80003110, 4)?, [0xbb, 0xbb, 0xbb, 0]);
+        assert_eq!(dol.read(0x80003120, 6)?, [0xcc, 0xdd, 0xcc, 0xdd, 0, 0]);
+        assert_eq!(dol.read(0x80003130, 6)?, [1, 2, 3, 4, 5, 0]);
+        assert!(dol.text[1].is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_insert_asm() -> Result<()> {
+        let codes = GeckoCode::parse_all(TEST_CODES)?;
+        let mut dol = make_test_dol();
+        apply_codes(&mut dol, &codes[1..])?;
+        assert_eq!(read_u32(&dol, 0x80003100), branch(0x80003100, CODE_START)?);
+        let section = dol.text[1].as_ref().unwrap();
+        assert_eq!(section.address, CODE_START);
+        assert_eq!(section.data.len(), 0x10);
+        assert_eq!(read_u32(&dol, CODE_START), 0x38600001);
+        assert_eq!(read_u32(&dol, CODE_START + 0x8), 0x38800002);
+        assert_eq!(read_u32(&dol, CODE_START + 0xc), branch(CODE_START + 0xc, 0x80003104)?);
+
+        // Applying more codes should append to the existing section
+        let code = GeckoCode { name: "More".into(), lines: vec![[0xc2003108, 1], [NOP, 0]] };
+        apply_codes(&mut dol, &[code])?;
+        assert_eq!(dol.text[1].as_ref().unwrap().data.len(), 0x18);
+        assert_eq!(read_u32(&dol, 0x80003108), branch(0x80003108, CODE_START + 0x10)?);
+        assert_eq!(read_u32(&dol, CODE_START + 0x14), branch(CODE_START + 0x14, 0x8000310c)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_branch() -> Result<()> {
+        let mut dol = make_test_dol();
+        let code = GeckoCode { name: "Branch".into(), lines: vec![[0xc6003180, 0x80003100]] };
+        apply_codes(&mut dol, &[code])?;
+        assert_eq!(read_u32(&dol, 0x80003180), 0x4bffff80);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_invalid() {
+        let mut dol = make_test_dol();
+        let apply = |dol: &mut Dol, lines: Vec<[u32; 2]>| {
+            apply_codes(dol, &[GeckoCode { name: "Test".into(), lines }])
+        };
+        assert!(matches!(
+            apply(&mut dol, vec![[0x04004000, 0]]),
+            Err(Error::UnmappedAddress { address: 0x80004000, .. })
+        ));
+        assert!(matches!(
+            apply(&mut dol, vec![[0x04005000, 0]]),
+            Err(Error::UnmappedAddress { address: 0x80005000, .. })
+        ));
+        assert!(matches!(
+            apply(&mut dol, vec![[0x14003100, 0]]),
+            Err(Error::UnsupportedCodeType { code_type: 0x14, .. })
+        ));
+        assert!(matches!(
+            apply(&mut dol, vec![[0x20003100, 0]]),
+            Err(Error::UnsupportedCodeType { code_type: 0x20, .. })
+        ));
+        assert!(matches!(apply(&mut dol, vec![[0x06003100, 9], [0, 0]]), Err(Error::Truncated(_))));
+        assert!(matches!(apply(&mut dol, vec![[0xc2003100, 0x301]]), Err(Error::Truncated(_))));
+        let mut lines = vec![[0xc2003100, 0x301]];
+        lines.resize(0x302, [NOP, NOP]);
+        assert!(matches!(apply(&mut dol, lines), Err(Error::NotEnoughSpace(0x1808))));
+    }
+}
@@ -0,0 +1,312 @@
+mod ciso;
+mod gcz;
+mod wia;
+
+pub use ciso::{write_ciso, CisoReader};
+pub use gcz::{write_gcz, GczReader};
+pub use wia::WiaReader;
+
+use crate::common::ReadSeek;
+use byteorder::{ReadBytesExt, LE};
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use thiserror::Error;
+
+/// The result type for disc image operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error type for disc image operations.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("invalid {0} header")]
+    InvalidHeader(ImageFormat),
+
+    #[error("unsupported {0} compression method: {1}")]
+    UnsupportedCompression(ImageFormat, u32),
+
+    #[error("Wii images are not supported")]
+    WiiNotSupported,
+
+    #[error("{0} images cannot be written")]
+    WriteNotSupported(ImageFormat),
+
+    #[error("the disc is too large to store in a {0} image")]
+    TooLarge(ImageFormat),
+
+    #[error("block at {0:#x} is corrupt")]
+    CorruptBlock(u64),
+
+    #[error("failed to decompress data: {0}")]
+    Decompress(String),
+
+    #[error(transparent)]
+    Io(Box<io::Error>),
+}
+
+from_error_boxed!(Error::Io, io::Error);
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(e) => *e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+const CISO_MAGIC: u32 = 0x4f534943; // "CISO"
+const GCZ_MAGIC: u32 = 0xb10bc001;
+const WIA_MAGIC: u32 = 0x01414957; // "WIA\x01"
+const RVZ_MAGIC: u32 = 0x015a5652; // "RVZ\x01"
+
+/// Disc image file formats.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImageFormat {
+    /// A raw, uncompressed disc image.
+    Iso,
+    /// A compact ISO which omits unused blocks.
+    Ciso,
+    /// Dolphin's zlib-compressed format.
+    Gcz,
+    /// Wii ISO Archive.
+    Wia,
+    /// Dolphin's successor to WIA.
+    Rvz,
+}
+
+impl ImageFormat {
+    /// Detects the format of the image in `reader` by looking at its magic number. The reader
+    /// will be rewound to the beginning afterward.
+    pub fn detect(reader: &mut (impl Read + Seek + ?Sized)) -> Result<Self> {
+        reader.rewind()?;
+        let magic = reader.read_u32::<LE>()?;
+        reader.rewind()?;
+        Ok(match magic {
+            CISO_MAGIC => Self::Ciso,
+            GCZ_MAGIC => Self::Gcz,
+            WIA_MAGIC => Self::Wia,
+            RVZ_MAGIC => Self::Rvz,
+            _ => Self::Iso,
+        })
+    }
+
+    /// Returns the image format corresponding to a file extension, if there is one.
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "iso" | "gcm" => Some(Self::Iso),
+            "ciso" => Some(Self::Ciso),
+            "gcz" => Some(Self::Gcz),
+            "wia" => Some(Self::Wia),
+            "rvz" => Some(Self::Rvz),
+            _ => None,
+        }
+    }
+
+    /// Returns true if images can be written in this format.
+    pub fn is_writable(self) -> bool {
+        matches!(self, Self::Iso | Self::Ciso | Self::Gcz)
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Iso => "ISO",
+            Self::Ciso => "CISO",
+            Self::Gcz => "GCZ",
+            Self::Wia => "WIA",
+            Self::Rvz => "RVZ",
+        })
+    }
+}
+
+/// Opens a disc image and returns its format along with a stream which reads the decoded disc
+/// data. Uncompressed images are returned as-is.
+pub fn open_image<'a>(
+    mut reader: impl ReadSeek + 'a,
+) -> Result<(ImageFormat, Box<dyn ReadSeek + 'a>)> {
+    let format = ImageFormat::detect(&mut reader)?;
+    let stream: Box<dyn ReadSeek + 'a> = match format {
+        ImageFormat::Iso => Box::new(reader),
+        ImageFormat::Ciso => Box::new(BlockStream::new(CisoReader::new(reader)?)),
+        ImageFormat::Gcz => Box::new(BlockStream::new(GczReader::new(reader)?)),
+        ImageFormat::Wia | ImageFormat::Rvz => Box::new(BlockStream::new(WiaReader::new(reader)?)),
+    };
+    Ok((format, stream))
+}
+
+/// Reads `size` bytes of disc data from `reader` and writes them to `writer` as an image in
+/// `format`.
+pub fn write_image(
+    format: ImageFormat,
+    reader: &mut dyn Read,
+    size: u64,
+    mut writer: impl Write + Seek,
+) -> Result<()> {
+    match format {
+        ImageFormat::Iso => {
+            if io::copy(&mut reader.take(size), &mut writer)? != size {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            Ok(())
+        }
+        ImageFormat::Ciso => write_ciso(reader, size, writer),
+        ImageFormat::Gcz => write_gcz(reader, size, writer),
+        ImageFormat::Wia | ImageFormat::Rvz => Err(Error::WriteNotSupported(format)),
+    }
+}
+
+/// Reads `buf.len()` bytes from `reader`, filling anything past the end of the stream with zeros.
+fn read_padded(reader: &mut dyn Read, buf: &mut [u8]) -> io::Result<()> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    buf[len..].fill(0);
+    Ok(())
+}
+
+/// A disc image format which stores disc data in independently-decodable blocks.
+pub trait BlockRead {
+    /// Returns the size of the decoded disc data in bytes.
+    fn data_size(&self) -> u64;
+
+    /// Decodes the block containing `offset` into `buf`, replacing its contents, and returns the
+    /// offset of the start of the block.
+    fn read_block(&mut self, offset: u64, buf: &mut Vec<u8>) -> Result<u64>;
+}
+
+/// Wraps a `BlockRead` implementation in a seekable stream of decoded disc data. The most recently
+/// read block is cached.
+pub struct BlockStream<B: BlockRead> {
+    inner: B,
+    pos: u64,
+    block: Vec<u8>,
+    block_offset: u64,
+}
+
+impl<B: BlockRead> BlockStream<B> {
+    /// Constructs a new `BlockStream` which reads blocks from `inner`.
+    pub fn new(inner: B) -> Self {
+        Self { inner, pos: 0, block: vec![], block_offset: 0 }
+    }
+
+    /// Returns a reference to the inner block reader.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Unwraps this stream, returning the inner block reader.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Returns true if the cached block contains `offset`.
+    fn is_cached(&self, offset: u64) -> bool {
+        offset >= self.block_offset && offset - self.block_offset < self.block.len() as u64
+    }
+}
+
+impl<B: BlockRead> Read for BlockStream<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.data_size();
+        if self.pos >= size || buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.is_cached(self.pos) {
+            self.block_offset = self.inner.read_block(self.pos, &mut self.block)?;
+            if !self.is_cached(self.pos) {
+                return Err(Error::CorruptBlock(self.block_offset).into());
+            }
+        }
+        let start = (self.pos - self.block_offset) as usize;
+        let available = (self.block.len() - start) as u64;
+        let len = buf.len().min(available.min(size - self.pos) as usize);
+        buf[..len].copy_from_slice(&self.block[start..(start + len)]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<B: BlockRead> Seek for BlockStream<B> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.inner.data_size().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        match new_pos {
+            Some(new_pos) => {
+                self.pos = new_pos;
+                Ok(new_pos)
+            }
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Generates test disc data which has some compressible and some incompressible areas.
+    pub(super) fn make_test_data(size: usize) -> Vec<u8> {
+        let mut state = 1u32;
+        (0..size)
+            .map(|i| {
+                if (i / 0x10000) % 2 == 0 {
+                    state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                    (state >> 16) as u8
+                } else {
+                    (i / 0x100) as u8
+                }
+            })
+            .collect()
+    }
+
+    /// Writes test data as an image in `format`, reads it back, and checks that it matches.
+    pub(super) fn assert_round_trip(format: ImageFormat, size: usize) -> Result<()> {
+        let data = make_test_data(size);
+        let mut image = Cursor::new(vec![]);
+        write_image(format, &mut data.as_slice(), size as u64, &mut image)?;
+        let (detected, mut stream) = open_image(image)?;
+        assert_eq!(detected, format);
+
+        let mut decoded = vec![0u8; size];
+        stream.read_exact(&mut decoded)?;
+        assert!(decoded == data);
+
+        // Try a read which crosses a block boundary
+        if size > 0x20010 {
+            stream.seek(SeekFrom::Start(0x1fff0))?;
+            let mut buf = [0u8; 0x20];
+            stream.read_exact(&mut buf)?;
+            assert_eq!(buf, data[0x1fff0..0x20010]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_format() -> Result<()> {
+        let detect = |bytes: &[u8]| ImageFormat::detect(&mut Cursor::new(bytes));
+        assert_eq!(detect(b"CISO\0\0\0\0")?, ImageFormat::Ciso);
+        assert_eq!(detect(&[0x01, 0xc0, 0x0b, 0xb1])?, ImageFormat::Gcz);
+        assert_eq!(detect(b"WIA\x01")?, ImageFormat::Wia);
+        assert_eq!(detect(b"RVZ\x01")?, ImageFormat::Rvz);
+        assert_eq!(detect(b"GGTE01")?, ImageFormat::Iso);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_iso() -> Result<()> {
+        assert_round_trip(ImageFormat::Iso, 0x50000)
+    }
+}
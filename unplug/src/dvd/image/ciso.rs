@@ -0,0 +1,116 @@
+use super::{read_padded, BlockRead, Error, ImageFormat, Result, CISO_MAGIC};
+use crate::common::ReadSeek;
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use std::io::{Read, Seek, SeekFrom, Write};
+use tracing::debug;
+
+/// Size of the CISO header, including the block map.
+const CISO_HEADER_SIZE: u64 = 0x8000;
+/// Maximum number of blocks in a CISO image.
+const CISO_MAP_SIZE: usize = 0x8000 - 8;
+/// Size of each block in images written by `write_ciso()`.
+const CISO_DEFAULT_BLOCK_SIZE: u32 = 0x200000;
+
+/// Reads blocks out of a CISO image. Blocks which are not present in the image read as zeros.
+pub struct CisoReader<R: ReadSeek> {
+    reader: R,
+    block_size: u32,
+    /// The file offset of each block in the image, or `None` if the block is not present.
+    blocks: Vec<Option<u64>>,
+}
+
+impl<R: ReadSeek> CisoReader<R> {
+    /// Opens a CISO image by reading its header from `reader`.
+    pub fn new(mut reader: R) -> Result<Self> {
+        reader.rewind()?;
+        if reader.read_u32::<LE>()? != CISO_MAGIC {
+            return Err(Error::InvalidHeader(ImageFormat::Ciso));
+        }
+        let block_size = reader.read_u32::<LE>()?;
+        if block_size == 0 {
+            return Err(Error::InvalidHeader(ImageFormat::Ciso));
+        }
+        let mut map = vec![0u8; CISO_MAP_SIZE];
+        reader.read_exact(&mut map)?;
+
+        // Only the blocks which are present are stored, so the offsets have to be counted up
+        let mut offset = CISO_HEADER_SIZE;
+        let mut blocks: Vec<_> = map
+            .iter()
+            .map(|&present| {
+                (present != 0).then(|| {
+                    let block_offset = offset;
+                    offset += block_size as u64;
+                    block_offset
+                })
+            })
+            .collect();
+        let num_blocks = blocks.iter().rposition(|b| b.is_some()).map_or(0, |i| i + 1);
+        blocks.truncate(num_blocks);
+        debug!("Opened CISO image: block size = {:#x}, {} blocks", block_size, num_blocks);
+        Ok(Self { reader, block_size, blocks })
+    }
+}
+
+impl<R: ReadSeek> BlockRead for CisoReader<R> {
+    fn data_size(&self) -> u64 {
+        self.blocks.len() as u64 * self.block_size as u64
+    }
+
+    fn read_block(&mut self, offset: u64, buf: &mut Vec<u8>) -> Result<u64> {
+        let index = offset / self.block_size as u64;
+        buf.clear();
+        buf.resize(self.block_size as usize, 0);
+        if let Some(Some(block_offset)) = self.blocks.get(index as usize) {
+            self.reader.seek(SeekFrom::Start(*block_offset))?;
+            read_padded(&mut self.reader, buf)?;
+        }
+        Ok(index * self.block_size as u64)
+    }
+}
+
+/// Reads `size` bytes of disc data from `reader` and writes them to `writer` as a CISO image.
+/// Blocks which are entirely zero are left out of the image, except for the last one so that the
+/// size of the disc is preserved.
+pub fn write_ciso(reader: &mut dyn Read, size: u64, mut writer: impl Write + Seek) -> Result<()> {
+    let block_size = CISO_DEFAULT_BLOCK_SIZE;
+    let num_blocks = size.div_ceil(block_size as u64) as usize;
+    if num_blocks > CISO_MAP_SIZE {
+        return Err(Error::TooLarge(ImageFormat::Ciso));
+    }
+
+    // Leave room for the header and fill it in after we know which blocks are present
+    let start = writer.stream_position()?;
+    writer.seek(SeekFrom::Start(start + CISO_HEADER_SIZE))?;
+    let mut map = vec![0u8; CISO_MAP_SIZE];
+    let mut block = vec![0u8; block_size as usize];
+    let mut reader = reader.take(size);
+    for (i, present) in map.iter_mut().enumerate().take(num_blocks) {
+        read_padded(&mut reader, &mut block)?;
+        if i == num_blocks - 1 || block.iter().any(|&b| b != 0) {
+            writer.write_all(&block)?;
+            *present = 1;
+        }
+    }
+
+    writer.seek(SeekFrom::Start(start))?;
+    writer.write_u32::<LE>(CISO_MAGIC)?;
+    writer.write_u32::<LE>(block_size)?;
+    writer.write_all(&map)?;
+    writer.seek(SeekFrom::End(0))?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::assert_round_trip;
+    use super::*;
+
+    #[test]
+    fn test_write_and_read_ciso() -> Result<()> {
+        // Make it span multiple blocks with a partial block at the end
+        assert_round_trip(ImageFormat::Ciso, 0x480000)?;
+        assert_round_trip(ImageFormat::Ciso, 0x100)
+    }
+}
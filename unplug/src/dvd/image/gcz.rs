@@ -0,0 +1,289 @@
+use super::{read_padded, BlockRead, Error, ImageFormat, Result, GCZ_MAGIC};
+use crate::common::{ReadFrom, ReadSeek, ReadStructExt, WriteStructExt, WriteTo};
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::{Read, Seek, SeekFrom, Write};
+use tracing::debug;
+
+/// Size of the GCZ header.
+const GCZ_HEADER_SIZE: u64 = 0x20;
+/// Size of each block in images written by `write_gcz()`.
+const GCZ_DEFAULT_BLOCK_SIZE: u32 = 0x8000;
+/// Set in a block pointer if the block is stored uncompressed.
+const GCZ_UNCOMPRESSED_FLAG: u64 = 1 << 63;
+/// GCZ sub-type for GameCube discs.
+const GCZ_SUB_TYPE_GAMECUBE: u32 = 0;
+
+/// The header at the start of a GCZ image.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct GczHeader {
+    sub_type: u32,
+    compressed_data_size: u64,
+    data_size: u64,
+    block_size: u32,
+    num_blocks: u32,
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for GczHeader {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        if reader.read_u32::<LE>()? != GCZ_MAGIC {
+            return Err(Error::InvalidHeader(ImageFormat::Gcz));
+        }
+        Ok(Self {
+            sub_type: reader.read_u32::<LE>()?,
+            compressed_data_size: reader.read_u64::<LE>()?,
+            data_size: reader.read_u64::<LE>()?,
+            block_size: reader.read_u32::<LE>()?,
+            num_blocks: reader.read_u32::<LE>()?,
+        })
+    }
+}
+
+impl<W: Write + ?Sized> WriteTo<W> for GczHeader {
+    type Error = Error;
+    fn write_to(&self, writer: &mut W) -> Result<()> {
+        writer.write_u32::<LE>(GCZ_MAGIC)?;
+        writer.write_u32::<LE>(self.sub_type)?;
+        writer.write_u64::<LE>(self.compressed_data_size)?;
+        writer.write_u64::<LE>(self.data_size)?;
+        writer.write_u32::<LE>(self.block_size)?;
+        writer.write_u32::<LE>(self.num_blocks)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_gcz_truncated_tables() -> Result<()> {
+        let mut image = std::io::Cursor::new(vec![]);
+        write_gcz(&mut [0u8; 0x100].as_slice(), 0x100, &mut image)?;
+        // Claim far more blocks than the file can hold
+        image.get_mut()[0x1c..0x20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(GczReader::new(image), Err(Error::InvalidHeader(ImageFormat::Gcz))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gcz_oversized_block() -> Result<()> {
+        let mut image = std::io::Cursor::new(vec![]);
+        write_gcz(&mut [0u8; 0x100].as_slice(), 0x100, &mut image)?;
+        // Shrink the block size so the stored block can't possibly fit in it
+        image.get_mut()[0x18..0x1c].copy_from_slice(&1u32.to_le_bytes());
+        let mut reader = GczReader::new(image)?;
+        assert!(matches!(reader.read_block(0, &mut vec![]), Err(Error::CorruptBlock(0))));
+        Ok(())
+    }
+}
+
+/// Reads blocks out of a GCZ image.
+pub struct GczReader<R: ReadSeek> {
+    reader: R,
+    header: GczHeader,
+    /// The offset of each block relative to the start of the compressed data.
+    pointers: Vec<u64>,
+    /// The Adler-32 checksum of each block as it is stored in the image.
+    hashes: Vec<u32>,
+    /// The offset of the start of the compressed data in the image.
+    data_offset: u64,
+    /// Buffer for compressed block data.
+    compressed: Vec<u8>,
+}
+
+impl<R: ReadSeek> GczReader<R> {
+    /// Opens a GCZ image by reading its header from `reader`.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        reader.rewind()?;
+        let header = reader.read_struct::<GczHeader>()?;
+        if header.block_size == 0 {
+            return Err(Error::InvalidHeader(ImageFormat::Gcz));
+        }
+        // Make sure the tables and data actually fit in the file before allocating anything
+        let data_offset = GCZ_HEADER_SIZE + header.num_blocks as u64 * 12;
+        if data_offset > len || header.compressed_data_size > len - data_offset {
+            return Err(Error::InvalidHeader(ImageFormat::Gcz));
+        }
+        let num_blocks = header.num_blocks as usize;
+        let mut pointers = vec![0u64; num_blocks];
+        reader.read_u64_into::<LE>(&mut pointers)?;
+        let mut hashes = vec![0u32; num_blocks];
+        reader.read_u32_into::<LE>(&mut hashes)?;
+        debug!(
+            "Opened GCZ image: block size = {:#x}, {} blocks",
+            header.block_size, header.num_blocks
+        );
+        Ok(Self { reader, header, pointers, hashes, data_offset, compressed: vec![] })
+    }
+}
+
+impl<R: ReadSeek> BlockRead for GczReader<R> {
+    fn data_size(&self) -> u64 {
+        self.header.data_size
+    }
+
+    fn read_block(&mut self, offset: u64, buf: &mut Vec<u8>) -> Result<u64> {
+        let index = (offset / self.header.block_size as u64) as usize;
+        let block_offset = index as u64 * self.header.block_size as u64;
+        let pointer = *self.pointers.get(index).ok_or(Error::CorruptBlock(block_offset))?;
+        let start = pointer & !GCZ_UNCOMPRESSED_FLAG;
+        let end = match self.pointers.get(index + 1) {
+            Some(next) => next & !GCZ_UNCOMPRESSED_FLAG,
+            None => self.header.compressed_data_size,
+        };
+        // Blocks are only stored compressed if that makes them smaller
+        if end < start || end - start > self.header.block_size as u64 {
+            return Err(Error::CorruptBlock(block_offset));
+        }
+
+        self.compressed.resize((end - start) as usize, 0);
+        self.reader.seek(SeekFrom::Start(self.data_offset + start))?;
+        self.reader.read_exact(&mut self.compressed)?;
+        if adler32(&self.compressed) != self.hashes[index] {
+            return Err(Error::CorruptBlock(block_offset));
+        }
+
+        buf.clear();
+        if pointer & GCZ_UNCOMPRESSED_FLAG != 0 {
+            buf.extend_from_slice(&self.compressed);
+        } else {
+            let decoder = ZlibDecoder::new(self.compressed.as_slice());
+            decoder.take(self.header.block_size.into()).read_to_end(buf)?;
+        }
+        Ok(block_offset)
+    }
+}
+
+/// Reads `size` bytes of disc data from `reader` and writes them to `writer` as a GCZ image.
+pub fn write_gcz(reader: &mut dyn Read, size: u64, mut writer: impl Write + Seek) -> Result<()> {
+    let block_size = GCZ_DEFAULT_BLOCK_SIZE;
+    let num_blocks = u32::try_from(size.div_ceil(block_size as u64))
+        .map_err(|_| Error::TooLarge(ImageFormat::Gcz))?;
+
+    // Leave room for the header and tables and fill them in at the end
+    let start = writer.stream_position()?;
+    let data_offset = start + GCZ_HEADER_SIZE + num_blocks as u64 * 12;
+    writer.seek(SeekFrom::Start(data_offset))?;
+
+    let mut pointers = Vec::with_capacity(num_blocks as usize);
+    let mut hashes = Vec::with_capacity(num_blocks as usize);
+    let mut block = vec![0u8; block_size as usize];
+    let mut compressed = Vec::with_capacity(block_size as usize);
+    let mut offset = 0;
+    let mut reader = reader.take(size);
+    for _ in 0..num_blocks {
+        read_padded(&mut reader, &mut block)?;
+        compressed.clear();
+        let mut encoder = ZlibEncoder::new(&mut compressed, Compression::default());
+        encoder.write_all(&block)?;
+        encoder.finish()?;
+        // Store the block uncompressed if compression doesn't actually help
+        let (stored, pointer) = if compressed.len() < block.len() {
+            (&compressed, offset)
+        } else {
+            (&block, offset | GCZ_UNCOMPRESSED_FLAG)
+        };
+        writer.write_all(stored)?;
+        pointers.push(pointer);
+        hashes.push(adler32(stored));
+        offset += stored.len() as u64;
+    }
+
+    let header = GczHeader {
+        sub_type: GCZ_SUB_TYPE_GAMECUBE,
+        compressed_data_size: offset,
+        data_size: size,
+        block_size,
+        num_blocks,
+    };
+    writer.seek(SeekFrom::Start(start))?;
+    writer.write_struct(&header)?;
+    for &pointer in &pointers {
+        writer.write_u64::<LE>(pointer)?;
+    }
+    for &hash in &hashes {
+        writer.write_u32::<LE>(hash)?;
+    }
+    writer.seek(SeekFrom::End(0))?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Calculates the Adler-32 checksum of `bytes`.
+fn adler32(bytes: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    // 5552 is the largest number of bytes which can be summed without overflowing
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::assert_round_trip;
+    use super::*;
+    use crate::assert_write_and_read;
+
+    #[test]
+    fn test_write_and_read_gcz_header() {
+        assert_write_and_read!(GczHeader {
+            sub_type: 1,
+            compressed_data_size: 2,
+            data_size: 3,
+            block_size: 4,
+            num_blocks: 5,
+        });
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(&[0xff; 0x10000]), 0x77970ef2);
+    }
+
+    #[test]
+    fn test_write_and_read_gcz() -> Result<()> {
+        assert_round_trip(ImageFormat::Gcz, 0x48000)?;
+        assert_round_trip(ImageFormat::Gcz, 0x100)
+    }
+
+    #[test]
+    fn test_gcz_checksum_mismatch() -> Result<()> {
+        let mut image = std::io::Cursor::new(vec![]);
+        write_gcz(&mut [0u8; 0x100].as_slice(), 0x100, &mut image)?;
+        let last = image.get_ref().len() - 1;
+        image.get_mut()[last] ^= 0xff;
+        let mut reader = GczReader::new(image)?;
+        assert!(matches!(reader.read_block(0, &mut vec![]), Err(Error::CorruptBlock(0))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gcz_truncated_tables() -> Result<()> {
+        let mut image = std::io::Cursor::new(vec![]);
+        write_gcz(&mut [0u8; 0x100].as_slice(), 0x100, &mut image)?;
+        // Claim far more blocks than the file can hold
+        image.get_mut()[0x1c..0x20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(GczReader::new(image), Err(Error::InvalidHeader(ImageFormat::Gcz))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_gcz_oversized_block() -> Result<()> {
+        let mut image = std::io::Cursor::new(vec![]);
+        write_gcz(&mut [0u8; 0x100].as_slice(), 0x100, &mut image)?;
+        // Shrink the block size so the stored block can't possibly fit in it
+        image.get_mut()[0x18..0x1c].copy_from_slice(&1u32.to_le_bytes());
+        let mut reader = GczReader::new(image)?;
+        assert!(matches!(reader.read_block(0, &mut vec![]), Err(Error::CorruptBlock(0))));
+        Ok(())
+    }
+}
@@ -0,0 +1,646 @@
+use super::{BlockRead, Error, ImageFormat, Result, RVZ_MAGIC, WIA_MAGIC};
+use crate::common::ReadSeek;
+use byteorder::{ByteOrder, ReadBytesExt, BE, LE};
+use lzma_rs::decompress::raw::{LzmaDecoder, LzmaParams, LzmaProperties};
+use std::io::{Read, SeekFrom};
+use tracing::debug;
+
+/// Size of the first header.
+const HEADER_1_SIZE: u64 = 0x48;
+/// Size of the second header's fixed fields.
+const HEADER_2_MIN_SIZE: usize = 0xdc;
+/// Size of a full GameCube disc.
+const MAX_ISO_SIZE: u64 = 0x57058000;
+/// Size of the part of the disc header which is stored in the second header.
+const DISC_HEADER_SIZE: usize = 0x80;
+/// Disc type for GameCube discs.
+const DISC_TYPE_GAMECUBE: u32 = 1;
+/// Raw data entries start on boundaries of this size.
+const SECTOR_SIZE: u64 = 0x8000;
+/// Size of the SHA-1 hash at the end of purged data.
+const PURGE_HASH_SIZE: usize = 20;
+/// Set in an RVZ group's data size if the group is compressed.
+const RVZ_COMPRESSED_FLAG: u32 = 0x80000000;
+/// Set in an RVZ packed data size if the data is junk which needs to be generated.
+const RVZ_JUNK_FLAG: u32 = 0x80000000;
+
+/// Compression methods supported by WIA and RVZ.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Compression {
+    None,
+    Purge,
+    Bzip2,
+    Lzma,
+    Lzma2,
+    Zstd,
+}
+
+impl Compression {
+    fn from_u32(format: ImageFormat, value: u32) -> Result<Self> {
+        Ok(match value {
+            0 => Self::None,
+            1 if format == ImageFormat::Wia => Self::Purge,
+            2 => Self::Bzip2,
+            3 => Self::Lzma,
+            4 => Self::Lzma2,
+            5 if format == ImageFormat::Rvz => Self::Zstd,
+            _ => return Err(Error::UnsupportedCompression(format, value)),
+        })
+    }
+}
+
+/// Describes a region of disc data which is stored in a sequence of groups.
+#[derive(Debug, Copy, Clone)]
+struct RawDataEntry {
+    data_offset: u64,
+    data_size: u64,
+    group_index: u32,
+    num_groups: u32,
+}
+
+impl RawDataEntry {
+    const SIZE: usize = 0x18;
+
+    fn parse(bytes: &[u8]) -> Self {
+        Self {
+            data_offset: BE::read_u64(&bytes[0x0..0x8]),
+            data_size: BE::read_u64(&bytes[0x8..0x10]),
+            group_index: BE::read_u32(&bytes[0x10..0x14]),
+            num_groups: BE::read_u32(&bytes[0x14..0x18]),
+        }
+    }
+
+    /// Returns the offset that groups start at. This is always aligned to a sector boundary.
+    fn groups_offset(&self) -> u64 {
+        self.data_offset - self.data_offset % SECTOR_SIZE
+    }
+
+    /// Returns the offset of the end of the entry's data.
+    fn end(&self) -> u64 {
+        self.data_offset + self.data_size
+    }
+}
+
+/// Describes where a chunk of data is stored in the image file.
+#[derive(Debug, Copy, Clone)]
+struct GroupEntry {
+    /// The offset of the data in the file.
+    offset: u64,
+    /// The size of the stored data.
+    size: u32,
+    /// True if the data is compressed.
+    compressed: bool,
+    /// If nonzero, the size of the data after decompression but before unpacking (RVZ only).
+    packed_size: u32,
+}
+
+impl GroupEntry {
+    const WIA_SIZE: usize = 0x8;
+    const RVZ_SIZE: usize = 0xc;
+
+    fn parse(format: ImageFormat, bytes: &[u8]) -> Self {
+        let offset = (BE::read_u32(&bytes[0x0..0x4]) as u64) << 2;
+        let size = BE::read_u32(&bytes[0x4..0x8]);
+        if format == ImageFormat::Rvz {
+            Self {
+                offset,
+                size: size & !RVZ_COMPRESSED_FLAG,
+                compressed: size & RVZ_COMPRESSED_FLAG != 0,
+                packed_size: BE::read_u32(&bytes[0x8..0xc]),
+            }
+        } else {
+            Self { offset, size, compressed: true, packed_size: 0 }
+        }
+    }
+}
+
+/// Reads blocks out of a WIA or RVZ image. Only GameCube discs are supported.
+///
+/// Each group of disc data is returned as a single block.
+pub struct WiaReader<R: ReadSeek> {
+    reader: R,
+    format: ImageFormat,
+    iso_size: u64,
+    compression: Compression,
+    compressor_data: Vec<u8>,
+    chunk_size: u64,
+    disc_header: Vec<u8>,
+    raw_data: Vec<RawDataEntry>,
+    groups: Vec<GroupEntry>,
+}
+
+impl<R: ReadSeek> WiaReader<R> {
+    /// Opens a WIA or RVZ image by reading its headers from `reader`.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        // Sizes in the headers have to be checked against the file before allocating anything
+        let fits = |offset: u64, size: u64| offset.checked_add(size).is_some_and(|end| end <= len);
+        reader.rewind()?;
+        let format = match reader.read_u32::<LE>()? {
+            WIA_MAGIC => ImageFormat::Wia,
+            RVZ_MAGIC => ImageFormat::Rvz,
+            _ => return Err(Error::InvalidHeader(ImageFormat::Wia)),
+        };
+        reader.seek(SeekFrom::Start(0xc))?;
+        let header_2_size = reader.read_u32::<BE>()? as usize;
+        reader.seek(SeekFrom::Start(0x24))?;
+        let iso_size = reader.read_u64::<BE>()?;
+        if header_2_size < HEADER_2_MIN_SIZE
+            || !fits(HEADER_1_SIZE, header_2_size as u64)
+            || iso_size > MAX_ISO_SIZE
+        {
+            return Err(Error::InvalidHeader(format));
+        }
+
+        reader.seek(SeekFrom::Start(HEADER_1_SIZE))?;
+        let mut header = vec![0u8; header_2_size];
+        reader.read_exact(&mut header)?;
+        if BE::read_u32(&header[0x0..0x4]) != DISC_TYPE_GAMECUBE {
+            return Err(Error::WiiNotSupported);
+        }
+        let compression = Compression::from_u32(format, BE::read_u32(&header[0x4..0x8]))?;
+        let chunk_size = BE::read_u32(&header[0xc..0x10]) as u64;
+        if chunk_size < SECTOR_SIZE {
+            return Err(Error::InvalidHeader(format));
+        }
+        let disc_header = header[0x10..(0x10 + DISC_HEADER_SIZE)].to_vec();
+        let num_raw_data = BE::read_u32(&header[0xb4..0xb8]) as usize;
+        let raw_data_offset = BE::read_u64(&header[0xb8..0xc0]);
+        let raw_data_size = BE::read_u32(&header[0xc0..0xc4]);
+        let num_groups = BE::read_u32(&header[0xc4..0xc8]) as usize;
+        let groups_offset = BE::read_u64(&header[0xc8..0xd0]);
+        let groups_size = BE::read_u32(&header[0xd0..0xd4]);
+        let compressor_data_size = (header[0xd4] as usize).min(7);
+        let compressor_data = header[0xd5..(0xd5 + compressor_data_size)].to_vec();
+        // Raw data entries are sector-aligned and each one can end with a partial group
+        if num_raw_data as u64 > iso_size.div_ceil(SECTOR_SIZE)
+            || num_groups as u64 > iso_size.div_ceil(chunk_size) + num_raw_data as u64
+            || !fits(raw_data_offset, raw_data_size.into())
+            || !fits(groups_offset, groups_size.into())
+        {
+            return Err(Error::InvalidHeader(format));
+        }
+
+        let mut wia = Self {
+            reader,
+            format,
+            iso_size,
+            compression,
+            compressor_data,
+            chunk_size,
+            disc_header,
+            raw_data: vec![],
+            groups: vec![],
+        };
+
+        let bytes =
+            wia.read_table(raw_data_offset, raw_data_size, num_raw_data * RawDataEntry::SIZE)?;
+        wia.raw_data = bytes.chunks_exact(RawDataEntry::SIZE).map(RawDataEntry::parse).collect();
+        let group_size =
+            if format == ImageFormat::Rvz { GroupEntry::RVZ_SIZE } else { GroupEntry::WIA_SIZE };
+        let bytes = wia.read_table(groups_offset, groups_size, num_groups * group_size)?;
+        wia.groups = bytes.chunks_exact(group_size).map(|b| GroupEntry::parse(format, b)).collect();
+        debug!(
+            "Opened {} image: compression = {:?}, chunk size = {:#x}, {} groups",
+            format, compression, chunk_size, num_groups
+        );
+        Ok(wia)
+    }
+
+    /// Reads and decompresses a table of entries.
+    fn read_table(&mut self, offset: u64, stored_size: u32, size: usize) -> Result<Vec<u8>> {
+        let mut stored = vec![0u8; stored_size as usize];
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader.read_exact(&mut stored)?;
+        let bytes = self.decompress(&stored, size)?;
+        if bytes.len() < size {
+            return Err(Error::InvalidHeader(self.format));
+        }
+        Ok(bytes)
+    }
+
+    /// Decompresses `data` which is expected to decompress to `size` bytes.
+    fn decompress(&self, data: &[u8], size: usize) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(size);
+        match self.compression {
+            Compression::None => out.extend_from_slice(data),
+            Compression::Purge => unpurge(data, size, &mut out)?,
+            Compression::Bzip2 => {
+                bzip2_rs::DecoderReader::new(data).read_to_end(&mut out)?;
+            }
+            Compression::Lzma => {
+                let props = *self.compressor_data.first().ok_or_else(bad_compressor_data)?;
+                let dict_size = match self.compressor_data.get(1..5) {
+                    Some(bytes) => LE::read_u32(bytes),
+                    None => return Err(bad_compressor_data()),
+                };
+                if props >= 9 * 5 * 5 {
+                    return Err(bad_compressor_data());
+                }
+                let properties = LzmaProperties {
+                    lc: (props % 9).into(),
+                    lp: ((props / 9) % 5).into(),
+                    pb: (props / 45).into(),
+                };
+                let params = LzmaParams::new(properties, dict_size, Some(size as u64));
+                LzmaDecoder::new(params, None)
+                    .and_then(|mut d| d.decompress(&mut &*data, &mut out))
+                    .map_err(|e| Error::Decompress(e.to_string()))?;
+            }
+            Compression::Lzma2 => {
+                lzma_rs::lzma2_decompress(&mut &*data, &mut out)
+                    .map_err(|e| Error::Decompress(e.to_string()))?;
+            }
+            Compression::Zstd => {
+                ruzstd::StreamingDecoder::new(data)
+                    .map_err(|e| Error::Decompress(e.to_string()))?
+                    .read_to_end(&mut out)?;
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl<R: ReadSeek> BlockRead for WiaReader<R> {
+    fn data_size(&self) -> u64 {
+        self.iso_size
+    }
+
+    fn read_block(&mut self, offset: u64, buf: &mut Vec<u8>) -> Result<u64> {
+        buf.clear();
+        if offset < DISC_HEADER_SIZE as u64 {
+            buf.extend_from_slice(&self.disc_header);
+            return Ok(0);
+        }
+
+        let entry = match self
+            .raw_data
+            .iter()
+            .find(|e| offset >= e.groups_offset() && offset < e.end())
+        {
+            Some(entry) => *entry,
+            None => {
+                // Data which isn't covered by any entry reads as zeros
+                let end =
+                    self.raw_data.iter().map(|e| e.groups_offset()).filter(|&o| o > offset).min();
+                let end = end.unwrap_or(self.iso_size).min(offset + self.chunk_size);
+                buf.resize((end - offset) as usize, 0);
+                return Ok(offset);
+            }
+        };
+
+        let index_in_entry = (offset - entry.groups_offset()) / self.chunk_size;
+        let start = entry.groups_offset() + index_in_entry * self.chunk_size;
+        if index_in_entry >= entry.num_groups.into() {
+            return Err(Error::CorruptBlock(start));
+        }
+        let group_index = entry.group_index as usize + index_in_entry as usize;
+        let group = *self.groups.get(group_index).ok_or(Error::CorruptBlock(start))?;
+        let size = (entry.end() - start).min(self.chunk_size) as usize;
+        if group.size == 0 {
+            buf.resize(size, 0);
+            return Ok(start);
+        }
+
+        let mut stored = vec![0u8; group.size as usize];
+        self.reader.seek(SeekFrom::Start(group.offset))?;
+        self.reader.read_exact(&mut stored)?;
+        let unpacked_size = if group.packed_size != 0 { group.packed_size as usize } else { size };
+        let data = if group.compressed { self.decompress(&stored, unpacked_size)? } else { stored };
+        if group.packed_size != 0 {
+            unpack(&data, start, size, buf).ok_or(Error::CorruptBlock(start))?;
+        } else {
+            buf.extend_from_slice(&data);
+        }
+        if buf.len() < size {
+            return Err(Error::CorruptBlock(start));
+        }
+        buf.truncate(size);
+        Ok(start)
+    }
+}
+
+fn bad_compressor_data() -> Error {
+    Error::Decompress("invalid LZMA properties".to_owned())
+}
+
+/// Decodes WIA purge-compressed data. The data consists of segments which each have an offset and
+/// size, with zeros in between, followed by a SHA-1 hash.
+fn unpurge(data: &[u8], size: usize, out: &mut Vec<u8>) -> Result<()> {
+    let invalid = || Error::Decompress("invalid purge segment".to_owned());
+    let segments = data.len().checked_sub(PURGE_HASH_SIZE).ok_or_else(invalid)?;
+    let mut data = &data[..segments];
+    while !data.is_empty() {
+        let offset = data.read_u32::<BE>()? as usize;
+        let len = data.read_u32::<BE>()? as usize;
+        if offset < out.len() || len > data.len() {
+            return Err(invalid());
+        }
+        out.resize(offset, 0);
+        out.extend_from_slice(&data[..len]);
+        data = &data[len..];
+    }
+    if out.len() < size {
+        out.resize(size, 0);
+    }
+    Ok(())
+}
+
+/// Unpacks RVZ packed data starting at disc offset `offset` and appends `size` bytes to `out`.
+/// Packed data is a sequence of entries which either contain literal data or a seed for junk data.
+/// Returns `None` if the data is invalid.
+fn unpack(mut data: &[u8], mut offset: u64, size: usize, out: &mut Vec<u8>) -> Option<()> {
+    let end = out.len() + size;
+    while out.len() < end {
+        let len = data.read_u32::<BE>().ok()?;
+        let size = (len & !RVZ_JUNK_FLAG) as usize;
+        if len & RVZ_JUNK_FLAG != 0 {
+            let mut seed = [0u32; LaggedFibonacci::SEED_SIZE];
+            data.read_u32_into::<BE>(&mut seed).ok()?;
+            let mut lfg = LaggedFibonacci::new(&seed);
+            lfg.skip((offset % SECTOR_SIZE) as usize);
+            lfg.generate(size, out);
+        } else {
+            out.extend_from_slice(data.get(..size)?);
+            data = &data[size..];
+        }
+        offset += size as u64;
+    }
+    out.truncate(end);
+    Some(())
+}
+
+/// The lagged Fibonacci generator which produces the junk data on GameCube and Wii discs.
+struct LaggedFibonacci {
+    buffer: [u32; Self::K],
+    position: usize,
+}
+
+impl LaggedFibonacci {
+    const K: usize = 521;
+    const J: usize = 32;
+    const SEED_SIZE: usize = 17;
+    const BUFFER_BYTES: usize = Self::K * 4;
+
+    fn new(seed: &[u32; Self::SEED_SIZE]) -> Self {
+        let mut buffer = [0u32; Self::K];
+        buffer[..Self::SEED_SIZE].copy_from_slice(seed);
+        for i in Self::SEED_SIZE..Self::K {
+            buffer[i] = (buffer[i - 17] << 23) ^ (buffer[i - 16] >> 9) ^ buffer[i - 1];
+        }
+        // The output is shifted oddly, so do it up-front to keep generation simple
+        for x in &mut buffer {
+            *x = (*x & 0xff00ffff) | ((*x >> 2) & 0x00ff0000);
+        }
+        let mut lfg = Self { buffer, position: 0 };
+        for _ in 0..4 {
+            lfg.advance();
+        }
+        lfg
+    }
+
+    /// Generates the next `K` values.
+    fn advance(&mut self) {
+        for i in 0..Self::J {
+            self.buffer[i] ^= self.buffer[i + Self::K - Self::J];
+        }
+        for i in Self::J..Self::K {
+            self.buffer[i] ^= self.buffer[i - Self::J];
+        }
+    }
+
+    /// Skips over `count` bytes of output.
+    fn skip(&mut self, count: usize) {
+        self.position += count;
+        while self.position >= Self::BUFFER_BYTES {
+            self.advance();
+            self.position -= Self::BUFFER_BYTES;
+        }
+    }
+
+    /// Generates `count` bytes of output and appends them to `out`.
+    fn generate(&mut self, mut count: usize, out: &mut Vec<u8>) {
+        while count > 0 {
+            let len = count.min(Self::BUFFER_BYTES - self.position);
+            let start = self.position;
+            out.extend(self.buffer.iter().flat_map(|x| x.to_be_bytes()).skip(start).take(len));
+            self.position += len;
+            count -= len;
+            if self.position == Self::BUFFER_BYTES {
+                self.advance();
+                self.position = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::open_image;
+    use super::super::tests::make_test_data;
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use std::io::{Cursor, Write};
+
+    const TEST_CHUNK_SIZE: usize = 0x8000;
+
+    /// Writes a GameCube WIA/RVZ image where each group is stored with `write_group`, which returns
+    /// the stored group data along with its RVZ packed size.
+    fn make_image(
+        format: ImageFormat,
+        compression: u32,
+        compressor_data: &[u8],
+        data: &[u8],
+        compress: impl Fn(&[u8]) -> Vec<u8>,
+        write_group: impl Fn(usize, &[u8]) -> (Vec<u8>, u32),
+    ) -> Vec<u8> {
+        let num_groups = data.len().div_ceil(TEST_CHUNK_SIZE);
+        let mut groups = vec![];
+        let mut group_data = vec![];
+        let data_start = 0x200;
+        for (i, chunk) in data.chunks(TEST_CHUNK_SIZE).enumerate() {
+            let (stored, packed_size) = write_group(i, chunk);
+            let offset = (data_start + group_data.len()) as u32 >> 2;
+            groups.write_u32::<BE>(offset).unwrap();
+            if format == ImageFormat::Rvz {
+                let flag = if compression != 0 { RVZ_COMPRESSED_FLAG } else { 0 };
+                groups.write_u32::<BE>(stored.len() as u32 | flag).unwrap();
+                groups.write_u32::<BE>(packed_size).unwrap();
+            } else {
+                groups.write_u32::<BE>(stored.len() as u32).unwrap();
+            }
+            group_data.extend(stored);
+            group_data.resize(group_data.len().next_multiple_of(4), 0);
+        }
+        let groups = compress(&groups);
+
+        let mut raw_data = vec![];
+        raw_data.write_u64::<BE>(DISC_HEADER_SIZE as u64).unwrap();
+        raw_data.write_u64::<BE>((data.len() - DISC_HEADER_SIZE) as u64).unwrap();
+        raw_data.write_u32::<BE>(0).unwrap();
+        raw_data.write_u32::<BE>(num_groups as u32).unwrap();
+        let raw_data = compress(&raw_data);
+
+        let mut header = vec![];
+        header.write_u32::<BE>(DISC_TYPE_GAMECUBE).unwrap();
+        header.write_u32::<BE>(compression).unwrap();
+        header.write_u32::<BE>(0).unwrap();
+        header.write_u32::<BE>(TEST_CHUNK_SIZE as u32).unwrap();
+        header.write_all(&data[..DISC_HEADER_SIZE]).unwrap();
+        header.extend([0; 0x24]);
+        let tables_offset = data_start + group_data.len();
+        header.write_u32::<BE>(1).unwrap();
+        header.write_u64::<BE>(tables_offset as u64).unwrap();
+        header.write_u32::<BE>(raw_data.len() as u32).unwrap();
+        header.write_u32::<BE>(num_groups as u32).unwrap();
+        header.write_u64::<BE>((tables_offset + raw_data.len()) as u64).unwrap();
+        header.write_u32::<BE>(groups.len() as u32).unwrap();
+        header.push(compressor_data.len() as u8);
+        header.extend(compressor_data);
+        header.resize(HEADER_2_MIN_SIZE, 0);
+
+        let mut image = vec![];
+        let magic = if format == ImageFormat::Rvz { RVZ_MAGIC } else { WIA_MAGIC };
+        image.write_u32::<LE>(magic).unwrap();
+        image.extend([0; 8]);
+        image.write_u32::<BE>(header.len() as u32).unwrap();
+        image.extend([0; 20]);
+        image.write_u64::<BE>(data.len() as u64).unwrap();
+        image.extend([0; 28]);
+        image.extend(header);
+        image.resize(data_start, 0);
+        image.extend(group_data);
+        image.extend(raw_data);
+        image.extend(groups);
+        image
+    }
+
+    fn assert_image_matches(image: Vec<u8>, format: ImageFormat, data: &[u8]) -> Result<()> {
+        let (detected, mut stream) = open_image(Cursor::new(image))?;
+        assert_eq!(detected, format);
+        let mut decoded = vec![];
+        stream.read_to_end(&mut decoded)?;
+        assert!(decoded == data);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_wia_uncompressed() -> Result<()> {
+        let data = make_test_data(0x1c000);
+        let image =
+            make_image(ImageFormat::Wia, 0, &[], &data, |b| b.to_vec(), |_, c| (c.to_vec(), 0));
+        assert_image_matches(image, ImageFormat::Wia, &data)
+    }
+
+    #[test]
+    fn test_wia_truncated_header() {
+        let data = make_test_data(0x1c000);
+        let image =
+            make_image(ImageFormat::Wia, 0, &[], &data, |b| b.to_vec(), |_, c| (c.to_vec(), 0));
+
+        let mut huge_header = image.clone();
+        huge_header[0xc..0x10].copy_from_slice(&u32::MAX.to_be_bytes());
+        let result = WiaReader::new(Cursor::new(huge_header));
+        assert!(matches!(result, Err(Error::InvalidHeader(ImageFormat::Wia))));
+
+        let mut bad_table = image.clone();
+        let groups_size = HEADER_1_SIZE as usize + 0xd0;
+        bad_table[groups_size..(groups_size + 4)].copy_from_slice(&u32::MAX.to_be_bytes());
+        let result = WiaReader::new(Cursor::new(bad_table));
+        assert!(matches!(result, Err(Error::InvalidHeader(ImageFormat::Wia))));
+
+        let mut many_groups = image;
+        let num_groups = HEADER_1_SIZE as usize + 0xc4;
+        many_groups[num_groups..(num_groups + 4)].copy_from_slice(&u32::MAX.to_be_bytes());
+        let result = WiaReader::new(Cursor::new(many_groups));
+        assert!(matches!(result, Err(Error::InvalidHeader(ImageFormat::Wia))));
+    }
+
+    #[test]
+    fn test_read_wia_purge() -> Result<()> {
+        let mut data = make_test_data(0x1c000);
+        data[0x8000..0x10000].fill(0);
+        data[0x10100..0x10200].fill(0);
+        let purge = |bytes: &[u8]| {
+            // Store each run of nonzero 0x100-byte blocks as a segment
+            let mut out = vec![];
+            for (i, block) in bytes.chunks(0x100).enumerate() {
+                if block.iter().any(|&b| b != 0) {
+                    out.write_u32::<BE>((i * 0x100) as u32).unwrap();
+                    out.write_u32::<BE>(block.len() as u32).unwrap();
+                    out.extend(block);
+                }
+            }
+            out.extend([0; PURGE_HASH_SIZE]);
+            out
+        };
+        let image = make_image(ImageFormat::Wia, 1, &[], &data, purge, |_, c| (purge(c), 0));
+        assert_image_matches(image, ImageFormat::Wia, &data)
+    }
+
+    #[test]
+    fn test_read_rvz_lzma() -> Result<()> {
+        let data = make_test_data(0x1c000);
+        let lzma = |bytes: &[u8]| {
+            let mut out = vec![];
+            lzma_rs::lzma_compress(&mut &*bytes, &mut out).unwrap();
+            // Strip the .lzma header
+            out.split_off(13)
+        };
+        let mut header = vec![];
+        lzma_rs::lzma_compress(&mut &[][..], &mut header).unwrap();
+        let image = make_image(ImageFormat::Rvz, 3, &header[..5], &data, lzma, |_, c| (lzma(c), 0));
+        assert_image_matches(image, ImageFormat::Rvz, &data)
+    }
+
+    #[test]
+    fn test_read_rvz_packed() -> Result<()> {
+        let seed: [u32; LaggedFibonacci::SEED_SIZE] = std::array::from_fn(|i| i as u32 * 0x1234567);
+        let mut data = make_test_data(0x18000);
+        // Put junk data at the end of the first group and the start of the second one
+        let mut junk = vec![];
+        let mut lfg = LaggedFibonacci::new(&seed);
+        lfg.generate(0x8000, &mut junk);
+        data[0x4000..0x8000].copy_from_slice(&junk[0x4000..0x8000]);
+        data[0x8000..0xb000].copy_from_slice(&junk[..0x3000]);
+
+        let pack = |i: usize, chunk: &[u8]| {
+            let mut out = vec![];
+            let write_junk = |out: &mut Vec<u8>, len: u32| {
+                out.write_u32::<BE>(len | RVZ_JUNK_FLAG).unwrap();
+                for &x in &seed {
+                    out.write_u32::<BE>(x).unwrap();
+                }
+            };
+            match i {
+                0 => {
+                    out.write_u32::<BE>(0x4000).unwrap();
+                    out.extend(&chunk[..0x4000]);
+                    write_junk(&mut out, 0x4000);
+                }
+                1 => {
+                    write_junk(&mut out, 0x3000);
+                    out.write_u32::<BE>(0x5000).unwrap();
+                    out.extend(&chunk[0x3000..]);
+                }
+                _ => return (chunk.to_vec(), 0),
+            }
+            let len = out.len() as u32;
+            (out, len)
+        };
+        let image = make_image(ImageFormat::Rvz, 0, &[], &data, |b| b.to_vec(), pack);
+        assert_image_matches(image, ImageFormat::Rvz, &data)
+    }
+
+    #[test]
+    fn test_lagged_fibonacci_skip() {
+        let seed: [u32; LaggedFibonacci::SEED_SIZE] = std::array::from_fn(|i| !(i as u32));
+        let mut expected = vec![];
+        LaggedFibonacci::new(&seed).generate(0x3000, &mut expected);
+        let mut actual = vec![];
+        let mut lfg = LaggedFibonacci::new(&seed);
+        lfg.skip(0x1234);
+        lfg.generate(0x3000 - 0x1234, &mut actual);
+        assert_eq!(actual, expected[0x1234..]);
+    }
+}
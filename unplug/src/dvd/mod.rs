@@ -1,13 +1,19 @@
 pub mod archive;
 pub mod banner;
+pub mod disasm;
 pub mod disc;
 pub mod dol;
 pub mod fst;
+pub mod gecko;
 pub mod glob;
+pub mod image;
+pub mod symbols;
 
 pub use archive::{ArchiveBuilder, ArchiveReader};
 pub use banner::{Banner, GameInfo};
-pub use disc::DiscStream;
-pub use dol::DolHeader;
+pub use disc::{ApploaderHeader, DiscBuilder, DiscHeader, DiscHeaderInfo, DiscStream, RegionCode};
+pub use dol::{Dol, DolHeader};
 pub use fst::{DirectoryEntry, EditFile, Entry, EntryId, FileEntry, FileTree, OpenFile};
 pub use glob::{Glob, GlobMode};
+pub use image::ImageFormat;
+pub use symbols::{Symbol, SymbolMap};
@@ -0,0 +1,310 @@
+use super::dol::{self, DolHeader, DolPointer};
+use crate::common::ReadFrom;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use thiserror::Error;
+
+/// The result type for symbol map operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error type for symbol map operations.
+#[derive(Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("symbol not found: {0}")]
+    NotFound(String),
+
+    #[error(transparent)]
+    Dol(Box<dol::Error>),
+
+    #[error(transparent)]
+    Io(Box<io::Error>),
+}
+
+from_error_boxed!(Error::Dol, dol::Error);
+from_error_boxed!(Error::Io, io::Error);
+
+/// A named location in an executable.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbol {
+    /// The symbol's name.
+    pub name: String,
+    /// The memory address of the start of the symbol.
+    pub address: u32,
+    /// The size of the symbol in bytes. This may be 0 if it is unknown.
+    pub size: u32,
+    /// The name of the section the symbol is in (e.g. `.text`), if known.
+    pub section: String,
+}
+
+impl Symbol {
+    /// Returns true if `address` is inside the symbol.
+    pub fn contains(&self, address: u32) -> bool {
+        address == self.address || (address > self.address && address - self.address < self.size)
+    }
+}
+
+/// A table of symbols in an executable which can be looked up by name or address.
+///
+/// Symbol maps can be read from the `.map` files written by the CodeWarrior linker and by Dolphin.
+/// Lines which do not describe a symbol are ignored, as are section symbols (e.g. `.text`).
+#[derive(Debug, Clone, Default)]
+pub struct SymbolMap {
+    /// The symbols sorted by address.
+    symbols: Vec<Symbol>,
+    /// Maps each name to the address of the first symbol which has it.
+    names: HashMap<String, u32>,
+}
+
+impl SymbolMap {
+    /// Constructs an empty `SymbolMap`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a symbol map from the text of a `.map` file.
+    pub fn parse(text: &str) -> Self {
+        let mut map = Self::new();
+        let mut section = "";
+        for line in text.lines() {
+            if line.contains("section layout") {
+                section = line.split_whitespace().next().unwrap_or_default();
+            } else if let Some(symbol) = parse_symbol(line, section) {
+                map.insert(symbol);
+            }
+        }
+        map
+    }
+
+    /// Adds `symbol` to the map.
+    pub fn insert(&mut self, symbol: Symbol) {
+        self.names.entry(symbol.name.clone()).or_insert(symbol.address);
+        let index = self.symbols.partition_point(|s| s.address <= symbol.address);
+        self.symbols.insert(index, symbol);
+    }
+
+    /// Returns the number of symbols in the map.
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    /// Returns true if the map has no symbols.
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Returns an iterator over the symbols in address order.
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    /// Returns the symbol named `name`. If more than one symbol has the name, the first one in the
+    /// map is returned.
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        let address = *self.names.get(name)?;
+        self.symbols_at(address).iter().find(|s| s.name == name)
+    }
+
+    /// Returns the address of the symbol named `name`.
+    pub fn address_of(&self, name: &str) -> Result<u32> {
+        self.get(name).map(|s| s.address).ok_or_else(|| Error::NotFound(name.to_owned()))
+    }
+
+    /// Returns a `DolPointer` to the symbol named `name`.
+    pub fn pointer_of(&self, header: &DolHeader, name: &str) -> Result<DolPointer> {
+        Ok(header.locate_address(self.address_of(name)?)?)
+    }
+
+    /// Returns the first symbol which starts at `address`.
+    pub fn at(&self, address: u32) -> Option<&Symbol> {
+        self.symbols_at(address).first()
+    }
+
+    /// Finds the symbol containing `address` and returns it along with the offset of the address
+    /// from the start of the symbol.
+    pub fn find(&self, address: u32) -> Option<(&Symbol, u32)> {
+        let end = self.symbols.partition_point(|s| s.address <= address);
+        // Prefer the symbol closest to the address, and between symbols at the same address,
+        // prefer the first one
+        let closest = self.symbols[..end].last()?.address;
+        let candidates = self.symbols_at(closest);
+        let symbol = candidates.iter().find(|s| s.contains(address))?;
+        Some((symbol, address - symbol.address))
+    }
+
+    /// Finds the symbol containing the location referenced by `pointer`.
+    pub fn find_pointer(
+        &self,
+        header: &DolHeader,
+        pointer: DolPointer,
+    ) -> Result<Option<(&Symbol, u32)>> {
+        Ok(self.find(header.address_of(pointer)?))
+    }
+
+    /// Returns a label for `address` in the form `name` or `name+0x10` if it is inside a symbol.
+    pub fn label(&self, address: u32) -> Option<String> {
+        self.find(address).map(|(symbol, offset)| match offset {
+            0 => symbol.name.clone(),
+            _ => format!("{}+{:#x}", symbol.name, offset),
+        })
+    }
+
+    fn symbols_at(&self, address: u32) -> &[Symbol] {
+        let start = self.symbols.partition_point(|s| s.address < address);
+        let end = self.symbols.partition_point(|s| s.address <= address);
+        &self.symbols[start..end]
+    }
+}
+
+impl<R: Read + ?Sized> ReadFrom<R> for SymbolMap {
+    type Error = Error;
+    fn read_from(reader: &mut R) -> Result<Self> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Ok(Self::parse(&String::from_utf8_lossy(&bytes)))
+    }
+}
+
+/// Parses a hexadecimal number which has at most 8 digits.
+fn parse_hex(s: &str) -> Option<u32> {
+    if s.is_empty() || s.len() > 8 {
+        return None;
+    }
+    u32::from_str_radix(s, 16).ok()
+}
+
+/// Parses a symbol line. These have the form `start size address [offset] [align] name ...`.
+fn parse_symbol(line: &str, section: &str) -> Option<Symbol> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    if tokens.len() < 4 {
+        return None;
+    }
+    parse_hex(tokens[0])?;
+    let size = parse_hex(tokens[1])?;
+    let address = parse_hex(tokens[2]).filter(|_| tokens[2].len() == 8)?;
+    let is_align = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    let mut index = 3;
+    // Newer linkers include the file offset before the alignment
+    if tokens.len() > 5 && tokens[3].len() == 8 && parse_hex(tokens[3]).is_some() {
+        index += 1;
+    }
+    if tokens.len() > index + 1 && is_align(tokens[index]) {
+        index += 1;
+    }
+    let name = tokens[index];
+    if name.starts_with('.') {
+        return None;
+    }
+    Some(Symbol { name: name.to_owned(), address, size, section: section.to_owned() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::ReadStructExt;
+
+    const CW_MAP: &str = "\
+.init section layout
+  Starting        Virtual
+  address  Size   address
+  -----------------------
+  00000000 0000e0 80003100  1 .init 	os.a __start.o
+  00000000 000024 80003100  4 __start 	os.a __start.o
+  00000024 000010 80003124  4 __init_registers 	os.a __start.o
+  UNUSED   000030 ........ OSGetConsoleType os.a OS.o
+
+
+.data section layout
+  Starting        Virtual  File
+  address  Size   address  offset
+  ---------------------------------
+  00000000 004b28 8021c70c 00219b0c  4 objects 	main.o
+  00004b28 000004 80221234 0021e634  4 @123 	main.o
+  00004b2c 000000 80221238 0021e638 4 empty 	main.o
+
+Memory map:
+       .init  80003100 000024a0 00000100
+";
+
+    const DOLPHIN_MAP: &str = "\
+.text section layout
+80003100 00000024 80003100 0 __start
+80003124 00000010 80003124 0 __init_registers
+.data section layout
+8021c70c 00004b28 8021c70c 0 objects
+";
+
+    fn names(map: &SymbolMap) -> Vec<&str> {
+        map.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_parse_codewarrior_map() {
+        let map = SymbolMap::parse(CW_MAP);
+        assert_eq!(names(&map), ["__start", "__init_registers", "objects", "@123", "empty"]);
+        let start = map.get("__start").unwrap();
+        assert_eq!((start.address, start.size), (0x80003100, 0x24));
+        assert_eq!(start.section, ".init");
+        let objects = map.get("objects").unwrap();
+        assert_eq!((objects.address, objects.size), (0x8021c70c, 0x4b28));
+        assert_eq!(objects.section, ".data");
+        assert!(map.get("OSGetConsoleType").is_none());
+    }
+
+    #[test]
+    fn test_parse_dolphin_map() {
+        let map = SymbolMap::parse(DOLPHIN_MAP);
+        assert_eq!(names(&map), ["__start", "__init_registers", "objects"]);
+        assert_eq!(map.get("__init_registers").unwrap().section, ".text");
+        assert_eq!(map.address_of("objects").unwrap(), 0x8021c70c);
+        assert!(matches!(map.address_of("foo"), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn test_read_symbol_map() -> Result<()> {
+        let map = DOLPHIN_MAP.as_bytes().read_struct::<SymbolMap>()?;
+        assert_eq!(map.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_symbol() {
+        let mut map = SymbolMap::parse(CW_MAP);
+        assert_eq!(map.at(0x80003124).unwrap().name, "__init_registers");
+        assert!(map.at(0x80003125).is_none());
+        assert_eq!(map.find(0x80003100).unwrap().0.name, "__start");
+        assert_eq!(map.find(0x80003123).unwrap(), (map.get("__start").unwrap(), 0x23));
+        assert!(map.find(0x80003134).is_none());
+        assert!(map.find(0x80003000).is_none());
+        assert_eq!(map.find(0x80221238).unwrap().0.name, "empty");
+        assert!(map.find(0x80221239).is_none());
+        assert_eq!(map.label(0x80003100).unwrap(), "__start");
+        assert_eq!(map.label(0x8000312c).unwrap(), "__init_registers+0x8");
+
+        // Symbols added later at the same address should come after existing ones
+        map.insert(Symbol {
+            name: "alias".into(),
+            address: 0x80003100,
+            size: 4,
+            ..Default::default()
+        });
+        assert_eq!(map.at(0x80003100).unwrap().name, "__start");
+        assert_eq!(map.get("alias").unwrap().size, 4);
+    }
+
+    #[test]
+    fn test_symbol_pointers() -> Result<()> {
+        let mut header = DolHeader::new();
+        header.text_offsets[0] = 0x100;
+        header.text_addresses[0] = 0x80003100;
+        header.text_sizes[0] = 0x100;
+        let map = SymbolMap::parse(DOLPHIN_MAP);
+        let pointer = map.pointer_of(&header, "__init_registers")?;
+        assert_eq!(pointer, DolPointer::Text { index: 0, offset: 0x24 });
+        assert_eq!(header.offset_of(pointer)?, 0x124);
+        let found = map.find_pointer(&header, DolPointer::Text { index: 0, offset: 0x28 })?;
+        assert_eq!(found.unwrap(), (map.get("__init_registers").unwrap(), 4));
+        assert!(matches!(map.pointer_of(&header, "objects"), Err(Error::Dol(_))));
+        Ok(())
+    }
+}
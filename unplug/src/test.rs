@@ -57,6 +57,96 @@ pub const TEST_OGG_WAV: &[u8] = include_bytes!("test/ionpack-ogg.wav");
 /// Dummy banner file
 pub const TEST_BANNER: &[u8] = include_bytes!("test/opening.bnr");
 
+/// A small stereo RWAV file
+#[rustfmt::skip]
+pub const TEST_RWAV: &[u8] = &[
+    0x52, 0x57, 0x41, 0x56, // magic
+    0xfe, 0xff, // endian
+    0x01, 0x02, // version
+    0x00, 0x00, 0x01, 0x28, // file_size
+    0x00, 0x20, // header_size
+    0x00, 0x02, // num_sections
+    0x00, 0x00, 0x00, 0x20, // info_offset
+    0x00, 0x00, 0x00, 0xe0, // info_size
+    0x00, 0x00, 0x01, 0x00, // data_offset
+    0x00, 0x00, 0x00, 0x28, // data_size
+
+    // info section
+    0x49, 0x4e, 0x46, 0x4f, // magic
+    0x00, 0x00, 0x00, 0xe0, // size
+    0x02, // codec
+    0x00, // looping
+    0x02, // num_channels
+    0x00,
+    0xac, 0x44, // sample_rate
+    0x00, 0x00,
+    0x00, 0x00, 0x00, 0x02, // start_address
+    0x00, 0x00, 0x00, 0x1f, // end_address
+    0x00, 0x00, 0x00, 0x1c, // channels_offset
+    0x00, 0x00, 0x00, 0xd8, // data_offset
+    0x00, 0x00, 0x00, 0x00, // unk_18
+
+    // channels
+    0x00, 0x00, 0x00, 0x24, // channels[0] offset
+    0x00, 0x00, 0x00, 0x40, // channels[1] offset
+
+    // channels[0]
+    0x00, 0x00, 0x00, 0x00, // data_offset
+    0x00, 0x00, 0x00, 0x5c, // codec_info_offset
+    0x01, 0x00, 0x00, 0x00, // unk_08
+    0x01, 0x00, 0x00, 0x00, // unk_0c
+    0x01, 0x00, 0x00, 0x00, // unk_10
+    0x01, 0x00, 0x00, 0x00, // unk_14
+    0x00, 0x00, 0x00, 0x00, // unk_18
+
+    // channels[1]
+    0x00, 0x00, 0x00, 0x10, // data_offset
+    0x00, 0x00, 0x00, 0x8c, // codec_info_offset
+    0x01, 0x00, 0x00, 0x00, // unk_08
+    0x01, 0x00, 0x00, 0x00, // unk_0c
+    0x01, 0x00, 0x00, 0x00, // unk_10
+    0x01, 0x00, 0x00, 0x00, // unk_14
+    0x00, 0x00, 0x00, 0x00, // unk_18
+
+    // channels[0].codec_info
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, // coefficients[0..4]
+    0x00, 0x04, 0x00, 0x05, 0x00, 0x06, 0x00, 0x07, // coefficient[4..8]
+    0x00, 0x08, 0x00, 0x09, 0x00, 0x0a, 0x00, 0x0b, // coefficient[8..12]
+    0x00, 0x0c, 0x00, 0x0d, 0x00, 0x0e, 0x00, 0x0f, // coefficient[12..16]
+    0x00, 0x00, // gain
+    0x00, 0x17, 0x00, 0x00, 0x00, 0x00, // context
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // loop_context
+    0x00, 0x00,
+
+    // channels[1].codec_info
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, // coefficients[0..4]
+    0x00, 0x04, 0x00, 0x05, 0x00, 0x06, 0x00, 0x07, // coefficients[4..8]
+    0x00, 0x08, 0x00, 0x09, 0x00, 0x0a, 0x00, 0x0b, // coefficients[8..12]
+    0x00, 0x0c, 0x00, 0x0d, 0x00, 0x0e, 0x00, 0x0f, // coefficients[12..16]
+    0x00, 0x00, // gain
+    0x00, 0x17, 0x00, 0x00, 0x00, 0x00, // context
+    0x00, 0x17, 0x00, 0x00, 0x00, 0x00, // loop_context
+    0x00, 0x00,
+
+    // padding
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00,
+
+    // data section
+    0x44, 0x41, 0x54, 0x41, // magic
+    0x00, 0x00, 0x00, 0x28, // size
+
+    // channels[0].data
+    0x17, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+    0x17, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+
+    // channels[1].data
+    0x17, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+    0x17, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
+];
+
 /// Asserts that writing a value to a byte array and reading it back produces the same value.
 #[macro_export]
 macro_rules! assert_write_and_read {